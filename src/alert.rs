@@ -0,0 +1,53 @@
+//! Getting someone's attention when a signal goes above the threshold line or
+//! is detected, for a run left unattended: the terminal bell, or a command of
+//! their own.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Rings the terminal's bell.
+pub fn ring_bell() -> io::Result<()> {
+    // straight to the terminal, rustty only writes to it on a redraw
+    let mut tty = try!(OpenOptions::new().write(true).open("/dev/tty"));
+    try!(tty.write_all(b"\x07"));
+    tty.flush()
+}
+
+/// `template` with `{freq}` replaced by a frequency in Hz and `{power}` by a
+/// power in dB, for a command line.
+pub fn fill_in(template: &str, freq_hz: f64, power_db: f32) -> String {
+    template.replace("{freq}", &format!("{:.0}", freq_hz))
+            .replace("{power}", &format!("{:.1}", power_db))
+}
+
+/// Starts `command` with `sh`, with the frequency and power of the signal
+/// in `ALERT_FREQ_HZ` and `ALERT_POWER_DB`, without waiting for it to finish.
+pub fn run_command(command: &str, freq_hz: f64, power_db: f32) -> io::Result<()> {
+    let mut child = try!(Command::new("sh")
+                             .arg("-c")
+                             .arg(command)
+                             .env("ALERT_FREQ_HZ", format!("{:.0}", freq_hz))
+                             .env("ALERT_POWER_DB", format!("{:.1}", power_db))
+                             .stdin(Stdio::null())
+                             .stdout(Stdio::null())
+                             .stderr(Stdio::null())
+                             .spawn());
+    // reaped off the UI thread, so a slow command doesn't hold up the display
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fill_in;
+
+    #[test]
+    fn test_fill_in() {
+        assert_eq!(fill_in("notify {freq} {power}", 433.92e6, 31.26),
+                   "notify 433920000 31.3");
+        assert_eq!(fill_in("echo {freq}{freq}", 1e6, 0.0), "echo 10000001000000");
+        assert_eq!(fill_in("true", 1e6, 0.0), "true");
+    }
+}
@@ -0,0 +1,499 @@
+//! What the canvas needs from a terminal: a grid of cells to draw into, a way
+//! to put them on the screen, and the keys typed.
+//!
+//! crossterm's terminal is the one used, with the `crossterm` feature on by
+//! default, and rustty's `Terminal` with the `rustty` feature instead. A
+//! backend only has to turn the cells into its own calls and its input into
+//! characters, the rest of the program sees nothing but this trait. crossterm
+//! draws true colors where `COLORTERM` says the terminal has them, and the
+//! nearest of the 256 otherwise; rustty always draws the 256. Tests draw on
+//! a `TestBackend`, which needs no terminal at all.
+
+#[cfg(any(test, feature = "crossterm"))]
+use std::collections::VecDeque;
+#[cfg(feature = "crossterm")]
+use std::env;
+use std::io;
+#[cfg(feature = "crossterm")]
+use std::io::{BufWriter, Stdout, Write};
+use std::time::Duration;
+#[cfg(feature = "crossterm")]
+use std::time::Instant;
+#[cfg(feature = "crossterm")]
+use crossterm::{cursor, event, style, terminal, QueueableCommand};
+#[cfg(feature = "crossterm")]
+use crossterm::event::{KeyCode, KeyEventKind, KeyModifiers, MouseButton, MouseEventKind};
+#[cfg(feature = "rustty")]
+use rustty;
+
+#[cfg(any(test, feature = "crossterm", feature = "rustty"))]
+use cells::{Cell, HasSize};
+#[cfg(any(feature = "crossterm", feature = "rustty"))]
+use cells::{Attr, Color};
+use cells::CellAccessor;
+
+#[cfg(not(any(feature = "crossterm", feature = "rustty")))]
+compile_error!("a terminal backend is needed: build with the crossterm or rustty feature");
+
+/// What a backend reads from the terminal.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A character typed, or one of those in the escape sequence for a key or
+    /// a click.
+    Key(char),
+}
+
+pub trait TerminalBackend: CellAccessor {
+    /// Puts the cells on the screen, sending only what's changed.
+    fn present(&mut self) -> io::Result<()>;
+
+    /// Picks up a change of the terminal's size, returning the new one if
+    /// there was one. The cells are cleared when it changes.
+    fn poll_resize(&mut self) -> io::Result<Option<(usize, usize)>>;
+
+    /// Waits at most `timeout` for the next character typed.
+    fn next_event(&mut self, timeout: Duration) -> io::Result<Option<Event>>;
+}
+
+/// The terminal the canvas draws on, unless given another.
+#[cfg(feature = "crossterm")]
+pub type DefaultBackend = CrosstermBackend;
+#[cfg(all(feature = "rustty", not(feature = "crossterm")))]
+pub type DefaultBackend = RusttyBackend;
+
+/// rustty's `Terminal`, drawn from the crate's cells, which are copied into
+/// its own on each present. True colors become the nearest of its 256.
+#[cfg(feature = "rustty")]
+pub struct RusttyBackend {
+    term: rustty::Terminal,
+    cols: usize,
+    rows: usize,
+    cells: Vec<Cell>,
+}
+
+#[cfg(feature = "rustty")]
+impl RusttyBackend {
+    pub fn new() -> io::Result<Self> {
+        let term = try!(rustty::Terminal::new());
+        let (cols, rows) = rustty::HasSize::size(&term);
+        Ok(RusttyBackend {
+            term: term,
+            cols: cols,
+            rows: rows,
+            cells: vec![Cell::default(); cols * rows],
+        })
+    }
+}
+
+#[cfg(feature = "rustty")]
+fn rustty_color(color: Color) -> rustty::Color {
+    match color {
+        Color::Default => rustty::Color::Default,
+        color => rustty::Color::Byte(color.as_byte()),
+    }
+}
+
+#[cfg(feature = "rustty")]
+fn rustty_cell(cell: &Cell) -> rustty::Cell {
+    let attrs = match cell.attrs() {
+        Attr::Default => rustty::Attr::Default,
+        Attr::Bold => rustty::Attr::Bold,
+        Attr::Underline => rustty::Attr::Underline,
+        Attr::BoldUnderline => rustty::Attr::BoldUnderline,
+        Attr::Reverse => rustty::Attr::Reverse,
+        Attr::BoldReverse => rustty::Attr::BoldReverse,
+        Attr::UnderlineReverse => rustty::Attr::UnderlineReverse,
+        Attr::BoldReverseUnderline => rustty::Attr::BoldReverseUnderline,
+    };
+    rustty::Cell::new(cell.ch(), rustty_color(cell.fg()), rustty_color(cell.bg()), attrs)
+}
+
+#[cfg(feature = "rustty")]
+impl HasSize for RusttyBackend {
+    fn size(&self) -> (usize, usize) {
+        (self.cols, self.rows)
+    }
+}
+
+#[cfg(feature = "rustty")]
+impl CellAccessor for RusttyBackend {
+    fn cellvec(&self) -> &Vec<Cell> {
+        &self.cells
+    }
+
+    fn cellvec_mut(&mut self) -> &mut Vec<Cell> {
+        &mut self.cells
+    }
+}
+
+#[cfg(feature = "rustty")]
+impl TerminalBackend for RusttyBackend {
+    fn present(&mut self) -> io::Result<()> {
+        {
+            let theirs = rustty::CellAccessor::cellvec_mut(&mut self.term);
+            for (theirs, ours) in theirs.iter_mut().zip(&self.cells) {
+                *theirs = rustty_cell(ours);
+            }
+        }
+        self.term.swap_buffers()
+    }
+
+    fn poll_resize(&mut self) -> io::Result<Option<(usize, usize)>> {
+        let size = try!(self.term.try_resize());
+        if let Some((cols, rows)) = size {
+            self.cols = cols;
+            self.rows = rows;
+            self.cells = vec![Cell::default(); cols * rows];
+        }
+        Ok(size)
+    }
+
+    fn next_event(&mut self, timeout: Duration) -> io::Result<Option<Event>> {
+        let event = try!(self.term.get_event(timeout));
+        Ok(event.map(|rustty::Event::Key(c)| Event::Key(c)))
+    }
+}
+
+/// The terminal through crossterm, in raw mode on the alternate screen until
+/// dropped. Its keys and mouse presses are turned back into the characters
+/// and escape sequences the terminal sent, which is what `read_key` decodes.
+#[cfg(feature = "crossterm")]
+pub struct CrosstermBackend {
+    out: BufWriter<Stdout>,
+    cols: usize,
+    rows: usize,
+    cells: Vec<Cell>,
+    /// The cells as on the screen.
+    screen: Vec<Cell>,
+    /// The characters of the last key read and not yet handed out.
+    typed: VecDeque<char>,
+    truecolor: bool,
+}
+
+#[cfg(feature = "crossterm")]
+impl CrosstermBackend {
+    pub fn new() -> io::Result<Self> {
+        let (cols, rows) = try!(terminal::size());
+        try!(terminal::enable_raw_mode());
+        let mut out = BufWriter::new(io::stdout());
+        try!(out.queue(terminal::EnterAlternateScreen));
+        try!(out.queue(cursor::Hide));
+        try!(out.queue(terminal::Clear(terminal::ClearType::All)));
+        try!(out.flush());
+        let (cols, rows) = (cols as usize, rows as usize);
+        // what terminals with true colors set, there being no way to ask
+        let truecolor = env::var("COLORTERM").map_or(false, |c| {
+            c == "truecolor" || c == "24bit"
+        });
+        Ok(CrosstermBackend {
+            out: out,
+            cols: cols,
+            rows: rows,
+            cells: vec![Cell::default(); cols * rows],
+            screen: vec![Cell::default(); cols * rows],
+            typed: VecDeque::new(),
+            truecolor: truecolor,
+        })
+    }
+
+    fn queue_style(&mut self, cell: &Cell) -> io::Result<()> {
+        try!(self.out.queue(style::SetAttribute(style::Attribute::Reset)));
+        let attrs = cell.attrs() as u8;
+        for &(bit, attr) in &[(Attr::Bold, style::Attribute::Bold),
+                              (Attr::Underline, style::Attribute::Underlined),
+                              (Attr::Reverse, style::Attribute::Reverse)] {
+            if attrs & bit as u8 != 0 {
+                try!(self.out.queue(style::SetAttribute(attr)));
+            }
+        }
+        let (fg, bg) = (crossterm_color(cell.fg(), self.truecolor),
+                        crossterm_color(cell.bg(), self.truecolor));
+        try!(self.out.queue(style::SetColors(style::Colors::new(fg, bg))));
+        Ok(())
+    }
+}
+
+#[cfg(feature = "crossterm")]
+fn crossterm_color(color: Color, truecolor: bool) -> style::Color {
+    match color {
+        Color::Default => style::Color::Reset,
+        Color::Rgb(r, g, b) if truecolor => style::Color::Rgb { r: r, g: g, b: b },
+        color => style::Color::AnsiValue(color.as_byte()),
+    }
+}
+
+/// What the terminal sent for `event`, as far as `read_key` goes. Releases,
+/// focus changes and the like send nothing.
+#[cfg(feature = "crossterm")]
+fn typed(event: event::Event) -> String {
+    match event {
+        event::Event::Key(key) if key.kind != KeyEventKind::Release => {
+            match key.code {
+                KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) &&
+                                    c.is_ascii_alphabetic() => {
+                    ((c.to_ascii_lowercase() as u8 & 0x1f) as char).to_string()
+                }
+                KeyCode::Char(c) => c.to_string(),
+                KeyCode::Enter => "\r".to_string(),
+                KeyCode::Backspace => "\x7f".to_string(),
+                KeyCode::Tab => "\t".to_string(),
+                KeyCode::Esc => "\x1b".to_string(),
+                KeyCode::Up => "\x1b[A".to_string(),
+                KeyCode::Down => "\x1b[B".to_string(),
+                KeyCode::Right => "\x1b[C".to_string(),
+                KeyCode::Left => "\x1b[D".to_string(),
+                KeyCode::Home => "\x1b[H".to_string(),
+                KeyCode::End => "\x1b[F".to_string(),
+                KeyCode::PageUp => "\x1b[5~".to_string(),
+                KeyCode::PageDown => "\x1b[6~".to_string(),
+                _ => String::new(),
+            }
+        }
+        event::Event::Mouse(mouse) => {
+            let button = match mouse.kind {
+                MouseEventKind::Down(MouseButton::Left) => 0,
+                MouseEventKind::ScrollUp => 64,
+                MouseEventKind::ScrollDown => 65,
+                _ => return String::new(),
+            };
+            format!("\x1b[<{};{};{}M", button, mouse.column + 1, mouse.row + 1)
+        }
+        // picked up by poll_resize, from the size itself
+        _ => String::new(),
+    }
+}
+
+#[cfg(feature = "crossterm")]
+impl HasSize for CrosstermBackend {
+    fn size(&self) -> (usize, usize) {
+        (self.cols, self.rows)
+    }
+}
+
+#[cfg(feature = "crossterm")]
+impl CellAccessor for CrosstermBackend {
+    fn cellvec(&self) -> &Vec<Cell> {
+        &self.cells
+    }
+
+    fn cellvec_mut(&mut self) -> &mut Vec<Cell> {
+        &mut self.cells
+    }
+}
+
+#[cfg(feature = "crossterm")]
+impl TerminalBackend for CrosstermBackend {
+    fn present(&mut self) -> io::Result<()> {
+        let mut style: Option<Cell> = None;
+        // where the cursor is, once a cell's been written
+        let mut at = None;
+        for i in 0..self.cells.len() {
+            let cell = self.cells[i];
+            if cell == self.screen[i] {
+                continue;
+            }
+            if at != Some(i) {
+                let (col, row) = (i % self.cols, i / self.cols);
+                try!(self.out.queue(cursor::MoveTo(col as u16, row as u16)));
+            }
+            let restyle = style.map_or(true, |last| {
+                (last.fg(), last.bg(), last.attrs()) != (cell.fg(), cell.bg(), cell.attrs())
+            });
+            if restyle {
+                try!(self.queue_style(&cell));
+                style = Some(cell);
+            }
+            try!(self.out.queue(style::Print(cell.ch())));
+            self.screen[i] = cell;
+            // the cursor stays put after the last column
+            at = if (i + 1) % self.cols == 0 { None } else { Some(i + 1) };
+        }
+        self.out.flush()
+    }
+
+    fn poll_resize(&mut self) -> io::Result<Option<(usize, usize)>> {
+        let (cols, rows) = try!(terminal::size());
+        let (cols, rows) = (cols as usize, rows as usize);
+        if (cols, rows) == (self.cols, self.rows) {
+            return Ok(None);
+        }
+        self.cols = cols;
+        self.rows = rows;
+        self.cells = vec![Cell::default(); cols * rows];
+        self.screen = vec![Cell::default(); cols * rows];
+        try!(self.out.queue(style::SetAttribute(style::Attribute::Reset)));
+        try!(self.out.queue(terminal::Clear(terminal::ClearType::All)));
+        try!(self.out.flush());
+        Ok(Some((cols, rows)))
+    }
+
+    fn next_event(&mut self, timeout: Duration) -> io::Result<Option<Event>> {
+        let deadline = Instant::now() + timeout;
+        while self.typed.is_empty() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if !try!(event::poll(left)) {
+                return Ok(None);
+            }
+            self.typed.extend(typed(try!(event::read())).chars());
+        }
+        Ok(self.typed.pop_front().map(Event::Key))
+    }
+}
+
+#[cfg(feature = "crossterm")]
+impl Drop for CrosstermBackend {
+    fn drop(&mut self) {
+        let _ = self.out.queue(style::SetAttribute(style::Attribute::Reset));
+        let _ = self.out.queue(cursor::Show);
+        let _ = self.out.queue(terminal::LeaveAlternateScreen);
+        let _ = self.out.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// A terminal kept in memory, for tests. What's presented is kept to look at,
+/// and keys and resizes are queued up for the canvas to find.
+#[cfg(test)]
+pub struct TestBackend {
+    cols: usize,
+    rows: usize,
+    cells: Vec<Cell>,
+    /// The cells as last presented.
+    screen: Vec<Cell>,
+    /// How many times the cells have been presented.
+    frames: usize,
+    keys: VecDeque<char>,
+    new_size: Option<(usize, usize)>,
+}
+
+#[cfg(test)]
+impl TestBackend {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        TestBackend {
+            cols: cols,
+            rows: rows,
+            cells: vec![Cell::default(); cols * rows],
+            screen: vec![Cell::default(); cols * rows],
+            frames: 0,
+            keys: VecDeque::new(),
+            new_size: None,
+        }
+    }
+
+    /// Queues up `keys` as if typed, escape sequences and all.
+    pub fn type_keys(&mut self, keys: &str) {
+        self.keys.extend(keys.chars());
+    }
+
+    /// Has the terminal change size, as the canvas finds out the next time it
+    /// looks.
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        self.new_size = Some((cols, rows));
+    }
+
+    /// The cells as last presented, row by row.
+    pub fn screen(&self) -> &[Cell] {
+        &self.screen
+    }
+
+    /// A row of what was last presented, as text.
+    pub fn screen_line(&self, row: usize) -> String {
+        self.screen[row * self.cols..][..self.cols].iter().map(Cell::ch).collect()
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+}
+
+#[cfg(test)]
+impl HasSize for TestBackend {
+    fn size(&self) -> (usize, usize) {
+        (self.cols, self.rows)
+    }
+}
+
+#[cfg(test)]
+impl CellAccessor for TestBackend {
+    fn cellvec(&self) -> &Vec<Cell> {
+        &self.cells
+    }
+
+    fn cellvec_mut(&mut self) -> &mut Vec<Cell> {
+        &mut self.cells
+    }
+}
+
+#[cfg(test)]
+impl TerminalBackend for TestBackend {
+    fn present(&mut self) -> io::Result<()> {
+        self.screen.clone_from(&self.cells);
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn poll_resize(&mut self) -> io::Result<Option<(usize, usize)>> {
+        let (cols, rows) = match self.new_size.take() {
+            Some(size) => size,
+            None => return Ok(None),
+        };
+        self.cols = cols;
+        self.rows = rows;
+        self.cells = vec![Cell::default(); cols * rows];
+        self.screen = vec![Cell::default(); cols * rows];
+        Ok(Some((cols, rows)))
+    }
+
+    fn next_event(&mut self, _timeout: Duration) -> io::Result<Option<Event>> {
+        // nothing more is coming, so there's no waiting for it
+        Ok(self.keys.pop_front().map(Event::Key))
+    }
+}
+
+#[cfg(all(test, feature = "crossterm"))]
+mod tests {
+    use std::time::Duration;
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent,
+                           MouseEventKind};
+    use crossterm::style;
+    use cells::Color;
+    use input::{read_key, Key, Mouse, INTERRUPT};
+    use super::{crossterm_color, typed, TestBackend};
+
+    #[test]
+    fn test_typed() {
+        let key = |code, modifiers| Event::Key(KeyEvent::new(code, modifiers));
+        let click = Event::Mouse(MouseEvent {
+            kind: MouseEventKind::Down(MouseButton::Left),
+            column: 9,
+            row: 4,
+            modifiers: KeyModifiers::NONE,
+        });
+        let events = vec![key(KeyCode::Char('q'), KeyModifiers::NONE),
+                          key(KeyCode::Char('c'), KeyModifiers::CONTROL),
+                          key(KeyCode::PageUp, KeyModifiers::NONE),
+                          key(KeyCode::Enter, KeyModifiers::NONE),
+                          Event::Resize(80, 24),
+                          click];
+        // read back as if the terminal had sent them itself
+        let mut term = TestBackend::new(1, 1);
+        for event in events {
+            term.type_keys(&typed(event));
+        }
+        let mut keys = Vec::new();
+        while let Some(key) = read_key(&mut term, Duration::from_secs(0)).unwrap() {
+            keys.push(key);
+        }
+        assert_eq!(keys, vec![Key::Char('q'), INTERRUPT, Key::PageUp, Key::Enter,
+                              Key::Mouse(Mouse::Click, 9, 4)]);
+    }
+
+    #[test]
+    fn test_crossterm_color() {
+        assert_eq!(crossterm_color(Color::Default, true), style::Color::Reset);
+        assert_eq!(crossterm_color(Color::Rgb(1, 2, 3), true),
+                   style::Color::Rgb { r: 1, g: 2, b: 3 });
+        assert_eq!(crossterm_color(Color::Rgb(0, 0, 0), false), style::Color::AnsiValue(16));
+        assert_eq!(crossterm_color(Color::Red, true), style::Color::AnsiValue(1));
+    }
+}
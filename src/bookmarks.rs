@@ -0,0 +1,84 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+/// A named frequency, such as a known channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bookmark {
+    pub name: String,
+    pub freq_hz: f64,
+    /// The span to show it with, if it needs a particular one.
+    pub bandwidth_hz: Option<f64>,
+}
+
+/// Reads bookmarks from a file with one `<freq-hz> <name>` pair per line.
+/// Blank lines and lines starting with `#` are ignored.
+pub fn load(path: &str) -> io::Result<Vec<Bookmark>> {
+    let file = try!(File::open(path));
+    let mut bookmarks = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = try!(line);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match parse_line(line) {
+            Some(bookmark) => bookmarks.push(bookmark),
+            None => {
+                let msg = format!("{}:{}: expected '<freq-hz> <name>'", path, i + 1);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+            }
+        }
+    }
+    Ok(bookmarks)
+}
+
+fn parse_line(line: &str) -> Option<Bookmark> {
+    let mut parts = line.splitn(2, char::is_whitespace);
+    let freq_hz = match parts.next().map(str::parse::<f64>) {
+        Some(Ok(f)) => f,
+        _ => return None,
+    };
+    let name = match parts.next() {
+        Some(name) if !name.trim().is_empty() => name.trim().to_string(),
+        _ => return None,
+    };
+    Some(Bookmark { name: name, freq_hz: freq_hz, bandwidth_hz: None })
+}
+
+/// The bookmark with the next higher frequency than `freq_hz`, or with
+/// `step` below 0 the next lower one, going round at the ends.
+pub fn step(bookmarks: &[Bookmark], freq_hz: f64, step: isize) -> Option<&Bookmark> {
+    let mut sorted: Vec<&Bookmark> = bookmarks.iter().collect();
+    sorted.sort_by(|a, b| a.freq_hz.partial_cmp(&b.freq_hz).unwrap());
+    // within a hertz is on it, as tuning rounds
+    if step < 0 {
+        sorted.iter().rev().find(|b| b.freq_hz < freq_hz - 1.0).or(sorted.last()).cloned()
+    } else {
+        sorted.iter().find(|b| b.freq_hz > freq_hz + 1.0).or(sorted.first()).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_line, step};
+
+    #[test]
+    fn test_parse_line() {
+        let b = parse_line("145.8e6  ISS downlink").unwrap();
+        assert_eq!(b.freq_hz, 145.8e6);
+        assert_eq!(b.name, "ISS downlink");
+        assert!(parse_line("145.8e6").is_none());
+        assert!(parse_line("two meters").is_none());
+
+        let bookmarks = vec![parse_line("433.92e6 ism").unwrap(),
+                             parse_line("145.8e6 iss").unwrap(),
+                             parse_line("162.4e6 weather").unwrap()];
+        let name = |freq_hz, s| step(&bookmarks, freq_hz, s).map(|b| &b.name[..]);
+        assert_eq!(name(145.8e6, 1), Some("weather"));
+        assert_eq!(name(145.8e6, -1), Some("ism"));
+        assert_eq!(name(150e6, -1), Some("iss"));
+        assert_eq!(name(500e6, 1), Some("iss"));
+        assert_eq!(step(&[], 100e6, 1), None);
+    }
+}
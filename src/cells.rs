@@ -0,0 +1,322 @@
+//! The cells everything is drawn into, before a backend puts them on the
+//! screen.
+//!
+//! These started out as rustty's and keep its names and methods, so drawing
+//! code reads the same, but belong to the crate now that rustty is only one
+//! of the backends. A `Color` can be a true color as well as one of the
+//! xterm 256, and a backend without true colors shows the nearest of those.
+
+/// Where a cell is, column then row.
+pub type Pos = (usize, usize);
+
+/// How big a grid of cells is, columns then rows.
+pub type Size = (usize, usize);
+
+pub trait HasSize {
+    fn size(&self) -> Size;
+}
+
+pub trait HasPosition {
+    fn origin(&self) -> Pos;
+    fn set_origin(&mut self, new_origin: Pos);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    /// An xterm 256-color index.
+    Byte(u8),
+    /// A true color, red, green and blue.
+    Rgb(u8, u8, u8),
+    /// Whatever the terminal draws with when not told.
+    Default,
+}
+
+impl Color {
+    /// The color as an xterm 256-color index, the nearest one for a true
+    /// color. Panics for `Default`, which has none.
+    pub fn as_byte(&self) -> u8 {
+        match *self {
+            Color::Black => 0x00,
+            Color::Red => 0x01,
+            Color::Green => 0x02,
+            Color::Yellow => 0x03,
+            Color::Blue => 0x04,
+            Color::Magenta => 0x05,
+            Color::Cyan => 0x06,
+            Color::White => 0x07,
+            Color::Byte(b) => b,
+            Color::Rgb(r, g, b) => nearest_xterm(r, g, b),
+            Color::Default => panic!("Attempted to cast default color to u8"),
+        }
+    }
+}
+
+/// The xterm 6x6x6 color cube's level nearest `x`, and its intensity.
+fn cube_level(x: u8) -> (u8, u8) {
+    let level = match x {
+        0..=47 => 0,
+        48..=114 => 1,
+        _ => (x - 35) / 40,
+    };
+    (level, if level == 0 { 0 } else { 55 + 40 * level })
+}
+
+/// The nearest xterm color to a true color, from the color cube or the gray
+/// ramp, whichever is closer.
+fn nearest_xterm(r: u8, g: u8, b: u8) -> u8 {
+    let dist = |(r2, g2, b2): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, r2) + d(g, g2) + d(b, b2)
+    };
+    let ((ri, rl), (gi, gl), (bi, bl)) = (cube_level(r), cube_level(g), cube_level(b));
+    let cube = 16 + 36 * ri + 6 * gi + bi;
+
+    // the ramp runs from 8 to 238 in steps of 10
+    let mean = (r as u32 + g as u32 + b as u32) / 3;
+    let step = (mean.saturating_sub(3) / 10).min(23) as u8;
+    let gray = 8 + 10 * step;
+    if dist((gray, gray, gray)) < dist((rl, gl, bl)) {
+        232 + step
+    } else {
+        cube
+    }
+}
+
+/// Bold, underlined or reversed, as bits that can be combined.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Attr {
+    Default = 0b000,
+    Bold = 0b001,
+    Underline = 0b010,
+    BoldUnderline = 0b011,
+    Reverse = 0b100,
+    BoldReverse = 0b101,
+    UnderlineReverse = 0b110,
+    BoldReverseUnderline = 0b111,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cell {
+    ch: char,
+    fg: Color,
+    bg: Color,
+    attrs: Attr,
+}
+
+impl Cell {
+    pub fn new(ch: char, fg: Color, bg: Color, attrs: Attr) -> Cell {
+        Cell {
+            ch: ch,
+            fg: fg,
+            bg: bg,
+            attrs: attrs,
+        }
+    }
+
+    pub fn with_char(ch: char) -> Cell {
+        Cell::new(ch, Color::Default, Color::Default, Attr::Default)
+    }
+
+    pub fn ch(&self) -> char {
+        self.ch
+    }
+
+    pub fn set_ch(&mut self, newch: char) -> &mut Cell {
+        self.ch = newch;
+        self
+    }
+
+    pub fn fg(&self) -> Color {
+        self.fg
+    }
+
+    pub fn set_fg(&mut self, newfg: Color) -> &mut Cell {
+        self.fg = newfg;
+        self
+    }
+
+    pub fn bg(&self) -> Color {
+        self.bg
+    }
+
+    pub fn set_bg(&mut self, newbg: Color) -> &mut Cell {
+        self.bg = newbg;
+        self
+    }
+
+    pub fn attrs(&self) -> Attr {
+        self.attrs
+    }
+
+    pub fn set_attrs(&mut self, newattrs: Attr) -> &mut Cell {
+        self.attrs = newattrs;
+        self
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Cell {
+        Cell::with_char(' ')
+    }
+}
+
+/// A grid of cells kept row by row.
+pub trait CellAccessor: HasSize {
+    fn cellvec(&self) -> &Vec<Cell>;
+    fn cellvec_mut(&mut self) -> &mut Vec<Cell>;
+
+    fn clear(&mut self, blank: Cell) {
+        for cell in self.cellvec_mut().iter_mut() {
+            *cell = blank;
+        }
+    }
+
+    fn pos_to_index(&self, x: usize, y: usize) -> Option<usize> {
+        let (cols, rows) = self.size();
+        if x < cols && y < rows {
+            Some(cols * y + x)
+        } else {
+            None
+        }
+    }
+
+    fn get(&self, x: usize, y: usize) -> Option<&Cell> {
+        match self.pos_to_index(x, y) {
+            Some(i) => self.cellvec().get(i),
+            None => None,
+        }
+    }
+
+    fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut Cell> {
+        match self.pos_to_index(x, y) {
+            Some(i) => self.cellvec_mut().get_mut(i),
+            None => None,
+        }
+    }
+}
+
+/// Writing text into cells.
+pub trait Painter: CellAccessor {
+    /// Writes `line` from `(x, y)` with the colors and attributes of `cell`,
+    /// cut off at the right edge.
+    fn printline_with_cell(&mut self, x: usize, y: usize, line: &str, cell: Cell) {
+        let (cols, _) = self.size();
+        for (i, ch) in line.chars().enumerate() {
+            if x + i >= cols {
+                break;
+            }
+            if let Some(c) = self.get_mut(x + i, y) {
+                *c = Cell { ch: ch, ..cell };
+            }
+        }
+    }
+
+    fn printline(&mut self, x: usize, y: usize, line: &str) {
+        self.printline_with_cell(x, y, line, Cell::default());
+    }
+
+    /// Draws a line box around the edge, over whatever's there. Needs at
+    /// least two columns and rows.
+    fn draw_box(&mut self) {
+        let (cols, rows) = self.size();
+        for x in 1..cols - 1 {
+            *self.get_mut(x, 0).unwrap() = Cell::with_char('─');
+            *self.get_mut(x, rows - 1).unwrap() = Cell::with_char('─');
+        }
+        for y in 1..rows - 1 {
+            *self.get_mut(0, y).unwrap() = Cell::with_char('│');
+            *self.get_mut(cols - 1, y).unwrap() = Cell::with_char('│');
+        }
+        // the corners keep their colors, only the lines are drawn plain
+        for &(x, y, ch) in &[(0, 0, '┌'), (cols - 1, 0, '┐'), (cols - 1, rows - 1, '┘'),
+                             (0, rows - 1, '└')] {
+            self.get_mut(x, y).unwrap().set_ch(ch);
+        }
+    }
+}
+
+impl<T: CellAccessor> Painter for T {}
+
+/// A block of cells, drawn over another at its origin.
+pub struct Widget {
+    origin: Pos,
+    size: Size,
+    buf: Vec<Cell>,
+}
+
+impl Widget {
+    pub fn new(cols: usize, rows: usize) -> Widget {
+        Widget {
+            origin: (0, 0),
+            size: (cols, rows),
+            buf: vec![Cell::default(); cols * rows],
+        }
+    }
+
+    /// Copies the widget's cells into `cells` at its origin, as far as they
+    /// fit.
+    pub fn draw_into(&self, cells: &mut dyn CellAccessor) {
+        let (cols, rows) = self.size();
+        let (x, y) = self.origin();
+        for iy in 0..rows {
+            for ix in 0..cols {
+                if let Some(cell) = cells.get_mut(x + ix, y + iy) {
+                    *cell = self.buf[iy * cols + ix];
+                }
+            }
+        }
+    }
+}
+
+impl HasSize for Widget {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl CellAccessor for Widget {
+    fn cellvec(&self) -> &Vec<Cell> {
+        &self.buf
+    }
+
+    fn cellvec_mut(&mut self) -> &mut Vec<Cell> {
+        &mut self.buf
+    }
+}
+
+impl HasPosition for Widget {
+    fn origin(&self) -> Pos {
+        self.origin
+    }
+
+    fn set_origin(&mut self, new_origin: Pos) {
+        self.origin = new_origin;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Color;
+
+    #[test]
+    fn test_nearest_xterm() {
+        // exactly on the cube or the ramp
+        assert_eq!(Color::Rgb(0, 0, 0).as_byte(), 16);
+        assert_eq!(Color::Rgb(255, 255, 0).as_byte(), 226);
+        assert_eq!(Color::Rgb(95, 135, 175).as_byte(), 67);
+        assert_eq!(Color::Rgb(8, 8, 8).as_byte(), 232);
+        assert_eq!(Color::Rgb(238, 238, 238).as_byte(), 255);
+        // a gray between the cube's
+        assert_eq!(Color::Rgb(120, 120, 120).as_byte(), 243);
+        assert_eq!(Color::Rgb(250, 10, 10).as_byte(), 196);
+        assert_eq!(Color::Byte(42).as_byte(), 42);
+    }
+}
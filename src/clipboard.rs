@@ -0,0 +1,115 @@
+//! Copying text to the system clipboard.
+//!
+//! Locally this goes through the platform's clipboard tool. Over SSH, or when
+//! there's no tool, it falls back to the OSC 52 escape sequence, which asks the
+//! terminal emulator itself to set the clipboard; most modern ones support it,
+//! though some need it enabling first.
+
+use std::env;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// How the text got to the clipboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Native(&'static str),
+    Osc52,
+}
+
+/// Copies `text` to the clipboard.
+pub fn copy(text: &str) -> io::Result<Method> {
+    // a native tool would set the clipboard of the remote machine
+    if env::var_os("SSH_TTY").is_none() {
+        for &(program, args) in native_tools() {
+            if run_tool(program, args, text).is_ok() {
+                return Ok(Method::Native(program));
+            }
+        }
+    }
+
+    try!(copy_osc52(text));
+    Ok(Method::Osc52)
+}
+
+/// Clipboard tools to try in order, with their arguments.
+#[cfg(target_os = "macos")]
+fn native_tools() -> &'static [(&'static str, &'static [&'static str])] {
+    const TOOLS: &'static [(&'static str, &'static [&'static str])] = &[("pbcopy", &[])];
+    TOOLS
+}
+
+#[cfg(windows)]
+fn native_tools() -> &'static [(&'static str, &'static [&'static str])] {
+    const TOOLS: &'static [(&'static str, &'static [&'static str])] = &[("clip", &[])];
+    TOOLS
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn native_tools() -> &'static [(&'static str, &'static [&'static str])] {
+    const WAYLAND: &'static [(&'static str, &'static [&'static str])] = &[("wl-copy", &[])];
+    const X11: &'static [(&'static str, &'static [&'static str])] =
+        &[("xclip", &["-selection", "clipboard"]), ("xsel", &["--clipboard", "--input"])];
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        WAYLAND
+    } else if env::var_os("DISPLAY").is_some() {
+        X11
+    } else {
+        &[]
+    }
+}
+
+fn run_tool(program: &str, args: &[&str], text: &str) -> io::Result<()> {
+    let mut child = try!(Command::new(program)
+                             .args(args)
+                             .stdin(Stdio::piped())
+                             .stdout(Stdio::null())
+                             .stderr(Stdio::null())
+                             .spawn());
+    try!(child.stdin.take().unwrap().write_all(text.as_bytes()));
+    let status = try!(child.wait());
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::Other, format!("{} failed", program)))
+    }
+}
+
+fn copy_osc52(text: &str) -> io::Result<()> {
+    // straight to the terminal, rustty only writes to it on a redraw
+    let mut tty = try!(OpenOptions::new().write(true).open("/dev/tty"));
+    try!(write!(tty, "\x1b]52;c;{}\x07", base64(text.as_bytes())));
+    tty.flush()
+}
+
+pub fn base64(data: &[u8]) -> String {
+    const ALPHABET: &'static [u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::base64;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"100125000 Hz"), "MTAwMTI1MDAwIEh6");
+    }
+}
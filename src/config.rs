@@ -0,0 +1,341 @@
+//! The config file, in a small subset of TOML: `[section]` headers and
+//! `key = value` lines, where a value is a number, `true`, `false` or a double
+//! quoted string, and `#` starts a comment. Named presets are sections called
+//! `preset.<name>`, and bookmarks `bookmark.<name>`:
+//!
+//! ```text
+//! [preset.fm]
+//! freq-hz = 98.5e6
+//! bandwidth-hz = 2e6
+//! lna-gain = 16
+//! vga-gain = 20
+//!
+//! [preset.ads-b]
+//! freq-hz = 1090e6
+//! amp = true
+//!
+//! [bookmark.ISS downlink]
+//! freq-hz = 145.8e6
+//! bandwidth-hz = 2e6  # optional
+//! ```
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use bookmarks::Bookmark;
+use radio::hackrf::{LNA_GAINS_DB, VGA_GAIN_MAX_DB};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(f64),
+    Bool(bool),
+    Str(String),
+}
+
+/// The settings a preset can give. Anything left out keeps its current value.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Preset {
+    pub name: String,
+    pub freq_hz: Option<u64>,
+    pub bandwidth_hz: Option<f64>,
+    pub lna_gain_db: Option<u32>,
+    pub vga_gain_db: Option<u32>,
+    pub amp: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Config {
+    /// In the order they appear in the file.
+    pub presets: Vec<Preset>,
+    pub bookmarks: Vec<Bookmark>,
+}
+
+/// The section the lines being parsed are in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Section {
+    /// Left for other parts of the program.
+    Other,
+    /// Indexes into the config's lists.
+    Preset(usize),
+    Bookmark(usize),
+}
+
+impl Config {
+    pub fn preset(&self, name: &str) -> Option<&Preset> {
+        self.presets.iter().find(|p| p.name == name)
+    }
+}
+
+/// `$XDG_CONFIG_HOME/terminal_spectrograph/config.toml`, or under
+/// `~/.config` if that isn't set.
+pub fn default_path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => match env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".config"),
+            None => return None,
+        },
+    };
+    Some(dir.join("terminal_spectrograph").join("config.toml"))
+}
+
+/// Adds `bookmark` to the end of the config file at `path`, creating it if
+/// there isn't one yet.
+pub fn append_bookmark(path: &Path, bookmark: &Bookmark) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        try!(fs::create_dir_all(dir));
+    }
+    let mut file = try!(OpenOptions::new().append(true).create(true).open(path));
+    try!(write!(file, "\n[bookmark.{}]\nfreq-hz = {:.0}\n", bookmark.name, bookmark.freq_hz));
+    if let Some(bandwidth_hz) = bookmark.bandwidth_hz {
+        try!(writeln!(file, "bandwidth-hz = {:.0}", bandwidth_hz));
+    }
+    Ok(())
+}
+
+/// Whether `name` can go in a section header.
+pub fn is_valid_name(name: &str) -> bool {
+    let name_chars = |c: char| !c.is_control() && !"[]#\"".contains(c);
+    !name.trim().is_empty() && name == name.trim() && name.chars().all(name_chars)
+}
+
+/// Reads the config file at `path`, or the default one if `path` is `None`.
+/// A missing default file is an empty config.
+pub fn load(path: Option<&str>) -> io::Result<Config> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => match default_path() {
+            Some(path) => {
+                if !path.exists() {
+                    return Ok(Config::default());
+                }
+                path
+            }
+            None => return Ok(Config::default()),
+        },
+    };
+
+    let mut text = String::new();
+    try!(try!(File::open(&path)).read_to_string(&mut text));
+    parse(&text).map_err(|msg| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}", path.display(), msg))
+    })
+}
+
+/// Parses a whole file. Errors are prefixed with the line number.
+fn parse(text: &str) -> Result<Config, String> {
+    let mut config = Config::default();
+    let mut section = None;
+    // where each bookmark starts, for saying which one has no frequency
+    let mut bookmark_lines = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let err = |msg: String| format!("{}: {}", i + 1, msg);
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            if !line.ends_with(']') {
+                return Err(err("expected ']' at the end of the section header".to_string()));
+            }
+            let name = line[1..line.len() - 1].trim();
+            section = Some(if name.starts_with("preset.") {
+                let preset_name = &name["preset.".len()..];
+                if preset_name.is_empty() || config.preset(preset_name).is_some() {
+                    return Err(err(format!("missing or repeated preset name in [{}]", name)));
+                }
+                config.presets.push(Preset { name: preset_name.to_string(), ..Preset::default() });
+                Section::Preset(config.presets.len() - 1)
+            } else if name.starts_with("bookmark.") {
+                let bookmark_name = &name["bookmark.".len()..];
+                if bookmark_name.is_empty() ||
+                   config.bookmarks.iter().any(|b| b.name == bookmark_name) {
+                    return Err(err(format!("missing or repeated bookmark name in [{}]", name)));
+                }
+                config.bookmarks.push(Bookmark {
+                    name: bookmark_name.to_string(),
+                    freq_hz: -1.0,
+                    bandwidth_hz: None,
+                });
+                bookmark_lines.push(i + 1);
+                Section::Bookmark(config.bookmarks.len() - 1)
+            } else {
+                Section::Other
+            });
+            continue;
+        }
+
+        let (key, value) = try!(parse_entry(line).map_err(&err));
+        match section {
+            None => return Err(err(format!("'{}' is outside of any section", key))),
+            Some(Section::Other) => {}
+            Some(Section::Preset(p)) => {
+                try!(set_preset_field(&mut config.presets[p], key, value).map_err(&err))
+            }
+            Some(Section::Bookmark(b)) => {
+                try!(set_bookmark_field(&mut config.bookmarks[b], key, value).map_err(&err))
+            }
+        }
+    }
+
+    for (bookmark, line) in config.bookmarks.iter().zip(bookmark_lines) {
+        if bookmark.freq_hz < 0.0 {
+            return Err(format!("{}: bookmark '{}' has no freq-hz", line, bookmark.name));
+        }
+    }
+    Ok(config)
+}
+
+/// Everything before a `#` that isn't inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_entry(line: &str) -> Result<(&str, Value), String> {
+    let mut parts = line.splitn(2, '=');
+    let key = parts.next().unwrap().trim();
+    let value = match parts.next() {
+        Some(value) => value.trim(),
+        None => return Err("expected 'key = value'".to_string()),
+    };
+    if key.is_empty() {
+        return Err("missing key before '='".to_string());
+    }
+
+    let value = if value == "true" {
+        Value::Bool(true)
+    } else if value == "false" {
+        Value::Bool(false)
+    } else if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        Value::Str(value[1..value.len() - 1].to_string())
+    } else {
+        match value.replace('_', "").parse::<f64>() {
+            Ok(x) => Value::Number(x),
+            Err(_) => return Err(format!("can't parse the value of '{}'", key)),
+        }
+    };
+    Ok((key, value))
+}
+
+fn set_preset_field(preset: &mut Preset, key: &str, value: Value) -> Result<(), String> {
+    let number = |value: &Value| match *value {
+        Value::Number(x) if x >= 0.0 => Ok(x),
+        _ => Err(format!("'{}' should be a positive number", key)),
+    };
+    match key {
+        "freq-hz" => preset.freq_hz = Some(try!(number(&value)) as u64),
+        "bandwidth-hz" => preset.bandwidth_hz = Some(try!(number(&value))),
+        "lna-gain" => {
+            let db = try!(number(&value));
+            if !LNA_GAINS_DB.iter().any(|&g| g as f64 == db) {
+                return Err(format!("'lna-gain' should be 0 to {} in steps of 8",
+                                   LNA_GAINS_DB[LNA_GAINS_DB.len() - 1]));
+            }
+            preset.lna_gain_db = Some(db as u32);
+        }
+        "vga-gain" => {
+            let db = try!(number(&value));
+            if db > VGA_GAIN_MAX_DB as f64 || db % 2.0 != 0.0 {
+                return Err(format!("'vga-gain' should be 0 to {} in steps of 2",
+                                   VGA_GAIN_MAX_DB));
+            }
+            preset.vga_gain_db = Some(db as u32);
+        }
+        "amp" => preset.amp = match value {
+            Value::Bool(b) => Some(b),
+            _ => return Err("'amp' should be true or false".to_string()),
+        },
+        _ => return Err(format!("unknown preset setting '{}'", key)),
+    }
+    Ok(())
+}
+
+fn set_bookmark_field(bookmark: &mut Bookmark, key: &str, value: Value) -> Result<(), String> {
+    let hz = match value {
+        Value::Number(x) if x >= 0.0 => x,
+        _ => return Err(format!("'{}' should be a positive number", key)),
+    };
+    match key {
+        "freq-hz" => bookmark.freq_hz = hz,
+        "bandwidth-hz" => bookmark.bandwidth_hz = Some(hz),
+        _ => return Err(format!("unknown bookmark setting '{}'", key)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    #[test]
+    fn test_parse_presets() {
+        let config = parse("
+# comments and other sections are skipped
+[display]
+palette = \"viridis\"  # a comment
+
+[preset.fm]
+freq-hz = 98.5e6
+bandwidth-hz = 2_000_000
+
+[preset.ads-b]
+freq-hz = 1090e6
+lna-gain = 32
+amp = true
+").unwrap();
+        assert_eq!(config.presets.len(), 2);
+        let fm = config.preset("fm").unwrap();
+        assert_eq!(fm.freq_hz, Some(98_500_000));
+        assert_eq!(fm.bandwidth_hz, Some(2e6));
+        assert_eq!(fm.lna_gain_db, None);
+        let adsb = config.preset("ads-b").unwrap();
+        assert_eq!(adsb.lna_gain_db, Some(32));
+        assert_eq!(adsb.amp, Some(true));
+
+        assert!(parse("[preset.x]\nfrequency = 1").unwrap_err().starts_with("2:"));
+        assert!(parse("[preset.x]\namp = 1").is_err());
+        assert_eq!(parse("[preset.x]\n\nlna-gain = 12").unwrap_err(),
+                   "3: 'lna-gain' should be 0 to 40 in steps of 8");
+        assert_eq!(parse("[preset.x]\nvga-gain = 64").unwrap_err(),
+                   "2: 'vga-gain' should be 0 to 62 in steps of 2");
+        assert!(parse("[preset.x]\nvga-gain = 21").is_err());
+        assert_eq!(parse("[preset.x]\nvga-gain = 62").unwrap().presets[0].vga_gain_db, Some(62));
+        assert!(parse("freq-hz = 1").is_err());
+        assert!(parse("[preset.x]\n[preset.x]").is_err());
+    }
+
+    #[test]
+    fn test_parse_bookmarks() {
+        let config = parse("
+[bookmark.ISS downlink]
+freq-hz = 145.8e6
+
+[bookmark.fm]
+freq-hz = 98.5e6
+bandwidth-hz = 2e6
+").unwrap();
+        assert_eq!(config.bookmarks.len(), 2);
+        assert_eq!(config.bookmarks[0].name, "ISS downlink");
+        assert_eq!((config.bookmarks[0].freq_hz, config.bookmarks[0].bandwidth_hz),
+                   (145.8e6, None));
+        assert_eq!(config.bookmarks[1].bandwidth_hz, Some(2e6));
+
+        assert_eq!(parse("[bookmark.x]\nbandwidth-hz = 1").unwrap_err(),
+                   "1: bookmark 'x' has no freq-hz");
+        assert!(parse("[bookmark.x]\nfreq-hz = 1\n[bookmark.x]\nfreq-hz = 2").is_err());
+        assert!(super::is_valid_name("ISS downlink"));
+        assert!(!super::is_valid_name("a # b"));
+    }
+}
@@ -0,0 +1,140 @@
+//! Spotting the sub-audible CTCSS tone under NFM audio, which is how a
+//! repeater or a group of radios picks out its own transmissions, so knowing
+//! it tells whose they are.
+
+/// The standard tones, in Hz.
+const TONES_HZ: [f32; 50] = [
+    67.0, 69.3, 71.9, 74.4, 77.0, 79.7, 82.5, 85.4, 88.5, 91.5,
+    94.8, 97.4, 100.0, 103.5, 107.2, 110.9, 114.8, 118.8, 123.0, 127.3,
+    131.8, 136.5, 141.3, 146.2, 151.4, 156.7, 159.8, 162.2, 165.5, 167.9,
+    171.3, 173.8, 177.3, 179.9, 183.5, 186.2, 189.9, 192.8, 196.6, 199.5,
+    203.5, 206.5, 210.7, 218.1, 225.7, 229.1, 233.6, 241.8, 250.3, 254.1,
+];
+
+/// The audio is averaged this many samples at a time first, which leaves the
+/// tones and takes out most of the speech above them.
+const DECIMATION: usize = 16;
+
+/// Audio looked at for each decision, in seconds, long enough to tell apart
+/// tones under 3 Hz apart.
+const BLOCK_SECS: f64 = 1.0;
+
+/// The tone has to carry at least this fraction of the audio's power, which
+/// noise spread over the whole band doesn't come near.
+const MIN_POWER_FRACTION: f32 = 0.01;
+
+/// Nor can it be quieter than this, a tone of about 0.015 in amplitude, or
+/// near silence would turn up tones of nothing. NFM audio is 1.0 at a fifth
+/// of the channel off the carrier, and tones are sent at around a tenth.
+const MIN_POWER: f32 = 1e-4;
+
+/// Runs a Goertzel filter for each tone over blocks of audio.
+pub struct Detector {
+    /// The coefficient and the last two states of each tone's filter.
+    coeffs: Vec<f32>,
+    states: Vec<(f32, f32)>,
+    block_len: usize,
+    sum: f32,
+    num_summed: usize,
+    num_samples: usize,
+    energy: f32,
+    tone: Option<f32>,
+}
+
+impl Detector {
+    pub fn new(audio_rate_hz: f64) -> Self {
+        let audio_rate_hz = audio_rate_hz / DECIMATION as f64;
+        let coeffs: Vec<f32> = TONES_HZ.iter().map(|&tone_hz| {
+            2.0 * (2.0 * ::std::f64::consts::PI * tone_hz as f64 / audio_rate_hz).cos() as f32
+        }).collect();
+        Detector {
+            states: vec![(0.0, 0.0); coeffs.len()],
+            coeffs: coeffs,
+            block_len: (BLOCK_SECS * audio_rate_hz) as usize,
+            sum: 0.0,
+            num_summed: 0,
+            num_samples: 0,
+            energy: 0.0,
+            tone: None,
+        }
+    }
+
+    /// Adds demodulated audio, deciding on the tone at the end of each block.
+    pub fn add_audio(&mut self, audio: &[f32]) {
+        for &x in audio {
+            self.sum += x;
+            self.num_summed += 1;
+            if self.num_summed < DECIMATION {
+                continue;
+            }
+            let x = self.sum / DECIMATION as f32;
+            self.sum = 0.0;
+            self.num_summed = 0;
+            for (state, &coeff) in self.states.iter_mut().zip(&self.coeffs) {
+                let s = x + coeff * state.0 - state.1;
+                *state = (s, state.0);
+            }
+            self.energy += x * x;
+            self.num_samples += 1;
+            if self.num_samples == self.block_len {
+                self.tone = self.strongest_tone();
+                for state in &mut self.states {
+                    *state = (0.0, 0.0);
+                }
+                self.num_samples = 0;
+                self.energy = 0.0;
+            }
+        }
+    }
+
+    /// The tone found in the last full block, if there was one.
+    pub fn tone(&self) -> Option<f32> {
+        self.tone
+    }
+
+    fn strongest_tone(&self) -> Option<f32> {
+        let n = self.num_samples as f32;
+        self.states.iter().zip(&self.coeffs).zip(TONES_HZ.iter())
+            .map(|((&(s1, s2), &coeff), &tone_hz)| {
+                // a sine's power is half its amplitude squared, and the
+                // filter's output is its amplitude times n / 2
+                let magnitude = s1 * s1 + s2 * s2 - coeff * s1 * s2;
+                (tone_hz, 2.0 * magnitude / (n * n))
+            })
+            .filter(|&(_, power)| {
+                power >= MIN_POWER && power >= MIN_POWER_FRACTION * self.energy / n
+            })
+            .fold(None, |best: Option<(f32, f32)>, (tone_hz, power)| match best {
+                Some((_, best_power)) if best_power >= power => best,
+                _ => Some((tone_hz, power)),
+            })
+            .map(|(tone_hz, _)| tone_hz)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+    use super::Detector;
+
+    #[test]
+    fn test_detect_tone() {
+        // a 100 Hz tone under a louder 1 kHz one, then just the 1 kHz one
+        let audio = |with_tone: bool| -> Vec<f32> {
+            (0..8000).map(|i| {
+                let t = i as f32 / 8000.0;
+                let tone = if with_tone { 0.1 * (2.0 * PI * 100.0 * t).sin() } else { 0.0 };
+                tone + 0.5 * (2.0 * PI * 1000.0 * t).sin()
+            }).collect()
+        };
+        let mut detector = Detector::new(8000.0);
+        // decided on a second at a time
+        let with_tone = audio(true);
+        detector.add_audio(&with_tone[..4000]);
+        assert_eq!(detector.tone(), None);
+        detector.add_audio(&with_tone[4000..]);
+        assert_eq!(detector.tone(), Some(100.0));
+        detector.add_audio(&audio(false));
+        assert_eq!(detector.tone(), None);
+    }
+}
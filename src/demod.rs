@@ -0,0 +1,784 @@
+//! Listening to the signal under the marker. A channel around it is mixed down
+//! to 0 Hz, filtered and demodulated on a thread of its own, and the audio is
+//! piped to the platform's audio player, as the clipboard goes through its
+//! tools.
+//!
+//! The player plays to the default output unless given a device, which is a
+//! PulseAudio sink or an ALSA device on Linux and a Core Audio device for sox
+//! on macOS.
+//!
+//! What's heard can also be recorded, to a mono 16 bit WAV file at the audio
+//! rate, for as long as there's something to listen to.
+//!
+//! WFM is mono, and the RDS on it is decoded for the station's name and
+//! radiotext.
+//!
+//! CW is heard as a tone, and read for the Morse on it.
+//!
+//! SSB is by the filter method: the channel filter only passes the sideband
+//! on one side of the marker, taken as the suppressed carrier, and the audio
+//! is what's left of it after dropping the imaginary part.
+
+use std::f32::consts::PI;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicUsize;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use num::Complex;
+
+use ctcss;
+use morse;
+use rds;
+use watchdog;
+
+/// Rate of the mono, signed 16 bit audio the player is given, matching the
+/// players' arguments.
+const AUDIO_RATE_HZ: f64 = 48000.0;
+
+/// Length of a WAV header, before the samples.
+const WAV_HEADER_BYTES: u32 = 44;
+
+/// Length of the channel filter, in taps per channel width at the channel
+/// rate, for its edges to take about an eighth of the width. SSB needs them
+/// that sharp to leave out the other sideband.
+const TAPS_PER_WIDTH: f64 = 26.0;
+
+/// How quickly the AM carrier level follows, per channel sample. About 20 ms
+/// at the usual channel rates, slower than the speech on it.
+const CARRIER_SMOOTHING: f32 = 1e-3;
+
+/// NFM deviation, as a fraction of the channel width: 2.5 kHz in 12.5 kHz.
+const DEVIATION_PER_HZ: f32 = 0.2;
+
+/// WFM deviation, the channel rate it needs at least, for the whole channel
+/// and the RDS subcarrier at 57 kHz, and the time constant of its
+/// de-emphasis, which is 50 us outside the Americas.
+const WFM_DEVIATION_HZ: f32 = 75e3;
+const WFM_CHANNEL_RATE_HZ: f64 = 250e3;
+const WFM_DEEMPHASIS_SECS: f64 = 75e-6;
+
+/// The CW channel rate, low enough for its narrow filter to stay short, and
+/// the pitch the carrier is heard at.
+const CW_CHANNEL_RATE_HZ: f64 = 8e3;
+const CW_PITCH_HZ: f64 = 700.0;
+
+/// SSB audio is scaled for its average level to be this fraction of full
+/// scale, leaving room for the peaks of speech, but turned up no more than
+/// for a sideband at `MIN_SSB_LEVEL`, so the noise between overs stays quiet.
+const SSB_LEVEL: f32 = 0.25;
+const MIN_SSB_LEVEL: f32 = 1e-3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Am,
+    Nfm,
+    /// FM broadcast.
+    Wfm,
+    /// Upper and lower sideband.
+    Usb,
+    Lsb,
+    /// Morse, keyed on and off.
+    Cw,
+}
+
+impl Mode {
+    /// Off, then each mode in turn, then off again.
+    pub fn cycle(mode: Option<Mode>) -> Option<Mode> {
+        match mode {
+            None => Some(Mode::Am),
+            Some(Mode::Am) => Some(Mode::Nfm),
+            Some(Mode::Nfm) => Some(Mode::Wfm),
+            Some(Mode::Wfm) => Some(Mode::Usb),
+            Some(Mode::Usb) => Some(Mode::Lsb),
+            Some(Mode::Lsb) => Some(Mode::Cw),
+            Some(Mode::Cw) => None,
+        }
+    }
+
+    /// Channel widths to pick from, narrowest first: for AM and NFM airband
+    /// AM and the narrow and wide ham and PMR channel spacings, for WFM
+    /// crowded and clear bands, for SSB the usual voice filters, and for CW
+    /// the usual filters and one for finding the carrier.
+    pub fn bandwidths_hz(self) -> &'static [f64] {
+        const CHANNELS_HZ: &'static [f64] = &[6e3, 12.5e3, 25e3];
+        const BROADCASTS_HZ: &'static [f64] = &[150e3, 200e3];
+        const SIDEBANDS_HZ: &'static [f64] = &[2.4e3, 2.7e3, 3e3];
+        const CW_FILTERS_HZ: &'static [f64] = &[250.0, 500.0, 2.4e3];
+        match self {
+            Mode::Am | Mode::Nfm => CHANNELS_HZ,
+            Mode::Wfm => BROADCASTS_HZ,
+            Mode::Usb | Mode::Lsb => SIDEBANDS_HZ,
+            Mode::Cw => CW_FILTERS_HZ,
+        }
+    }
+
+    /// Index of the width a mode starts with.
+    pub fn default_bandwidth(self) -> usize {
+        match self {
+            Mode::Am => 0,
+            Mode::Nfm | Mode::Wfm | Mode::Usb | Mode::Lsb | Mode::Cw => 1,
+        }
+    }
+
+    /// Where the middle of the channel is from the marker, as a fraction of
+    /// its width.
+    fn passband_center(self) -> f64 {
+        match self {
+            Mode::Am | Mode::Nfm | Mode::Wfm | Mode::Cw => 0.0,
+            Mode::Usb => 0.5,
+            Mode::Lsb => -0.5,
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Mode::Am => "AM",
+            Mode::Nfm => "NFM",
+            Mode::Wfm => "WFM",
+            Mode::Usb => "USB",
+            Mode::Lsb => "LSB",
+            Mode::Cw => "CW",
+        })
+    }
+}
+
+/// What to listen to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Channel {
+    pub mode: Mode,
+    /// From the center frequency.
+    pub offset_hz: f64,
+    pub bandwidth_hz: f64,
+    pub sample_rate_hz: f64,
+}
+
+/// Turns the raw samples into audio for one channel.
+struct Demodulator {
+    channel: Channel,
+    /// Mixes the channel down to 0 Hz, turning by `step` every sample.
+    phasor: Complex<f32>,
+    step: Complex<f32>,
+    /// Samples are averaged `decimation` at a time, down to about the audio
+    /// rate, or for WFM and CW the rates they need, before the channel
+    /// filter.
+    decimation: usize,
+    sum: Complex<f32>,
+    num_summed: usize,
+    taps: Vec<Complex<f32>>,
+    /// The newest decimated samples, as a ring starting at `next`.
+    recent: Vec<Complex<f32>>,
+    next: usize,
+    /// For NFM and WFM, the filtered sample before.
+    last: Complex<f32>,
+    /// For WFM, how far the de-emphasis moves each sample, the audio after
+    /// it, and the RDS decoder, given the multiplex before it.
+    deemphasis: f32,
+    deemphasized: f32,
+    rds: Option<rds::Decoder>,
+    /// For CW, the tone it's heard at, turning by `pitch_step` every sample,
+    /// and the Morse decoder, given the carrier's level.
+    pitch: Complex<f32>,
+    pitch_step: Complex<f32>,
+    morse: Option<morse::Decoder>,
+    /// For AM, the level of the carrier, and for SSB, of the sideband.
+    level: f32,
+    /// Channel samples per audio sample, where the next one falls after the
+    /// last demodulated sample, and that sample.
+    resample_step: f64,
+    resample_at: f64,
+    last_audio: f32,
+}
+
+impl Demodulator {
+    fn new(channel: Channel) -> Self {
+        let min_rate_hz = match channel.mode {
+            Mode::Wfm => WFM_CHANNEL_RATE_HZ,
+            Mode::Cw => CW_CHANNEL_RATE_HZ,
+            _ => AUDIO_RATE_HZ,
+        };
+        let decimation = ((channel.sample_rate_hz / min_rate_hz) as usize).max(1);
+        let channel_rate_hz = channel.sample_rate_hz / decimation as f64;
+        let turn = -2.0 * PI * (channel.offset_hz / channel.sample_rate_hz) as f32;
+        let taps = channel_taps(channel, channel_rate_hz);
+        let pitch_turn = 2.0 * PI * (CW_PITCH_HZ / channel_rate_hz) as f32;
+        Demodulator {
+            channel: channel,
+            phasor: Complex::new(1.0, 0.0),
+            step: Complex::new(turn.cos(), turn.sin()),
+            decimation: decimation,
+            sum: Complex::new(0.0, 0.0),
+            num_summed: 0,
+            recent: vec![Complex::new(0.0, 0.0); taps.len()],
+            taps: taps,
+            next: 0,
+            last: Complex::new(0.0, 0.0),
+            deemphasis: (1.0 - (-1.0 / (WFM_DEEMPHASIS_SECS * channel_rate_hz)).exp()) as f32,
+            deemphasized: 0.0,
+            rds: if channel.mode == Mode::Wfm {
+                Some(rds::Decoder::new(channel_rate_hz))
+            } else {
+                None
+            },
+            pitch: Complex::new(1.0, 0.0),
+            pitch_step: Complex::new(pitch_turn.cos(), pitch_turn.sin()),
+            morse: if channel.mode == Mode::Cw {
+                Some(morse::Decoder::new(channel_rate_hz))
+            } else {
+                None
+            },
+            level: 0.0,
+            resample_step: channel_rate_hz / AUDIO_RATE_HZ,
+            resample_at: 0.0,
+            last_audio: 0.0,
+        }
+    }
+
+    /// Demodulates a buffer of raw samples, adding the audio to `audio`.
+    fn process(&mut self, buff: &[Complex<i8>], audio: &mut Vec<f32>) {
+        for x in buff {
+            let x = Complex::new(x.re as f32 / 128.0, x.im as f32 / 128.0);
+            self.sum = self.sum + x * self.phasor;
+            self.phasor = self.phasor * self.step;
+            self.num_summed += 1;
+            if self.num_summed == self.decimation {
+                let sample = self.sum / self.decimation as f32;
+                self.sum = Complex::new(0.0, 0.0);
+                self.num_summed = 0;
+                let filtered = self.filter(sample);
+                let demodulated = self.demodulate(filtered);
+                self.resample(demodulated, audio);
+            }
+        }
+        // rounding would otherwise let them drift away from the unit circle
+        self.phasor = self.phasor / self.phasor.norm();
+        self.pitch = self.pitch / self.pitch.norm();
+    }
+
+    fn filter(&mut self, sample: Complex<f32>) -> Complex<f32> {
+        self.recent[self.next] = sample;
+        self.next = (self.next + 1) % self.recent.len();
+        let (older, newer) = self.recent.split_at(self.next);
+        newer.iter().chain(older).zip(&self.taps)
+            .fold(Complex::new(0.0, 0.0), |acc, (&x, &tap)| acc + x * tap)
+    }
+
+    /// Audio from a filtered channel sample, full scale at about 1.
+    fn demodulate(&mut self, sample: Complex<f32>) -> f32 {
+        match self.channel.mode {
+            Mode::Am => {
+                let envelope = self.follow_level(sample);
+                if self.level > 0.0 { envelope / self.level - 1.0 } else { 0.0 }
+            }
+            Mode::Nfm => {
+                let turned = (sample * self.last.conj()).arg();
+                self.last = sample;
+                let channel_rate_hz = (self.resample_step * AUDIO_RATE_HZ) as f32;
+                let deviation_hz = DEVIATION_PER_HZ * self.channel.bandwidth_hz as f32;
+                turned * channel_rate_hz / (2.0 * PI * deviation_hz)
+            }
+            Mode::Wfm => {
+                let turned = (sample * self.last.conj()).arg();
+                self.last = sample;
+                let channel_rate_hz = (self.resample_step * AUDIO_RATE_HZ) as f32;
+                let mpx = turned * channel_rate_hz / (2.0 * PI * WFM_DEVIATION_HZ);
+                if let Some(ref mut rds) = self.rds {
+                    rds.add_mpx(mpx);
+                }
+                // also takes the pilot and the stereo and RDS subcarriers
+                // down, before they're resampled
+                self.deemphasized += (mpx - self.deemphasized) * self.deemphasis;
+                self.deemphasized
+            }
+            Mode::Usb | Mode::Lsb => {
+                self.follow_level(sample);
+                SSB_LEVEL * sample.re / self.level.max(MIN_SSB_LEVEL)
+            }
+            Mode::Cw => {
+                let envelope = self.follow_level(sample);
+                if let Some(ref mut morse) = self.morse {
+                    morse.add_level(envelope);
+                }
+                let tone = sample * self.pitch;
+                self.pitch = self.pitch * self.pitch_step;
+                SSB_LEVEL * tone.re / self.level.max(MIN_SSB_LEVEL)
+            }
+        }
+    }
+
+    /// Moves the level towards the envelope of `sample`, returning the
+    /// envelope.
+    fn follow_level(&mut self, sample: Complex<f32>) -> f32 {
+        let envelope = sample.norm();
+        if self.level == 0.0 {
+            // rather than a burst of noise while it catches up
+            self.level = envelope;
+        }
+        self.level += (envelope - self.level) * CARRIER_SMOOTHING;
+        envelope
+    }
+
+    /// Brings the demodulated samples to the audio rate, between each one and
+    /// the one before.
+    fn resample(&mut self, sample: f32, audio: &mut Vec<f32>) {
+        while self.resample_at < 1.0 {
+            let t = self.resample_at as f32;
+            audio.push(self.last_audio + (sample - self.last_audio) * t);
+            self.resample_at += self.resample_step;
+        }
+        self.resample_at -= 1.0;
+        self.last_audio = sample;
+    }
+}
+
+/// The channel filter for `channel` at `channel_rate_hz`, a low-pass filter
+/// moved up or down to the sideband for SSB.
+fn channel_taps(channel: Channel, channel_rate_hz: f64) -> Vec<Complex<f32>> {
+    // odd, so it's centered on a sample
+    let num_taps = (TAPS_PER_WIDTH * channel_rate_hz / channel.bandwidth_hz) as usize | 1;
+    let cutoff = (channel.bandwidth_hz / 2.0 / channel_rate_hz) as f32;
+    let shift = (channel.mode.passband_center() * channel.bandwidth_hz / channel_rate_hz) as f32;
+    low_pass_taps(num_taps, cutoff).iter().enumerate().map(|(i, &tap)| {
+        let turn = 2.0 * PI * shift * (i as f32 - (num_taps - 1) as f32 / 2.0);
+        Complex::new(tap * turn.cos(), tap * turn.sin())
+    }).collect()
+}
+
+/// A windowed sinc low-pass filter passing up to `cutoff` cycles per sample,
+/// with a gain of 1 at 0 Hz.
+pub fn low_pass_taps(num_taps: usize, cutoff: f32) -> Vec<f32> {
+    let cutoff = cutoff.min(0.5);
+    let middle = (num_taps - 1) as f32 / 2.0;
+    let taps: Vec<f32> = (0..num_taps).map(|i| {
+        let t = i as f32 - middle;
+        let sinc = if t == 0.0 { 2.0 * cutoff } else { (2.0 * PI * cutoff * t).sin() / (PI * t) };
+        let hamming = 0.54 - 0.46 * (2.0 * PI * i as f32 / (num_taps - 1) as f32).cos();
+        sinc * hamming
+    }).collect();
+    let gain: f32 = taps.iter().sum();
+    taps.iter().map(|tap| tap / gain).collect()
+}
+
+/// How much each press of the volume keys changes the volume by, and the
+/// loudest it goes, as gains.
+const VOLUME_STEP: f32 = 0.1;
+const MAX_VOLUME: f32 = 2.0;
+
+/// The player, fed from a thread of its own so a slow one doesn't hold up
+/// the samples. Killed when dropped.
+struct Player {
+    child: Child,
+    send: Sender<Vec<i16>>,
+}
+
+impl Player {
+    fn start(device: Option<&str>) -> io::Result<Self> {
+        for &(program, args) in players() {
+            let mut command = Command::new(program);
+            command.args(args);
+            if let Some(device) = device {
+                choose_device(&mut command, program, device);
+            }
+            let mut child = match command.stdin(Stdio::piped())
+                                      .stdout(Stdio::null())
+                                      .stderr(Stdio::null())
+                                      .spawn() {
+                Ok(child) => child,
+                Err(_) => continue,
+            };
+            let mut stdin = child.stdin.take().unwrap();
+            let (send, recv) = channel::<Vec<i16>>();
+            thread::spawn(move || {
+                for samples in recv.iter() {
+                    let mut bytes = Vec::with_capacity(2 * samples.len());
+                    for x in samples {
+                        bytes.push(x as u8);
+                        bytes.push((x >> 8) as u8);
+                    }
+                    if stdin.write_all(&bytes).is_err() {
+                        return;
+                    }
+                }
+            });
+            return Ok(Player { child: child, send: send });
+        }
+        let names: Vec<&str> = players().iter().map(|&(program, _)| program).collect();
+        Err(io::Error::new(io::ErrorKind::NotFound,
+                           format!("no audio player found, tried {}", names.join(", "))))
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn players() -> &'static [(&'static str, &'static [&'static str])] {
+    const PLAYERS: &'static [(&'static str, &'static [&'static str])] =
+        &[("play", &["-q", "-t", "raw", "-e", "signed", "-b", "16", "-c", "1", "-r", "48000",
+                     "-"])];
+    PLAYERS
+}
+
+/// Whether `--audio-device` can pick the output. There's nothing to list the
+/// Core Audio devices with on macOS, so it only goes to the default one.
+pub const CHOOSES_OUTPUT: bool = !cfg!(target_os = "macos");
+
+#[cfg(target_os = "macos")]
+fn choose_device(_command: &mut Command, _program: &str, _device: &str) {
+    unreachable!("--audio-device isn't supported on macOS");
+}
+
+#[cfg(not(target_os = "macos"))]
+fn players() -> &'static [(&'static str, &'static [&'static str])] {
+    const PLAYERS: &'static [(&'static str, &'static [&'static str])] = &[
+        ("paplay", &["--raw", "--format=s16le", "--channels=1", "--rate=48000"]),
+        ("aplay", &["-q", "-t", "raw", "-f", "S16_LE", "-c", "1", "-r", "48000"]),
+    ];
+    PLAYERS
+}
+
+#[cfg(not(target_os = "macos"))]
+fn choose_device(command: &mut Command, program: &str, device: &str) {
+    if program == "paplay" {
+        command.arg(format!("--device={}", device));
+    } else {
+        command.arg("-D").arg(device);
+    }
+}
+
+/// The outputs `--audio-device` can name: the PulseAudio sinks, or without
+/// PulseAudio the ALSA devices.
+#[cfg(not(target_os = "macos"))]
+pub fn list_outputs() -> io::Result<Vec<String>> {
+    match Command::new("pactl").arg("list").arg("short").arg("sinks").output() {
+        Ok(ref output) if output.status.success() => {
+            // id, name, driver, format and state, tab separated
+            let text = String::from_utf8_lossy(&output.stdout);
+            return Ok(text.lines().filter_map(|line| line.split('\t').nth(1))
+                          .map(|name| name.to_string()).collect());
+        }
+        _ => {}
+    }
+    let output = try!(Command::new("aplay").arg("-L").output());
+    // names start a line, their descriptions are indented below
+    let text = String::from_utf8_lossy(&output.stdout);
+    Ok(text.lines().filter(|line| !line.is_empty() && !line.starts_with(char::is_whitespace))
+           .map(|name| name.to_string()).collect())
+}
+
+/// The audio being written to a WAV file, as demodulated, whatever the volume.
+struct Recording {
+    out: BufWriter<File>,
+    path: PathBuf,
+    num_samples: u32,
+    /// Writing stops at the first error, which `stop_recording` returns.
+    error: Option<io::Error>,
+}
+
+impl Recording {
+    fn write(&mut self, samples: &[i16]) {
+        if self.error.is_some() {
+            return;
+        }
+        let mut bytes = Vec::with_capacity(2 * samples.len());
+        for &x in samples {
+            bytes.push(x as u8);
+            bytes.push((x >> 8) as u8);
+        }
+        match self.out.write_all(&bytes) {
+            Ok(()) => self.num_samples += samples.len() as u32,
+            Err(e) => self.error = Some(e),
+        }
+    }
+}
+
+/// The header of a WAV file of `num_samples` mono 16 bit samples at the audio
+/// rate.
+fn wav_header(num_samples: u32) -> Vec<u8> {
+    let rate = AUDIO_RATE_HZ as u32;
+    let data_bytes = 2 * num_samples;
+    let mut header = Vec::with_capacity(WAV_HEADER_BYTES as usize);
+    let push_u32 = |header: &mut Vec<u8>, x: u32| {
+        header.extend_from_slice(&[x as u8, (x >> 8) as u8, (x >> 16) as u8, (x >> 24) as u8]);
+    };
+    header.extend_from_slice(b"RIFF");
+    push_u32(&mut header, WAV_HEADER_BYTES - 8 + data_bytes);
+    header.extend_from_slice(b"WAVEfmt ");
+    // the format chunk's size, then PCM, 1 channel
+    push_u32(&mut header, 16);
+    header.extend_from_slice(&[1, 0, 1, 0]);
+    push_u32(&mut header, rate);
+    // bytes a second, then bytes a sample and bits a sample
+    push_u32(&mut header, 2 * rate);
+    header.extend_from_slice(&[2, 0, 16, 0]);
+    header.extend_from_slice(b"data");
+    push_u32(&mut header, data_bytes);
+    header
+}
+
+struct State {
+    channel: Option<Channel>,
+    player: Option<Player>,
+    recording: Option<Recording>,
+    /// The CTCSS tone under NFM audio, if there's one.
+    tone_hz: Option<f32>,
+    /// The station's name and radiotext from WFM's RDS, as far as they've
+    /// come.
+    station_name: Option<String>,
+    radiotext: Option<String>,
+    /// The Morse read from CW.
+    morse_text: Option<String>,
+    device: Option<String>,
+    /// A gain, 1 for as demodulated.
+    volume: f32,
+    muted: bool,
+}
+
+/// Shared between the UI, which picks the channel, and the thread passing
+/// the samples on.
+pub struct Listener {
+    state: Arc<Mutex<State>>,
+}
+
+impl Listener {
+    /// A listener playing to `device`, or the default output.
+    pub fn new(device: Option<String>) -> Self {
+        Listener {
+            state: Arc::new(Mutex::new(State {
+                channel: None,
+                player: None,
+                recording: None,
+                tone_hz: None,
+                station_name: None,
+                radiotext: None,
+                morse_text: None,
+                device: device,
+                volume: 1.0,
+                muted: false,
+            })),
+        }
+    }
+
+    /// Passes the samples from `recv` on, demodulating them while listening,
+    /// counting the buffers dropped for falling behind in `overruns`.
+    pub fn tee(&self, recv: Receiver<Vec<Complex<i8>>>, overruns: Arc<AtomicUsize>)
+               -> Receiver<Vec<Complex<i8>>> {
+        let state = self.state.clone();
+        let mut demodulator: Option<Demodulator> = None;
+        let mut tones = ctcss::Detector::new(AUDIO_RATE_HZ);
+        let mut audio = Vec::new();
+        watchdog::tee(recv, overruns, move |buff| {
+            let channel = state.lock().unwrap().channel;
+            match channel {
+                Some(channel) => {
+                    if demodulator.as_ref().map_or(true, |d| d.channel != channel) {
+                        demodulator = Some(Demodulator::new(channel));
+                        tones = ctcss::Detector::new(AUDIO_RATE_HZ);
+                        let mut state = state.lock().unwrap();
+                        state.station_name = None;
+                        state.radiotext = None;
+                        state.morse_text = None;
+                    }
+                    audio.clear();
+                    demodulator.as_mut().unwrap().process(buff, &mut audio);
+                    if channel.mode == Mode::Nfm {
+                        tones.add_audio(&audio);
+                    }
+                    let mut state = state.lock().unwrap();
+                    state.tone_hz = tones.tone();
+                    let demodulator = demodulator.as_ref().unwrap();
+                    if let Some(ref rds) = demodulator.rds {
+                        state.station_name = rds.station_name();
+                        state.radiotext = rds.radiotext();
+                    }
+                    if let Some(ref morse) = demodulator.morse {
+                        state.morse_text = Some(morse.text().to_string());
+                    }
+                    if let Some(ref mut recording) = state.recording {
+                        let samples: Vec<i16> = audio.iter()
+                            .map(|&x| (x.max(-1.0).min(1.0) * 32767.0) as i16)
+                            .collect();
+                        recording.write(&samples);
+                    }
+                    // silence keeps the player fed, so unmuting is right away
+                    let gain = if state.muted { 0.0 } else { state.volume };
+                    let samples = audio.iter()
+                        .map(|&x| ((x * gain).max(-1.0).min(1.0) * 32767.0) as i16)
+                        .collect();
+                    if let Some(ref player) = state.player {
+                        let _ = player.send.send(samples);
+                    }
+                }
+                None => {
+                    demodulator = None;
+                    let mut state = state.lock().unwrap();
+                    state.tone_hz = None;
+                    state.station_name = None;
+                    state.radiotext = None;
+                    state.morse_text = None;
+                }
+            }
+        })
+    }
+
+    /// Starts listening to `channel`, starting the player if it isn't going,
+    /// or stops with `None`.
+    pub fn listen(&self, channel: Option<Channel>) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if channel == state.channel {
+            return Ok(());
+        }
+        if channel.is_none() {
+            state.player = None;
+        } else if state.player.is_none() {
+            let player = try!(Player::start(state.device.as_ref().map(|d| &d[..])));
+            state.player = Some(player);
+        }
+        state.channel = channel;
+        Ok(())
+    }
+
+    /// Turns the volume up or down by `steps` steps, returning it as a gain.
+    pub fn step_volume(&self, steps: i32) -> f32 {
+        let mut state = self.state.lock().unwrap();
+        let volume = state.volume + steps as f32 * VOLUME_STEP;
+        // whole steps, whatever rounding there's been
+        state.volume = ((volume / VOLUME_STEP).round() * VOLUME_STEP).max(0.0).min(MAX_VOLUME);
+        state.volume
+    }
+
+    /// Mutes or unmutes the audio, returning whether it's muted.
+    pub fn toggle_mute(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.muted = !state.muted;
+        state.muted
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.state.lock().unwrap().muted
+    }
+
+    /// The CTCSS tone heard under NFM in the last second, if there was one.
+    pub fn tone_hz(&self) -> Option<f32> {
+        self.state.lock().unwrap().tone_hz
+    }
+
+    /// The name of the station heard in WFM, from its RDS.
+    pub fn station_name(&self) -> Option<String> {
+        self.state.lock().unwrap().station_name.clone()
+    }
+
+    /// The radiotext of the station heard in WFM.
+    pub fn radiotext(&self) -> Option<String> {
+        self.state.lock().unwrap().radiotext.clone()
+    }
+
+    /// The Morse read from the carrier listened to in CW.
+    pub fn morse_text(&self) -> Option<String> {
+        self.state.lock().unwrap().morse_text.clone()
+    }
+
+    /// Starts writing what's heard to `path`, replacing it, stopping any
+    /// recording already going.
+    pub fn start_recording(&self, path: &Path) -> io::Result<()> {
+        try!(self.stop_recording());
+        let mut out = BufWriter::new(try!(File::create(path)));
+        // filled in with the sizes when it's stopped
+        try!(out.write_all(&wav_header(0)));
+        self.state.lock().unwrap().recording = Some(Recording {
+            out: out,
+            path: path.to_path_buf(),
+            num_samples: 0,
+            error: None,
+        });
+        Ok(())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.state.lock().unwrap().recording.is_some()
+    }
+
+    /// Stops the recording, if there is one, returning its path and length
+    /// in seconds.
+    pub fn stop_recording(&self) -> io::Result<Option<(PathBuf, f64)>> {
+        let r = match self.state.lock().unwrap().recording.take() {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+        if let Some(e) = r.error {
+            return Err(e);
+        }
+        let mut out = r.out;
+        try!(out.seek(SeekFrom::Start(0)));
+        try!(out.write_all(&wav_header(r.num_samples)));
+        try!(out.flush());
+        Ok(Some((r.path, r.num_samples as f64 / AUDIO_RATE_HZ)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num::Complex;
+    use super::{channel_taps, low_pass_taps, wav_header, Channel, Demodulator, Mode};
+
+    /// Gain of `taps` at `freq` cycles per sample.
+    fn gain(taps: &[Complex<f32>], freq: f32) -> f32 {
+        taps.iter().enumerate().fold(Complex::new(0.0, 0.0), |acc, (i, &tap)| {
+            let turn = -2.0 * ::std::f32::consts::PI * freq * i as f32;
+            acc + tap * Complex::new(turn.cos(), turn.sin())
+        }).norm()
+    }
+
+    #[test]
+    fn test_demodulate() {
+        let taps = low_pass_taps(31, 0.1);
+        assert!((taps.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+
+        // a steady carrier under the marker is silence in NFM
+        let channel = Channel {
+            mode: Mode::Nfm,
+            offset_hz: 10e3,
+            bandwidth_hz: 12.5e3,
+            sample_rate_hz: 480e3,
+        };
+        let samples: Vec<Complex<i8>> = (0..48000).map(|i| {
+            let phase = 2.0 * ::std::f32::consts::PI * 10e3 * i as f32 / 480e3;
+            Complex::new((100.0 * phase.cos()) as i8, (100.0 * phase.sin()) as i8)
+        }).collect();
+        let mut demodulator = Demodulator::new(channel);
+        let mut audio = Vec::new();
+        demodulator.process(&samples, &mut audio);
+        // 0.1 s of audio
+        assert_eq!(audio.len(), 4800);
+        assert!(audio[1000..].iter().all(|x| x.abs() < 0.05));
+
+        // USB passes 1 kHz above the marker and not 1 kHz below, LSB the
+        // other way around
+        let usb = channel_taps(Channel { mode: Mode::Usb, bandwidth_hz: 2.7e3, ..channel }, 48e3);
+        let lsb = channel_taps(Channel { mode: Mode::Lsb, bandwidth_hz: 2.7e3, ..channel }, 48e3);
+        let (up, down) = (1e3 / 48e3, -1e3 / 48e3);
+        assert!(gain(&usb, up) > 0.9 && gain(&usb, down) < 0.01);
+        assert!(gain(&lsb, down) > 0.9 && gain(&lsb, up) < 0.01);
+    }
+
+    #[test]
+    fn test_wav_header() {
+        let header = wav_header(48000);
+        assert_eq!(header.len(), 44);
+        assert_eq!(&header[..4], b"RIFF");
+        // 36 bytes of header after the size, then a second of samples
+        assert_eq!(&header[4..8], &[0x24, 0x77, 0x01, 0x00]);
+        assert_eq!(&header[24..28], &[0x80, 0xbb, 0x00, 0x00]);
+        assert_eq!(&header[36..44], &[b'd', b'a', b't', b'a', 0x00, 0x77, 0x01, 0x00]);
+    }
+}
@@ -1,168 +1,3075 @@
+//! The spectrum and waterfall views. `Canvas` lays them out over a whole
+//! terminal, but the `draw_` functions draw into any cells, like a `Widget`
+//! of some bigger interface, from spectra `normalize_spectrum` has scaled to
+//! between 0 and 1. The widgets in `widgets` do that scaling themselves.
+
 use std::char;
 use std::cmp::{max, min};
 use std::collections::VecDeque;
+use std::fmt;
+use std::iter;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 use num::{Complex, Float};
-use rustty::{Attr, Color, Terminal, Cell, CellAccessor, HasSize};
-use rustty::ui::{Alignable, Widget, VerticalAlign, HorizontalAlign};
-use itertools::{Itertools, EitherOrBoth};
+use itertools::Itertools;
 use std::io;
 
-pub struct Canvas {
-    term: Terminal,
-    spectrum: Widget,
-    waterfall: Widget,
-    history: VecDeque<Vec<f32>>,
+use backend::{DefaultBackend, TerminalBackend};
+use bookmarks::Bookmark;
+use cells::{Attr, Color, Cell, CellAccessor, HasPosition, HasSize, Painter, Widget};
+use export::local_date_time_at;
+use history::{History, Row};
+use input::set_mouse_reporting;
+use kitty::{self, Image};
+use palette::Palette;
+use processing::{find_peaks, Peak};
+use render::{self, Job, Renderer, Style, Zoom};
+use stats::Stats;
+use units::format_frequency;
+use wait::Waker;
+use widgets::SpectrumWidget;
+
+/// Default power range of the spectrum view, in dB, from bottom to top.
+const MIN_DB: f32 = 0.0;
+const MAX_DB: f32 = 50.0;
+
+/// How far above the local noise floor a bin must be to be labeled as a peak.
+const PEAK_THRESHOLD_DB: f32 = 10.0;
+const PEAK_GUARD_BINS: usize = 2;
+const PEAK_TRAINING_BINS: usize = 16;
+
+/// Number of spectra kept for scrolling back through the waterfall while
+/// paused, unless the history's set deeper.
+const HISTORY_LEN: usize = 4096;
+
+/// Width of the gutter right of the waterfall for timestamps, a space then
+/// the time, and how many rows apart they go.
+const TIMESTAMP_COLS: usize = 9;
+const TIMESTAMP_EVERY_ROWS: usize = 5;
+
+/// Rough sizes of the escape sequences the backend sends to move the cursor and
+/// change the colors, for estimating the terminal output.
+const CURSOR_MOVE_BYTES: usize = 8;
+const STYLE_BYTES: usize = 20;
+
+/// Bit for each dot of a braille character, indexed by row then column.
+const BRAILLE_PIXEL_MAP: [[u8; 2]; 4] = [[0x01, 0x08],
+                                         [0x02, 0x10],
+                                         [0x04, 0x20],
+                                         [0x40, 0x80]];
+
+/// Keys listed at the end of the status bar, if it has room.
+const KEY_HINTS: &'static str = "q quit  ? help  p peaks  c palette  b braille  space pause  tab focus";
+
+/// Number of spectra the average trace is over, about.
+const AVERAGE_TRACE_SPECTRA: f32 = 20.0;
+
+/// Size of the pane text decoded from the audio is shown in, inside its box.
+const TEXT_PANE_COLS: usize = 40;
+const TEXT_PANE_LINES: usize = 3;
+
+/// Size of the IQ scope, counting its border, about as tall as it's wide.
+const SCOPE_COLS: usize = 30;
+const SCOPE_ROWS: usize = 14;
+
+/// Characters of increasing intensity for the ASCII waterfall.
+const ASCII_RAMP: [char; 10] = [' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+
+/// How much of the persistence view is left after each spectrum.
+const PERSISTENCE_DECAY: f32 = 0.95;
+/// Hits a spot of the persistence view has when the trace crosses it every
+/// spectrum, which gets the top color.
+const PERSISTENCE_FULL_HITS: f32 = 1.0 / (1.0 - PERSISTENCE_DECAY);
+/// Spots with fewer hits than this have faded out.
+const PERSISTENCE_MIN_HITS: f32 = 0.05;
+
+/// A line the spectrum view can draw, each in its own color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trace {
+    /// The newest spectrum, filled in under it.
+    Live,
+    Average,
+    /// The strongest and weakest each bin has been since the trace was shown.
+    MaxHold,
+    MinHold,
+}
+
+/// Every trace, in the order they're drawn, from the bottom up.
+const TRACES: [Trace; 4] = [Trace::Live, Trace::Average, Trace::MaxHold, Trace::MinHold];
+
+impl Trace {
+    fn color(self) -> Color {
+        match self {
+            Trace::Live => Color::Default,
+            Trace::Average => Color::Cyan,
+            Trace::MaxHold => Color::Red,
+            Trace::MinHold => Color::Green,
+        }
+    }
+
+    /// Drawn at the top of each column in ASCII.
+    fn ascii_char(self) -> char {
+        match self {
+            Trace::Live => '*',
+            Trace::Average => '~',
+            Trace::MaxHold => '^',
+            Trace::MinHold => '_',
+        }
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Trace::Live => "Live",
+            Trace::Average => "Average",
+            Trace::MaxHold => "Max hold",
+            Trace::MinHold => "Min hold",
+        })
+    }
+}
+
+/// How the live trace fills the area under it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpectrumStyle {
+    /// Braille dots all the way down.
+    Dots,
+    /// Dots, each column colored by its height with the waterfall's palette,
+    /// so a strong signal stands out even in a short spectrum view.
+    Colored,
+    /// Solid blocks below the braille at the top, which read better on some
+    /// terminals and in screenshots.
+    Solid,
+    /// Solid, colored by height with the waterfall's palette.
+    Gradient,
+}
+
+impl SpectrumStyle {
+    pub fn next(self) -> Self {
+        match self {
+            SpectrumStyle::Dots => SpectrumStyle::Colored,
+            SpectrumStyle::Colored => SpectrumStyle::Solid,
+            SpectrumStyle::Solid => SpectrumStyle::Gradient,
+            SpectrumStyle::Gradient => SpectrumStyle::Dots,
+        }
+    }
+}
+
+impl fmt::Display for SpectrumStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            SpectrumStyle::Dots => "dots",
+            SpectrumStyle::Colored => "colored",
+            SpectrumStyle::Solid => "solid",
+            SpectrumStyle::Gradient => "gradient",
+        })
+    }
+}
+
+/// The characters the spectrum view is drawn with, short of ASCII.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, RustcDecodable)]
+pub enum Glyphs {
+    /// Braille dots, two bins to a cell and four pixels high.
+    Braille,
+    /// Eighth blocks, a column to a cell and eight pixels high, for fonts
+    /// that draw braille poorly or not at all.
+    Blocks,
+}
+
+/// How the panes share the terminal, when there's more than one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, RustcDecodable)]
+pub enum Layout {
+    /// One above the other.
+    Stacked,
+    /// Side by side, each as wide as the others.
+    Columns,
+}
+
+/// One stacked row of a pane, showing a contiguous part of its span.
+struct Band {
+    spectrum: SpectrumWidget,
+    /// A row between the spectrum and the waterfall labeling bookmarks, only
+    /// shown if there are any.
+    bookmark_strip: Widget,
+    waterfall: Widget,
+    /// The waterfall as last rendered, which `waterfall` is drawn from with
+    /// the marks and labels on top.
+    grid: Widget,
+    /// For the persistence view, how often the trace has crossed each half
+    /// cell of the spectrum view, fading over time. Row by row.
+    persistence: Vec<f32>,
+    /// Right of the band, as tall as it, with the times of the waterfall rows
+    /// by them. No columns unless timestamps are shown.
+    gutter: Widget,
+}
+
+/// Display settings shared by every pane.
+struct Settings {
+    num_bands: usize,
+    bookmarks: Vec<Bookmark>,
+    exclude_dc: bool,
+    /// Flips the spectra left to right, for a converter that inverts them.
+    invert: bool,
+    /// How far a converter ahead of the radio shifts the signals, taken off
+    /// the radio's tuning for every frequency shown.
+    freq_offset_hz: f64,
+    num_peaks: usize,
+    show_peaks: bool,
+    palette: Palette,
+    ascii: bool,
+    braille_waterfall: bool,
+    /// Leaves the waterfall's cells empty, the images from `kitty` going
+    /// under them.
+    kitty: bool,
+    /// Scales each waterfall row between its own weakest and strongest bins,
+    /// rather than by the spectrum view's range.
+    normalize_rows: bool,
+    /// Shows how often the trace crosses each spot of the spectrum view, as
+    /// a color, instead of the newest spectrum.
+    persistence: bool,
+    /// Folds older spectra of the waterfall together, more the further back
+    /// they are, so it shows a longer stretch of time.
+    compress_history: bool,
+    /// Prints the time of every few waterfall rows in a gutter on its right.
+    timestamps: bool,
+    /// Adds waterfall rows at the bottom, scrolling it up, rather than at the
+    /// top.
+    scroll_up: bool,
+    min_db: f32,
+    max_db: f32,
+    /// Level in dB of the threshold line, only drawn and watched while
+    /// `threshold` is set.
+    threshold: bool,
+    threshold_db: f32,
+    /// Number of spectra in the running average, 1 for none.
+    average: usize,
+    /// The traces shown, in the order of `TRACES`.
+    traces: Vec<Trace>,
+    spectrum_style: SpectrumStyle,
+    glyphs: Glyphs,
+    /// Lowest and highest frequency, as shown, of another receiver's
+    /// passband, marked on the spectrum view.
+    passband: Option<(f64, f64)>,
+}
+
+impl Settings {
+    /// Number of history spectra drawn in each row of the waterfall.
+    fn spectra_per_waterfall_row(&self) -> usize {
+        if self.ascii {
+            1
+        } else if self.kitty {
+            kitty::SPECTRA_PER_ROW
+        } else if self.braille_waterfall {
+            4
+        } else {
+            2
+        }
+    }
+
+    /// The row from the top of a waterfall `rows` tall that the row `row`
+    /// back from the newest is drawn on.
+    fn waterfall_row(&self, row: usize, rows: usize) -> usize {
+        if self.scroll_up { rows - 1 - row } else { row }
+    }
+
+    /// Columns taken from a pane `cols` wide for the timestamp gutter, none
+    /// unless they're shown and there's room.
+    fn gutter_cols(&self, cols: usize) -> usize {
+        if self.timestamps && cols >= 2 * TIMESTAMP_COLS { TIMESTAMP_COLS } else { 0 }
+    }
+}
+
+/// Keeps an estimate of how much is sent to the terminal, for slow links.
+struct OutputMeter {
+    /// The cells of the last frame sent.
+    last_frame: Vec<Cell>,
+    /// When each frame of the last second was sent, and its estimated size.
+    frames: VecDeque<(Instant, usize)>,
+}
+
+impl OutputMeter {
+    fn add_frame(&mut self, frame: &[Cell], cols: usize) {
+        if self.last_frame.len() != frame.len() {
+            // the backends clear the screen on a resize
+            self.last_frame = vec![Cell::default(); frame.len()];
+        }
+        let bytes = frame_bytes(&self.last_frame, frame, cols);
+        self.last_frame.clone_from_slice(frame);
+
+        let now = Instant::now();
+        self.frames.push_back((now, bytes));
+        while self.frames.front().map_or(false, |&(t, _)| now.duration_since(t).as_secs() >= 1) {
+            self.frames.pop_front();
+        }
+    }
+
+    fn bytes_per_second(&self) -> usize {
+        self.frames.iter().map(|&(_, bytes)| bytes).sum()
+    }
+}
+
+/// A list to pick from, shown in an overlay.
+struct Picker {
+    title: String,
+    items: Vec<String>,
+    selected: usize,
+    /// The keys it takes, at the bottom.
+    hint: String,
+}
+
+/// An overlay for typing a line of text into.
+struct Prompt {
+    title: String,
+    text: String,
+    /// The keys it takes, or what was wrong with the text, at the bottom.
+    hint: String,
+}
+
+/// A rectangle of the history: spectra `time.0` to `time.1` back from the
+/// newest, and bins `bins.0` to `bins.1`, inclusive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Region {
+    time: (usize, usize),
+    bins: (usize, usize),
+}
+
+/// A region being picked out of a paused waterfall, as (time, bin) corners.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Selection {
+    anchor: (usize, usize),
+    cursor: (usize, usize),
+}
+
+/// The powers, in dB, of a region of the history.
+pub struct RegionData {
+    /// Frequency of each bin, in Hz.
+    pub freqs_hz: Vec<f64>,
+    /// How many spectra back from the newest each one is, and its powers.
+    pub spectra: Vec<(usize, Vec<f32>)>,
+}
+
+/// The focused pane's newest spectra as drawn, scaled between the bottom and
+/// the top of the spectrum view, with what it takes to label them.
+pub struct Snapshot {
+    pub center_freq_hz: f64,
+    pub sample_rate_hz: f64,
+    pub min_db: f32,
+    pub max_db: f32,
+    pub palette: Palette,
+    /// Newest first, from where the pane is scrolled to.
+    pub spectra: Vec<Arc<Vec<f32>>>,
+}
+
+/// The traces besides the live one, each empty while it's hidden or until
+/// the first spectrum.
+struct Traces {
+    average: Vec<f32>,
+    max_hold: Vec<f32>,
+    min_hold: Vec<f32>,
+}
+
+impl Traces {
+    fn new() -> Self {
+        Traces { average: Vec::new(), max_hold: Vec::new(), min_hold: Vec::new() }
+    }
+
+    /// Adds a normalized spectrum to the traces in `shown`.
+    fn add_spectrum(&mut self, shown: &[Trace], spec: &[f32]) {
+        for &trace in shown {
+            let held = match trace {
+                Trace::Live => continue,
+                Trace::Average => &mut self.average,
+                Trace::MaxHold => &mut self.max_hold,
+                Trace::MinHold => &mut self.min_hold,
+            };
+            // starting again at a new spectrum width
+            if held.len() == spec.len() {
+                for (h, &x) in held.iter_mut().zip(spec) {
+                    *h = match trace {
+                        Trace::Average => *h + (x - *h) / AVERAGE_TRACE_SPECTRA,
+                        Trace::MaxHold => h.max(x),
+                        _ => h.min(x),
+                    };
+                }
+            } else {
+                *held = spec.to_vec();
+            }
+        }
+    }
+
+    fn hide(&mut self, trace: Trace) {
+        match trace {
+            Trace::Live => {}
+            Trace::Average => self.average.clear(),
+            Trace::MaxHold => self.max_hold.clear(),
+            Trace::MinHold => self.min_hold.clear(),
+        }
+    }
+
+    fn clear(&mut self) {
+        for &trace in &TRACES {
+            self.hide(trace);
+        }
+    }
+
+    /// What `trace` has to draw, with `live` the newest spectrum, if it has
+    /// anything yet.
+    fn bins<'a>(&'a self, trace: Trace, live: &'a [f32]) -> Option<(Trace, &'a [f32])> {
+        let bins = match trace {
+            Trace::Live => live,
+            Trace::Average => &self.average,
+            Trace::MaxHold => &self.max_hold,
+            Trace::MinHold => &self.min_hold,
+        };
+        if bins.is_empty() { None } else { Some((trace, bins)) }
+    }
+}
+
+/// A spectrum and waterfall view of one stream of spectra, with its own
+/// history and pause state.
+struct Pane {
+    /// The span is wrapped across these, lowest frequencies first.
+    bands: Vec<Band>,
+    /// Shared with the renderer, which may still be drawing older spectra.
+    history: History,
+    /// The newest spectrum of the history at full precision, for the
+    /// spectrum view and readouts, empty until the first one.
+    newest: Vec<f32>,
+    /// When each spectrum of the history was added, in the same order.
+    added_at: VecDeque<SystemTime>,
+    /// Running average of the normalized spectra, empty until the first one.
+    average: Vec<f32>,
+    traces: Traces,
+    center_freq_hz: f64,
+    sample_rate_hz: f64,
+    paused: bool,
+    /// How many spectra back from the newest one the waterfall starts at.
+    scroll: usize,
+    /// Bin of the newest spectrum the marker reads out.
+    marker: Option<usize>,
+    /// Only while paused.
+    selection: Option<Selection>,
+    /// Region of the history the waterfall is stretched over, only while paused.
+    zoom: Option<Region>,
+    /// Number of spectra added so far, counting those dropped from the history.
+    num_added: usize,
+    /// Labeled rows of the waterfall, oldest first.
+    marks: VecDeque<Mark>,
+    /// Whether the spectrum views have changed since they were last put on
+    /// the terminal's cells.
+    spectrum_damaged: bool,
+    /// The same for the waterfalls, with their bookmark strips and gutters.
+    waterfall_damaged: bool,
+}
+
+/// A label on the waterfall row where something happened, like a retune.
+struct Mark {
+    /// Number of spectra added before it happened.
+    num_added: usize,
+    label: String,
+    is_retune: bool,
+}
+
+impl Pane {
+    fn new() -> Self {
+        Pane {
+            bands: Vec::new(),
+            history: History::new(HISTORY_LEN),
+            newest: Vec::new(),
+            added_at: VecDeque::new(),
+            average: Vec::new(),
+            traces: Traces::new(),
+            center_freq_hz: 0.0,
+            sample_rate_hz: 0.0,
+            paused: false,
+            scroll: 0,
+            marker: None,
+            selection: None,
+            zoom: None,
+            num_added: 0,
+            marks: VecDeque::new(),
+            spectrum_damaged: true,
+            waterfall_damaged: true,
+        }
+    }
+
+    /// Lays the pane out in `cols` columns from column `left` and `rows` rows
+    /// from row `top`.
+    fn layout(&mut self, settings: &Settings, left: usize, cols: usize, top: usize,
+              rows: usize) {
+        self.spectrum_damaged = true;
+        self.waterfall_damaged = true;
+        let num_bands = settings.num_bands;
+        let strip_height = if settings.bookmarks.is_empty() || rows < 2 * num_bands { 0 } else { 1 };
+        let gutter_cols = settings.gutter_cols(cols);
+        let cols = cols - gutter_cols;
+
+        self.bands.clear();
+        let mut band_top = top;
+        for i in 0..num_bands {
+            // the last band takes any leftover rows
+            let band_height = if i + 1 == num_bands {
+                top + rows - band_top
+            } else {
+                rows / num_bands
+            };
+            let spectrum_height = (band_height - strip_height) / 2;
+            let waterfall_height = band_height - strip_height - spectrum_height;
+
+            let mut band = Band {
+                spectrum: SpectrumWidget::new(cols, spectrum_height),
+                bookmark_strip: Widget::new(cols, strip_height),
+                waterfall: Widget::new(cols, waterfall_height),
+                grid: Widget::new(cols, waterfall_height),
+                persistence: Vec::new(),
+                gutter: Widget::new(gutter_cols, band_height),
+            };
+            band.spectrum.set_origin((left, band_top));
+            band.bookmark_strip.set_origin((left, band_top + spectrum_height));
+            band.waterfall.set_origin((left, band_top + spectrum_height + strip_height));
+            band.gutter.set_origin((left + cols, band_top));
+            self.bands.push(band);
+
+            band_top += band_height;
+        }
+    }
+
+    /// Returns the (cols, rows) the pane currently occupies, if every widget
+    /// agrees on the column count, counting the gutter.
+    fn size(&self) -> Option<(usize, usize)> {
+        let mut cols = None;
+        let mut rows = 0;
+        for band in &self.bands {
+            for &(widget_cols, widget_rows) in &[band.spectrum.size(), band.bookmark_strip.size(),
+                                                 band.waterfall.size()] {
+                if cols.map_or(false, |c| c != widget_cols) {
+                    return None;
+                }
+                cols = Some(widget_cols);
+                rows += widget_rows;
+            }
+        }
+        let gutter_cols = self.bands.first().map_or(0, |band| band.gutter.size().0);
+        cols.map(|c| (c + gutter_cols, rows))
+    }
+
+    /// Number of bins the bands show between them, two per column.
+    fn num_bins(&self) -> usize {
+        self.bands.first().map_or(0, |band| 2 * band.spectrum.size().0 * self.bands.len())
+    }
+
+    /// Stretches or squeezes the history to `num_bins` bins, keeping the
+    /// marker, selection and zoom on the same frequencies.
+    fn fit_history(&mut self, num_bins: usize) {
+        let old_bins = self.history.front().map_or(0, |row| row.len());
+        if num_bins == 0 || old_bins == 0 || old_bins == num_bins {
+            return;
+        }
+        self.history.fit(num_bins);
+        if !self.newest.is_empty() {
+            self.newest = rescale_row(&self.newest, num_bins);
+        }
+        self.average.clear();
+        self.traces.clear();
+
+        let scale = |bin: usize| min(bin * num_bins / old_bins, num_bins - 1);
+        self.marker = self.marker.map(&scale);
+        if let Some(ref mut zoom) = self.zoom {
+            zoom.bins = (scale(zoom.bins.0), scale(zoom.bins.1));
+        }
+        if let Some(ref mut selection) = self.selection {
+            selection.anchor.1 = scale(selection.anchor.1);
+            selection.cursor.1 = scale(selection.cursor.1);
+        }
+    }
+
+    fn add_spectrum(&mut self, settings: &Settings, spec: &[Complex<f32>]) {
+        let mut normalized = normalize_spectrum_fast(spec, settings.min_db, settings.max_db,
+                                                     settings.invert);
+        if settings.exclude_dc {
+            let dc = dc_bin(normalized.len());
+            suppress_dc_bin(&mut normalized, dc);
+        }
+        self.add_row(settings, normalized);
+    }
+
+    /// Adds a row already normalized, lowest frequencies first.
+    fn add_row(&mut self, settings: &Settings, mut normalized: Vec<f32>) {
+        // spectra taken before a resize, or recorded on another terminal
+        let num_bins = self.num_bins();
+        if num_bins > 0 && normalized.len() != num_bins {
+            normalized = rescale_row(&normalized, num_bins);
+        }
+
+        if settings.average > 1 {
+            // restart the average when the spectrum width changes
+            if self.average.len() == normalized.len() {
+                for (a, x) in self.average.iter_mut().zip(&normalized) {
+                    *a += (x - *a) / settings.average as f32;
+                }
+            } else {
+                self.average = normalized.clone();
+            }
+            normalized.copy_from_slice(&self.average);
+        }
+
+        if let Some(marker) = self.marker {
+            self.marker = Some(min(marker, normalized.len() - 1));
+        }
+        self.traces.add_spectrum(&settings.traces, &normalized);
+
+        // push spectrum onto the history
+        self.history.push_front(Arc::new(Row::new(&normalized)));
+        self.newest = normalized;
+        self.added_at.push_front(SystemTime::now());
+        self.added_at.truncate(self.history.len());
+        self.num_added += 1;
+        let max_len = self.history.max_len();
+        while self.marks.front().map_or(false, |m| m.num_added + max_len < self.num_added) {
+            self.marks.pop_front();
+        }
+
+        if settings.persistence {
+            self.add_persistence();
+        }
+        self.draw_spectrum(settings);
+    }
+
+    /// Fades each band's persistence view and adds the newest spectrum.
+    fn add_persistence(&mut self) {
+        if self.newest.is_empty() {
+            return;
+        }
+        let newest = &self.newest;
+        let band_len = newest.len() / self.bands.len();
+        for (i, band) in self.bands.iter_mut().enumerate() {
+            let size = band.spectrum.size();
+            add_persistence(&mut band.persistence, size, &newest[i * band_len..][..band_len]);
+        }
+    }
+
+    /// Draws the newest spectrum, with its labels, in the spectrum view.
+    fn draw_spectrum(&mut self, settings: &Settings) {
+        if self.newest.is_empty() {
+            return;
+        }
+        let normalized = &self.newest;
+        let dc = dc_bin(normalized.len());
+        self.spectrum_damaged = true;
+
+        let peaks = if settings.show_peaks {
+            find_peaks(normalized, PEAK_GUARD_BINS, PEAK_TRAINING_BINS,
+                       PEAK_THRESHOLD_DB / (settings.max_db - settings.min_db), settings.num_peaks)
+        } else {
+            Vec::new()
+        };
+
+        let num_bands = self.bands.len();
+        let band_len = normalized.len() / num_bands;
+        let held = &self.traces;
+        for (i, band) in self.bands.iter_mut().enumerate() {
+            let start = i * band_len;
+            let bins = &normalized[start..start + band_len];
+            let (center_freq_hz, sample_rate_hz) =
+                band_tuning(self.center_freq_hz, self.sample_rate_hz, i, num_bands);
+
+            // the persistence view takes the live trace's place
+            let traces: Vec<(Trace, &[f32])> = settings.traces.iter()
+                .filter(|&&trace| !(settings.persistence && trace == Trace::Live))
+                .filter_map(|&trace| held.bins(trace, normalized))
+                .map(|(trace, spec)| (trace, &spec[start..start + band_len]))
+                .collect();
+            if settings.persistence {
+                draw_persistence(&mut band.spectrum, &band.persistence, settings.palette,
+                                 settings.ascii);
+            } else {
+                band.spectrum.clear(Cell::default());
+            }
+            band.spectrum.set_palette(settings.palette);
+            band.spectrum.set_glyphs(settings.glyphs);
+            band.spectrum.set_style(settings.spectrum_style);
+            band.spectrum.set_ascii(settings.ascii);
+            band.spectrum.draw_traces(&traces);
+            if dc >= start && dc < start + band_len {
+                mark_dc_bin(&mut band.spectrum, dc - start, settings.exclude_dc);
+            }
+            if settings.threshold {
+                let level = (settings.threshold_db - settings.min_db) /
+                            (settings.max_db - settings.min_db);
+                draw_threshold(&mut band.spectrum, level, settings.ascii);
+            }
+            if let Some(passband) = settings.passband {
+                draw_passband(&mut band.spectrum, passband, center_freq_hz, sample_rate_hz,
+                              settings.ascii);
+            }
+
+            let band_peaks: Vec<Peak> = peaks.iter()
+                .filter(|p| p.bin >= start && p.bin < start + band_len)
+                .map(|p| Peak { bin: p.bin - start, power: p.power })
+                .collect();
+            draw_peak_labels(&mut band.spectrum, bins, &band_peaks,
+                             center_freq_hz, sample_rate_hz);
+
+            if num_bands > 1 {
+                draw_band_range(&mut band.spectrum, center_freq_hz, sample_rate_hz);
+            }
+
+            if let Some(marker) = self.marker {
+                if marker >= start && marker < start + band_len {
+                    let label = format!("M {}", format_frequency(
+                        bin_frequency(marker, normalized.len(), self.center_freq_hz,
+                                      self.sample_rate_hz)));
+                    draw_marker(&mut band.spectrum, marker - start, &label);
+                }
+            }
+        }
+    }
+
+    /// The marker's frequency, in Hz, and power, in dB, in the newest spectrum.
+    fn marker_reading(&self, settings: &Settings) -> Option<(f64, f32)> {
+        let bin = match self.marker {
+            Some(bin) if !self.newest.is_empty() => bin,
+            _ => return None,
+        };
+        let spec = &self.newest;
+        let freq_hz = bin_frequency(bin, spec.len(), self.center_freq_hz, self.sample_rate_hz);
+        let power_db = settings.min_db + spec[bin] * (settings.max_db - settings.min_db);
+        Some((freq_hz, power_db))
+    }
+
+    /// The frequency, in Hz, and power, in dB, of the strongest bin of the
+    /// newest spectrum above `threshold_db`, if any is.
+    fn strongest_above(&self, settings: &Settings, threshold_db: f32) -> Option<(f64, f32)> {
+        if self.newest.is_empty() {
+            return None;
+        }
+        let spec = &self.newest;
+        let level = (threshold_db - settings.min_db) / (settings.max_db - settings.min_db);
+        strongest_bin_above(spec, level).map(|bin| {
+            let freq_hz = bin_frequency(bin, spec.len(), self.center_freq_hz,
+                                        self.sample_rate_hz);
+            (freq_hz, settings.min_db + spec[bin] * (settings.max_db - settings.min_db))
+        })
+    }
+
+    /// What the renderer needs to draw this pane's waterfall, the pane being
+    /// number `pane` of the canvas.
+    fn waterfall_job(&self, settings: &Settings, pane: usize) -> Job {
+        let (cols, num_rows) = self.waterfall_size();
+        let num_spectra = num_rows * settings.spectra_per_waterfall_row();
+        let (rows, zoom) = match self.zoom {
+            Some(region) => {
+                let (t0, t1) = region.time;
+                let rows = self.history.iter_from(t0).take(t1 - t0 + 1).collect();
+                let zoom = Zoom {
+                    bins: region.bins,
+                    num_spectra: num_spectra,
+                    width: 2 * cols * self.bands.len(),
+                };
+                (rows, Some(zoom))
+            }
+            None => match self.slot_sizes(settings, num_spectra) {
+                Some(sizes) => (fold_history(self.history.iter_from(self.scroll), &sizes), None),
+                None => {
+                    let rows = self.history.iter_from(self.scroll).take(num_spectra);
+                    (rows.collect(), None)
+                }
+            },
+        };
+        Job {
+            pane: pane,
+            rows: rows,
+            zoom: zoom,
+            cols: cols,
+            band_rows: self.bands.iter().map(|band| band.waterfall.size().1).collect(),
+            spectra_per_row: settings.spectra_per_waterfall_row(),
+            style: Style {
+                palette: settings.palette,
+                ascii: settings.ascii,
+                braille: settings.braille_waterfall,
+                kitty: settings.kitty,
+                normalize_rows: settings.normalize_rows,
+                newest_at_bottom: settings.scroll_up,
+            },
+        }
+    }
+
+    /// With the history compressed, how many spectra go into each of the
+    /// `num_spectra` waterfall slots, newest first. A half of them take one, a
+    /// quarter two, an eighth four and so on. Selections take the spectra one
+    /// to a slot, so they're never compressed.
+    fn slot_sizes(&self, settings: &Settings, num_spectra: usize) -> Option<Vec<usize>> {
+        if !settings.compress_history || self.selection.is_some() {
+            return None;
+        }
+        let mut sizes = Vec::with_capacity(num_spectra);
+        let mut size = 1;
+        while sizes.len() < num_spectra {
+            let count = max((num_spectra - sizes.len()) / 2, 1);
+            sizes.extend(iter::repeat(size).take(count));
+            size *= 2;
+        }
+        Some(sizes)
+    }
+
+    /// Keeps rendered grids, one for each band, unless the pane has been laid
+    /// out again since they were asked for.
+    fn set_grids(&mut self, grids: Vec<Widget>) {
+        let fits = grids.len() == self.bands.len() &&
+                   self.bands.iter().zip(&grids).all(|(band, grid)| {
+                       band.waterfall.size() == grid.size()
+                   });
+        if !fits {
+            return;
+        }
+        for (band, grid) in self.bands.iter_mut().zip(grids) {
+            band.grid = grid;
+        }
+    }
+
+    /// Renders the waterfall right away, rather than on the renderer's thread.
+    fn draw_waterfall(&mut self, settings: &Settings) {
+        let grids = render::render(&self.waterfall_job(settings, 0));
+        self.set_grids(grids);
+        self.compose_waterfall(settings);
+    }
+
+    /// Draws the last rendered waterfall, with the bookmarks, marks, selection
+    /// and pause label over it.
+    fn compose_waterfall(&mut self, settings: &Settings) {
+        self.waterfall_damaged = true;
+        for band in &mut self.bands {
+            band.waterfall.cellvec_mut().clone_from(band.grid.cellvec());
+            band.gutter.clear(Cell::default());
+        }
+
+        let (center_freq_hz, sample_rate_hz) = match self.zoom {
+            Some(region) => self.region_tuning(region),
+            None => (self.center_freq_hz, self.sample_rate_hz),
+        };
+        let num_bands = self.bands.len();
+        for (i, band) in self.bands.iter_mut().enumerate() {
+            let (band_center_hz, band_rate_hz) =
+                band_tuning(center_freq_hz, sample_rate_hz, i, num_bands);
+            draw_bookmark_strip(&mut band.bookmark_strip, &settings.bookmarks, band_center_hz,
+                                band_rate_hz, settings.ascii);
+        }
+
+        if self.zoom.is_none() {
+            self.draw_marks(settings);
+            self.draw_timestamps(settings);
+        }
+        if let Some(selection) = self.selection {
+            self.draw_selection(settings, selection);
+        }
+
+        if self.paused {
+            let label = if self.selection.is_some() {
+                " SELECT: arrows resize, Enter zooms, x exports, Esc cancels ".to_string()
+            } else if let Some(region) = self.zoom {
+                format!(" ZOOM {} - {}, -{} to -{}, x exports, Esc returns ",
+                        format_frequency(center_freq_hz - sample_rate_hz / 2.0),
+                        format_frequency(center_freq_hz + sample_rate_hz / 2.0),
+                        region.time.0, region.time.1)
+            } else {
+                format!(" PAUSED -{} ", self.scroll)
+            };
+            self.bands[0].waterfall.printline_with_cell(0, 0, &label,
+                Cell::new(' ', Color::Black, Color::White, Attr::Default));
+        }
+    }
+
+    /// Changes the tuning, marking where it happened on the waterfall.
+    fn retune(&mut self, center_freq_hz: f64, sample_rate_hz: f64) {
+        self.center_freq_hz = center_freq_hz;
+        self.sample_rate_hz = sample_rate_hz;
+        // only the last of several retunes without a spectrum between shows
+        if self.marks.back().map_or(false, |m| m.is_retune && m.num_added == self.num_added) {
+            self.marks.pop_back();
+        }
+        self.add_mark(format_frequency(center_freq_hz), true);
+        self.traces.clear();
+    }
+
+    fn add_mark(&mut self, label: String, is_retune: bool) {
+        let mark = Mark { num_added: self.num_added, label: label, is_retune: is_retune };
+        self.marks.push_back(mark);
+    }
+
+    /// Draws the marks at the right edge of the first band, any on the same
+    /// row side by side.
+    fn draw_marks(&mut self, settings: &Settings) {
+        let (cols, num_rows) = self.waterfall_size();
+        let spectra_per_row = settings.spectra_per_waterfall_row();
+        let line = if settings.ascii { "-" } else { "─" };
+
+        let sizes = self.slot_sizes(settings, num_rows * spectra_per_row);
+        let mut rows: Vec<(usize, String)> = Vec::new();
+        for mark in &self.marks {
+            // the first spectrum after the mark, as spectra back from the newest
+            let back = match self.num_added.checked_sub(mark.num_added + 1) {
+                Some(back) if back >= self.scroll => back - self.scroll,
+                _ => continue,
+            };
+            let slot = match sizes {
+                Some(ref sizes) => {
+                    let mut end = 0;
+                    match sizes.iter().position(|&size| { end += size; end > back }) {
+                        Some(slot) => slot,
+                        None => continue,
+                    }
+                }
+                None => back,
+            };
+            let row = slot / spectra_per_row;
+            if row >= num_rows {
+                continue;
+            }
+            let label = format!("{}{} {} ", line, line, mark.label);
+            match rows.iter().position(|&(r, _)| r == row) {
+                Some(i) => rows[i].1.push_str(&label),
+                None => rows.push((row, label)),
+            }
+        }
+
+        let band_rows = self.bands[0].waterfall.size().1;
+        for (row, label) in rows {
+            let len = label.chars().count();
+            if len <= cols && row < band_rows {
+                let row = settings.waterfall_row(row, band_rows);
+                self.bands[0].waterfall.printline_with_cell(cols - len, row, &label,
+                    Cell::new(' ', Color::Black, Color::White, Attr::Default));
+            }
+        }
+    }
+
+    /// Prints the time of every few waterfall rows in each band's gutter, by
+    /// the row, that of its newest spectrum.
+    fn draw_timestamps(&mut self, settings: &Settings) {
+        let spectra_per_row = settings.spectra_per_waterfall_row();
+        let (_, num_rows) = self.waterfall_size();
+        let sizes = self.slot_sizes(settings, num_rows * spectra_per_row);
+        let scroll = self.scroll;
+        let added_at = &self.added_at;
+        for band in &mut self.bands {
+                let top = band.waterfall.origin().1 - band.gutter.origin().1;
+            let band_rows = band.waterfall.size().1;
+            for row in (0..band_rows).step_by(TIMESTAMP_EVERY_ROWS) {
+                let slot = row * spectra_per_row;
+                let back = match sizes {
+                    Some(ref sizes) => sizes.iter().take(slot).sum(),
+                    None => slot,
+                };
+                if let Some(&time) = added_at.get(scroll + back) {
+                    let row = top + settings.waterfall_row(row, band_rows);
+                    band.gutter.printline(1, row, &local_date_time_at(time).1);
+                }
+            }
+        }
+    }
+
+    /// Columns and rows of the waterfall of each band, using the tallest.
+    fn waterfall_size(&self) -> (usize, usize) {
+        let cols = self.bands.first().map_or(0, |band| band.waterfall.size().0);
+        let rows = self.bands.iter().map(|band| band.waterfall.size().1).max().unwrap_or(0);
+        (cols, rows)
+    }
+
+    /// Center frequency and width of the bins of `region`.
+    fn region_tuning(&self, region: Region) -> (f64, f64) {
+        let len = self.history.front().map_or(1, |spec| spec.len());
+        let low = bin_frequency(region.bins.0, len, self.center_freq_hz, self.sample_rate_hz);
+        let high = bin_frequency(region.bins.1 + 1, len, self.center_freq_hz, self.sample_rate_hz);
+        ((low + high) / 2.0, high - low)
+    }
+
+    /// The region covered by the cells between the selection's corners.
+    fn selection_region(&self, settings: &Settings, selection: Selection) -> Region {
+        let spectra_per_row = settings.spectra_per_waterfall_row();
+        let (a, c) = (selection.anchor, selection.cursor);
+        let len = self.history.front().map_or(1, |spec| spec.len());
+        Region {
+            time: (min(a.0, c.0), min(max(a.0, c.0) + spectra_per_row, self.history.len()) - 1),
+            bins: (min(a.1, c.1), min(max(a.1, c.1) + 2, len) - 1),
+        }
+    }
+
+    /// Outlines the selection on the waterfall.
+    fn draw_selection(&mut self, settings: &Settings, selection: Selection) {
+        let (cols, rows) = self.waterfall_size();
+        let spectra_per_row = settings.spectra_per_waterfall_row();
+        let (a, c) = (selection.anchor, selection.cursor);
+        // rows and columns of the whole pane, with the bands laid side by side
+        let to_row = |time: usize| (time.saturating_sub(self.scroll)) / spectra_per_row;
+        let (r0, r1) = (to_row(min(a.0, c.0)), to_row(max(a.0, c.0)));
+        let (c0, c1) = (min(a.1, c.1) / 2, max(a.1, c.1) / 2);
+
+        // top left, top right, bottom left, bottom right, horizontal, vertical
+        let border = if settings.ascii {
+            ['+', '+', '+', '+', '-', '|']
+        } else {
+            ['┌', '┐', '└', '┘', '─', '│']
+        };
+        for row in r0..r1 + 1 {
+            // the newest row is the bottom edge when scrolling up
+            let (top, bottom) = if settings.scroll_up { (row == r1, row == r0) } else {
+                (row == r0, row == r1)
+            };
+            for col in c0..c1 + 1 {
+                let ch = match (top, bottom, col == c0, col == c1) {
+                    (true, _, true, _) => border[0],
+                    (true, _, _, true) => border[1],
+                    (_, true, true, _) => border[2],
+                    (_, true, _, true) => border[3],
+                    (true, _, _, _) | (_, true, _, _) => border[4],
+                    (_, _, true, _) | (_, _, _, true) => border[5],
+                    _ => continue,
+                };
+                if cols == 0 || row >= rows {
+                    continue;
+                }
+                if let Some(band) = self.bands.get_mut(col / cols) {
+                    let band_rows = band.waterfall.size().1;
+                    if row >= band_rows {
+                        continue;
+                    }
+                    let row = settings.waterfall_row(row, band_rows);
+                    if let Some(cell) = band.waterfall.get_mut(col % cols, row) {
+                        *cell = Cell::new(ch, Color::White, cell.bg(), Attr::Bold);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Puts the views that have changed since the last time on `term`, or
+    /// every one of them with `all` set.
+    fn draw_into<T: CellAccessor>(&mut self, term: &mut T, all: bool) {
+        for band in &self.bands {
+            if all || self.spectrum_damaged {
+                band.spectrum.draw_into(term);
+            }
+            if all || self.waterfall_damaged {
+                band.bookmark_strip.draw_into(term);
+                band.waterfall.draw_into(term);
+                band.gutter.draw_into(term);
+            }
+        }
+        self.spectrum_damaged = false;
+        self.waterfall_damaged = false;
+    }
+
+    fn scroll_waterfall(&mut self, settings: &Settings, pages: isize) {
+        let (_, rows) = self.bands[0].waterfall.size();
+        let visible = rows * settings.spectra_per_waterfall_row();
+        let page = visible as isize;
+        let max_scroll = self.history.len().saturating_sub(visible) as isize;
+        let scroll = self.scroll as isize + pages * page;
+        self.scroll = min(max(scroll, 0), max_scroll) as usize;
+    }
+}
+
+/// Receiver settings for the status bar, which the canvas has no other use for.
+#[derive(Debug, Clone, Copy)]
+pub struct RadioSettings {
+    pub fft_rate_hz: u32,
+    pub lna_gain_db: u32,
+    pub vga_gain_db: u32,
+    pub amp: bool,
+}
+
+/// Draws on the default backend's terminal unless given another backend.
+pub struct Canvas<B: TerminalBackend = DefaultBackend> {
+    term: B,
+    /// From the top of the terminal down, or left to right.
+    panes: Vec<Pane>,
+    layout: Layout,
+    /// The bottom row of the terminal, below the panes.
+    status_bar: Widget,
+    /// The pane that pause and scroll keys apply to.
+    focus: usize,
+    settings: Settings,
+    /// Unknown when replaying a session.
+    radio: Option<RadioSettings>,
+    /// Where the frames drawn are counted, and what's shown of the pipeline.
+    stats: Option<Arc<Stats>>,
+    overruns: usize,
+    /// The help overlay, drawn over everything else until dismissed.
+    help: Option<Widget>,
+    picker: Option<Picker>,
+    prompt: Option<Prompt>,
+    /// Feedback for the last key, in the status bar.
+    notice: Option<String>,
+    /// Draws the waterfalls off the UI thread, once `render_in_background`
+    /// is called.
+    renderer: Option<Renderer>,
+    /// Shortest time between frames, if capped.
+    frame_interval: Option<Duration>,
+    last_frame_at: Instant,
+    /// Whether the cap held back a redraw, or just putting it on the screen.
+    redraw_pending: bool,
+    present_pending: bool,
+    /// Whether the terminal's cells have lost the views, so every one has
+    /// to be put back, not just those that have changed.
+    cells_stale: bool,
+    /// Whether an overlay or the decoded text went over the views last time.
+    overlaid: bool,
+    /// Only while the frame rate is capped.
+    output: Option<OutputMeter>,
+    /// Whether the terminal reports the mouse, until dropped.
+    mouse: bool,
+    /// The waterfall of each band of every pane, in order, waiting to be put
+    /// on the screen with the kitty graphics protocol.
+    images: Option<Vec<Image>>,
+    /// What's being listened to, for the status bar.
+    listening: Option<String>,
+    /// A title and the text decoded from what's being listened to, shown in
+    /// a pane over the bottom of the waterfall.
+    decoded: Option<(String, String)>,
+    /// Samples the IQ scope plots, over the top right corner, while it's shown.
+    scope: Option<Vec<Complex<i8>>>,
+    /// The strongest bin above the threshold in the newest spectrum, its
+    /// frequency and power, and when the alert started.
+    alert: Option<(f64, f32, Instant)>,
+}
+
+impl Canvas {
+    pub fn new() -> Result<Self, io::Error> {
+        Ok(Canvas::with_backend(try!(DefaultBackend::new())))
+    }
+}
+
+impl<B: TerminalBackend> Canvas<B> {
+    /// Draws on `term`, which the canvas takes over the whole of.
+    pub fn with_backend(term: B) -> Self {
+        let mut canvas = Canvas {
+            term: term,
+            panes: vec![Pane::new()],
+            layout: Layout::Stacked,
+            status_bar: Widget::new(0, 0),
+            focus: 0,
+            settings: Settings {
+                num_bands: 1,
+                bookmarks: Vec::new(),
+                exclude_dc: false,
+                invert: false,
+                freq_offset_hz: 0.0,
+                num_peaks: 5,
+                show_peaks: true,
+                palette: Palette::Classic,
+                ascii: false,
+                braille_waterfall: false,
+                kitty: false,
+                normalize_rows: false,
+                compress_history: false,
+                timestamps: false,
+                scroll_up: false,
+                persistence: false,
+                min_db: MIN_DB,
+                max_db: MAX_DB,
+                threshold: false,
+                threshold_db: (MIN_DB + MAX_DB) / 2.0,
+                average: 1,
+                traces: vec![Trace::Live],
+                spectrum_style: SpectrumStyle::Dots,
+                glyphs: Glyphs::Braille,
+                passband: None,
+            },
+            radio: None,
+            stats: None,
+            overruns: 0,
+            help: None,
+            picker: None,
+            prompt: None,
+            notice: None,
+            renderer: None,
+            frame_interval: None,
+            last_frame_at: Instant::now(),
+            redraw_pending: false,
+            present_pending: false,
+            cells_stale: true,
+            overlaid: false,
+            output: None,
+            mouse: false,
+            images: None,
+            listening: None,
+            decoded: None,
+            scope: None,
+            alert: None,
+        };
+
+        canvas.resize();
+        canvas
+    }
+
+    fn resize(&mut self) {
+        let (cols, rows) = self.term.size();
+        let status_rows = min(rows, 1);
+        self.status_bar = Widget::new(cols, status_rows);
+        self.status_bar.set_origin((0, rows - status_rows));
+        let rows = rows - status_rows;
+
+        let num_panes = self.panes.len();
+        let pane_cols = self.pane_cols();
+        let mut top = 0;
+        for (i, pane) in self.panes.iter_mut().enumerate() {
+            match self.layout {
+                Layout::Stacked => {
+                    // the last pane takes any leftover rows
+                    let pane_rows = if i + 1 == num_panes { rows - top } else { rows / num_panes };
+                    pane.layout(&self.settings, 0, cols, top, pane_rows);
+                    top += pane_rows;
+                }
+                Layout::Columns => pane.layout(&self.settings, i * pane_cols, pane_cols, 0, rows),
+            }
+            let num_bins = pane.num_bins();
+            pane.fit_history(num_bins);
+            pane.draw_spectrum(&self.settings);
+        }
+        // everything has to be drawn again at the new size
+        self.redraw_pending = true;
+        self.cells_stale = true;
+    }
+
+    fn check_and_resize(&mut self) {
+        let (cols, rows) = self.term.size();
+        let (status_cols, status_rows) = self.status_bar.size();
+        let mut same_cols = status_cols == cols;
+        let mut total_rows = status_rows;
+        for pane in &self.panes {
+            match pane.size() {
+                Some((pane_cols, pane_rows)) => {
+                    same_cols &= pane_cols == self.pane_cols();
+                    match self.layout {
+                        Layout::Stacked => total_rows += pane_rows,
+                        Layout::Columns => same_cols &= status_rows + pane_rows == rows,
+                    }
+                }
+                None => same_cols = false,
+            }
+        }
+        if self.layout == Layout::Columns {
+            total_rows = rows;
+        }
+        // if the terminal size has changed...
+        if !same_cols || rows != total_rows {
+            self.resize();
+        }
+    }
+
+    /// Adds a spectrum to the history and draws it on the waterfall
+    /// and the spectrum view. Spectra for a paused pane are dropped.
+    pub fn add_spectrum(&mut self, spec: &[Complex<f32>]) {
+        self.add_spectrum_to(0, spec);
+    }
+
+    /// Like `add_spectrum`, for the stream of pane `pane`. Alerts are only
+    /// raised for the first.
+    pub fn add_spectrum_to(&mut self, pane: usize, spec: &[Complex<f32>]) {
+        if self.panes[pane].paused {
+            return;
+        }
+        self.panes[pane].add_spectrum(&self.settings, spec);
+        if pane == 0 {
+            self.update_alert();
+        }
+        self.redraw_waterfall();
+    }
+
+    /// Like `add_spectrum_to`, for something other than power in each bin,
+    /// already put from 0 at the bottom of the colors to 1 at the top, in
+    /// the FFTs' order.
+    pub fn add_levels_to(&mut self, pane: usize, levels: &[f32]) {
+        if self.panes[pane].paused {
+            return;
+        }
+        let row = shift_levels(levels, self.settings.invert);
+        self.panes[pane].add_row(&self.settings, row);
+        self.redraw_waterfall();
+    }
+
+    /// Adds a pane, for another input, with the first's tuning and history
+    /// length, kept in memory until `set_history`, and `label` marked at the
+    /// start of its waterfall. Returns its number, for `add_spectrum_to` and
+    /// `add_levels_to`.
+    pub fn add_pane(&mut self, label: String) -> usize {
+        let mut pane = Pane::new();
+        pane.center_freq_hz = self.panes[0].center_freq_hz;
+        pane.sample_rate_hz = self.panes[0].sample_rate_hz;
+        pane.history = History::new(self.panes[0].history.max_len());
+        pane.add_mark(label, false);
+        self.panes.push(pane);
+        self.resize();
+        self.panes.len() - 1
+    }
+
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+        self.resize();
+    }
+
+    /// How many columns each pane takes, counting its gutter.
+    fn pane_cols(&self) -> usize {
+        let cols = self.term.size().0;
+        match self.layout {
+            Layout::Stacked => cols,
+            Layout::Columns => cols / self.panes.len(),
+        }
+    }
+
+    fn update_alert(&mut self) {
+        let reading = if self.settings.threshold {
+            self.panes[0].strongest_above(&self.settings, self.settings.threshold_db)
+        } else {
+            None
+        };
+        self.alert = match (reading, self.alert) {
+            (Some((freq_hz, power_db)), Some((_, _, since))) => Some((freq_hz, power_db, since)),
+            (Some((freq_hz, power_db)), None) => Some((freq_hz, power_db, Instant::now())),
+            (None, _) => None,
+        };
+    }
+
+    /// The strongest bin above the threshold in the newest spectrum, its
+    /// frequency in Hz and power in dB, while there is one.
+    pub fn alert(&self) -> Option<(f64, f32)> {
+        self.alert.map(|(freq_hz, power_db, _)| (freq_hz, power_db))
+    }
+
+    /// Renders the waterfalls on a thread of their own from now on, waking
+    /// `waker` when there are some to collect with `collect_rendered`. Until
+    /// they are, the screen keeps the last ones.
+    pub fn render_in_background(&mut self, waker: Waker) {
+        self.renderer = Some(Renderer::new(waker));
+    }
+
+    /// True while the renderer has waterfalls to hand back.
+    pub fn is_rendering(&self) -> bool {
+        self.renderer.as_ref().map_or(false, |r| r.is_busy())
+    }
+
+    /// Shows the waterfalls the renderer has finished, if any.
+    pub fn collect_rendered(&mut self) {
+        let finished = match self.renderer {
+            Some(ref mut renderer) => renderer.take_finished(),
+            None => return,
+        };
+        if finished.is_empty() {
+            return;
+        }
+        // only the panes with new waterfalls
+        for (i, grids) in finished {
+            if let Some(pane) = self.panes.get_mut(i) {
+                pane.set_grids(grids);
+                pane.compose_waterfall(&self.settings);
+            }
+        }
+        if self.is_frame_due() {
+            self.present();
+        } else {
+            self.present_pending = true;
+        }
+    }
+
+    /// Draws at most `fps` frames a second, holding back the ones in between
+    /// until `draw_pending_frame`, and shows an estimate of the terminal
+    /// output in the status bar.
+    pub fn set_max_fps(&mut self, fps: f64) {
+        self.frame_interval = Some(Duration::from_millis((1000.0 / fps) as u64));
+        self.output = Some(OutputMeter { last_frame: Vec::new(), frames: VecDeque::new() });
+    }
+
+    fn is_frame_due(&self) -> bool {
+        self.frame_interval.map_or(true, |interval| self.last_frame_at.elapsed() >= interval)
+    }
+
+    /// How long until a held back frame can be drawn, if there is one.
+    pub fn time_to_next_frame(&self) -> Option<Duration> {
+        if !self.redraw_pending && !self.present_pending {
+            return None;
+        }
+        let interval = self.frame_interval.unwrap_or(Duration::from_secs(0));
+        Some(interval.checked_sub(self.last_frame_at.elapsed()).unwrap_or(Duration::from_secs(0)))
+    }
+
+    /// Draws the frame the cap held back, once it's time. After a terminal
+    /// resize, that's a whole new frame at the new size.
+    pub fn draw_pending_frame(&mut self) {
+        if let Ok(Some(_)) = self.term.poll_resize() {
+            self.resize();
+        }
+        if !self.is_frame_due() {
+            return;
+        }
+        if self.redraw_pending {
+            self.redraw_waterfall();
+        } else if self.present_pending {
+            self.present();
+        }
+    }
+
+    fn redraw_waterfall(&mut self) {
+        if !self.is_frame_due() {
+            self.redraw_pending = true;
+            return;
+        }
+        self.redraw_pending = false;
+        if self.settings.kitty {
+            // quick enough to make here, from the history rather than the cells
+            let images = self.panes.iter().enumerate().flat_map(|(i, pane)| {
+                render::render_images(&pane.waterfall_job(&self.settings, i))
+            });
+            self.images = Some(images.collect());
+        }
+        match self.renderer {
+            Some(ref mut renderer) => {
+                // the last waterfalls are shown until the new ones are ready
+                for (i, pane) in self.panes.iter_mut().enumerate() {
+                    renderer.submit(pane.waterfall_job(&self.settings, i));
+                    pane.compose_waterfall(&self.settings);
+                }
+            }
+            None => {
+                for pane in &mut self.panes {
+                    pane.draw_waterfall(&self.settings);
+                }
+            }
+        }
+        self.present();
+    }
+
+    /// Puts everything on the terminal. Only the views that have changed are
+    /// put on its cells, unlike whatever goes over them, which the terminal
+    /// then sends only the changed cells of.
+    fn present(&mut self) {
+        let overlaid = self.decoded.is_some() || self.scope.is_some() || self.help.is_some() ||
+                       self.picker.is_some() || self.prompt.is_some();
+        // what went over the views last time has to be covered up again
+        let all = self.cells_stale || overlaid || self.overlaid;
+        for pane in &mut self.panes {
+            pane.draw_into(&mut self.term, all);
+        }
+        self.cells_stale = false;
+        self.overlaid = overlaid;
+        self.draw_status_bar();
+        self.status_bar.draw_into(&mut self.term);
+        if let Some((ref title, ref text)) = self.decoded {
+            draw_text_pane(title, text, self.term.size(), self.settings.ascii)
+                .draw_into(&mut self.term);
+        }
+        if let Some(ref samples) = self.scope {
+            draw_scope(samples, self.term.size(), self.settings.ascii).draw_into(&mut self.term);
+        }
+        if let Some(ref help) = self.help {
+            help.draw_into(&mut self.term);
+        }
+        if let Some(ref picker) = self.picker {
+            let mut lines = vec![picker.title.clone(), String::new()];
+            lines.extend(picker.items.iter().map(|item| format!("  {}", item)));
+            lines.push(String::new());
+            lines.push(picker.hint.clone());
+            draw_overlay(&lines, self.term.size(), self.settings.ascii, Some(picker.selected + 2))
+                .draw_into(&mut self.term);
+        }
+        if let Some(ref prompt) = self.prompt {
+            let lines = vec![prompt.title.clone(), String::new(), format!("> {}_", prompt.text),
+                             String::new(), prompt.hint.clone()];
+            draw_overlay(&lines, self.term.size(), self.settings.ascii, None)
+                .draw_into(&mut self.term);
+        }
+        if let Some(ref mut output) = self.output {
+            output.add_frame(self.term.cellvec(), self.term.size().0);
+        }
+        self.term.present().unwrap();
+        if let Some(ref stats) = self.stats {
+            stats.add_frame_drawn();
+        }
+        if let Some(images) = self.images.take() {
+            let waterfalls = self.panes.iter().flat_map(|pane| &pane.bands)
+                .map(|band| &band.waterfall);
+            let placed: Vec<_> = images.iter().zip(waterfalls).collect();
+            if let Err(e) = kitty::show(&placed) {
+                self.notice = Some(format!("Couldn't draw the waterfall images: {}", e));
+            }
+        }
+        self.last_frame_at = Instant::now();
+        self.present_pending = false;
+
+        self.check_and_resize();
+    }
+
+    fn draw_status_bar(&mut self) {
+        let pane = &self.panes[0];
+        let num_bins = pane.history.front().map_or(self.get_spectrum_width(), |s| s.len());
+        let mut status = format!("{}Hz  span {}Hz  RBW {}Hz",
+                                 format_frequency(pane.center_freq_hz),
+                                 format_frequency(pane.sample_rate_hz),
+                                 format_frequency(pane.sample_rate_hz / num_bins as f64));
+        if let Some(radio) = self.radio {
+            status.push_str(&format!("  {} FFT/s", radio.fft_rate_hz));
+        }
+        if self.settings.average > 1 {
+            status.push_str(&format!("  avg {}", self.settings.average));
+        } else {
+            status.push_str("  avg off");
+        }
+        if let Some(radio) = self.radio {
+            status.push_str(&format!("  LNA {} VGA {}{}", radio.lna_gain_db, radio.vga_gain_db,
+                                     if radio.amp { " amp" } else { "" }));
+            if let Some(ref stats) = self.stats {
+                status.push_str(&format!("  {}", stats.snapshot()));
+            }
+            if self.overruns > 0 {
+                status.push_str(&format!("  overruns {}", self.overruns));
+            }
+        }
+
+        if let Some((freq_hz, power_db)) = self.marker_reading() {
+            status.push_str(&format!("  M {}Hz {:.1} dB", format_frequency(freq_hz), power_db));
+        }
+        if let Some(ref listening) = self.listening {
+            status.push_str(&format!("  {}", listening));
+        }
+        if let Some(ref output) = self.output {
+            let rate = output.bytes_per_second() as f64;
+            status.push_str(&format!("  out {}B/s", format_frequency(rate)));
+        }
+
+        let (cols, rows) = self.status_bar.size();
+        self.status_bar.clear(Cell::default());
+        if rows == 0 {
+            return;
+        }
+        // an alert goes at the very end, then a notice or the hints when
+        // there's room for them
+        let alert = self.alert.map(|(freq_hz, power_db, since)| {
+            (format!(" ALERT {}Hz {:.1} dB ", format_frequency(freq_hz), power_db), since)
+        });
+        let alert_len = alert.as_ref().map_or(0, |&(ref text, _)| text.chars().count());
+        let len = status.chars().count();
+        let hints_len = KEY_HINTS.chars().count();
+        if self.notice.is_none() && alert.is_none() && len + 2 + hints_len <= cols {
+            self.status_bar.printline_with_cell(cols - hints_len, 0, KEY_HINTS,
+                Cell::new(' ', Color::Cyan, Color::Default, Attr::Default));
+        }
+        let status: String = status.chars().take(cols).collect();
+        self.status_bar.printline_with_cell(0, 0, &status,
+            Cell::new(' ', Color::White, Color::Default, Attr::Bold));
+        if let Some(ref notice) = self.notice {
+            let notice: String = notice.chars().take(cols.saturating_sub(alert_len)).collect();
+            let start = cols - alert_len.min(cols) - notice.chars().count();
+            self.status_bar.printline_with_cell(start, 0, &notice,
+                Cell::new(' ', Color::Yellow, Color::Default, Attr::Bold));
+        }
+        if let Some((alert, since)) = alert {
+            // flashes for as long as it lasts, for someone glancing over
+            let alert: String = alert.chars().take(cols).collect();
+            let cell = if since.elapsed().subsec_nanos() < 500_000_000 {
+                Cell::new(' ', Color::White, Color::Red, Attr::Bold)
+            } else {
+                Cell::new(' ', Color::Red, Color::Default, Attr::Bold)
+            };
+            self.status_bar.printline_with_cell(cols - alert.chars().count(), 0, &alert, cell);
+        }
+    }
+
+    /// Shows `notice` at the end of the status bar, over the key hints, or
+    /// clears it.
+    pub fn set_notice(&mut self, notice: Option<String>) {
+        if notice == self.notice {
+            return;
+        }
+        self.notice = notice;
+        self.redraw_waterfall();
+    }
+
+    /// Draws an overlay listing `keys`, pairs of key and what it does, and the
+    /// current settings, until `hide_help` is called.
+    pub fn show_help(&mut self, keys: &[(&str, &str)]) {
+        let mut lines = vec!["Keys".to_string()];
+        for &(key, action) in keys {
+            lines.push(format!("  {:<10} {}", key, action));
+        }
+
+        let settings = &self.settings;
+        lines.push(String::new());
+        lines.push("Settings".to_string());
+        let waterfall = if settings.ascii {
+            "ASCII"
+        } else if settings.kitty {
+            "kitty graphics"
+        } else if settings.braille_waterfall {
+            "braille"
+        } else {
+            "half blocks"
+        };
+        lines.push(format!("  Palette    {}", settings.palette));
+        lines.push(format!("  Spectrum   {}, {}{}{}",
+                           if settings.persistence { "persistence" } else { "newest" },
+                           settings.spectrum_style,
+                           if settings.glyphs == Glyphs::Blocks { ", blocks" } else { "" },
+                           if settings.invert { ", inverted" } else { "" }));
+        lines.push(format!("  Waterfall  {}, {} scale{}", waterfall,
+                           if settings.normalize_rows { "per row" } else { "global" },
+                           if settings.compress_history { ", compressed" } else { "" }));
+        lines.push(format!("  Range      {} to {} dB", settings.min_db, settings.max_db));
+        if settings.threshold {
+            lines.push(format!("  Threshold  {} dB", settings.threshold_db));
+        } else {
+            lines.push("  Threshold  off".to_string());
+        }
+        let traces: Vec<String> = settings.traces.iter().map(|t| t.to_string()).collect();
+        lines.push(format!("  Traces     {}",
+                           if traces.is_empty() { "none".to_string() } else { traces.join(", ") }));
+        if settings.average > 1 {
+            lines.push(format!("  Averaging  {} spectra", settings.average));
+        } else {
+            lines.push("  Averaging  off".to_string());
+        }
+        if settings.show_peaks {
+            lines.push(format!("  Peaks      up to {}", settings.num_peaks));
+        } else {
+            lines.push("  Peaks      hidden".to_string());
+        }
+        lines.push(format!("  DC bin     {}", if settings.exclude_dc { "excluded" } else { "shown" }));
+        lines.push(format!("  Bands      {}", settings.num_bands));
+        if settings.freq_offset_hz != 0.0 {
+            lines.push(format!("  Converter  {}Hz shift",
+                               format_frequency(settings.freq_offset_hz)));
+        }
+        lines.push(format!("  Bookmarks  {}", settings.bookmarks.len()));
+        if let Some(radio) = self.radio {
+            lines.push(format!("  FFT rate   {}/s", radio.fft_rate_hz));
+            lines.push(format!("  Gains      LNA {} dB, VGA {} dB, amp {}", radio.lna_gain_db,
+                               radio.vga_gain_db, if radio.amp { "on" } else { "off" }));
+        }
+        lines.push(String::new());
+        lines.push("Press any key to close.".to_string());
+
+        self.help = Some(draw_overlay(&lines, self.term.size(), settings.ascii, None));
+        self.redraw_waterfall();
+    }
+
+    pub fn is_help_shown(&self) -> bool {
+        self.help.is_some()
+    }
+
+    pub fn hide_help(&mut self) {
+        self.help = None;
+        self.redraw_waterfall();
+    }
+
+    /// Draws an overlay for picking one of `items`, starting at `selected`,
+    /// with `hint` below them, until `hide_picker` is called.
+    pub fn show_picker(&mut self, title: &str, items: Vec<String>, selected: usize,
+                       hint: &str) {
+        self.picker = Some(Picker {
+            title: title.to_string(),
+            items: items,
+            selected: selected,
+            hint: hint.to_string(),
+        });
+        self.redraw_waterfall();
+    }
+
+    pub fn is_picker_shown(&self) -> bool {
+        self.picker.is_some()
+    }
+
+    /// Moves the picker's selection by `items`, stopping at the ends.
+    pub fn move_picker(&mut self, items: isize) {
+        if let Some(ref mut picker) = self.picker {
+            let last = picker.items.len().saturating_sub(1) as isize;
+            picker.selected = (picker.selected as isize + items).max(0).min(last) as usize;
+        }
+        self.redraw_waterfall();
+    }
+
+    /// Closes the picker, returning the index of the item it had selected.
+    pub fn hide_picker(&mut self) -> Option<usize> {
+        let selected = self.picker.take().map(|picker| picker.selected);
+        self.redraw_waterfall();
+        selected
+    }
+
+    /// Draws an overlay for typing into, starting with `text`, with `hint`
+    /// below it, until `hide_prompt` is called.
+    pub fn show_prompt(&mut self, title: &str, text: &str, hint: &str) {
+        self.prompt = Some(Prompt {
+            title: title.to_string(),
+            text: text.to_string(),
+            hint: hint.to_string(),
+        });
+        self.redraw_waterfall();
+    }
+
+    /// The title of the prompt, if one is shown, which says what it's for.
+    pub fn prompt_title(&self) -> Option<&str> {
+        self.prompt.as_ref().map(|prompt| &prompt.title[..])
+    }
+
+    /// Adds `c` to the end of the prompt's text, or with `None` takes the last
+    /// character off.
+    pub fn edit_prompt(&mut self, c: Option<char>) {
+        if let Some(ref mut prompt) = self.prompt {
+            match c {
+                Some(c) => prompt.text.push(c),
+                None => {
+                    prompt.text.pop();
+                }
+            }
+        }
+        self.redraw_waterfall();
+    }
+
+    /// Closes the prompt, returning what was typed.
+    pub fn hide_prompt(&mut self) -> Option<String> {
+        let text = self.prompt.take().map(|prompt| prompt.text);
+        self.redraw_waterfall();
+        text
+    }
+
+    /// Draws the waterfall as images with the kitty graphics protocol, if the
+    /// terminal answers that it supports it, until the canvas is dropped.
+    pub fn enable_kitty(&mut self) -> io::Result<bool> {
+        if !try!(kitty::query(&mut self.term)) {
+            return Ok(false);
+        }
+        self.settings.kitty = true;
+        self.redraw_waterfall();
+        Ok(true)
+    }
+
+    /// Has the terminal report clicks and the wheel as keys, until the canvas
+    /// is dropped.
+    pub fn enable_mouse(&mut self) -> io::Result<()> {
+        try!(set_mouse_reporting(true));
+        self.mouse = true;
+        Ok(())
+    }
+
+    /// The pane shown at a cell of the terminal, if it's in a spectrum view,
+    /// and the bin of the pane's spectra under it.
+    fn spectrum_bin_at(&self, col: usize, row: usize) -> Option<(usize, usize)> {
+        for (p, pane) in self.panes.iter().enumerate() {
+            let len = match pane.history.front() {
+                Some(spec) => spec.len(),
+                None => continue,
+            };
+            let band_len = len / pane.bands.len();
+            for (i, band) in pane.bands.iter().enumerate() {
+                let (x, y) = band.spectrum.origin();
+                let (cols, rows) = band.spectrum.size();
+                if col >= x && col < x + cols && row >= y && row < y + rows {
+                    return Some((p, min(i * band_len + (col - x) * band_len / cols, len - 1)));
+                }
+            }
+        }
+        None
+    }
+
+    /// Focuses the pane shown at a cell of a spectrum view, with its marker on
+    /// the bin there. Returns whether there was a spectrum there.
+    pub fn place_marker_at(&mut self, col: usize, row: usize) -> bool {
+        let (p, bin) = match self.spectrum_bin_at(col, row) {
+            Some(at) => at,
+            None => return false,
+        };
+        self.focus = p;
+        {
+            let pane = &mut self.panes[p];
+            pane.marker = Some(bin);
+            pane.draw_spectrum(&self.settings);
+        }
+        self.redraw_waterfall();
+        true
+    }
+
+    /// The frequency, in Hz, shown at a cell of a spectrum view.
+    pub fn frequency_at(&self, col: usize, row: usize) -> Option<f64> {
+        self.spectrum_bin_at(col, row).map(|(p, bin)| {
+            let pane = &self.panes[p];
+            let len = pane.history.front().map_or(0, |spec| spec.len());
+            bin_frequency(bin, len, pane.center_freq_hz, pane.sample_rate_hz)
+        })
+    }
+
+    /// Puts a marker on the focused pane's strongest bin, or removes it.
+    pub fn toggle_marker(&mut self) {
+        {
+            let pane = &mut self.panes[self.focus];
+            pane.marker = match pane.marker {
+                None => pane.newest.iter()
+                                   .enumerate()
+                                   .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+                                   .map(|(i, _)| i),
+                Some(_) => None,
+            };
+            pane.draw_spectrum(&self.settings);
+        }
+        self.redraw_waterfall();
+    }
+
+    /// Moves the focused pane's marker by `bins`, stopping at the edges.
+    pub fn move_marker(&mut self, bins: isize) {
+        {
+            let pane = &mut self.panes[self.focus];
+            let len = pane.history.front().map_or(0, |spec| spec.len()) as isize;
+            pane.marker = pane.marker.map(|m| min(max(m as isize + bins, 0), len - 1) as usize);
+            pane.draw_spectrum(&self.settings);
+        }
+        self.redraw_waterfall();
+    }
+
+    /// The frequency, in Hz, and power, in dB, at the focused pane's marker.
+    pub fn marker_reading(&self) -> Option<(f64, f32)> {
+        self.panes[self.focus].marker_reading(&self.settings)
+    }
+
+    /// Shows what's being listened to in the status bar, like `NFM 12.5kHz`.
+    pub fn set_listening(&mut self, listening: Option<String>) {
+        self.listening = listening;
+    }
+
+    /// Shows text decoded from what's being listened to, under a title, or
+    /// hides it.
+    pub fn set_decoded(&mut self, decoded: Option<(String, String)>) {
+        self.decoded = decoded;
+    }
+
+    /// Shows the IQ scope of `samples`, or hides it.
+    pub fn set_scope(&mut self, samples: Option<Vec<Complex<i8>>>) {
+        self.scope = samples;
+    }
+
+    /// Shows the receiver's settings in the status bar.
+    pub fn set_radio_settings(&mut self, radio: RadioSettings) {
+        self.radio = Some(radio);
+    }
+
+    /// Counts the frames drawn in `stats`, and shows them in the status bar
+    /// with what the processing thread counts there.
+    pub fn set_stats(&mut self, stats: Arc<Stats>) {
+        self.stats = Some(stats);
+    }
+
+    /// Sets the count of sample buffers dropped on the way from the radio
+    /// because processing fell behind, shown in the status bar once there
+    /// are any.
+    pub fn set_overruns(&mut self, overruns: usize) {
+        self.overruns = overruns;
+    }
+
+    /// Shows `msg` on an otherwise blank screen, for while there's nothing
+    /// else to draw.
+    pub fn show_message(&mut self, msg: &str) {
+        self.cells_stale = true;
+        self.term.clear(Cell::default());
+        self.term.printline(0, 0, msg);
+        self.term.present().unwrap();
+    }
+
+    /// Wraps the span across `num_bands` stacked rows, each showing a
+    /// `1 / num_bands` slice of it at full width.
+    pub fn set_num_bands(&mut self, num_bands: usize) {
+        self.settings.num_bands = max(num_bands, 1);
+        self.resize();
+    }
+
+    /// Moves the focus, which pause and scrolling apply to, to the next pane.
+    pub fn cycle_focus(&mut self) {
+        self.focus = (self.focus + 1) % self.panes.len();
+    }
+
+    /// While a pane is paused new spectra aren't added to it, and its
+    /// waterfall can be scrolled back through the stored history.
+    pub fn toggle_pause(&mut self) {
+        {
+            let pane = &mut self.panes[self.focus];
+            pane.paused = !pane.paused;
+            pane.scroll = 0;
+            pane.selection = None;
+            pane.zoom = None;
+        }
+        self.redraw_waterfall();
+    }
+
+    /// Starts selecting a region of the focused pane's waterfall, from the
+    /// middle of it. Returns false, doing nothing, unless the pane is paused.
+    pub fn start_selection(&mut self) -> bool {
+        {
+            let settings = &self.settings;
+            let pane = &mut self.panes[self.focus];
+            if !pane.paused || pane.history.is_empty() {
+                return false;
+            }
+            let (cols, rows) = pane.waterfall_size();
+            let spectra_per_row = settings.spectra_per_waterfall_row();
+            let time = min(pane.scroll + rows / 2 * spectra_per_row, pane.history.len() - 1);
+            let bin = cols * pane.bands.len() / 2 * 2;
+            let start = (time - time % spectra_per_row, bin);
+            pane.zoom = None;
+            pane.selection = Some(Selection { anchor: start, cursor: start });
+        }
+        self.redraw_waterfall();
+        true
+    }
+
+    pub fn is_selecting(&self) -> bool {
+        self.panes[self.focus].selection.is_some()
+    }
+
+    /// Moves the corner of the selection by `cols` columns and `rows` rows of
+    /// the waterfall, positive being right and down, within what's visible.
+    pub fn move_selection(&mut self, cols: isize, rows: isize) {
+        {
+            let settings = &self.settings;
+            let pane = &mut self.panes[self.focus];
+            let (num_cols, num_rows) = pane.waterfall_size();
+            let spectra_per_row = settings.spectra_per_waterfall_row();
+            let len = pane.history.front().map_or(0, |spec| spec.len());
+            let first = pane.scroll as isize;
+            let last = min(pane.scroll + num_rows * spectra_per_row, pane.history.len()) as isize;
+            let num_bins = min(2 * num_cols * pane.bands.len(), len) as isize;
+            // down is back in time, unless the waterfall scrolls up
+            let rows = if settings.scroll_up { -rows } else { rows };
+            if let Some(ref mut selection) = pane.selection {
+                let (time, bin) = selection.cursor;
+                let time = time as isize + rows * spectra_per_row as isize;
+                let bin = bin as isize + 2 * cols;
+                selection.cursor = (min(max(time, first), last - 1) as usize,
+                                    min(max(bin, 0), num_bins - 1) as usize);
+            }
+        }
+        self.redraw_waterfall();
+    }
+
+    /// Stretches the selected region over the focused pane's waterfall.
+    pub fn zoom_to_selection(&mut self) {
+        {
+            let settings = &self.settings;
+            let pane = &mut self.panes[self.focus];
+            if let Some(selection) = pane.selection.take() {
+                pane.zoom = Some(pane.selection_region(settings, selection));
+            }
+        }
+        self.redraw_waterfall();
+    }
+
+    /// Drops the selection, or if there isn't one, the zoom.
+    pub fn cancel_selection(&mut self) {
+        {
+            let pane = &mut self.panes[self.focus];
+            if pane.selection.take().is_none() {
+                pane.zoom = None;
+            }
+        }
+        self.redraw_waterfall();
+    }
+
+    /// The powers in the focused pane's selection, or its zoomed region.
+    pub fn region_data(&self) -> Option<RegionData> {
+        let pane = &self.panes[self.focus];
+        let region = match (pane.selection, pane.zoom) {
+            (Some(selection), _) => pane.selection_region(&self.settings, selection),
+            (None, Some(region)) => region,
+            (None, None) => return None,
+        };
+
+        let len = pane.history.front().map_or(0, |spec| spec.len());
+        let (min_db, max_db) = (self.settings.min_db, self.settings.max_db);
+        let freqs_hz = (region.bins.0..region.bins.1 + 1).map(|bin| {
+            bin_frequency(bin, len, pane.center_freq_hz, pane.sample_rate_hz)
+        }).collect();
+        let spectra = (region.time.0..region.time.1 + 1).map(|t| {
+            let row = pane.history.get(t);
+            let powers = (region.bins.0..region.bins.1 + 1).map(|bin| {
+                row.as_ref().and_then(|row| row.power(bin))
+                   .map_or(::std::f32::NAN, |x| min_db + x * (max_db - min_db))
+            }).collect();
+            (t, powers)
+        }).collect();
+        Some(RegionData { freqs_hz: freqs_hz, spectra: spectra })
+    }
+
+    /// What's on the screen, the cells row by row and how many to a row.
+    pub fn screen(&self) -> (&[Cell], usize) {
+        (self.term.cellvec(), self.term.size().0)
+    }
+
+    /// Up to `num_spectra` of the focused pane's spectra, for drawing them
+    /// elsewhere. `None` until there's one.
+    pub fn snapshot(&self, num_spectra: usize) -> Option<Snapshot> {
+        let pane = &self.panes[self.focus];
+        let spectra: Vec<_> = pane.history.iter_from(pane.scroll).take(num_spectra)
+                                  .map(|row| Arc::new(row.powers())).collect();
+        if spectra.is_empty() {
+            return None;
+        }
+        Some(Snapshot {
+            center_freq_hz: pane.center_freq_hz,
+            sample_rate_hz: pane.sample_rate_hz,
+            min_db: self.settings.min_db,
+            max_db: self.settings.max_db,
+            palette: self.settings.palette,
+            spectra: spectra,
+        })
+    }
+
+    /// Draws with plain ASCII characters and no 256-color palette, for
+    /// terminals or fonts without braille glyphs.
+    pub fn set_ascii(&mut self, ascii: bool) {
+        self.settings.ascii = ascii;
+    }
+
+    /// Draws the spectrum view with `glyphs` when not in ASCII.
+    pub fn set_glyphs(&mut self, glyphs: Glyphs) {
+        self.settings.glyphs = glyphs;
+    }
+
+    /// Sets the powers, in dB, at the bottom and top of the spectrum view,
+    /// which also span the waterfall's colors.
+    pub fn set_db_range(&mut self, min_db: f32, max_db: f32) {
+        self.settings.min_db = min_db;
+        self.settings.max_db = max_db;
+    }
+
+    pub fn get_db_range(&self) -> (f32, f32) {
+        (self.settings.min_db, self.settings.max_db)
+    }
+
+    /// Moves the bottom and top of the scale by `db`, for the spectra from now
+    /// on.
+    pub fn shift_db_range(&mut self, db: f32) {
+        self.settings.min_db += db;
+        self.settings.max_db += db;
+    }
+
+    /// Draws a line at `threshold_db` on the spectrum view and raises an alert
+    /// whenever a bin of the newest spectrum is above it.
+    pub fn set_threshold(&mut self, threshold_db: f32) {
+        self.settings.threshold = true;
+        self.settings.threshold_db = threshold_db;
+        self.redraw_threshold();
+    }
+
+    /// Shows or hides the threshold line, returning whether it's shown.
+    pub fn toggle_threshold(&mut self) -> bool {
+        self.settings.threshold = !self.settings.threshold;
+        self.redraw_threshold();
+        self.settings.threshold
+    }
+
+    /// Moves the threshold up by `db`, showing it if it wasn't, and returns
+    /// where it is.
+    pub fn move_threshold(&mut self, db: f32) -> f32 {
+        self.settings.threshold = true;
+        self.settings.threshold_db += db;
+        self.redraw_threshold();
+        self.settings.threshold_db
+    }
+
+    fn redraw_threshold(&mut self) {
+        self.update_alert();
+        for pane in &mut self.panes {
+            pane.draw_spectrum(&self.settings);
+        }
+        self.redraw_waterfall();
+    }
+
+    /// Marks another receiver's passband, its lowest and highest frequency
+    /// as shown, on the spectrum view, or stops marking it.
+    pub fn set_passband(&mut self, passband: Option<(f64, f64)>) {
+        self.settings.passband = passband;
+        for pane in &mut self.panes {
+            pane.draw_spectrum(&self.settings);
+        }
+        self.redraw_waterfall();
+    }
+
+    /// Smooths the spectrum with a running average over about `num_spectra`
+    /// spectra, so weak signals stand out of the noise. 1 turns it off.
+    pub fn set_average(&mut self, num_spectra: usize) {
+        self.settings.average = max(num_spectra, 1);
+        for pane in &mut self.panes {
+            pane.average.clear();
+        }
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.settings.palette = palette;
+    }
+
+    /// Draws the waterfall with braille dots, a bin per dot column, rather
+    /// than averaging pairs of bins into half blocks.
+    pub fn set_braille_waterfall(&mut self, braille: bool) {
+        self.settings.braille_waterfall = braille;
+    }
+
+    /// Scales each waterfall row by its own range, so weak signals show up
+    /// next to a strong one. Peaks and the marker still use the real powers.
+    pub fn set_normalize_rows(&mut self, normalize: bool) {
+        self.settings.normalize_rows = normalize;
+    }
+
+    pub fn toggle_normalize_rows(&mut self) {
+        self.settings.normalize_rows = !self.settings.normalize_rows;
+        self.redraw_waterfall();
+    }
+
+    /// Averages older spectra of the waterfall together, two at a time for
+    /// the second half of it, four for the next quarter and so on, so recent
+    /// detail and a long stretch of time fit on one screen.
+    pub fn set_compress_history(&mut self, compress: bool) {
+        self.settings.compress_history = compress;
+    }
+
+    pub fn toggle_compress_history(&mut self) {
+        self.settings.compress_history = !self.settings.compress_history;
+        self.redraw_waterfall();
+    }
+
+    /// Keeps `len` spectra of each pane for scrolling back through, all but
+    /// the newest few thousand in a file under `spill_dir` if it's given.
+    /// What's kept so far is let go.
+    pub fn set_history(&mut self, len: usize, spill_dir: Option<&Path>) -> io::Result<()> {
+        for pane in &mut self.panes {
+            pane.history = match spill_dir {
+                Some(dir) => try!(History::spilled(len, dir)),
+                None => History::new(len),
+            };
+            pane.added_at.clear();
+            pane.scroll = 0;
+        }
+        self.redraw_waterfall();
+        Ok(())
+    }
+
+    /// Prints the time of every few waterfall rows in a gutter on its right,
+    /// taking the columns from the spectrum and the waterfall.
+    pub fn set_timestamps(&mut self, timestamps: bool) {
+        self.settings.timestamps = timestamps;
+        self.resize();
+    }
+
+    pub fn toggle_timestamps(&mut self) -> bool {
+        let timestamps = !self.settings.timestamps;
+        self.set_timestamps(timestamps);
+        self.redraw_waterfall();
+        timestamps
+    }
+
+    /// Adds waterfall rows at the bottom, so it scrolls up as in SDR#, rather
+    /// than at the top.
+    pub fn set_scroll_up(&mut self, scroll_up: bool) {
+        self.settings.scroll_up = scroll_up;
+    }
+
+    pub fn toggle_scroll_up(&mut self) -> bool {
+        self.settings.scroll_up = !self.settings.scroll_up;
+        self.redraw_waterfall();
+        self.settings.scroll_up
+    }
+
+    pub fn toggle_braille_waterfall(&mut self) {
+        self.settings.braille_waterfall = !self.settings.braille_waterfall;
+        self.redraw_waterfall();
+    }
+
+    /// Switches to the next waterfall palette, redrawing the visible history with it.
+    pub fn cycle_palette(&mut self) {
+        self.settings.palette = self.settings.palette.next();
+        self.redraw_waterfall();
+    }
+
+    /// True if every pane is paused, so there's nothing to draw new spectra on.
+    pub fn is_paused(&self) -> bool {
+        self.panes.iter().all(|pane| pane.paused)
+    }
+
+    /// Scrolls the focused pane's waterfall by `pages` screens, positive being
+    /// further back in time. Does nothing unless the pane is paused.
+    pub fn scroll_waterfall(&mut self, pages: isize) {
+        if !self.panes[self.focus].paused {
+            return;
+        }
+        self.panes[self.focus].scroll_waterfall(&self.settings, pages);
+        self.redraw_waterfall();
+    }
+
+    /// When set, the DC bin is replaced by the average of its neighbours before
+    /// anything else sees the spectrum, so the LO leakage spike doesn't show up
+    /// as a signal.
+    pub fn set_exclude_dc(&mut self, exclude: bool) {
+        self.settings.exclude_dc = exclude;
+    }
+
+    /// Sets the frequency range covered by the spectrum, used for labels.
+    /// Takes the radio's tuning, which is shown less the converter's offset.
+    /// Every pane shows the same frequencies.
+    pub fn set_tuning(&mut self, center_freq_hz: f64, sample_rate_hz: f64) {
+        for pane in &mut self.panes {
+            pane.center_freq_hz = center_freq_hz - self.settings.freq_offset_hz;
+            pane.sample_rate_hz = sample_rate_hz;
+        }
+    }
+
+    /// Like `set_tuning`, for a change while running, which is marked on the
+    /// waterfall with the new center frequency.
+    pub fn retune(&mut self, center_freq_hz: f64, sample_rate_hz: f64) {
+        for pane in &mut self.panes {
+            pane.retune(center_freq_hz - self.settings.freq_offset_hz, sample_rate_hz);
+        }
+        self.redraw_waterfall();
+    }
+
+    /// Labels the waterfall where the next spectrum will go, for something
+    /// that happened in between, like an outage.
+    /// Shows frequencies as they are ahead of a converter that shifts them by
+    /// `offset_hz` on their way to the radio, up for an upconverter and down
+    /// for a downconverter.
+    pub fn set_freq_offset(&mut self, offset_hz: f64) {
+        for pane in &mut self.panes {
+            pane.center_freq_hz += self.settings.freq_offset_hz - offset_hz;
+        }
+        self.settings.freq_offset_hz = offset_hz;
+    }
+
+    pub fn freq_offset(&self) -> f64 {
+        self.settings.freq_offset_hz
+    }
+
+    pub fn set_invert(&mut self, invert: bool) {
+        self.settings.invert = invert;
+    }
+
+    /// Flips the spectra from now on, the traces and average starting over
+    /// rather than mixing the two. Returns whether they're flipped.
+    pub fn toggle_invert(&mut self) -> bool {
+        self.settings.invert = !self.settings.invert;
+        for pane in &mut self.panes {
+            pane.traces.clear();
+            pane.average.clear();
+        }
+        let label = if self.settings.invert { "inverted" } else { "upright" };
+        self.mark_waterfall(label.to_string());
+        self.settings.invert
+    }
+
+    pub fn mark_waterfall(&mut self, label: String) {
+        self.panes[0].add_mark(label, false);
+        self.redraw_waterfall();
+    }
+
+    pub fn set_bookmarks(&mut self, bookmarks: Vec<Bookmark>) {
+        self.settings.bookmarks = bookmarks;
+        self.resize();
+    }
+
+    pub fn add_bookmarks(&mut self, bookmarks: Vec<Bookmark>) {
+        self.settings.bookmarks.extend(bookmarks);
+        self.resize();
+    }
+
+    pub fn get_bookmarks(&self) -> &[Bookmark] {
+        &self.settings.bookmarks
+    }
+
+    /// Sets the maximum number of peaks that get a frequency label.
+    pub fn set_num_peaks(&mut self, num_peaks: usize) {
+        self.settings.num_peaks = num_peaks;
+    }
+
+    pub fn toggle_peaks(&mut self) {
+        self.settings.show_peaks = !self.settings.show_peaks;
+    }
+
+    /// Switches the live trace to the next style, returning it.
+    pub fn cycle_spectrum_style(&mut self) -> SpectrumStyle {
+        self.settings.spectrum_style = self.settings.spectrum_style.next();
+        for pane in &mut self.panes {
+            pane.draw_spectrum(&self.settings);
+        }
+        self.redraw_waterfall();
+        self.settings.spectrum_style
+    }
+
+    /// Shows or hides `trace`, returning whether it's shown. The average and
+    /// holds start again from the next spectrum when shown.
+    pub fn toggle_trace(&mut self, trace: Trace) -> bool {
+        let shown = !self.settings.traces.contains(&trace);
+        let traces = &self.settings.traces;
+        self.settings.traces = TRACES.iter()
+            .filter(|&&t| if t == trace { shown } else { traces.contains(&t) })
+            .cloned()
+            .collect();
+        for pane in &mut self.panes {
+            pane.traces.hide(trace);
+            pane.draw_spectrum(&self.settings);
+        }
+        self.redraw_waterfall();
+        shown
+    }
+
+    /// Shows a fading record of where the spectrum has been in the spectrum
+    /// view, colored by how often, so rare transients and hopping signals
+    /// stand out.
+    pub fn set_persistence(&mut self, persistence: bool) {
+        self.settings.persistence = persistence;
+    }
+
+    pub fn toggle_persistence(&mut self) {
+        self.settings.persistence = !self.settings.persistence;
+        for pane in &mut self.panes {
+            // starts from the newest spectrum rather than a stale record
+            for band in &mut pane.bands {
+                band.persistence.clear();
+            }
+            if self.settings.persistence {
+                pane.add_persistence();
+            }
+            pane.draw_spectrum(&self.settings);
+        }
+        self.redraw_waterfall();
+    }
+
+    pub fn get_term(&mut self) -> &mut B {
+        &mut self.term
+    }
+
+    /// The terminal's columns and rows.
+    pub fn size(&self) -> (usize, usize) {
+        self.term.size()
+    }
+
+    /// Of each pane, the same for all of them.
+    pub fn get_spectrum_width(&self) -> usize {
+        let cols = self.pane_cols();
+        2 * (cols - self.settings.gutter_cols(cols)) * self.settings.num_bands
+    }
+}
+
+impl<B: TerminalBackend> Drop for Canvas<B> {
+    fn drop(&mut self) {
+        // left on, the shell would get the escape sequences
+        if self.mouse {
+            let _ = set_mouse_reporting(false);
+        }
+        // the images would stay on the screen after it
+        if self.settings.kitty {
+            let _ = kitty::clear();
+        }
+    }
+}
+
+/// A border like `Painter::draw_box`, in ASCII.
+fn draw_ascii_box<T>(canvas: &mut T)
+    where T: CellAccessor + HasSize
+{
+    let (cols, rows) = canvas.size();
+    for x in 0..cols {
+        for &y in &[0, rows - 1] {
+            canvas.get_mut(x, y).unwrap().set_ch(if x == 0 || x == cols - 1 { '+' } else { '-' });
+        }
+    }
+    for y in 1..rows - 1 {
+        for &x in &[0, cols - 1] {
+            canvas.get_mut(x, y).unwrap().set_ch('|');
+        }
+    }
+}
+
+/// Average of the pair of bins shown in column `col`, or `None` past the end
+/// of the spectrum.
+fn bin_pair_average(spec: &[f32], col: usize) -> Option<f32> {
+    match (spec.get(2 * col), spec.get(2 * col + 1)) {
+        (Some(&a), Some(&b)) => Some((a + b) / 2.0),
+        (Some(&a), None) => Some(a),
+        _ => None,
+    }
+}
+
+/// Draws two spectra per row with half blocks, averaging each pair of bins
+/// since the waterfall has half the horizontal resolution of the spectrum view.
+pub fn draw_waterfall<'a, T, I>(canvas: &mut T, spectra: I, palette: Palette)
+    where T: CellAccessor + HasSize,
+          I: Iterator<Item = &'a [f32]>
+{
+    canvas.clear(Cell::default());
+    let (cols, rows) = canvas.size();
+    for (row, mut specs) in (0..rows).zip(&spectra.chunks_lazy(2)) {
+        let upper = specs.next();
+        let lower = specs.next();
+        for c in 0..cols {
+            let u = upper.and_then(|s| bin_pair_average(s, c));
+            let l = lower.and_then(|s| bin_pair_average(s, c));
+            if u.is_none() && l.is_none() {
+                break;
+            }
+            *canvas.get_mut(c, row).unwrap() = spectrum_heights_to_waterfall_cell(
+                u.unwrap_or(0.0), l.unwrap_or(0.0), palette);
+        }
+    }
+}
+
+/// Draws four spectra per row with braille dots, one bin per dot column. Dots
+/// brighter than the middle of the cell's range are lit, and the cell is
+/// colored by its brightest dot.
+pub fn draw_waterfall_braille<'a, T, I>(canvas: &mut T, spectra: I, palette: Palette)
+    where T: CellAccessor + HasSize,
+          I: Iterator<Item = &'a [f32]>
+{
+    canvas.clear(Cell::default());
+    let (cols, rows) = canvas.size();
+    for (row, specs) in (0..rows).zip(&spectra.chunks_lazy(4)) {
+        let specs: Vec<&[f32]> = specs.collect();
+        for c in 0..cols {
+            let dots: Vec<(usize, usize, f32)> = specs.iter().enumerate().flat_map(|(dy, s)| {
+                (0..2).filter_map(move |dx| s.get(2 * c + dx).map(|&v| (dy, dx, v)))
+            }).collect();
+            if dots.is_empty() {
+                break;
+            }
+
+            let hi = dots.iter().fold(::std::f32::MIN, |m, &(_, _, v)| m.max(v));
+            let lo = dots.iter().fold(::std::f32::MAX, |m, &(_, _, v)| m.min(v));
+            let mid = (hi + lo) / 2.0;
+            let mut bits = 0;
+            for &(dy, dx, v) in &dots {
+                // a flat cell lights every dot, rather than none
+                if v >= mid {
+                    bits |= BRAILLE_PIXEL_MAP[dy][dx];
+                }
+            }
+
+            *canvas.get_mut(c, row).unwrap() = Cell::new(
+                char::from_u32(0x2800 + bits as u32).unwrap(),
+                palette.cell_color(hi),
+                Color::Default,
+                Attr::Default);
+        }
+    }
+}
+
+/// Draws a spectrum per row with characters getting denser with the power,
+/// for terminals without colors.
+pub fn draw_waterfall_ascii<'a, T, I>(canvas: &mut T, spectra: I)
+    where T: CellAccessor + HasSize,
+          I: Iterator<Item = &'a [f32]>
+{
+    canvas.clear(Cell::default());
+    let (cols, rows) = canvas.size();
+    for (row, spec) in (0..rows).zip(spectra) {
+        for c in 0..cols {
+            let f = match bin_pair_average(spec, c) {
+                Some(f) => f,
+                None => break,
+            };
+            let idx = (f.max(0.0) * ASCII_RAMP.len() as f32) as usize;
+            let ch = ASCII_RAMP[min(idx, ASCII_RAMP.len() - 1)];
+            *canvas.get_mut(c, row).unwrap() = Cell::with_char(ch);
+        }
+    }
+}
+
+/// Fades `hits` and adds the trace of `spec`, two bins per column, for a
+/// spectrum view of `size` cells with two spots in each.
+fn add_persistence(hits: &mut Vec<f32>, size: (usize, usize), spec: &[f32]) {
+    let (cols, rows) = size;
+    let spot_rows = 2 * rows;
+    if hits.len() != cols * spot_rows {
+        *hits = vec![0.0; cols * spot_rows];
+    }
+    for h in hits.iter_mut() {
+        *h *= PERSISTENCE_DECAY;
+    }
+    if spot_rows == 0 {
+        return;
+    }
+    for (c, chunk) in (0..cols).zip(spec.chunks(2)) {
+        for &x in chunk {
+            let height = min((x.max(0.0) * spot_rows as f32) as usize, spot_rows - 1);
+            hits[(spot_rows - 1 - height) * cols + c] += 1.0;
+        }
+    }
+}
+
+/// Draws the persistence view, with a half block per pair of spots. The
+/// colors go by the log of the hits, so a single one still shows.
+fn draw_persistence<T>(canvas: &mut T, hits: &[f32], palette: Palette, ascii: bool)
+    where T: CellAccessor + HasSize
+{
+    canvas.clear(Cell::default());
+    let (cols, rows) = canvas.size();
+    if hits.len() != cols * 2 * rows {
+        return;
+    }
+    let intensity = |h: f32| ((1.0 + h).ln() / (1.0 + PERSISTENCE_FULL_HITS).ln()).min(1.0);
+    for row in 0..rows {
+        for c in 0..cols {
+            let (upper, lower) = (hits[2 * row * cols + c], hits[(2 * row + 1) * cols + c]);
+            if upper < PERSISTENCE_MIN_HITS && lower < PERSISTENCE_MIN_HITS {
+                continue;
+            }
+            *canvas.get_mut(c, row).unwrap() = if ascii {
+                let idx = (intensity(upper.max(lower)) * ASCII_RAMP.len() as f32) as usize;
+                // the faintest still shows as something
+                Cell::with_char(ASCII_RAMP[idx.max(1).min(ASCII_RAMP.len() - 1)])
+            } else {
+                spectrum_heights_to_waterfall_cell(intensity(upper), intensity(lower), palette)
+            };
+        }
+    }
 }
 
-impl Canvas {
-    pub fn new() -> Result<Self, io::Error> {
-        let term = try!(Terminal::new());
+fn spectrum_heights_to_waterfall_cell(upper: f32, lower: f32, palette: Palette) -> Cell {
+    Cell::new('▀',
+              palette.cell_color(upper),
+              palette.cell_color(lower),
+              Attr::Default)
+}
 
-        let mut canvas = Canvas {
-            term: term,
-            spectrum: Widget::new(0, 0),
-            waterfall: Widget::new(0, 0),
-            history: VecDeque::new(),
-        };
+/// A box holding `lines`, centered in a terminal of `term_size`, with a column
+/// of padding either side. The `highlight` line, if any, is drawn reversed.
+fn draw_overlay(lines: &[String], term_size: (usize, usize), ascii: bool,
+                highlight: Option<usize>) -> Widget {
+    let (term_cols, term_rows) = term_size;
+    let cols = min(lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) + 4, term_cols);
+    let rows = min(lines.len() + 2, term_rows);
+    let mut overlay = Widget::new(cols, rows);
+    overlay.set_origin(((term_cols - cols) / 2, (term_rows - rows) / 2));
+    if cols >= 2 && rows >= 2 {
+        if ascii {
+            draw_ascii_box(&mut overlay);
+        } else {
+            overlay.draw_box();
+        }
+    }
+    for (i, line) in lines.iter().enumerate() {
+        if highlight == Some(i) {
+            overlay.printline_with_cell(2, i + 1, line,
+                Cell::new(' ', Color::Default, Color::Default, Attr::Reverse));
+        } else {
+            overlay.printline(2, i + 1, line);
+        }
+    }
+    overlay
+}
 
-        canvas.resize();
+/// A box at the bottom left, above the status bar, with the end of `text`
+/// in it and `title` on its top edge.
+fn draw_text_pane(title: &str, text: &str, term_size: (usize, usize), ascii: bool) -> Widget {
+    let (term_cols, term_rows) = term_size;
+    let cols = min(TEXT_PANE_COLS + 4, term_cols);
+    let rows = min(TEXT_PANE_LINES + 2, term_rows.saturating_sub(1));
+    let mut pane = Widget::new(cols, rows);
+    pane.set_origin((0, term_rows.saturating_sub(1 + rows)));
+    if cols < 5 || rows < 3 {
+        return pane;
+    }
+    if ascii {
+        draw_ascii_box(&mut pane);
+    } else {
+        pane.draw_box();
+    }
+    pane.printline(2, 0, &format!(" {} ", title));
+    for (i, line) in last_lines(text, cols - 4, rows - 2).iter().enumerate() {
+        pane.printline(2, i + 1, line);
+    }
+    pane
+}
+
+/// The IQ scope in the top right corner: `samples` as dots, I across and Q
+/// up, at full scale each way, so clipping's at the edges and a DC offset
+/// off the middle, with what they add up to along the bottom.
+fn draw_scope(samples: &[Complex<i8>], term_size: (usize, usize), ascii: bool) -> Widget {
+    let (term_cols, term_rows) = term_size;
+    let cols = min(SCOPE_COLS, term_cols);
+    let rows = min(SCOPE_ROWS, term_rows.saturating_sub(1));
+    let mut scope = Widget::new(cols, rows);
+    scope.set_origin((term_cols - cols, 0));
+    if cols < 5 || rows < 3 {
+        return scope;
+    }
+    if ascii {
+        draw_ascii_box(&mut scope);
+    } else {
+        scope.draw_box();
+    }
+    let (plot_cols, plot_rows) = (cols - 2, rows - 2);
+    let mut dots = vec![0u8; plot_cols * plot_rows];
+    for x in samples {
+        let px = (x.re as i32 + 128) as usize * 2 * plot_cols / 256;
+        let py = (127 - x.im as i32) as usize * 4 * plot_rows / 256;
+        dots[py / 4 * plot_cols + px / 2] |= BRAILLE_PIXEL_MAP[py % 4][px % 2];
+    }
+    for (i, &d) in dots.iter().enumerate().filter(|&(_, &d)| d != 0) {
+        let ch = if ascii { '*' } else { char::from_u32(0x2800 + d as u32).unwrap() };
+        scope.get_mut(1 + i % plot_cols, 1 + i / plot_cols).unwrap().set_ch(ch);
+    }
+    scope.printline(2, 0, " IQ ");
+    if let Some(summary) = scope_summary(samples) {
+        let summary: String = summary.chars().take(cols - 2).collect();
+        scope.printline(1, rows - 1, &summary);
+    }
+    scope
+}
 
-        Ok(canvas)
+/// The DC offset of `samples`, as a share of full scale, how much stronger I
+/// is than Q, and the share clipped, if there are any.
+fn scope_summary(samples: &[Complex<i8>]) -> Option<String> {
+    if samples.is_empty() {
+        return None;
     }
+    let n = samples.len() as f64;
+    let (mut i_sum, mut q_sum, mut i_squares, mut q_squares) = (0.0, 0.0, 0.0, 0.0);
+    let mut clipped = 0;
+    let at_edge = |x: i8| x == 127 || x == -128;
+    for x in samples {
+        let (i, q) = (x.re as f64, x.im as f64);
+        i_sum += i;
+        q_sum += q;
+        i_squares += i * i;
+        q_squares += q * q;
+        if at_edge(x.re) || at_edge(x.im) {
+            clipped += 1;
+        }
+    }
+    let (i_mean, q_mean) = (i_sum / n, q_sum / n);
+    let i_var = i_squares / n - i_mean * i_mean;
+    let q_var = q_squares / n - q_mean * q_mean;
+    let imbalance_db = if i_var > 0.0 && q_var > 0.0 {
+        10.0 * (i_var / q_var).log10()
+    } else {
+        0.0
+    };
+    Some(format!("DC {:.1}% IQ {:+.1}dB clip {:.0}%", i_mean.hypot(q_mean) / 128.0 * 100.0,
+                 imbalance_db, clipped as f64 / n * 100.0))
+}
 
-    fn resize(&mut self) {
-        let (cols, rows) = self.term.size();
-        let spectrum_height = rows / 2;
-        let waterfall_height = if rows % 2 == 0 { rows / 2 } else { rows / 2 + 1 };
+/// The last `num_lines` lines of `text` broken every `width` characters,
+/// fewer if it's shorter.
+fn last_lines(text: &str, width: usize, num_lines: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let lines: Vec<String> = chars.chunks(width).map(|line| line.iter().cloned().collect())
+                                  .collect();
+    let skip = lines.len().saturating_sub(num_lines);
+    lines.into_iter().skip(skip).collect()
+}
+
+/// Estimates the bytes the backend sends to turn the screen from `prev` into
+/// `next`: for each changed cell its character, with a style change when it
+/// differs from the last one sent and a cursor move when it doesn't follow it.
+fn frame_bytes(prev: &[Cell], next: &[Cell], cols: usize) -> usize {
+    let mut bytes = 0;
+    let mut last_sent: Option<usize> = None;
+    let mut style: Option<Cell> = None;
+    for (i, (old, new)) in prev.iter().zip(next).enumerate() {
+        if old == new {
+            continue;
+        }
+        if last_sent.map_or(true, |last| last + 1 != i || i % cols == 0) {
+            bytes += CURSOR_MOVE_BYTES;
+        }
+        let cell_style = Cell::new(' ', new.fg(), new.bg(), new.attrs());
+        if style != Some(cell_style) {
+            bytes += STYLE_BYTES;
+            style = Some(cell_style);
+        }
+        bytes += new.ch().len_utf8();
+        last_sent = Some(i);
+    }
+    bytes
+}
+
+/// Turns an FFT into powers between 0 at `min_db` and 1 at `max_db`, lowest
+/// frequency first, as the `draw_` functions take them. With `invert` set,
+/// each frequency is swapped with its negative first, for a converter that
+/// inverts the spectrum. DC stays where it is. The powers are worked out in
+/// the FFT's own precision, only the scaled ones being single.
+pub fn normalize_spectrum<T: Float>(spec: &[Complex<T>], min_db: f32, max_db: f32, invert: bool)
+    -> Vec<f32>
+{
+    let inverted: Vec<Complex<T>>;
+    let spec = if invert && spec.len() > 1 {
+        inverted = iter::once(spec[0]).chain(spec[1..].iter().rev().cloned()).collect();
+        &inverted[..]
+    } else {
+        spec
+    };
+
+    // FFT shift
+    let (first_half, last_half) = spec.split_at((spec.len() + 1) / 2);
+    let shifted_spec = last_half.iter().chain(first_half.iter());
+
+    // normalize and take the log
+    let span = db_span(min_db, max_db);
+    shifted_spec.map(|x| x.norm().log10().to_f32().unwrap_or(::std::f32::NEG_INFINITY))
+                .map(|x| 10.0 * x)
+                .map(|x| (x - min_db) / span)
+                .collect()
+}
+
+/// The power range the spectrum is scaled to, kept from being 0 so a range
+/// with the same top and bottom splits the powers at it rather than giving NaN.
+fn db_span(min_db: f32, max_db: f32) -> f32 {
+    let span = max_db - min_db;
+    if span == 0.0 { ::std::f32::EPSILON } else { span }
+}
+
+/// `normalize_spectrum` for single precision, as every spectrum shown goes
+/// through it. It takes the log of the squared magnitude, saving the square
+/// root, with `fast_log2`, and works out the scaling once, to within a
+/// ten-thousandth of a dB.
+pub fn normalize_spectrum_fast(spec: &[Complex<f32>], min_db: f32, max_db: f32, invert: bool)
+    -> Vec<f32>
+{
+    // 10 log10 |x| is 5 log10(2) log2 |x|^2
+    let span = db_span(min_db, max_db);
+    let scale = 5.0 * ::std::f32::consts::LOG10_2 / span;
+    let offset = -min_db / span;
+    let scaled = |x: &Complex<f32>| fast_log2(x.norm_sqr()) * scale + offset;
 
-        self.spectrum = Widget::new(cols, spectrum_height);
-        self.spectrum.align(&self.term, HorizontalAlign::Middle, VerticalAlign::Top, 0);
+    let len = spec.len();
+    let half = len.div_ceil(2);
+    let mut normalized = Vec::with_capacity(len);
+    if invert && len > 1 {
+        // bin i of the shifted spectrum is bin -(i + half) of the FFT
+        normalized.extend((0..len).map(|i| scaled(&spec[(len - (i + half) % len) % len])));
+    } else {
+        // FFT shift, a half at a time so each goes straight through
+        normalized.extend(spec[half..].iter().map(&scaled));
+        normalized.extend(spec[..half].iter().map(&scaled));
+    }
+    normalized
+}
 
-        self.waterfall = Widget::new(cols, waterfall_height);
-        self.waterfall.align(&self.term, HorizontalAlign::Middle, VerticalAlign::Bottom, 0);
+/// Levels in the FFTs' order put lowest frequencies first, as
+/// `normalize_spectrum_fast` puts powers.
+fn shift_levels(levels: &[f32], invert: bool) -> Vec<f32> {
+    let len = levels.len();
+    let half = len.div_ceil(2);
+    if invert && len > 1 {
+        (0..len).map(|i| levels[(len - (i + half) % len) % len]).collect()
+    } else {
+        levels[half..].iter().chain(&levels[..half]).cloned().collect()
+    }
+}
 
-        self.history.reserve(waterfall_height * 2);
+/// The base 2 log of `x`, to within 2e-5, from its exponent and a polynomial
+/// for its mantissa. Powers of two come out exact, and 0 as minus infinity.
+/// Subnormals aren't handled, being far below any spectrum's range.
+fn fast_log2(x: f32) -> f32 {
+    if x <= 0.0 {
+        return ::std::f32::NEG_INFINITY;
     }
+    let bits = x.to_bits();
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127;
+    // the mantissa, as 1 + t for t from 0 up to 1
+    let t = f32::from_bits((bits & 0x007f_ffff) | 0x3f80_0000) - 1.0;
+    let log_mantissa = t * (1.441_835_9 + t * (-0.708_372_2 + t * (0.413_555_05 +
+                           t * (-0.191_262_52 + t * 0.044_243_74))));
+    exponent as f32 + log_mantissa
+}
 
-    fn check_and_resize(&mut self) {
-        let (cols, rows) = self.term.size();
-        let (spectrum_cols, spectrum_rows) = self.spectrum.size();
-        let (waterfall_cols, waterfall_rows) = self.waterfall.size();
-        // if the terminal size has changed...
-        if cols != spectrum_cols || cols != waterfall_cols ||
-            rows != (spectrum_rows + waterfall_rows) {
-            self.resize();
+/// The spectra of `history`, newest first, with each run of them `sizes` says
+/// goes together averaged into one.
+fn fold_history<I>(mut history: I, sizes: &[usize]) -> Vec<Arc<Row>>
+    where I: Iterator<Item = Arc<Row>>
+{
+    let mut folded = Vec::with_capacity(sizes.len());
+    for &size in sizes {
+        let first = match history.next() {
+            Some(row) => row,
+            None => break,
+        };
+        if size == 1 {
+            folded.push(first);
+            continue;
+        }
+        let mut sum = first.powers();
+        let mut count = 1;
+        while count < size {
+            let row = match history.next() {
+                Some(row) => row,
+                None => break,
+            };
+            for (s, x) in sum.iter_mut().zip(row.powers()) {
+                *s += x;
+            }
+            count += 1;
         }
+        let mean: Vec<f32> = sum.iter().map(|s| s / count as f32).collect();
+        folded.push(Arc::new(Row::new(&mean)));
     }
+    folded
+}
 
-    /// Adds a spectrum to the history and draws it on the waterfall
-    /// and the spectrum view.
-    pub fn add_spectrum(&mut self, spec: Vec<Complex<f32>>) {
-        let normalized = normalize_spectrum(&spec, 50.0);
+/// `row` stretched or squeezed to `len` bins. Each bin takes the strongest of
+/// those merged into it, so narrow signals don't disappear.
+pub fn rescale_row(row: &[f32], len: usize) -> Vec<f32> {
+    if row.is_empty() {
+        return vec![0.0; len];
+    }
+    (0..len).map(|i| {
+        let start = i * row.len() / len;
+        let end = max((i + 1) * row.len() / len, start + 1);
+        row[start..end].iter().fold(::std::f32::MIN, |m, &x| m.max(x))
+    }).collect()
+}
 
-        draw_spectrum(&mut self.spectrum, &normalized);
+/// Rescales `row` so its weakest bin is 0 and its strongest 1.
+pub fn normalize_row(row: &[f32]) -> Vec<f32> {
+    let lo = row.iter().fold(::std::f32::MAX, |m, &x| m.min(x));
+    let hi = row.iter().fold(::std::f32::MIN, |m, &x| m.max(x));
+    if hi - lo <= 0.0 {
+        return vec![0.0; row.len()];
+    }
+    row.iter().map(|&x| (x - lo) / (hi - lo)).collect()
+}
 
-        // Since the waterfall has half the horizontal resolution of the spectrum view,
-        // average every two values and store the averaged spectrum.
-        let averaged = normalized.chunks(2).map(|v| (v[0] + v[1]) / 2.0).collect();
+/// Index of the DC bin in a spectrum that has been FFT shifted by `normalize_spectrum`.
+fn dc_bin(len: usize) -> usize {
+    len / 2
+}
 
-        // push spectrum onto the history
-        self.history.push_front(averaged);
-        let (_, rows) = self.waterfall.size();
-        if self.history.len() >= rows * 2 {
-            self.history.pop_back();
+fn suppress_dc_bin(spec: &mut [f32], dc: usize) {
+    if dc == 0 || dc + 1 >= spec.len() {
+        return;
+    }
+    spec[dc] = (spec[dc - 1] + spec[dc + 1]) / 2.0;
+}
+
+/// Colors the spectrum column containing the DC bin and labels it, so the
+/// center spike isn't mistaken for a real signal.
+/// The strongest bin of a normalized spectrum above `level`, leaving out the
+/// DC bin, which is always strong.
+fn strongest_bin_above(spec: &[f32], level: f32) -> Option<usize> {
+    let dc = dc_bin(spec.len());
+    spec.iter().enumerate()
+        .filter(|&(i, &x)| i != dc && x > level)
+        .fold(None, |best: Option<(usize, f32)>, (i, &x)| match best {
+            Some((_, y)) if y >= x => best,
+            _ => Some((i, x)),
+        })
+        .map(|(i, _)| i)
+}
+
+/// Draws a line across the spectrum view at `level`, 0 at the bottom and 1
+/// at the top, in the cells the trace leaves blank.
+fn draw_threshold<T: CellAccessor + HasSize>(canvas: &mut T, level: f32, ascii: bool) {
+    let (cols, rows) = canvas.size();
+    if rows == 0 || level < 0.0 || level > 1.0 {
+        return;
+    }
+    let height = (level * rows as f32).floor() as usize;
+    let row = rows - min(height, rows - 1) - 1;
+    let ch = if ascii { '-' } else { '─' };
+    for col in 0..cols {
+        let cell = canvas.get_mut(col, row).unwrap();
+        if cell.ch() == ' ' {
+            *cell = Cell::new(ch, Color::Yellow, Color::Default, Attr::Default);
         }
+    }
+}
 
-        draw_waterfall(&mut self.waterfall, &self.history);
+/// Shades the columns from `passband.0` to `passband.1` Hz, at least one wide,
+/// behind whatever's drawn there.
+fn draw_passband<T>(canvas: &mut T, passband: (f64, f64), center_freq_hz: f64,
+                    sample_rate_hz: f64, ascii: bool)
+    where T: CellAccessor + HasSize
+{
+    let (cols, rows) = canvas.size();
+    let low = center_freq_hz - sample_rate_hz / 2.0;
+    let col = |freq_hz: f64| (freq_hz - low) / sample_rate_hz * cols as f64;
+    let (first, last) = (col(passband.0), col(passband.1));
+    if cols == 0 || last < 0.0 || first >= cols as f64 {
+        return;
+    }
+    let first = first.max(0.0) as usize;
+    let last = min(last.max(0.0) as usize, cols - 1);
+    let shade = if ascii { Color::Blue } else { Color::Byte(236) };
+    for col in first..last + 1 {
+        for row in 0..rows {
+            canvas.get_mut(col, row).unwrap().set_bg(shade);
+        }
+    }
+}
 
-        self.spectrum.draw_into(&mut self.term);
-        self.waterfall.draw_into(&mut self.term);
-        self.term.swap_buffers().unwrap();
+fn mark_dc_bin<T: CellAccessor + HasSize>(canvas: &mut T, dc: usize, excluded: bool) {
+    let (cols, rows) = canvas.size();
+    let col = dc / 2;
+    if col >= cols || rows == 0 {
+        return;
+    }
 
-        self.check_and_resize();
+    for row in 0..rows {
+        let cell = canvas.get_mut(col, row).unwrap();
+        if cell.ch() != ' ' {
+            cell.set_fg(Color::Red);
+        }
     }
 
-    pub fn get_term(&mut self) -> &mut Terminal {
-        &mut self.term
+    let label = if excluded { "DC (LO leakage, excluded)" } else { "DC (LO leakage)" };
+    let len = label.chars().count();
+    let x = if col + 1 + len <= cols {
+        col + 1
+    } else if col >= len + 1 {
+        col - len - 1
+    } else {
+        0
+    };
+    canvas.printline_with_cell(x, 0, label,
+                               Cell::new(' ', Color::Red, Color::Default, Attr::Default));
+}
+
+/// Frequency of bin `bin` of an FFT shifted spectrum of length `len`.
+fn bin_frequency(bin: usize, len: usize, center_freq_hz: f64, sample_rate_hz: f64) -> f64 {
+    center_freq_hz + (bin as f64 - dc_bin(len) as f64) * sample_rate_hz / len as f64
+}
+
+/// Center frequency and width of band `band` of `num_bands` equal slices of a span.
+fn band_tuning(center_freq_hz: f64, sample_rate_hz: f64, band: usize,
+               num_bands: usize) -> (f64, f64) {
+    let width = sample_rate_hz / num_bands as f64;
+    let low = center_freq_hz - sample_rate_hz / 2.0;
+    (low + (band as f64 + 0.5) * width, width)
+}
+
+/// Labels the top right of a band's spectrum with the range it covers.
+fn draw_band_range<T>(canvas: &mut T, center_freq_hz: f64, sample_rate_hz: f64)
+    where T: CellAccessor + HasSize
+{
+    let (cols, rows) = canvas.size();
+    let label = format!("{} - {}", format_frequency(center_freq_hz - sample_rate_hz / 2.0),
+                        format_frequency(center_freq_hz + sample_rate_hz / 2.0));
+    let len = label.chars().count();
+    if rows == 0 || len > cols {
+        return;
     }
+    canvas.printline_with_cell(cols - len, 0, &label,
+                               Cell::new(' ', Color::White, Color::Default, Attr::Default));
+}
 
-    pub fn get_spectrum_width(&self) -> usize {
-        2 * self.term.cols()
+/// Column of frequency `freq_hz` in a view `cols` wide, if it's in view.
+fn frequency_column(freq_hz: f64, cols: usize, center_freq_hz: f64,
+                    sample_rate_hz: f64) -> Option<usize> {
+    let low = center_freq_hz - sample_rate_hz / 2.0;
+    let col = (freq_hz - low) / sample_rate_hz * cols as f64;
+    if col >= 0.0 && col < cols as f64 {
+        Some(col as usize)
+    } else {
+        None
     }
 }
 
-fn draw_waterfall<T: CellAccessor + HasSize>(canvas: &mut T, spectra: &VecDeque<Vec<f32>>) {
+/// Highlights the column of `bin` and labels it at the top of the spectrum.
+fn draw_marker<T>(canvas: &mut T, bin: usize, label: &str)
+    where T: CellAccessor + HasSize
+{
     let (cols, rows) = canvas.size();
-    for (row, mut specs) in (0..rows).zip(&spectra.iter().chunks_lazy(2)) {
-        let upper_heights = specs.next().into_iter().flat_map(|x| x);
-        let lower_heights = specs.next().into_iter().flat_map(|x| x);
-        for (c, heights) in (0..cols).zip(upper_heights.zip_longest(lower_heights)) {
-            let (u, l) = match heights {
-                EitherOrBoth::Both(&upper, &lower) => (upper, lower),
-                EitherOrBoth::Left(&upper) => (upper, 0.0),
-                EitherOrBoth::Right(&lower) => (0.0, lower),
-            };
-            *canvas.get_mut(c, row).unwrap() = spectrum_heights_to_waterfall_cell(u, l);
+    let col = bin / 2;
+    if col >= cols || rows == 0 {
+        return;
+    }
+
+    for row in 0..rows {
+        let cell = canvas.get_mut(col, row).unwrap();
+        if cell.ch() == ' ' {
+            cell.set_ch('|');
         }
+        cell.set_fg(Color::Magenta);
     }
-}
 
-fn spectrum_heights_to_waterfall_cell(upper: f32, lower: f32) -> Cell {
-    Cell::new('▀',
-              Color::Byte(color_mapping(upper)),
-              Color::Byte(color_mapping(lower)),
-              Attr::Default)
+    // to the right of the marker, or the left if there's no room
+    let len = label.chars().count();
+    let start = if col + 1 + len <= cols { col + 1 } else { col.saturating_sub(len) };
+    canvas.printline_with_cell(start, 0, label,
+                               Cell::new(' ', Color::Magenta, Color::Default, Attr::Bold));
 }
 
-/// Assumes `f` is between 0 and 1. Anything outside of this range
-/// will be clamped.
-fn color_mapping(f: f32) -> u8 {
-    let mapping = [16, 17, 18, 19, 21, 27, 33, 39, 45, 51,
-                   50, 49, 48, 47, 46, 82, 118, 154, 190, 226];
-    let idx = (f * (mapping.len() as f32)) as i32;
-    if idx < 0 {
-        mapping[0]
-    } else if idx >= mapping.len() as i32 {
-        mapping[mapping.len() - 1]
-    } else {
-        mapping[idx as usize]
+/// Draws a marker at each bookmark in the current span, followed by its name
+/// truncated to fit before the next one.
+fn draw_bookmark_strip<T>(canvas: &mut T, bookmarks: &[Bookmark], center_freq_hz: f64,
+                          sample_rate_hz: f64, ascii: bool)
+    where T: CellAccessor + HasSize
+{
+    canvas.clear(Cell::default());
+    let (cols, rows) = canvas.size();
+    if rows == 0 || sample_rate_hz <= 0.0 {
+        return;
+    }
+
+    let mut in_view: Vec<(usize, &Bookmark)> = bookmarks.iter()
+        .filter_map(|b| frequency_column(b.freq_hz, cols, center_freq_hz, sample_rate_hz)
+                            .map(|col| (col, b)))
+        .collect();
+    in_view.sort_by_key(|&(col, _)| col);
+
+    let style = Cell::new(' ', Color::Cyan, Color::Default, Attr::Default);
+    let marker = if ascii { "v" } else { "▼" };
+    for (i, &(col, bookmark)) in in_view.iter().enumerate() {
+        let end = in_view.get(i + 1).map(|&(next, _)| next).unwrap_or(cols);
+        canvas.printline_with_cell(col, 0, marker, style);
+        let room = end.saturating_sub(col + 2);
+        let name: String = bookmark.name.chars().take(room).collect();
+        canvas.printline_with_cell(col + 1, 0, &name, style);
     }
 }
 
-fn normalize_spectrum(spec: &[Complex<f32>], max_db: f32) -> Vec<f32> {
-    // FFT shift
-    let (first_half, last_half) = spec.split_at((spec.len() + 1) / 2);
-    let shifted_spec = last_half.iter().chain(first_half.iter());
+/// Writes a frequency label just above each peak, skipping labels that would
+/// overlap one that has already been drawn.
+fn draw_peak_labels<T>(canvas: &mut T, spec: &[f32], peaks: &[Peak],
+                       center_freq_hz: f64, sample_rate_hz: f64)
+    where T: CellAccessor + HasSize
+{
+    let (cols, rows) = canvas.size();
+    if rows == 0 {
+        return;
+    }
+    let mut drawn: Vec<(usize, usize, usize)> = Vec::new();
 
-    // normalize and take the log
-    shifted_spec.map(Complex::norm)
-                .map(Float::log10)
-                .map(|x| 10.0 * x)
-                .map(|x| x / max_db)
-                .collect()
+    for peak in peaks {
+        let label = format_frequency(bin_frequency(peak.bin, spec.len(),
+                                                   center_freq_hz, sample_rate_hz));
+        let len = label.chars().count();
+        if len > cols {
+            continue;
+        }
+
+        // the row just above the top of the trace
+        let height = min((spec[peak.bin] * rows as f32).floor().max(0.0) as usize, rows - 1);
+        let row = (rows - height - 1).saturating_sub(1);
+        let start = min((peak.bin / 2).saturating_sub(len / 2), cols - len);
+        let end = start + len;
+
+        if drawn.iter().any(|&(r, s, e)| r == row && start < e && s < end) {
+            continue;
+        }
+        drawn.push((row, start, end));
+
+        canvas.printline_with_cell(start, row, &label,
+                                   Cell::new(' ', Color::Yellow, Color::Default, Attr::Default));
+    }
 }
 
 // indexing is from the top of the cell
 fn pixel_nums_to_braille(p1: Option<u8>, p2: Option<u8>) -> char {
-    let pixel_map = [[0x01, 0x08],
-                     [0x02, 0x10],
-                     [0x04, 0x20],
-                     [0x40, 0x80]];
-
     let mut c = 0;
     if let Some(p) = p1 {
         for i in p..4 {
-            c |= pixel_map[i as usize][0];
+            c |= BRAILLE_PIXEL_MAP[i as usize][0];
         }
     }
 
     if let Some(p) = p2 {
         for i in p..4 {
-            c |= pixel_map[i as usize][1];
+            c |= BRAILLE_PIXEL_MAP[i as usize][1];
         }
     }
 
-    char::from_u32((0x2800 + c) as u32).unwrap()
+    char::from_u32(0x2800 + c as u32).unwrap()
 }
 
 fn char_to_cell(c: char) -> Cell {
@@ -225,7 +3132,9 @@ fn draw_pixel_pair<T>(canvas: &mut T, col_idx: usize, p1: usize, p2: usize)
     }
 }
 
-fn draw_spectrum<T: CellAccessor + HasSize>(canvas: &mut T, spec: &[f32]) {
+/// Draws the spectrum with braille dots, one dot column per bin, filled in
+/// below.
+pub fn draw_spectrum<T: CellAccessor + HasSize>(canvas: &mut T, spec: &[f32]) {
     canvas.clear(Cell::default());
     let (num_cols, num_rows) = canvas.size();
     let pixel_height = num_rows * 4;
@@ -233,20 +3142,176 @@ fn draw_spectrum<T: CellAccessor + HasSize>(canvas: &mut T, spec: &[f32]) {
     for (col_idx, chunk) in (0..num_cols).zip(spec.chunks(2)) {
         // height in float between 0 and 1.
         let h1 = chunk[0];
-        let h2 = chunk[1];
+        // an odd number of bins leaves the last column with one
+        let h2 = *chunk.get(1).unwrap_or(&h1);
 
         // The "pixel" height of each point.
-        let p1 = (h1 * pixel_height as f32).floor().max(0.0) as usize;
-        let p2 = (h2 * pixel_height as f32).floor().max(0.0) as usize;
+        let p1 = pixel_of(h1, pixel_height);
+        let p2 = pixel_of(h2, pixel_height);
 
         draw_pixel_pair(canvas, col_idx, p1, p2);
     }
 }
 
+/// Draws the spectrum as columns of eighth blocks, one cell per pair of
+/// bins at the taller one's height.
+pub fn draw_spectrum_blocks<T: CellAccessor + HasSize>(canvas: &mut T, spec: &[f32]) {
+    const EIGHTHS: [char; 8] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇'];
+    canvas.clear(Cell::default());
+    let (num_cols, num_rows) = canvas.size();
+    if num_rows == 0 {
+        return;
+    }
+
+    for (col_idx, chunk) in (0..num_cols).zip(spec.chunks(2)) {
+        let h = chunk.iter().fold(0.0, |a: f32, &b| a.max(b));
+        // the bottom pixel is always lit, as with braille
+        let height = pixel_of(h, num_rows * 8) + 1;
+        for row_idx in 0..height / 8 {
+            *canvas.get_mut(col_idx, num_rows - row_idx - 1).unwrap() = char_to_cell('█');
+        }
+        let eighths = height % 8;
+        if eighths > 0 {
+            *canvas.get_mut(col_idx, num_rows - height / 8 - 1).unwrap() =
+                char_to_cell(EIGHTHS[eighths]);
+        }
+    }
+}
+
+/// The pixel, counting up from 0 at the bottom, at height `h` between 0 and
+/// 1 of a view `pixel_height` high, whatever the glyphs.
+fn pixel_of(h: f32, pixel_height: usize) -> usize {
+    min((h * pixel_height as f32).floor().max(0.0) as usize, pixel_height.saturating_sub(1))
+}
+
+/// Draws each of `traces` in its color, in order: the live trace filled in
+/// under it in `style`, the others as lines over what's there, sharing its
+/// braille cells. With blocks the lines are drawn as in ASCII, since eighth
+/// blocks can't be laid over each other.
+pub fn draw_traces<T: CellAccessor + HasSize>(canvas: &mut T, traces: &[(Trace, &[f32])],
+                                              ascii: bool, glyphs: Glyphs, style: SpectrumStyle,
+                                              palette: Palette) {
+    let blocks = !ascii && glyphs == Glyphs::Blocks;
+    for &(trace, spec) in traces {
+        match (trace, ascii) {
+            (Trace::Live, true) => draw_spectrum_ascii(canvas, spec),
+            (Trace::Live, false) => {
+                if blocks {
+                    draw_spectrum_blocks(canvas, spec);
+                } else {
+                    draw_spectrum(canvas, spec);
+                }
+                fill_spectrum(canvas, spec, style, palette);
+            }
+            _ => draw_trace_line(canvas, spec, trace, ascii || blocks),
+        }
+    }
+}
+
+/// Colors a spectrum just drawn from `spec` for `style`, by each column's
+/// height or each cell's, and turns its full braille cells solid.
+fn fill_spectrum<T: CellAccessor + HasSize>(canvas: &mut T, spec: &[f32], style: SpectrumStyle,
+                                            palette: Palette) {
+    if style == SpectrumStyle::Dots {
+        return;
+    }
+    let (cols, rows) = canvas.size();
+    let full = pixel_nums_to_braille(Some(0), Some(0));
+    for (col, chunk) in (0..cols).zip(spec.chunks(2)) {
+        let column_height = chunk.iter().fold(0.0, |a: f32, &b| a.max(b));
+        for row in 0..rows {
+            let cell = canvas.get_mut(col, row).unwrap();
+            if cell.ch() == ' ' {
+                continue;
+            }
+            // the middle of the row, from the bottom
+            let row_height = 1.0 - (row as f32 + 0.5) / rows as f32;
+            match style {
+                SpectrumStyle::Dots => {}
+                SpectrumStyle::Colored => {
+                    cell.set_fg(palette.cell_color(column_height));
+                }
+                SpectrumStyle::Solid | SpectrumStyle::Gradient => {
+                    if cell.ch() == full {
+                        cell.set_ch('█');
+                    }
+                    if style == SpectrumStyle::Gradient {
+                        cell.set_fg(palette.cell_color(row_height));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Draws the top of the spectrum in `trace`'s color, a pixel for each bin,
+/// or in ASCII a character for each pair of bins.
+fn draw_trace_line<T>(canvas: &mut T, spec: &[f32], trace: Trace, ascii: bool)
+    where T: CellAccessor + HasSize
+{
+    let (num_cols, num_rows) = canvas.size();
+    if num_rows == 0 {
+        return;
+    }
+    let pixel_height = if ascii { num_rows } else { 4 * num_rows };
+    for (col_idx, chunk) in (0..num_cols).zip(spec.chunks(2)) {
+        if ascii {
+            let h = chunk.iter().fold(0.0, |a: f32, &b| a.max(b));
+            let height = pixel_of(h, num_rows);
+            *canvas.get_mut(col_idx, num_rows - height - 1).unwrap() =
+                Cell::new(trace.ascii_char(), trace.color(), Color::Default, Attr::Default);
+            continue;
+        }
+        // an odd number of bins leaves the last column with one
+        let pair = [chunk[0], *chunk.get(1).unwrap_or(&chunk[0])];
+        for (side, &h) in pair.iter().enumerate() {
+            let pixel = pixel_of(h, pixel_height);
+            // from the top, as the terminal counts
+            let pixel = pixel_height - pixel - 1;
+            let cell = canvas.get_mut(col_idx, pixel / 4).unwrap();
+            let ch = cell.ch() as u32;
+            let dots = if ch >= 0x2800 && ch <= 0x28ff { ch - 0x2800 } else { 0 };
+            let dots = dots | BRAILLE_PIXEL_MAP[pixel % 4][side] as u32;
+            *cell = Cell::new(char::from_u32(0x2800 + dots).unwrap(), trace.color(),
+                              Color::Default, Attr::Bold);
+        }
+    }
+}
+
+/// Draws the spectrum as columns of `#` topped with a `*`, one cell per pair
+/// of bins.
+pub fn draw_spectrum_ascii<T: CellAccessor + HasSize>(canvas: &mut T, spec: &[f32]) {
+    canvas.clear(Cell::default());
+    let (num_cols, num_rows) = canvas.size();
+    if num_rows == 0 {
+        return;
+    }
+
+    for (col_idx, chunk) in (0..num_cols).zip(spec.chunks(2)) {
+        let h = chunk.iter().fold(0.0, |a: f32, &b| a.max(b));
+        let top = num_rows - pixel_of(h, num_rows) - 1;
+        *canvas.get_mut(col_idx, top).unwrap() = Cell::with_char('*');
+        for row_idx in top + 1..num_rows {
+            *canvas.get_mut(col_idx, row_idx).unwrap() = Cell::with_char('#');
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{pixel_nums_to_braille, draw_pixel_pair};
-    use rustty::Terminal;
+    use super::{pixel_nums_to_braille, draw_pixel_pair, draw_spectrum_ascii,
+                draw_waterfall_braille, fast_log2, fold_history, normalize_row,
+                normalize_spectrum, normalize_spectrum_fast, rescale_row, frame_bytes,
+                shift_levels, draw_scope, scope_summary, add_persistence, strongest_bin_above,
+                last_lines, draw_traces, SpectrumStyle, Trace, Glyphs, Canvas, draw_spectrum,
+                draw_waterfall, CURSOR_MOVE_BYTES, SCOPE_COLS, SCOPE_ROWS, STYLE_BYTES};
+    use std::iter;
+    use std::sync::Arc;
+    use num::Complex;
+    use history::Row;
+    use palette::Palette;
+    use backend::TestBackend;
+    use cells::{Attr, Cell, CellAccessor, Color, HasPosition, HasSize, Widget};
 
     #[test]
     fn test_pixel_nums() {
@@ -257,32 +3322,316 @@ mod tests {
         assert_eq!(pixel_nums_to_braille(None, None), '⠀');
     }
 
+    #[test]
+    fn test_canvas() {
+        let mut canvas = Canvas::with_backend(TestBackend::new(20, 9));
+        canvas.set_num_peaks(0);
+        canvas.set_tuning(100e6, 2e6);
+        // 40 bins, all at 0 dB but a 50 dB tone 10 bins above the center
+        let mut spec = vec![Complex::new(1.0, 0.0); canvas.get_spectrum_width()];
+        spec[10] = Complex::new(1e5, 0.0);
+        canvas.add_spectrum(&spec);
+
+        let term = canvas.get_term();
+        assert_eq!(term.frames(), 1);
+        // the tone's column goes to the top, the rest are at the bottom
+        assert!(term.screen_line(0).starts_with("DC (LO leakage)⡇"));
+        assert_eq!(term.screen_line(1), format!("{:15}⡇{:4}", "", ""));
+        assert_eq!(term.screen_line(3), format!("{}⣇{}", "⣀".repeat(15), "⣀".repeat(4)));
+        // the waterfall, a half block each, the tone brightest
+        let top = &term.screen()[4 * 20..5 * 20];
+        assert!(top.iter().all(|cell| cell.ch() == '▀'));
+        assert!(top[15].fg() != top[14].fg());
+        assert!(term.screen_line(8).starts_with("100MHz  span 2MHz"));
+
+        // a smaller terminal, drawn again at its size
+        canvas.get_term().resize(10, 5);
+        canvas.draw_pending_frame();
+        let term = canvas.get_term();
+        assert_eq!(term.size(), (10, 5));
+        assert!(term.screen_line(4).starts_with("100MHz"));
+    }
+
+    #[test]
+    fn test_damage() {
+        let mut canvas = Canvas::with_backend(TestBackend::new(20, 9));
+        let spec = vec![Complex::new(1.0, 0.0); canvas.get_spectrum_width()];
+        canvas.add_spectrum(&spec);
+        // scribbled over the spectrum view and the waterfall
+        let x = Cell::new('x', Color::Default, Color::Default, Attr::Default);
+        canvas.get_term().cellvec_mut()[0] = x;
+        canvas.get_term().cellvec_mut()[4 * 20] = x;
+
+        // only the waterfall is drawn again for a notice
+        canvas.set_notice(Some("notice".to_string()));
+        assert_eq!(canvas.get_term().screen_line(0).chars().next(), Some('x'));
+        assert!(canvas.get_term().screen_line(4).starts_with('▀'));
+
+        // but all of it after the cells are cleared
+        canvas.get_term().resize(20, 9);
+        canvas.draw_pending_frame();
+        assert!(canvas.get_term().screen_line(0).starts_with("DC"));
+    }
+
+    #[test]
+    fn test_draw_into_widget() {
+        // a spectrum and a waterfall in the right half of a bigger screen
+        let mut term = TestBackend::new(8, 4);
+        let mut spectrum = Widget::new(4, 2);
+        let mut waterfall = Widget::new(4, 2);
+        spectrum.set_origin((4, 0));
+        waterfall.set_origin((4, 2));
+        let spec = [0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0];
+        draw_spectrum(&mut spectrum, &spec);
+        draw_waterfall(&mut waterfall, [&spec[..], &spec[..]].iter().cloned(), Palette::Classic);
+        spectrum.draw_into(&mut term);
+        waterfall.draw_into(&mut term);
+
+        let column: String = (0..4).map(|row| term.get(5, row).unwrap().ch()).collect();
+        assert_eq!(column, "⣿⣿▀ ");
+        assert!(term.cellvec()[..4].iter().all(|&cell| cell == Cell::default()));
+    }
+
     #[test]
     fn test_draw_pixel_pair() {
-        let mut term = Terminal::new().unwrap();
+        let mut term = TestBackend::new(2, 8);
+        let rows = term.size().1;
 
         // Test drawing with the same top cell
         draw_pixel_pair(&mut term, 0, 4, 6);
-        assert_eq!(term[(0, term.rows() - 3)].ch(), ' ');
-        assert_eq!(term[(0, term.rows() - 2)].ch(), '⣰');
-        assert_eq!(term[(0, term.rows() - 1)].ch(), '⣿');
-        term.clear().unwrap();
+        assert_eq!(term.get(0, rows - 3).unwrap().ch(), ' ');
+        assert_eq!(term.get(0, rows - 2).unwrap().ch(), '⣰');
+        assert_eq!(term.get(0, rows - 1).unwrap().ch(), '⣿');
+        term.clear(Cell::default());
 
         // Test drawing with the top pixel in each column being in
         // different cells
         draw_pixel_pair(&mut term, 0, 4, 8);
-        assert_eq!(term[(0, term.rows() - 4)].ch(), ' ');
-        assert_eq!(term[(0, term.rows() - 3)].ch(), '⢀');
-        assert_eq!(term[(0, term.rows() - 2)].ch(), '⣸');
-        assert_eq!(term[(0, term.rows() - 1)].ch(), '⣿');
-        term.clear().unwrap();
+        assert_eq!(term.get(0, rows - 4).unwrap().ch(), ' ');
+        assert_eq!(term.get(0, rows - 3).unwrap().ch(), '⢀');
+        assert_eq!(term.get(0, rows - 2).unwrap().ch(), '⣸');
+        assert_eq!(term.get(0, rows - 1).unwrap().ch(), '⣿');
+        term.clear(Cell::default());
 
         draw_pixel_pair(&mut term, 1, 13, 2);
-        assert_eq!(term[(1, term.rows() - 5)].ch(), ' ');
-        assert_eq!(term[(1, term.rows() - 4)].ch(), '⡄');
-        assert_eq!(term[(1, term.rows() - 3)].ch(), '⡇');
-        assert_eq!(term[(1, term.rows() - 2)].ch(), '⡇');
-        assert_eq!(term[(1, term.rows() - 1)].ch(), '⣷');
-        term.clear().unwrap();
+        assert_eq!(term.get(1, rows - 5).unwrap().ch(), ' ');
+        assert_eq!(term.get(1, rows - 4).unwrap().ch(), '⡄');
+        assert_eq!(term.get(1, rows - 3).unwrap().ch(), '⡇');
+        assert_eq!(term.get(1, rows - 2).unwrap().ch(), '⡇');
+        assert_eq!(term.get(1, rows - 1).unwrap().ch(), '⣷');
+        term.clear(Cell::default());
+    }
+
+    #[test]
+    fn test_draw_spectrum_ascii() {
+        let mut widget = Widget::new(2, 4);
+        draw_spectrum_ascii(&mut widget, &[0.5, 0.2, 0.0, 0.0]);
+        let column: String = (0..4).map(|row| widget.get(0, row).unwrap().ch()).collect();
+        assert_eq!(column, " *##");
+        let column: String = (0..4).map(|row| widget.get(1, row).unwrap().ch()).collect();
+        assert_eq!(column, "   *");
+    }
+
+    #[test]
+    fn test_draw_waterfall_braille() {
+        let mut widget = Widget::new(2, 1);
+        // a line moving right in the first column, flat in the second
+        let spectra = vec![vec![1.0, 0.0, 0.5, 0.5],
+                           vec![0.0, 1.0, 0.5, 0.5],
+                           vec![0.0, 1.0, 0.5, 0.5],
+                           vec![0.0, 1.0, 0.5, 0.5]];
+        draw_waterfall_braille(&mut widget, spectra.iter().map(|s| &s[..]), Palette::Classic);
+        assert_eq!(widget.get(0, 0).unwrap().ch(), '\u{28b1}');
+        assert_eq!(widget.get(0, 0).unwrap().fg().as_byte(), Palette::Classic.color(1.0));
+        assert_eq!(widget.get(1, 0).unwrap().ch(), '\u{28ff}');
+    }
+
+    #[test]
+    fn test_normalize_row() {
+        assert_eq!(normalize_row(&[0.25, 0.75, 0.5]), vec![0.0, 1.0, 0.5]);
+        assert_eq!(normalize_row(&[0.7, 0.7]), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_normalize_spectrum() {
+        // DC, 1, 2 then -1, at 10 dB to 40 dB
+        let spec = [Complex::new(10.0, 0.0), Complex::new(100.0, 0.0),
+                    Complex::new(1000.0, 0.0), Complex::new(10000.0, 0.0)];
+        assert_eq!(normalize_spectrum(&spec, 0.0, 40.0, false), vec![0.75, 1.0, 0.25, 0.5]);
+        // 2 is its own negative, so only 1 and -1 swap
+        assert_eq!(normalize_spectrum(&spec, 0.0, 40.0, true), vec![0.75, 0.5, 0.25, 1.0]);
+        // a range of nothing splits the powers at it
+        let flat = normalize_spectrum(&spec, 20.0, 20.0, false);
+        assert!(flat[0] > 1.0 && flat[1] > 1.0 && flat[2] < 0.0 && flat[3] == 0.0);
+        assert!(normalize_spectrum_fast(&[Complex::new(1.0, 0.0)], 0.0, 0.0, false)[0] == 0.0);
+        // far below what single precision reaches, from double
+        let spec = [Complex::new(1e-50f64, 0.0), Complex::new(1e-40, 0.0)];
+        assert_eq!(normalize_spectrum(&spec, -500.0, -400.0, false), vec![1.0, 0.0]);
+
+        // the fast way, odd lengths and nothing at all included
+        let spec: Vec<Complex<f32>> = (0..7).map(|i| Complex::new(i as f32 * 12.3, 4.0))
+                                            .chain(iter::once(Complex::new(0.0, 0.0)))
+                                            .collect();
+        for &len in &[7, 8] {
+            for &invert in &[false, true] {
+                let exact = normalize_spectrum(&spec[..len], 5.0, 35.0, invert);
+                let fast = normalize_spectrum_fast(&spec[..len], 5.0, 35.0, invert);
+                for (x, y) in exact.iter().zip(&fast) {
+                    assert!(x == y || (x - y).abs() < 1e-5, "{} and {}", x, y);
+                }
+                // levels go where the powers of their bins do
+                let levels: Vec<f32> = (0..len).map(|i| i as f32).collect();
+                let powers: Vec<Complex<f32>> = levels.iter().map(|&x| {
+                    Complex::new(10f32.powf(x / 10.0), 0.0)
+                }).collect();
+                let expected = normalize_spectrum(&powers, 0.0, 1.0, invert);
+                for (x, y) in shift_levels(&levels, invert).iter().zip(&expected) {
+                    assert!((x - y).abs() < 1e-3, "{} and {}", x, y);
+                }
+            }
+        }
+        assert_eq!(fast_log2(0.25), -2.0);
+        assert_eq!(fast_log2(0.0), ::std::f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_fold_history() {
+        let history: Vec<Arc<Row>> = (0..8).map(|i| Arc::new(Row::new(&[i as f32 / 10.0; 2])))
+                                           .collect();
+        let check = |folded: Vec<Arc<Row>>, bin: usize, expected: &[f32]| {
+            assert_eq!(folded.len(), expected.len());
+            for (row, x) in folded.iter().zip(expected) {
+                // to within the history's steps
+                assert!((row.power(bin).unwrap() - x).abs() < 0.01, "{:?}", row.powers());
+            }
+        };
+        check(fold_history(history.iter().cloned(), &[1, 1, 2, 4]), 0, &[0.0, 0.1, 0.25, 0.55]);
+        // the oldest slot takes what's left
+        check(fold_history(history.iter().take(5).cloned(), &[1, 2, 4]), 1, &[0.0, 0.15, 0.35]);
+    }
+
+    #[test]
+    fn test_add_persistence() {
+        let mut hits = Vec::new();
+        // two columns of two cells, four spots high
+        add_persistence(&mut hits, (2, 2), &[0.0, 0.1, 0.9, 1.5]);
+        assert_eq!(hits, vec![0.0, 2.0, 0.0, 0.0, 0.0, 0.0, 2.0, 0.0]);
+        add_persistence(&mut hits, (2, 2), &[0.3, 0.3, 0.9, 0.9]);
+        assert_eq!(hits, vec![0.0, 3.9, 0.0, 0.0, 2.0, 0.0, 1.9, 0.0]);
+    }
+
+    #[test]
+    fn test_rescale_row() {
+        // squeezing keeps the strongest of each pair, stretching repeats bins
+        assert_eq!(rescale_row(&[0.1, 0.9, 0.3, 0.2], 2), vec![0.9, 0.3]);
+        assert_eq!(rescale_row(&[0.1, 0.9], 4), vec![0.1, 0.1, 0.9, 0.9]);
+        assert_eq!(rescale_row(&[0.1, 0.9, 0.3], 2), vec![0.1, 0.9]);
+        assert_eq!(rescale_row(&[], 2), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_strongest_bin_above() {
+        // bin 4 is DC for 8 bins
+        let spec = [0.1, 0.6, 0.2, 0.7, 0.9, 0.3, 0.5, 0.0];
+        assert_eq!(strongest_bin_above(&spec, 0.5), Some(3));
+        assert_eq!(strongest_bin_above(&spec, 0.7), None);
+        assert_eq!(strongest_bin_above(&[], 0.5), None);
+    }
+
+    #[test]
+    fn test_draw_traces() {
+        let mut widget = Widget::new(2, 1);
+        let live = [0.0, 0.0, 0.5, 0.5];
+        let max_hold = [1.0, 0.0, 0.5, 0.75];
+        let traces = [(Trace::Live, &live[..]), (Trace::MaxHold, &max_hold[..])];
+        draw_traces(&mut widget, &traces, false, Glyphs::Braille, SpectrumStyle::Dots,
+                    Palette::Classic);
+        // the max hold's dots at the top left and the bottom right, in its
+        // color, with the live trace's along the bottom
+        assert_eq!(widget.get(0, 0).unwrap().ch(), '\u{28c1}');
+        assert_eq!(widget.get(0, 0).unwrap().fg(), Color::Red);
+        // where they meet, and one higher on the right
+        assert_eq!(widget.get(1, 0).unwrap().ch(), '\u{28fe}');
+
+        draw_traces(&mut widget, &traces, true, Glyphs::Braille, SpectrumStyle::Dots,
+                    Palette::Classic);
+        assert_eq!(widget.get(0, 0).unwrap().ch(), '^');
+        assert_eq!(widget.get(1, 0).unwrap().ch(), '^');
+
+        // solid below the top row, colored by height
+        let mut widget = Widget::new(1, 2);
+        draw_traces(&mut widget, &[(Trace::Live, &[0.5, 1.0])], false, Glyphs::Braille,
+                    SpectrumStyle::Gradient, Palette::Classic);
+        assert_eq!(widget.get(0, 0).unwrap().ch(), '\u{28f8}');
+        assert_eq!(widget.get(0, 1).unwrap().ch(), '█');
+        assert_eq!(widget.get(0, 1).unwrap().fg(), Color::Byte(Palette::Classic.color(0.25)));
+
+        // dots colored by the column's height
+        draw_traces(&mut widget, &[(Trace::Live, &[0.5, 1.0])], false, Glyphs::Braille,
+                    SpectrumStyle::Colored, Palette::Classic);
+        assert_eq!(widget.get(0, 1).unwrap().ch(), '\u{28ff}');
+        assert_eq!(widget.get(0, 1).unwrap().fg(), Color::Byte(Palette::Classic.color(1.0)));
+
+        // eighth blocks, with the max hold as characters over them
+        let mut widget = Widget::new(2, 2);
+        let live = [0.5, 0.5, 0.3, 0.0];
+        let max_hold = [1.0, 0.0, 0.0, 0.0];
+        let traces = [(Trace::Live, &live[..]), (Trace::MaxHold, &max_hold[..])];
+        draw_traces(&mut widget, &traces, false, Glyphs::Blocks, SpectrumStyle::Dots,
+                    Palette::Classic);
+        assert_eq!(widget.get(0, 1).unwrap().ch(), '█');
+        assert_eq!(widget.get(0, 0).unwrap().ch(), '^');
+        assert_eq!(widget.get(0, 0).unwrap().fg(), Color::Red);
+        assert_eq!(widget.get(1, 0).unwrap().ch(), ' ');
+        assert_eq!(widget.get(1, 1).unwrap().ch(), '^');
+
+        draw_traces(&mut widget, &traces[..1], false, Glyphs::Blocks, SpectrumStyle::Dots,
+                    Palette::Classic);
+        assert_eq!(widget.get(0, 0).unwrap().ch(), '▁');
+        assert_eq!(widget.get(1, 1).unwrap().ch(), '▅');
+    }
+
+    #[test]
+    fn test_last_lines() {
+        assert_eq!(last_lines("CQ CQ DE N0CALL", 6, 2), vec!["DE N0C", "ALL"]);
+        assert_eq!(last_lines("CQ", 6, 2), vec!["CQ"]);
+        assert!(last_lines("", 6, 2).is_empty());
+    }
+
+    #[test]
+    fn test_draw_scope() {
+        // full scale up and to the left, the middle, and clipped right
+        let samples = [Complex::new(-128i8, 127i8), Complex::new(0, 0), Complex::new(127, 0)];
+        let scope = draw_scope(&samples, (80, 24), false);
+        assert_eq!(scope.origin(), (80 - SCOPE_COLS, 0));
+        let dot = |col: usize, row: usize| scope.get(col, row).unwrap().ch();
+        assert_eq!(dot(1, 1), '\u{2801}');
+        // just below the middle, as 0 is the upper of the two middle values
+        assert_eq!(dot(15, 6), '\u{2840}');
+        assert_eq!(dot(28, 6), '\u{2880}');
+        let bottom: String = (1..SCOPE_COLS - 1).map(|col| dot(col, SCOPE_ROWS - 1)).collect();
+        assert_eq!(bottom, "DC 33.1% IQ +4.8dB clip 67%─");
+
+        assert_eq!(scope_summary(&[]), None);
+        let balanced: Vec<Complex<i8>> = (0..4).map(|i| {
+            Complex::new([10, 0, -10, 0][i], [0, 10, 0, -10][i])
+        }).collect();
+        assert_eq!(scope_summary(&balanced), Some("DC 0.0% IQ +0.0dB clip 0%".to_string()));
+    }
+
+    #[test]
+    fn test_frame_bytes() {
+        let blank = vec![Cell::default(); 8];
+        let mut next = blank.clone();
+        next[1] = Cell::with_char('a');
+        next[2] = Cell::with_char('b');
+        // a cursor move then two characters in a row of one style
+        assert_eq!(frame_bytes(&blank, &next, 4), CURSOR_MOVE_BYTES + STYLE_BYTES + 2);
+
+        // a change of style, and a jump to the next row
+        next[5] = Cell::new('\u{2588}', Color::Red, Color::Default, Attr::Default);
+        assert_eq!(frame_bytes(&blank, &next, 4),
+                   2 * (CURSOR_MOVE_BYTES + STYLE_BYTES) + 2 + 3);
+        assert_eq!(frame_bytes(&next, &next, 4), 0);
     }
 }
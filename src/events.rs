@@ -0,0 +1,201 @@
+//! A log of the signals seen during a run, one line for each when it goes
+//! away, so the band's occupancy can be reviewed afterwards.
+//!
+//! The file is CSV, with a header line and then:
+//!
+//! ```text
+//! <start>,<end>,<freq-hz>,<peak-db>,<duration-secs>
+//! ```
+//!
+//! where the times are local, as `YYYY-MM-DD HH:MM:SS`, and the frequency is
+//! where the signal was strongest.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::{Duration, SystemTime};
+use num::Complex;
+
+use export::{local_date_time_at, shifted_powers_db};
+use processing::find_peaks;
+
+/// Detection settings, matching the report.
+const THRESHOLD_DB: f32 = 10.0;
+const GUARD_BINS: usize = 2;
+const TRAINING_BINS: usize = 16;
+const MAX_PEAKS: usize = 32;
+
+/// A peak this many bins or fewer from where a signal was last seen is the
+/// same signal, drifting.
+const MATCH_BINS: f64 = 3.0;
+
+/// A signal that hasn't been seen for this long has ended.
+const HANG_TIME: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Signal {
+    start: SystemTime,
+    end: SystemTime,
+    /// Where it was last seen, and where it was strongest.
+    last_freq_hz: f64,
+    freq_hz: f64,
+    peak_db: f32,
+}
+
+pub struct EventLog {
+    out: BufWriter<File>,
+    /// Signals that haven't ended yet.
+    open: Vec<Signal>,
+}
+
+impl EventLog {
+    pub fn create(path: &str) -> io::Result<Self> {
+        let mut out = BufWriter::new(try!(File::create(path)));
+        try!(writeln!(out, "start,end,freq_hz,peak_db,duration_secs"));
+        try!(out.flush());
+        Ok(EventLog { out: out, open: Vec::new() })
+    }
+
+    /// Looks for signals in an unshifted FFT output taken at the given
+    /// tuning, logging those that have ended.
+    pub fn add_spectrum(&mut self, spec: &[Complex<f32>], center_freq_hz: f64,
+                        sample_rate_hz: f64) -> io::Result<()> {
+        let powers = shifted_powers_db(spec);
+        let (_, ended) = add_powers(&mut self.open, &powers, center_freq_hz, sample_rate_hz,
+                                    SystemTime::now());
+        self.write(&ended)
+    }
+
+    /// Logs every signal still going, for a retune or the end of the run.
+    pub fn end_all(&mut self) -> io::Result<()> {
+        let ended: Vec<Signal> = self.open.drain(..).collect();
+        self.write(&ended)
+    }
+
+    fn write(&mut self, signals: &[Signal]) -> io::Result<()> {
+        if signals.is_empty() {
+            return Ok(());
+        }
+        for signal in signals {
+            try!(writeln!(self.out, "{}", event_line(signal)));
+        }
+        // a run left going overnight should have its log readable as it goes
+        self.out.flush()
+    }
+}
+
+/// Spots signals as they appear, the same way as the log, for running a
+/// command on each.
+pub struct Detector {
+    open: Vec<Signal>,
+}
+
+impl Detector {
+    pub fn new() -> Self {
+        Detector { open: Vec::new() }
+    }
+
+    /// Looks for signals in an unshifted FFT output taken at the given
+    /// tuning, returning the frequency and power of each new one.
+    pub fn add_spectrum(&mut self, spec: &[Complex<f32>], center_freq_hz: f64,
+                        sample_rate_hz: f64) -> Vec<(f64, f32)> {
+        let powers = shifted_powers_db(spec);
+        let (started, _) = add_powers(&mut self.open, &powers, center_freq_hz, sample_rate_hz,
+                                      SystemTime::now());
+        started.iter().map(|s| (s.freq_hz, s.peak_db)).collect()
+    }
+
+    /// Forgets the signals seen so far, for a retune.
+    pub fn clear(&mut self) {
+        self.open.clear();
+    }
+}
+
+/// Matches the peaks in a shifted spectrum of powers in dB to the open
+/// signals, returning those that have just started and those that have ended.
+fn add_powers(open: &mut Vec<Signal>, powers: &[f32], center_freq_hz: f64,
+              sample_rate_hz: f64, now: SystemTime) -> (Vec<Signal>, Vec<Signal>) {
+    if powers.is_empty() {
+        return (Vec::new(), Vec::new());
+    }
+    let mut started = Vec::new();
+    let bin_width_hz = sample_rate_hz / powers.len() as f64;
+    let dc = powers.len() / 2;
+
+    for peak in find_peaks(powers, GUARD_BINS, TRAINING_BINS, THRESHOLD_DB, MAX_PEAKS) {
+        // the LO leakage isn't a signal
+        if peak.bin == dc {
+            continue;
+        }
+        let freq_hz = center_freq_hz + (peak.bin as f64 - dc as f64) * bin_width_hz;
+        let matched = open.iter_mut().find(|s| {
+            (s.last_freq_hz - freq_hz).abs() <= MATCH_BINS * bin_width_hz
+        });
+        match matched {
+            Some(signal) => {
+                signal.end = now;
+                signal.last_freq_hz = freq_hz;
+                if peak.power > signal.peak_db {
+                    signal.peak_db = peak.power;
+                    signal.freq_hz = freq_hz;
+                }
+            }
+            None => {
+                let signal = Signal {
+                    start: now,
+                    end: now,
+                    last_freq_hz: freq_hz,
+                    freq_hz: freq_hz,
+                    peak_db: peak.power,
+                };
+                open.push(signal);
+                started.push(signal);
+            }
+        }
+    }
+
+    let (ended, still_open) = open.drain(..).partition(|s| {
+        now.duration_since(s.end).map_or(false, |since| since >= HANG_TIME)
+    });
+    *open = still_open;
+    (started, ended)
+}
+
+fn event_line(signal: &Signal) -> String {
+    let (start_date, start_time) = local_date_time_at(signal.start);
+    let (end_date, end_time) = local_date_time_at(signal.end);
+    let duration = signal.end.duration_since(signal.start).unwrap_or(Duration::from_secs(0));
+    let secs = duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9;
+    format!("{} {},{} {},{:.0},{:.1},{:.1}", start_date, start_time, end_date, end_time,
+            signal.freq_hz, signal.peak_db, secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+    use super::add_powers;
+
+    #[test]
+    fn test_add_powers() {
+        let mut open = Vec::new();
+        let at = |ms| UNIX_EPOCH + Duration::from_millis(ms);
+        for i in 0..5 {
+            let mut powers = vec![0.0; 64];
+            // drifting by a bin at 1 kHz per bin, strongest in the middle
+            powers[40 + i % 2] = if i == 2 { 40.0 } else { 30.0 };
+            let (started, ended) = add_powers(&mut open, &powers, 1_000_000.0, 64_000.0,
+                                              at(100 * i as u64));
+            // only new the first time it's seen
+            assert_eq!(started.len(), if i == 0 { 1 } else { 0 });
+            assert!(ended.is_empty());
+        }
+        assert_eq!(open.len(), 1);
+
+        // still in its hang time
+        assert!(add_powers(&mut open, &[0.0; 64], 1_000_000.0, 64_000.0, at(800)).1.is_empty());
+        let (_, ended) = add_powers(&mut open, &[0.0; 64], 1_000_000.0, 64_000.0, at(900));
+        assert_eq!(ended.len(), 1);
+        assert!(open.is_empty());
+        assert_eq!((ended[0].start, ended[0].end), (at(0), at(400)));
+        assert_eq!((ended[0].freq_hz, ended[0].peak_db), (1_008_000.0, 40.0));
+    }
+}
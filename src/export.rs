@@ -0,0 +1,267 @@
+//! Writing spectrum data out to files.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::time::{SystemTime, UNIX_EPOCH};
+use libc;
+use num::Complex;
+
+use cells::{Attr, Cell, Color};
+use drawing::RegionData;
+use palette::Palette;
+use png::Appender;
+
+/// Writes `region` as CSV: a header row of bin frequencies in Hz, then one
+/// row per spectrum starting with how many spectra back from the newest it
+/// is, followed by its powers in dB.
+pub fn write_region_csv(path: &str, region: &RegionData) -> io::Result<()> {
+    let mut out = BufWriter::new(try!(File::create(path)));
+    try!(write!(out, "spectra_back"));
+    for freq_hz in &region.freqs_hz {
+        try!(write!(out, ",{:.0}", freq_hz));
+    }
+    try!(writeln!(out));
+
+    for &(back, ref powers) in &region.spectra {
+        try!(write!(out, "{}", back));
+        for power in powers {
+            try!(write!(out, ",{:.2}", power));
+        }
+        try!(writeln!(out));
+    }
+    out.flush()
+}
+
+/// Writes the screen, `cells` row by row `cols` wide, as text with the ANSI
+/// escape sequences for its colors, to `cat` later.
+pub fn write_screen_ansi(path: &str, cells: &[Cell], cols: usize) -> io::Result<()> {
+    let mut out = BufWriter::new(try!(File::create(path)));
+    try!(out.write_all(screen_ansi(cells, cols).as_bytes()));
+    out.flush()
+}
+
+fn screen_ansi(cells: &[Cell], cols: usize) -> String {
+    let mut text = String::new();
+    for row in cells.chunks(cols) {
+        // blanks at the end of a row aren't worth keeping
+        let len = row.iter().rposition(|cell| *cell != Cell::default()).map_or(0, |i| i + 1);
+        let mut style = None;
+        for cell in &row[..len] {
+            let cell_style = (cell.fg(), cell.bg(), cell.attrs());
+            if style != Some(cell_style) {
+                text.push_str(&sgr(cell));
+                style = Some(cell_style);
+            }
+            text.push(cell.ch());
+        }
+        if style.is_some() {
+            text.push_str("\x1b[0m");
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// The escape sequence setting `cell`'s colors and attributes, from scratch.
+fn sgr(cell: &Cell) -> String {
+    let mut codes = vec!["0".to_string()];
+    let attrs = cell.attrs() as u8;
+    for &(bit, code) in &[(Attr::Bold as u8, "1"), (Attr::Underline as u8, "4"),
+                          (Attr::Reverse as u8, "7")] {
+        if attrs & bit != 0 {
+            codes.push(code.to_string());
+        }
+    }
+    if let Some(code) = sgr_color(cell.fg()) {
+        codes.push(format!("38;{}", code));
+    }
+    if let Some(code) = sgr_color(cell.bg()) {
+        codes.push(format!("48;{}", code));
+    }
+    format!("\x1b[{}m", codes.join(";"))
+}
+
+/// How `color` follows the code for a foreground or background, if it isn't
+/// the default.
+fn sgr_color(color: Color) -> Option<String> {
+    match color {
+        Color::Default => None,
+        Color::Rgb(r, g, b) => Some(format!("2;{};{};{}", r, g, b)),
+        color => Some(format!("5;{}", color.as_byte())),
+    }
+}
+
+/// Opens `path` for appending, creating it if needed, or stdout for `-`.
+fn open_output(path: &str) -> io::Result<BufWriter<Box<dyn Write>>> {
+    let out: Box<dyn Write> = if path == "-" {
+        Box::new(io::stdout())
+    } else {
+        Box::new(try!(OpenOptions::new().append(true).create(true).open(path)))
+    };
+    Ok(BufWriter::new(out))
+}
+
+/// The power of each bin of an unshifted FFT output in dB, lowest frequency
+/// first.
+pub fn shifted_powers_db(spec: &[Complex<f32>]) -> Vec<f32> {
+    let (first_half, last_half) = spec.split_at((spec.len() + 1) / 2);
+    last_half.iter().chain(first_half.iter()).map(|x| 10.0 * x.norm().log10()).collect()
+}
+
+/// Appends spectra to a file in rtl_power's CSV format, so its tools (like
+/// heatmap.py) can process long captures. Each row is the local date and time,
+/// the lowest and highest frequency and the bin width in Hz, the number of
+/// samples in the FFT, then the power of each bin in dB, lowest bin first.
+pub struct RtlPowerWriter {
+    out: BufWriter<Box<dyn Write>>,
+}
+
+impl RtlPowerWriter {
+    /// Opens `path` for appending, creating it if needed, or stdout for `-`.
+    pub fn open(path: &str) -> io::Result<Self> {
+        Ok(RtlPowerWriter { out: try!(open_output(path)) })
+    }
+
+    /// Writes an unshifted FFT output taken at the given tuning as a row.
+    pub fn write_spectrum(&mut self, spec: &[Complex<f32>], center_freq_hz: f64,
+                          sample_rate_hz: f64) -> io::Result<()> {
+        let (date, time) = local_date_time();
+        try!(write!(self.out, "{}, {}, {:.0}, {:.0}, {:.2}, {}", date, time,
+                    center_freq_hz - sample_rate_hz / 2.0, center_freq_hz + sample_rate_hz / 2.0,
+                    sample_rate_hz / spec.len() as f64, spec.len()));
+        for power in shifted_powers_db(spec) {
+            try!(write!(self.out, ", {:.2}", power));
+        }
+        try!(writeln!(self.out));
+        // a capture is usually ended by killing it, so don't leave rows buffered
+        self.out.flush()
+    }
+}
+
+/// Appends spectra as JSON, one object per line:
+///
+/// ```text
+/// {"time_ms":1700000000000,"center_freq_hz":100000000,"sample_rate_hz":2000000,"powers_db":[...]}
+/// ```
+///
+/// with the time since the Unix epoch and the powers lowest bin first.
+pub struct JsonWriter {
+    out: BufWriter<Box<dyn Write>>,
+}
+
+impl JsonWriter {
+    /// Opens `path` for appending, creating it if needed, or stdout for `-`.
+    pub fn open(path: &str) -> io::Result<Self> {
+        Ok(JsonWriter { out: try!(open_output(path)) })
+    }
+
+    /// Writes an unshifted FFT output taken at the given tuning as a line.
+    pub fn write_spectrum(&mut self, spec: &[Complex<f32>], center_freq_hz: f64,
+                          sample_rate_hz: f64) -> io::Result<()> {
+        try!(writeln!(self.out, "{}", spectrum_json(spec, center_freq_hz, sample_rate_hz)));
+        self.out.flush()
+    }
+}
+
+/// An unshifted FFT output taken at the given tuning as a `JsonWriter` line,
+/// without the newline.
+pub fn spectrum_json(spec: &[Complex<f32>], center_freq_hz: f64, sample_rate_hz: f64) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let ms = now.as_secs() * 1000 + (now.subsec_nanos() / 1_000_000) as u64;
+    let mut json = format!("{{\"time_ms\":{},\"center_freq_hz\":{:.0},\"sample_rate_hz\":{:.0},\
+                            \"powers_db\":[",
+                           ms, center_freq_hz, sample_rate_hz);
+    for (i, power) in shifted_powers_db(spec).iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        // JSON has no infinities, which an empty bin gives
+        if power.is_finite() {
+            json.push_str(&format!("{:.2}", power));
+        } else {
+            json.push_str("null");
+        }
+    }
+    json.push_str("]}");
+    json
+}
+
+/// Adds every spectrum as a row of pixels at the bottom of a PNG, colored like
+/// the waterfall, so a long run can be looked over as one tall image. Rows
+/// keep the width of the first spectrum, later ones stretched or squeezed to
+/// it.
+pub struct WaterfallPng {
+    /// The file until the first spectrum gives the width.
+    file: Option<File>,
+    png: Option<Appender<File>>,
+    min_db: f32,
+    max_db: f32,
+    palette: Palette,
+}
+
+impl WaterfallPng {
+    /// Creates `path`, to color powers from `min_db` to `max_db` with
+    /// `palette`.
+    pub fn create(path: &str, min_db: f32, max_db: f32, palette: Palette) -> io::Result<Self> {
+        let file = try!(OpenOptions::new().read(true).write(true).create(true).truncate(true)
+                                          .open(path));
+        Ok(WaterfallPng { file: Some(file), png: None, min_db: min_db, max_db: max_db,
+                          palette: palette })
+    }
+
+    /// Adds an unshifted FFT output as the bottom row.
+    pub fn write_spectrum(&mut self, spec: &[Complex<f32>]) -> io::Result<()> {
+        let powers = shifted_powers_db(spec);
+        if powers.is_empty() {
+            return Ok(());
+        }
+        if let Some(file) = self.file.take() {
+            self.png = Some(try!(Appender::new(file, powers.len())));
+        }
+        let png = self.png.as_mut().unwrap();
+        let width = png.width();
+        let mut row = Vec::with_capacity(3 * width);
+        for i in 0..width {
+            let power = powers[i * powers.len() / width];
+            let (r, g, b) = self.palette.rgb((power - self.min_db) / (self.max_db - self.min_db));
+            row.extend(&[r, g, b]);
+        }
+        png.add_row(&row)
+    }
+}
+
+/// The current local date and time, as `YYYY-MM-DD` and `HH:MM:SS`.
+pub fn local_date_time() -> (String, String) {
+    local_date_time_at(SystemTime::now())
+}
+
+/// `time` as a local date and time, like `local_date_time`.
+pub fn local_date_time_at(time: SystemTime) -> (String, String) {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let t = secs as libc::time_t;
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    unsafe {
+        libc::localtime_r(&t, &mut tm);
+    }
+    (format!("{:04}-{:02}-{:02}", tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday),
+     format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec))
+}
+
+#[cfg(test)]
+mod tests {
+    use cells::{Attr, Cell, Color};
+    use super::screen_ansi;
+
+    #[test]
+    fn test_screen_ansi() {
+        let red = Cell::new('#', Color::Red, Color::Default, Attr::Bold);
+        let cells = [red, red, Cell::with_char('x'), Cell::default(),
+                     Cell::default(), Cell::default(), Cell::default(), Cell::default()];
+        assert_eq!(screen_ansi(&cells, 4), "\x1b[0;1;38;5;1m##\x1b[0mx\x1b[0m\n\n");
+        let blue = Cell::new(' ', Color::Default, Color::Byte(21), Attr::Default);
+        assert_eq!(screen_ansi(&[blue], 1), "\x1b[0;48;5;21m \x1b[0m\n");
+        let orange = Cell::new('o', Color::Rgb(255, 128, 0), Color::Default, Attr::Default);
+        assert_eq!(screen_ansi(&[orange], 1), "\x1b[0;38;2;255;128;0mo\x1b[0m\n");
+    }
+}
@@ -0,0 +1,125 @@
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+use num::Complex;
+
+use processing::SignalProcessor;
+use radio::hackrf::{HackRF, LNA_GAINS_DB, VGA_GAIN_MAX_DB};
+
+/// FFT length used for the sweep measurements.
+const FFT_LEN: usize = 1024;
+const FFT_RATE_HZ: u32 = 50;
+/// The VGA is stepped more coarsely than the hardware allows to keep the sweep short.
+const VGA_SWEEP_STEP_DB: u32 = 8;
+
+/// Averages over one gain setting, powers in dB as the spectrum view shows them.
+#[derive(Debug, Clone, Copy)]
+pub struct Level {
+    /// Median bin power.
+    pub noise_floor_db: f32,
+    /// Strongest bin power, ignoring the DC bin.
+    pub peak_db: f32,
+    /// Fraction of samples at the limits of the ADC.
+    pub clipped_fraction: f32,
+}
+
+impl Level {
+    pub fn snr_db(&self) -> f32 {
+        self.peak_db - self.noise_floor_db
+    }
+}
+
+struct Measurement {
+    lna_gain_db: u32,
+    vga_gain_db: u32,
+    level: Level,
+}
+
+/// Steps through the LNA and VGA gains, measuring the noise floor (median bin
+/// power) and the strongest signal at each setting, and prints a table. The
+/// FFTs are shared out between `threads` threads.
+pub fn run(radio: &mut HackRF, sample_rate_hz: u32, threads: usize, dwell: Duration) {
+    let recv = radio.start_rx();
+    let mut measurements = Vec::new();
+
+    println!("{:>4} {:>4} {:>10} {:>10} {:>8}", "LNA", "VGA", "noise(dB)", "peak(dB)", "SNR(dB)");
+    for &lna in LNA_GAINS_DB.iter() {
+        for vga in (0..VGA_GAIN_MAX_DB + 1).filter(|g| g % VGA_SWEEP_STEP_DB == 0) {
+            radio.set_lna_gain(lna).expect("Couldn't set LNA gain");
+            radio.set_vga_gain(vga).expect("Couldn't set VGA gain");
+
+            let m = match measure(&recv, sample_rate_hz, FFT_LEN, threads, dwell) {
+                Some(level) => Measurement { lna_gain_db: lna, vga_gain_db: vga, level: level },
+                None => {
+                    println!("{:>4} {:>4}  no samples received", lna, vga);
+                    continue;
+                }
+            };
+
+            let l = m.level;
+            let bar: String = (0..l.snr_db().max(0.0) as usize).map(|_| '#').collect();
+            println!("{:>4} {:>4} {:>10.1} {:>10.1} {:>8.1} {}",
+                     m.lna_gain_db, m.vga_gain_db, l.noise_floor_db, l.peak_db, l.snr_db(), bar);
+            measurements.push(m);
+        }
+    }
+
+    radio.stop_rx().expect("Couldn't stop receiving");
+
+    let best = measurements.iter().fold(None, |best: Option<&Measurement>, m| match best {
+        Some(b) if b.level.snr_db() >= m.level.snr_db() => Some(b),
+        _ => Some(m),
+    });
+    if let Some(m) = best {
+        println!("\nBest SNR {:.1} dB with --lna-gain={} --vga-gain={}",
+                 m.level.snr_db(), m.lna_gain_db, m.vga_gain_db);
+    }
+}
+
+/// Measures the samples arriving over `dwell` with `fft_len` point FFTs, on
+/// `threads` threads. The powers depend on the FFT length, so pass the
+/// display's to compare them with the spectrum view. Returns `None` if no
+/// spectra were produced.
+pub fn measure(recv: &Receiver<Vec<Complex<i8>>>, sample_rate_hz: u32, fft_len: usize,
+               threads: usize, dwell: Duration) -> Option<Level> {
+    // throw away anything captured before the gain change took effect
+    while let Ok(_) = recv.try_recv() {}
+
+    let mut processor = SignalProcessor::builder(sample_rate_hz).fft_rate_hz(FFT_RATE_HZ)
+                                                                .fft_len(fft_len)
+                                                                .threads(threads)
+                                                                .build();
+    let (mut noise_sum, mut peak_sum, mut count) = (0.0, 0.0, 0);
+    let (mut num_samples, mut num_clipped) = (0u64, 0u64);
+    let start = Instant::now();
+    while start.elapsed() < dwell {
+        let buff = match recv.recv_timeout(dwell) {
+            Ok(buff) => buff,
+            Err(_) => break,
+        };
+        num_samples += buff.len() as u64;
+        num_clipped += buff.iter().filter(|x| {
+            x.re == -128 || x.re == 127 || x.im == -128 || x.im == 127
+        }).count() as u64;
+
+        for spectrum in processor.add_signal_buffer(buff) {
+            // skip the DC bin, it's LO leakage rather than signal
+            let mut powers: Vec<f32> = spectrum[1..].iter()
+                                                    .map(|x| 10.0 * x.norm().log10())
+                                                    .collect();
+            powers.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            noise_sum += powers[powers.len() / 2];
+            peak_sum += powers[powers.len() - 1];
+            count += 1;
+        }
+    }
+
+    if count == 0 {
+        None
+    } else {
+        Some(Level {
+            noise_floor_db: noise_sum / count as f32,
+            peak_db: peak_sum / count as f32,
+            clipped_fraction: num_clipped as f32 / num_samples as f32,
+        })
+    }
+}
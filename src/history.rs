@@ -0,0 +1,310 @@
+//! The waterfall's history, a byte to each bin. Spectra come in scaled
+//! between 0 and 1, as the `draw_` functions take them, and are kept a
+//! quarter the size of floats, so a deep history fits. Only the newest is
+//! kept at full precision, by the pane, for the spectrum view. For deeper
+//! still, all but the newest rows can go to a file, read back as they're
+//! scrolled to.
+
+use std::cmp::min;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use drawing::rescale_row;
+
+/// The powers kept, past the range shown a little below the bottom and a
+/// bit more above the top, where strong signals go, so rows can still be
+/// rescaled to their own range. Beyond it they're clamped, and nothing at
+/// all is the bottom. The bottom and top of the range shown come out exact.
+const LOWEST: f32 = -0.2;
+const HIGHEST: f32 = 1.3;
+
+/// Rows kept in memory by a history that spills the rest to a file.
+const IN_MEMORY: usize = 4096;
+
+/// The file's dead part, from rows let go, is only reclaimed once it's at
+/// least this many bytes and half the file.
+const MIN_RECLAIM_BYTES: u64 = 1 << 20;
+
+/// Bytes moved at a time while reclaiming.
+const COPY_BYTES: usize = 1 << 16;
+
+/// For naming the files of histories made by the same process apart.
+static NUM_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Levels in each of the powers between 0 and 1, 170: a step of about a
+/// third of a dB over a 50 dB range.
+const SCALE: f32 = 255.0 / (HIGHEST - LOWEST);
+
+/// A spectrum of the history, lowest frequency first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    levels: Vec<u8>,
+}
+
+impl Row {
+    pub fn new(powers: &[f32]) -> Self {
+        // out of range casts saturate, and NaN goes to 0
+        Row { levels: powers.iter().map(|&x| ((x - LOWEST) * SCALE).round() as u8).collect() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn power(&self, bin: usize) -> Option<f32> {
+        self.levels.get(bin).map(|&level| to_power(level))
+    }
+
+    /// The powers, scaled between 0 and 1 like those it was made from.
+    pub fn powers(&self) -> Vec<f32> {
+        self.levels.iter().map(|&level| to_power(level)).collect()
+    }
+}
+
+fn to_power(level: u8) -> f32 {
+    level as f32 / SCALE + LOWEST
+}
+
+/// Up to `max_len` spectra, newest first, the oldest let go as new ones
+/// come. Shared rows stay valid after they're let go, for the renderer.
+pub struct History {
+    /// Those in memory, the newest ones.
+    rows: VecDeque<Arc<Row>>,
+    max_len: usize,
+    /// Bins rows read back from the file are rescaled to, if there are any.
+    num_bins: usize,
+    spill: Option<Spill>,
+}
+
+/// The older rows of a history, in a file that's already removed, so it's
+/// gone once the history is even if the process is killed.
+struct Spill {
+    file: File,
+    /// Where each row starts in the file and its bins, newest first.
+    rows: VecDeque<(u64, usize)>,
+    /// End of the last row written. Everything before the oldest row's
+    /// start is from rows let go.
+    end: u64,
+}
+
+impl History {
+    /// A history kept all in memory.
+    pub fn new(max_len: usize) -> Self {
+        History { rows: VecDeque::new(), max_len: max_len, num_bins: 0, spill: None }
+    }
+
+    /// A history keeping all but its newest rows in a file under `dir`.
+    pub fn spilled(max_len: usize, dir: &Path) -> io::Result<Self> {
+        let path = dir.join(format!("tspec-history-{}-{}", process::id(),
+                                    NUM_FILES.fetch_add(1, Ordering::Relaxed)));
+        let file = try!(OpenOptions::new().read(true).write(true).create_new(true).open(&path));
+        // only where open files can be removed, but that's wherever the
+        // terminal handling works
+        let _ = fs::remove_file(&path);
+        let mut history = History::new(max_len);
+        history.spill = Some(Spill { file: file, rows: VecDeque::new(), end: 0 });
+        Ok(history)
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len() + self.spill.as_ref().map_or(0, |spill| spill.rows.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    pub fn front(&self) -> Option<&Arc<Row>> {
+        self.rows.front()
+    }
+
+    /// The row `t` spectra back from the newest. If it can't be read back
+    /// from the file, `None`, as if it was never there.
+    pub fn get(&self, t: usize) -> Option<Arc<Row>> {
+        if t < self.rows.len() {
+            return Some(self.rows[t].clone());
+        }
+        let spill = match self.spill {
+            Some(ref spill) => spill,
+            None => return None,
+        };
+        let (start, len) = match spill.rows.get(t - self.rows.len()) {
+            Some(&at) => at,
+            None => return None,
+        };
+        let mut levels = vec![0; len];
+        let mut file = &spill.file;
+        if file.seek(SeekFrom::Start(start)).and_then(|_| file.read_exact(&mut levels)).is_err() {
+            return None;
+        }
+        let row = Row { levels: levels };
+        if self.num_bins > 0 && len != self.num_bins {
+            // written before a resize
+            return Some(Arc::new(Row::new(&rescale_row(&row.powers(), self.num_bins))));
+        }
+        Some(Arc::new(row))
+    }
+
+    /// The rows from `t` spectra back, newest first.
+    pub fn iter_from(&self, t: usize) -> Iter<'_> {
+        Iter { history: self, next: t }
+    }
+
+    pub fn push_front(&mut self, row: Arc<Row>) {
+        self.rows.push_front(row);
+        if self.spill.is_some() && self.rows.len() > IN_MEMORY {
+            let oldest = self.rows.pop_back().unwrap();
+            let written = self.spill.as_mut().unwrap().write(&oldest);
+            if written.is_err() {
+                // out of room, so the history's only what's in memory
+                self.spill = None;
+            }
+        }
+        while self.len() > self.max_len {
+            match self.spill {
+                Some(ref mut spill) if !spill.rows.is_empty() => {
+                    spill.rows.pop_back();
+                }
+                _ => {
+                    self.rows.pop_back();
+                }
+            }
+        }
+    }
+
+    /// Stretches or squeezes the rows to `num_bins` bins. Those in the file
+    /// are only rescaled as they're read back.
+    pub fn fit(&mut self, num_bins: usize) {
+        for row in self.rows.iter_mut() {
+            if row.len() != num_bins {
+                *row = Arc::new(Row::new(&rescale_row(&row.powers(), num_bins)));
+            }
+        }
+        self.num_bins = num_bins;
+    }
+}
+
+impl Spill {
+    fn write(&mut self, row: &Row) -> io::Result<()> {
+        try!(self.reclaim());
+        try!(self.file.seek(SeekFrom::Start(self.end)));
+        try!(self.file.write_all(&row.levels));
+        self.rows.push_front((self.end, row.len()));
+        self.end += row.len() as u64;
+        Ok(())
+    }
+
+    /// Moves the rows still kept to the start of the file, once enough of it
+    /// is from rows let go, so it stays about the size of what's kept.
+    fn reclaim(&mut self) -> io::Result<()> {
+        let oldest = self.rows.back().map_or(self.end, |&(start, _)| start);
+        if oldest < MIN_RECLAIM_BYTES || oldest < self.end / 2 {
+            return Ok(());
+        }
+        // the rows are one after the other from the oldest, and it's moving
+        // back at least as far as there is to move
+        let mut chunk = vec![0; COPY_BYTES];
+        let mut from = oldest;
+        while from < self.end {
+            let n = min(COPY_BYTES as u64, self.end - from) as usize;
+            try!(self.file.seek(SeekFrom::Start(from)));
+            try!(self.file.read_exact(&mut chunk[..n]));
+            try!(self.file.seek(SeekFrom::Start(from - oldest)));
+            try!(self.file.write_all(&chunk[..n]));
+            from += n as u64;
+        }
+        self.end -= oldest;
+        try!(self.file.set_len(self.end));
+        for row in self.rows.iter_mut() {
+            row.0 -= oldest;
+        }
+        Ok(())
+    }
+}
+
+/// Rows of a history, read back from its file as they're reached.
+pub struct Iter<'a> {
+    history: &'a History,
+    next: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Arc<Row>;
+
+    fn next(&mut self) -> Option<Arc<Row>> {
+        if self.next >= self.history.len() {
+            return None;
+        }
+        let row = self.history.get(self.next);
+        self.next += 1;
+        row
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::sync::Arc;
+    use super::{History, Row, IN_MEMORY, MIN_RECLAIM_BYTES, SCALE};
+
+    #[test]
+    fn test_row() {
+        let powers = [0.0, 0.5, 1.0, 0.123, 2.0, ::std::f32::NEG_INFINITY];
+        let row = Row::new(&powers);
+        assert_eq!(row.len(), 6);
+        assert_eq!(row.power(0), Some(0.0));
+        assert_eq!(row.power(2), Some(1.0));
+        assert_eq!(row.power(6), None);
+        let back = row.powers();
+        for (x, y) in powers[..4].iter().zip(&back) {
+            assert!((x - y).abs() <= 0.5 / SCALE, "{} and {}", x, y);
+        }
+        // clamped either side
+        assert!((back[4] - 1.3).abs() < 1e-6);
+        assert!((back[5] + 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_history() {
+        // each row's level tells it apart, wrapping every 200
+        let row = |i: usize| Arc::new(Row { levels: vec![(i % 200) as u8; 256] });
+        let mut history = History::new(3);
+        for i in 0..5 {
+            history.push_front(row(i));
+        }
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.get(0), Some(row(4)));
+        assert_eq!(history.get(3), None);
+
+        let max_len = IN_MEMORY + 3000;
+        let mut history = History::spilled(max_len, &env::temp_dir()).unwrap();
+        let num_pushed = 3 * max_len;
+        for i in 0..num_pushed {
+            history.push_front(row(i));
+        }
+        assert_eq!(history.len(), max_len);
+        for &t in &[0, IN_MEMORY - 1, IN_MEMORY, max_len - 1] {
+            assert_eq!(history.get(t), Some(row(num_pushed - 1 - t)));
+        }
+        let levels: Vec<u8> = history.iter_from(max_len - 2).map(|row| row.levels[0]).collect();
+        assert_eq!(levels, vec![((num_pushed - max_len + 1) % 200) as u8,
+                                ((num_pushed - max_len) % 200) as u8]);
+        // the rows let go were reclaimed from the file
+        let end = history.spill.as_ref().unwrap().end;
+        assert!(end <= 2 * (3000 * 256) as u64 + MIN_RECLAIM_BYTES, "{}", end);
+
+        // rows read back are rescaled once the width changes
+        history.fit(128);
+        assert_eq!(history.get(0).unwrap().len(), 128);
+        assert_eq!(history.get(max_len - 1).unwrap().len(), 128);
+    }
+}
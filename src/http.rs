@@ -0,0 +1,127 @@
+//! Just enough of HTTP/1.1 to answer GET requests, for the metrics and the
+//! web mirror. Every connection gets one response and is closed.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// A client that takes longer than this to send its request, or take the
+/// response, is hung up on.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Response {
+    /// Status code and reason, like `200 OK`.
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn ok(content_type: &'static str, body: Vec<u8>) -> Self {
+        Response { status: "200 OK", content_type: content_type, body: body }
+    }
+
+    pub fn not_found() -> Self {
+        Response {
+            status: "404 Not Found",
+            content_type: "text/plain",
+            body: b"Not found\n".to_vec(),
+        }
+    }
+}
+
+/// Answers requests on `port` of `addr` with what `handler` makes of each
+/// one's path.
+pub fn serve<F>(addr: IpAddr, port: u16, handler: F) -> io::Result<()>
+    where F: Fn(&str) -> Response + Send + Sync + 'static
+{
+    let listener = try!(TcpListener::bind((addr, port)));
+    serve_listener(listener, handler);
+    Ok(())
+}
+
+fn serve_listener<F>(listener: TcpListener, handler: F)
+    where F: Fn(&str) -> Response + Send + Sync + 'static
+{
+    let handler = Arc::new(handler);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            // a slow client shouldn't hold up the next one
+            let handler = handler.clone();
+            thread::spawn(move || {
+                let _ = answer(stream, &*handler);
+            });
+        }
+    });
+}
+
+fn answer<F: Fn(&str) -> Response>(stream: TcpStream, handler: &F) -> io::Result<()> {
+    try!(stream.set_read_timeout(Some(TIMEOUT)));
+    try!(stream.set_write_timeout(Some(TIMEOUT)));
+    let mut reader = BufReader::new(try!(stream.try_clone()));
+    let mut request = String::new();
+    try!(reader.read_line(&mut request));
+    // the headers don't matter, but they're read so the client isn't reset
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if try!(reader.read_line(&mut line)) == 0 || line.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let mut words = request.split_whitespace();
+    let response = match (words.next(), words.next()) {
+        (Some("GET"), Some(target)) => {
+            // the query doesn't matter either
+            handler(target.split('?').next().unwrap_or(target))
+        }
+        _ => Response {
+            status: "405 Method Not Allowed",
+            content_type: "text/plain",
+            body: b"Only GET is supported\n".to_vec(),
+        },
+    };
+    let mut stream = stream;
+    try!(write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+                         Connection: close\r\n\r\n",
+                response.status, response.content_type, response.body.len()));
+    try!(stream.write_all(&response.body));
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use super::{serve_listener, Response};
+
+    #[test]
+    fn test_serve() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        serve_listener(listener, |path: &str| match path {
+            "/hello" => Response::ok("text/plain", b"hi".to_vec()),
+            _ => Response::not_found(),
+        });
+        let get = |request: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("GET /hello?x=1 HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains("Content-Length: 2\r\n"));
+        assert!(response.ends_with("\r\n\r\nhi"));
+        assert!(get("GET / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404 Not Found"));
+        assert!(get("POST /hello HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 405"));
+    }
+}
@@ -0,0 +1,182 @@
+use std::cell::Cell;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use backend::{Event, TerminalBackend};
+
+/// Longest gap between the clicks of a double click.
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+
+thread_local! {
+    /// When and where the last click was, for spotting a double click.
+    static LAST_CLICK: Cell<Option<(Instant, usize, usize)>> = Cell::new(None);
+}
+
+/// A key press, with terminal escape sequences decoded.
+///
+/// rustty hands us raw characters, so keys like PageUp arrive as `ESC [ 5 ~`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Backspace,
+    Esc,
+    /// At a column and row of the terminal, counting from 0.
+    Mouse(Mouse, usize, usize),
+}
+
+/// What the mouse did, once `set_mouse_reporting` has turned it on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mouse {
+    Click,
+    /// A second click on the same cell soon after the first, which arrives
+    /// after a `Click`.
+    DoubleClick,
+    WheelUp,
+    WheelDown,
+}
+
+/// Ctrl-C, which arrives as a key rather than a signal while the terminal is
+/// in raw mode.
+pub const INTERRUPT: Key = Key::Char('\x03');
+
+/// Waits at most `timeout` for a key press.
+///
+/// Returns `Ok(None)` on timeout or if an unrecognized escape sequence was read.
+pub fn read_key<B: TerminalBackend>(term: &mut B, timeout: Duration) -> io::Result<Option<Key>> {
+    let c = match try!(term.next_event(timeout)) {
+        Some(Event::Key(c)) => c,
+        None => return Ok(None),
+    };
+
+    let key = match c {
+        '\x1b' => return read_escape(term).map(|key| key.map(spot_double_click)),
+        '\r' | '\n' => Key::Enter,
+        '\x7f' | '\x08' => Key::Backspace,
+        c => Key::Char(c),
+    };
+    Ok(Some(key))
+}
+
+fn next_char<B: TerminalBackend>(term: &mut B) -> io::Result<Option<char>> {
+    // The rest of an escape sequence is read from the terminal in the same
+    // chunk as the ESC, so there's no need to wait for it.
+    match try!(term.next_event(Duration::from_secs(0))) {
+        Some(Event::Key(c)) => Ok(Some(c)),
+        None => Ok(None),
+    }
+}
+
+fn read_escape<B: TerminalBackend>(term: &mut B) -> io::Result<Option<Key>> {
+    match try!(next_char(term)) {
+        Some('[') | Some('O') => {}
+        Some(_) | None => return Ok(Some(Key::Esc)),
+    }
+
+    let mut params = String::new();
+    loop {
+        match try!(next_char(term)) {
+            // final byte of a CSI sequence
+            Some(c) if c >= '\x40' && c <= '\x7e' => {
+                return Ok(decode_csi(&params, c));
+            }
+            Some(c) => params.push(c),
+            None => return Ok(None),
+        }
+    }
+}
+
+/// Has the terminal report mouse presses and the wheel as escape sequences, in
+/// its SGR encoding, or stop.
+pub fn set_mouse_reporting(on: bool) -> io::Result<()> {
+    let mut tty = try!(OpenOptions::new().write(true).open("/dev/tty"));
+    let set = if on { 'h' } else { 'l' };
+    write!(tty, "\x1b[?1000{}\x1b[?1006{}", set, set)
+}
+
+/// Turns a click into a double click if it's a second one.
+fn spot_double_click(key: Key) -> Key {
+    let (col, row) = match key {
+        Key::Mouse(Mouse::Click, col, row) => (col, row),
+        key => return key,
+    };
+    LAST_CLICK.with(|last| {
+        let double = last.get().map_or(false, |(at, last_col, last_row)| {
+            (last_col, last_row) == (col, row) && at.elapsed() < DOUBLE_CLICK_TIME
+        });
+        // a third click starts another double click
+        last.set(if double { None } else { Some((Instant::now(), col, row)) });
+        if double { Key::Mouse(Mouse::DoubleClick, col, row) } else { key }
+    })
+}
+
+fn decode_csi(params: &str, fin: char) -> Option<Key> {
+    match (params, fin) {
+        (p, 'M') if p.starts_with('<') => decode_mouse(&p[1..]),
+        (_, 'A') => Some(Key::Up),
+        (_, 'B') => Some(Key::Down),
+        (_, 'C') => Some(Key::Right),
+        (_, 'D') => Some(Key::Left),
+        (_, 'H') | ("1", '~') | ("7", '~') => Some(Key::Home),
+        (_, 'F') | ("4", '~') | ("8", '~') => Some(Key::End),
+        ("5", '~') => Some(Key::PageUp),
+        ("6", '~') => Some(Key::PageDown),
+        _ => None,
+    }
+}
+
+/// Decodes the `<button>;<col>;<row>` of an SGR mouse press. Releases end in
+/// `m` instead, and are left out along with drags and the other buttons.
+fn decode_mouse(params: &str) -> Option<Key> {
+    let nums: Vec<usize> = params.split(';').filter_map(|n| n.parse().ok()).collect();
+    if nums.len() != 3 || nums[1] == 0 || nums[2] == 0 {
+        return None;
+    }
+    // held down modifier keys add 4, 8 and 16
+    let mouse = match nums[0] & !0x1c {
+        0 => Mouse::Click,
+        64 => Mouse::WheelUp,
+        65 => Mouse::WheelDown,
+        _ => return None,
+    };
+    Some(Key::Mouse(mouse, nums[1] - 1, nums[2] - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use backend::TestBackend;
+    use super::{decode_csi, read_key, Key, Mouse};
+
+    #[test]
+    fn test_decode_mouse() {
+        assert_eq!(decode_csi("<0;10;5", 'M'), Some(Key::Mouse(Mouse::Click, 9, 4)));
+        assert_eq!(decode_csi("<65;1;1", 'M'), Some(Key::Mouse(Mouse::WheelDown, 0, 0)));
+        assert_eq!(decode_csi("<4;3;2", 'M'), Some(Key::Mouse(Mouse::Click, 2, 1)));
+        // releases, drags and the right button
+        assert_eq!(decode_csi("<0;10;5", 'm'), None);
+        assert_eq!(decode_csi("<32;10;5", 'M'), None);
+        assert_eq!(decode_csi("<2;10;5", 'M'), None);
+        assert_eq!(decode_csi("5", '~'), Some(Key::PageUp));
+    }
+
+    #[test]
+    fn test_read_key() {
+        let mut term = TestBackend::new(1, 1);
+        term.type_keys("q\x1b[5~\r\x1b");
+        let mut keys = Vec::new();
+        while let Some(key) = read_key(&mut term, Duration::from_secs(0)).unwrap() {
+            keys.push(key);
+        }
+        assert_eq!(keys, vec![Key::Char('q'), Key::PageUp, Key::Enter, Key::Esc]);
+    }
+}
@@ -0,0 +1,196 @@
+//! Teeing the raw samples to disk while they're displayed, so interesting
+//! moments can be demodulated later.
+//!
+//! Recordings are interleaved signed 8 bit I and Q, as `hackrf_transfer -r`
+//! writes them, which the input picker can play back. Paths ending in
+//! `.sigmf-data` also get a SigMF `.sigmf-meta` file next to them, with the
+//! sample rate and a capture for each tuning.
+//!
+//! Between recordings the latest samples can be kept, so a recording started
+//! by a trigger also has what came just before it.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicUsize;
+use std::sync::mpsc::Receiver;
+use num::Complex;
+
+use watchdog;
+
+const SIGMF_DATA_EXTENSION: &'static str = "sigmf-data";
+
+struct Recording {
+    out: BufWriter<File>,
+    path: PathBuf,
+    num_samples: u64,
+    sample_rate_hz: f64,
+    /// The first sample at each center frequency.
+    captures: Vec<(u64, f64)>,
+    /// Writing stops at the first error, which `stop` returns.
+    error: Option<io::Error>,
+}
+
+struct State {
+    recording: Option<Recording>,
+    /// Buffers from before the recording, oldest first, and their samples.
+    recent: VecDeque<Vec<Complex<i8>>>,
+    num_recent: usize,
+    max_recent: usize,
+}
+
+impl State {
+    /// Writes a buffer out while recording, and otherwise keeps it with the
+    /// recent ones.
+    fn write(&mut self, buff: &[Complex<i8>]) {
+        if let Some(ref mut r) = self.recording {
+            if r.error.is_none() {
+                let mut bytes = Vec::with_capacity(2 * buff.len());
+                for x in buff {
+                    bytes.push(x.re as u8);
+                    bytes.push(x.im as u8);
+                }
+                match r.out.write_all(&bytes) {
+                    Ok(()) => r.num_samples += buff.len() as u64,
+                    Err(e) => r.error = Some(e),
+                }
+            }
+            return;
+        }
+        if self.max_recent == 0 {
+            return;
+        }
+        self.num_recent += buff.len();
+        self.recent.push_back(buff.to_vec());
+        while self.num_recent - self.recent[0].len() >= self.max_recent {
+            let oldest = self.recent.pop_front().unwrap();
+            self.num_recent -= oldest.len();
+        }
+    }
+}
+
+/// Shared between the UI, which starts and stops recordings, and the thread
+/// passing the samples on.
+pub struct IqRecorder {
+    state: Arc<Mutex<State>>,
+}
+
+impl IqRecorder {
+    pub fn new() -> Self {
+        IqRecorder {
+            state: Arc::new(Mutex::new(State {
+                recording: None,
+                recent: VecDeque::new(),
+                num_recent: 0,
+                max_recent: 0,
+            })),
+        }
+    }
+
+    /// Keeps at least the last `num_samples` samples between recordings, to
+    /// start the next one with.
+    pub fn keep_recent(&self, num_samples: usize) {
+        self.state.lock().unwrap().max_recent = num_samples;
+    }
+
+    /// Passes the samples from `recv` on, writing them out while recording,
+    /// counting the buffers dropped for falling behind in `overruns`.
+    pub fn tee(&self, recv: Receiver<Vec<Complex<i8>>>, overruns: Arc<AtomicUsize>)
+               -> Receiver<Vec<Complex<i8>>> {
+        let state = self.state.clone();
+        watchdog::tee(recv, overruns, move |buff| state.lock().unwrap().write(buff))
+    }
+
+    /// Starts writing to `path`, replacing it, stopping any recording already
+    /// going. The recording starts with the samples kept from before.
+    pub fn start(&self, path: &Path, center_freq_hz: f64, sample_rate_hz: f64)
+                 -> io::Result<()> {
+        try!(self.stop());
+        let out = BufWriter::new(try!(File::create(path)));
+        let mut state = self.state.lock().unwrap();
+        state.recording = Some(Recording {
+            out: out,
+            path: path.to_path_buf(),
+            num_samples: 0,
+            sample_rate_hz: sample_rate_hz,
+            captures: vec![(0, center_freq_hz)],
+            error: None,
+        });
+        state.num_recent = 0;
+        let recent: Vec<_> = state.recent.drain(..).collect();
+        for buff in recent {
+            state.write(&buff);
+        }
+        Ok(())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.state.lock().unwrap().recording.is_some()
+    }
+
+    /// Notes a change of center frequency in the SigMF metadata.
+    pub fn retune(&self, center_freq_hz: f64) {
+        let mut state = self.state.lock().unwrap();
+        // samples kept from the last frequency would be labeled with this one
+        state.recent.clear();
+        state.num_recent = 0;
+        if let Some(ref mut r) = state.recording {
+            let num_samples = r.num_samples;
+            // a retune before any samples at the last frequency replaces it
+            if r.captures.last().map_or(false, |&(start, _)| start == num_samples) {
+                r.captures.pop();
+            }
+            r.captures.push((num_samples, center_freq_hz));
+        }
+    }
+
+    /// Finishes the recording, if there is one, returning its path and how
+    /// many samples it has.
+    pub fn stop(&self) -> io::Result<Option<(PathBuf, u64)>> {
+        let r = match self.state.lock().unwrap().recording.take() {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+        if let Some(e) = r.error {
+            return Err(e);
+        }
+        let mut out = r.out;
+        try!(out.flush());
+        if r.path.extension().map_or(false, |ext| ext == SIGMF_DATA_EXTENSION) {
+            let meta_path = r.path.with_extension("sigmf-meta");
+            let mut meta = BufWriter::new(try!(File::create(meta_path)));
+            try!(meta.write_all(sigmf_meta(r.sample_rate_hz, &r.captures).as_bytes()));
+            try!(meta.flush());
+        }
+        Ok(Some((r.path, r.num_samples)))
+    }
+}
+
+fn sigmf_meta(sample_rate_hz: f64, captures: &[(u64, f64)]) -> String {
+    let captures: Vec<String> = captures.iter().map(|&(start, freq_hz)| {
+        format!("    {{\"core:sample_start\": {}, \"core:frequency\": {:.0}}}", start, freq_hz)
+    }).collect();
+    format!("{{\n  \"global\": {{\n    \"core:datatype\": \"ci8\",\n    \
+             \"core:sample_rate\": {:.0},\n    \"core:version\": \"1.0.0\",\n    \
+             \"core:recorder\": \"terminal_spectrograph\"\n  }},\n  \"captures\": [\n{}\n  ],\n  \
+             \"annotations\": []\n}}\n",
+            sample_rate_hz, captures.join(",\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sigmf_meta;
+
+    #[test]
+    fn test_sigmf_meta() {
+        assert_eq!(sigmf_meta(2e6, &[(0, 100e6), (262144, 102e6)]),
+                   "{\n  \"global\": {\n    \"core:datatype\": \"ci8\",\n    \
+                    \"core:sample_rate\": 2000000,\n    \"core:version\": \"1.0.0\",\n    \
+                    \"core:recorder\": \"terminal_spectrograph\"\n  },\n  \"captures\": [\n    \
+                    {\"core:sample_start\": 0, \"core:frequency\": 100000000},\n    \
+                    {\"core:sample_start\": 262144, \"core:frequency\": 102000000}\n  ],\n  \
+                    \"annotations\": []\n}\n");
+    }
+}
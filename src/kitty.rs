@@ -0,0 +1,173 @@
+//! Drawing the waterfall as an image with the kitty graphics protocol, which
+//! kitty, ghostty and a few others understand. Each spectrum gets its own row
+//! of pixels and each bin its own column, where cells have to share them.
+//!
+//! The terminal is asked at startup whether it knows the protocol, since one
+//! that doesn't would print the image as garbage.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use backend::{Event, TerminalBackend};
+use cells::{HasPosition, HasSize, Widget};
+use clipboard::base64;
+use palette::Palette;
+
+/// Spectra in each row of cells of the waterfall.
+pub const SPECTRA_PER_ROW: usize = 8;
+
+/// Longest wait for the terminal to answer at startup. A terminal that
+/// doesn't know the protocol still answers the device attributes query
+/// after it, so this is only for one that answers nothing.
+const QUERY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Base64 sent in each escape sequence, the most the protocol takes.
+const CHUNK_LEN: usize = 4096;
+
+/// Under everything, even cells with a background, so the overlays and the
+/// labels on the waterfall stay on top.
+const Z_INDEX: i32 = -1_073_741_825;
+
+/// Pixels of RGBA, row by row from the top.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// A waterfall `width` bins wide and `height` spectra tall of `spectra`,
+    /// newest at the top, each padded or cut to the width. Past the last
+    /// spectrum it's see-through.
+    pub fn from_spectra<'a, I>(spectra: I, width: usize, height: usize, palette: Palette) -> Self
+        where I: Iterator<Item = &'a [f32]>
+    {
+        let mut pixels = Vec::with_capacity(4 * width * height);
+        for spec in spectra.take(height) {
+            for i in 0..width {
+                match spec.get(i) {
+                    Some(&x) => {
+                        let (r, g, b) = palette.rgb(x);
+                        pixels.extend(&[r, g, b, 255]);
+                    }
+                    // past the end of a row from before a resize
+                    None => pixels.extend(&[0, 0, 0, 0]),
+                }
+            }
+        }
+        pixels.resize(4 * width * height, 0);
+        Image { width: width, height: height, pixels: pixels }
+    }
+}
+
+/// Asks whether the terminal draws images sent with the protocol. The
+/// answer comes back as input, so it's read through `term`.
+pub fn query<B: TerminalBackend>(term: &mut B) -> io::Result<bool> {
+    let mut tty = try!(OpenOptions::new().write(true).open("/dev/tty"));
+    // a one pixel image to check, not keep, then the device attributes
+    try!(write!(tty, "\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\\x1b[c"));
+    try!(tty.flush());
+
+    let deadline = Instant::now() + QUERY_TIMEOUT;
+    let mut reply = String::new();
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        match try!(term.next_event(deadline - now)) {
+            Some(Event::Key(c)) => {
+                reply.push(c);
+                if c == 'c' && reply.contains("\x1b[?") {
+                    break;
+                }
+            }
+            None => break,
+        }
+    }
+    Ok(reply.contains("\x1b_Gi=31;OK"))
+}
+
+/// Replaces the images on the screen with `images`, each stretched over the
+/// cells of its widget.
+pub fn show(images: &[(&Image, &Widget)]) -> io::Result<()> {
+    // straight to the terminal, rustty only writes cells
+    let mut tty = try!(OpenOptions::new().write(true).open("/dev/tty"));
+    let mut out = delete_bytes();
+    for &(image, widget) in images {
+        out.extend(image_bytes(image, widget.origin(), widget.size()));
+    }
+    try!(tty.write_all(&out));
+    tty.flush()
+}
+
+/// Takes every image off the screen, and out of the terminal's memory.
+pub fn clear() -> io::Result<()> {
+    let mut tty = try!(OpenOptions::new().write(true).open("/dev/tty"));
+    try!(tty.write_all(&delete_bytes()));
+    tty.flush()
+}
+
+fn delete_bytes() -> Vec<u8> {
+    b"\x1b_Ga=d,d=A,q=2\x1b\\".to_vec()
+}
+
+/// The escape sequences placing `image`, with the cursor left where it was.
+fn image_bytes(image: &Image, (col, row): (usize, usize), (cols, rows): (usize, usize))
+    -> Vec<u8>
+{
+    let mut out = format!("\x1b[{};{}H", row + 1, col + 1).into_bytes();
+    if image.height == 0 || cols == 0 || rows == 0 {
+        return out;
+    }
+    let data = base64(&image.pixels);
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(CHUNK_LEN).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = if i + 1 < chunks.len() { 1 } else { 0 };
+        if i == 0 {
+            out.extend(format!("\x1b_Ga=T,f=32,s={},v={},c={},r={},C=1,z={},q=2,m={};",
+                               image.width, image.height, cols, rows, Z_INDEX, more)
+                       .into_bytes());
+        } else {
+            out.extend(format!("\x1b_Gm={};", more).into_bytes());
+        }
+        out.extend(*chunk);
+        out.extend(b"\x1b\\");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{image_bytes, Image, CHUNK_LEN};
+    use palette::Palette;
+
+    #[test]
+    fn test_image() {
+        let rows = [vec![0.0, 1.0], vec![1.0]];
+        let image = Image::from_spectra(rows.iter().map(|row| &row[..]), 2, 2,
+                                        Palette::Grayscale);
+        assert_eq!(image.pixels, vec![8, 8, 8, 255, 238, 238, 238, 255,
+                                      238, 238, 238, 255, 0, 0, 0, 0]);
+        // padded out below the spectra there are
+        let taller = Image::from_spectra(rows.iter().map(|row| &row[..]), 2, 3,
+                                         Palette::Grayscale);
+        assert_eq!(&taller.pixels[..16], &image.pixels[..]);
+        assert_eq!(taller.pixels[16..], [0; 8]);
+
+        let out = String::from_utf8(image_bytes(&image, (0, 3), (1, 1))).unwrap();
+        assert!(out.starts_with("\x1b[4;1H\x1b_Ga=T,f=32,s=2,v=2,c=1,r=1,"));
+        assert!(out.ends_with(",m=0;CAgI/+7u7v/u7u7/AAAAAA==\x1b\\"));
+
+        // big enough to go in several chunks
+        let rows = [vec![0.5; CHUNK_LEN]];
+        let image = Image::from_spectra(rows.iter().map(|row| &row[..]), CHUNK_LEN, 1,
+                                        Palette::Classic);
+        let out = String::from_utf8(image_bytes(&image, (0, 0), (1, 1))).unwrap();
+        assert_eq!(out.matches("\x1b_G").count(), 6);
+        assert!(out.contains(",m=1;"));
+        assert!(out.contains("\x1b\\\x1b_Gm=0;"));
+    }
+}
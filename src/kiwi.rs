@@ -0,0 +1,234 @@
+//! A client for KiwiSDRs, the web-connected HF receivers many of whose
+//! owners share them publicly, for looking at a band from wherever one is.
+//! It asks for IQ, about 12 kHz of it on most, and takes up the sample rate
+//! the Kiwi says it's sending.
+
+use std::io;
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use num::Complex;
+
+use source::FeedEvent;
+use udp::Context;
+use ws;
+
+/// The port KiwiSDRs serve on, unless they're given another.
+const DEFAULT_PORT: u16 = 8073;
+
+/// How long to wait before connecting again once the Kiwi's gone, or turned
+/// its users away.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often to tell the Kiwi the client's still there, which it hangs up
+/// on otherwise.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The part of the IQ to keep, either side of the frequency.
+const PASSBAND_FRACTION: f64 = 0.45;
+
+/// Sound packets in IQ mode have a GPS timestamp ahead of the samples.
+const GPS_HEADER_LEN: usize = 10;
+
+/// Takes the IQ of a KiwiSDR tuned to one frequency, connecting again
+/// whenever it goes, until dropped.
+pub struct KiwiClient {
+    stop: Arc<AtomicBool>,
+    /// The connection there is, to hang up on when dropped.
+    stream: Arc<Mutex<Option<TcpStream>>>,
+    events: Receiver<FeedEvent>,
+}
+
+impl KiwiClient {
+    /// Starts listening to the Kiwi at `addr`, like `kiwi.example.org:8073`,
+    /// at `freq_hz`.
+    pub fn start(addr: &str, freq_hz: f64)
+                 -> Result<(KiwiClient, Receiver<Vec<Complex<i8>>>), String> {
+        let host = try!(parse_addr(addr));
+        let (send, recv) = channel();
+        let (event_send, events) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stream = Arc::new(Mutex::new(None));
+        let thread_stop = stop.clone();
+        let thread_stream = stream.clone();
+        thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                let gone = listen(&host, freq_hz, &thread_stream, &thread_stop, &send,
+                                  &event_send);
+                *thread_stream.lock().unwrap() = None;
+                if thread_stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                let error = match gone {
+                    Ok(()) => return,
+                    Err(e) => e.to_string(),
+                };
+                if event_send.send(FeedEvent::Lost(error)).is_err() {
+                    return;
+                }
+                thread::sleep(RECONNECT_INTERVAL);
+            }
+        });
+        Ok((KiwiClient { stop: stop, stream: stream, events: events }, recv))
+    }
+
+    /// What's happened since the last look, if anything.
+    pub fn next_event(&self) -> Option<FeedEvent> {
+        self.events.try_recv().ok()
+    }
+}
+
+impl Drop for KiwiClient {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(ref stream) = *self.stream.lock().unwrap() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+/// The `host:port` of an address, which can be a URL like the Kiwi's page.
+fn parse_addr(addr: &str) -> Result<String, String> {
+    let host = addr.splitn(2, "://").last().unwrap_or(addr).trim_end_matches('/');
+    if host.is_empty() || host.contains('/') {
+        return Err(format!("'{}' isn't a KiwiSDR's address", addr));
+    }
+    match host.rfind(':') {
+        Some(i) if host[i + 1..].parse::<u16>().is_ok() => Ok(host.to_string()),
+        Some(_) => Err(format!("'{}' has a bad port", addr)),
+        None => Ok(format!("{}:{}", host, DEFAULT_PORT)),
+    }
+}
+
+/// Connects to the Kiwi and sends on its samples until either end goes.
+/// `Ok` once the UI's gone.
+fn listen(host: &str, freq_hz: f64, current: &Mutex<Option<TcpStream>>, stop: &AtomicBool,
+          send: &Sender<Vec<Complex<i8>>>, events: &Sender<FeedEvent>) -> io::Result<()> {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs());
+    let mut stream = try!(ws::connect(host, &format!("/{}/SND", secs)));
+    *current.lock().unwrap() = Some(try!(stream.try_clone()));
+    // checked again, in case it was dropped while connecting
+    if stop.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    try!(ws::send_text(&mut stream, "SET auth t=kiwi p="));
+
+    let mut keepalive = try!(stream.try_clone());
+    thread::spawn(move || {
+        // stops once the connection's closed
+        while ws::send_text(&mut keepalive, "SET keepalive").is_ok() {
+            thread::sleep(KEEPALIVE_INTERVAL);
+        }
+    });
+
+    loop {
+        let message = try!(ws::read_message(&mut stream));
+        match parse_message(&message) {
+            Message::SampleRate(rate_hz) => {
+                let context = Context { freq_hz: None, sample_rate_hz: Some(rate_hz) };
+                if events.send(FeedEvent::Tuned(context)).is_err() {
+                    return Ok(());
+                }
+                for command in &setup(freq_hz, rate_hz) {
+                    try!(ws::send_text(&mut stream, command));
+                }
+            }
+            Message::Refused(why) => return Err(io::Error::new(io::ErrorKind::Other, why)),
+            Message::Samples(samples) => {
+                if !samples.is_empty() && send.send(samples).is_err() {
+                    return Ok(());
+                }
+            }
+            Message::Other => {}
+        }
+    }
+}
+
+/// What the Kiwi's said.
+#[derive(Debug, PartialEq)]
+enum Message {
+    SampleRate(f64),
+    /// Turned away, and why.
+    Refused(String),
+    Samples(Vec<Complex<i8>>),
+    Other,
+}
+
+fn parse_message(message: &[u8]) -> Message {
+    if message.starts_with(b"MSG ") {
+        let text = String::from_utf8_lossy(&message[4..]);
+        for param in text.split_whitespace() {
+            let mut parts = param.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some("sample_rate"), Some(rate)) => {
+                    if let Ok(rate_hz) = rate.parse::<f64>() {
+                        return Message::SampleRate(rate_hz);
+                    }
+                }
+                (Some("too_busy"), _) => {
+                    return Message::Refused("every channel's in use".to_string());
+                }
+                (Some("badp"), Some("1")) => {
+                    return Message::Refused("it needs a password".to_string());
+                }
+                (Some("down"), _) => return Message::Refused("it's down".to_string()),
+                _ => {}
+            }
+        }
+        return Message::Other;
+    }
+    // flags, a sequence number and the S meter, then the GPS timestamp
+    let start = 3 + 7 + GPS_HEADER_LEN;
+    if !message.starts_with(b"SND") || message.len() < start {
+        return Message::Other;
+    }
+    // signed 16 bit big endian I and Q
+    Message::Samples(message[start..].chunks(4).filter(|iq| iq.len() == 4)
+                                     .map(|iq| Complex::new(iq[0] as i8, iq[2] as i8))
+                                     .collect())
+}
+
+/// What's sent once the Kiwi's said its sample rate, to have it send IQ
+/// around `freq_hz`.
+fn setup(freq_hz: f64, rate_hz: f64) -> Vec<String> {
+    let cut_hz = (rate_hz * PASSBAND_FRACTION).round();
+    vec![format!("SET AR OK in={} out=44100", rate_hz.round()),
+         "SET squelch=0 max=0".to_string(),
+         format!("SET mod=iq low_cut={} high_cut={} freq={:.3}", -cut_hz, cut_hz,
+                 freq_hz / 1000.0),
+         "SET agc=1 hang=0 thresh=-100 slope=6 decay=1000 manGain=50".to_string(),
+         "SET compression=0".to_string(),
+         "SET ident_user=terminal_spectrograph".to_string()]
+}
+
+#[cfg(test)]
+mod tests {
+    use num::Complex;
+    use super::{parse_addr, parse_message, setup, Message};
+
+    #[test]
+    fn test_kiwi() {
+        assert_eq!(parse_addr("kiwi.example.org"), Ok("kiwi.example.org:8073".to_string()));
+        assert_eq!(parse_addr("http://kiwi.example.org:8074/"),
+                   Ok("kiwi.example.org:8074".to_string()));
+        assert!(parse_addr("kiwi.example.org:http").is_err());
+
+        assert_eq!(parse_message(b"MSG audio_init=0 sample_rate=12001.135"),
+                   Message::SampleRate(12001.135));
+        assert_eq!(parse_message(b"MSG too_busy=4"),
+                   Message::Refused("every channel's in use".to_string()));
+        assert_eq!(parse_message(b"MSG version_maj=1"), Message::Other);
+        let mut sound = b"SND".to_vec();
+        sound.extend(&[0; 7 + 10]);
+        sound.extend(&[0x12, 0x34, 0xff, 0x00, 0x80, 0x00, 0x7f, 0xff, 0x01]);
+        assert_eq!(parse_message(&sound),
+                   Message::Samples(vec![Complex::new(0x12, -1), Complex::new(-128, 0x7f)]));
+
+        let commands = setup(7100000.0, 12000.0);
+        assert_eq!(commands[0], "SET AR OK in=12000 out=44100");
+        assert_eq!(commands[2], "SET mod=iq low_cut=-5400 high_cut=5400 freq=7100.000");
+    }
+}
@@ -0,0 +1,94 @@
+//! The spectrograph's DSP and drawing, for use in other programs. Samples
+//! become spectra through a `SignalProcessor`, or a `SpectrumStream` for one
+//! as an iterator, and are drawn by `SpectrumWidget` and `WaterfallWidget`
+//! into any cells, or by a `Canvas` over a whole terminal. The `tspec` binary
+//! is built on the rest, which is public for it rather than kept stable.
+//! The `futures` feature adds async versions: `SpectrumStream` as a
+//! `futures::Stream`, fed by `HackRF::start_rx_stream`, and `spectra_task`.
+//! A `Canvas` draws through crossterm, or rustty with the `rustty` feature in
+//! place of the default `crossterm` one.
+//!
+//! ```
+//! extern crate num;
+//! extern crate terminal_spectrograph;
+//!
+//! use num::Complex;
+//! use terminal_spectrograph::{SignalProcessor, Window};
+//!
+//! # fn main() {
+//! let mut processor = SignalProcessor::builder(10240).fft_rate_hz(10).fft_len(1024)
+//!                                                    .window(Window::Hann).build();
+//! let spectra = processor.add_signal_buffer(vec![Complex::new(0i8, 0i8); 4096]);
+//! assert_eq!(spectra.len(), 4);
+//! # }
+//! ```
+
+extern crate libc;
+extern crate num;
+extern crate rustfft;
+#[cfg(feature = "rustty")]
+extern crate rustty;
+#[cfg(feature = "crossterm")]
+extern crate crossterm;
+extern crate rustc_serialize;
+extern crate itertools;
+extern crate rayon;
+#[cfg(feature = "futures")]
+extern crate futures;
+
+pub mod radio;
+pub mod drawing;
+pub mod processing;
+pub mod gain_sweep;
+pub mod units;
+pub mod input;
+pub mod session;
+pub mod palette;
+pub mod wait;
+pub mod bookmarks;
+pub mod self_test;
+pub mod probe;
+pub mod clipboard;
+pub mod config;
+pub mod export;
+pub mod events;
+pub mod scan;
+pub mod render;
+pub mod report;
+pub mod watchdog;
+pub mod source;
+pub mod iq_record;
+pub mod trigger;
+pub mod wizard;
+pub mod ws;
+pub mod alert;
+pub mod demod;
+pub mod ctcss;
+pub mod rds;
+pub mod morse;
+pub mod kitty;
+pub mod png;
+pub mod screenshot;
+pub mod backend;
+pub mod cells;
+pub mod widgets;
+pub mod stream;
+pub mod pool;
+pub mod ring;
+pub mod stats;
+pub mod history;
+pub mod rigctl;
+pub mod http;
+pub mod metrics;
+pub mod web;
+pub mod udp;
+pub mod zmq;
+pub mod kiwi;
+pub mod scope;
+
+pub use drawing::{normalize_spectrum, Canvas};
+pub use processing::{SignalProcessor, SignalProcessorBuilder, Window};
+pub use stream::SpectrumStream;
+#[cfg(feature = "futures")]
+pub use stream::spectra_task;
+pub use widgets::{SpectrumWidget, WaterfallWidget};
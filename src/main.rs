@@ -1,30 +1,69 @@
-extern crate libc;
 extern crate num;
-extern crate rustfft;
-extern crate rustty;
 extern crate rustc_serialize;
 extern crate docopt;
-extern crate itertools;
+extern crate terminal_spectrograph;
 
-mod radio;
-mod drawing;
-mod processing;
+use terminal_spectrograph::{radio, drawing, processing, gain_sweep, units, input, session,
+                            palette, wait, bookmarks, self_test, probe, clipboard, config,
+                            export, events, scan, report, watchdog, source, iq_record, trigger,
+                            wizard, ws, alert, demod, screenshot, pool, stats, rigctl, metrics,
+                            web, udp, zmq, kiwi, scope};
 
-use std::sync::mpsc::sync_channel;
+use std::sync::mpsc::{sync_channel, Receiver, TryRecvError};
 use std::sync::{Arc, Mutex};
-use rustty::Event;
 use docopt::Docopt;
 
 use radio::hackrf::HackRF;
-use drawing::Canvas;
-use processing::process_signal;
-use std::time::Duration;
+use radio::synth::Generator;
+use bookmarks::Bookmark;
+use clipboard::Method;
+use config::Preset;
+use demod::{Channel, Listener, Mode};
+use drawing::{Canvas, Glyphs, Layout, RadioSettings, Trace};
+use events::{Detector, EventLog};
+use export::{JsonWriter, RtlPowerWriter, WaterfallPng};
+use input::{read_key, Key, Mouse, INTERRUPT};
+use iq_record::IqRecorder;
+use kiwi::KiwiClient;
+use processing::{process_pair, process_signal, step_fft_rate, Backpressure, CrossSpectrum,
+                 PairedInput, Params, Pools};
+use pool::BufferPool;
+use report::Report;
+use rigctl::{Follower, Reply, Request, RigEvent};
+use scan::Scanner;
+use scope::Scope;
+use source::{Feed, FeedEvent, FilePlayer, Source};
+use stats::Stats;
+use trigger::{Edge, Trigger};
+use udp::UdpReceiver;
+use zmq::Subscriber;
+use palette::Palette;
+use probe::{ProbeMode, Suggestion};
+use session::{Header, Record, SessionReader, SessionWriter};
+use units::{format_frequency, parse_duration, parse_frequency};
+use wait::{WaitSet, Wakeup};
+use watchdog::{Event, Watchdog};
+use wizard::{MAX_FREQ_HZ, MIN_FREQ_HZ};
+use std::cmp::{max, min};
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 const USAGE: &'static str = "
 Terminal Spectrograph
 
 Usage:
-  terminal_spectrograph <freq-hz> <bandwidth-hz> [options]
+  terminal_spectrograph live [<freq-hz> [<bandwidth-hz>]] [options]
+  terminal_spectrograph play <iq-file> [<freq-hz> [<bandwidth-hz>]] [options]
+  terminal_spectrograph record <iq-file> [<freq-hz> [<bandwidth-hz>]] [options]
+  terminal_spectrograph udp <udp-port> [<freq-hz> [<bandwidth-hz>]] [options]
+  terminal_spectrograph scan <freqs> [<bandwidth-hz>] [options]
+  terminal_spectrograph devices
+  terminal_spectrograph gain-sweep <freq-hz> <bandwidth-hz> [--dwell=<secs>] [--threads=<n>]
+  terminal_spectrograph replay <session-file> [options]
+  terminal_spectrograph self-test
+  terminal_spectrograph [<freq-hz> [<bandwidth-hz>]] [options]
   terminal_spectrograph (-h | --help)
   terminal_spectrograph --version
 
@@ -32,51 +71,2588 @@ Options:
   -h --help          Show this screen.
   --version          Show version.
   --fft-rate=<rate>  Number of FFTs per second. [default: 10].
+  --backpressure=<policy>  What happens to spectra while the display is behind:
+                     drop-newest, drop-oldest (keeping the latest), coalesce
+                     (averaging them) or block (holding up the samples).
+                     [default: drop-newest]
+  --threads=<n>      Threads the windowing, FFTs and averaging are shared out
+                     between, for when one core can't keep up with the FFT
+                     length and sample rate, or 0 for one a core. The gain
+                     sweep and probe use them too. [default: 1]
+  --exclude-dc       Hide the DC bin (LO leakage) from the spectrum.
+  --freq-offset=<hz>  Show frequencies as they are ahead of a converter that
+                      shifts them by <hz> on the way to the radio, with the
+                      radio tuned to where they come out: 120M for a Ham-It-Up
+                      upconverter, or below 0 for a downconverter like an LNB.
+                      [default: 0]
+  --invert           Flip the spectrum left to right, for a converter that
+                     inverts it.
+  --peaks=<n>        Number of peaks to label, toggled with 'p'. [default: 5]
+  --lna-gain=<db>    IF gain, 0-40 dB in 8 dB steps. Defaults to 16.
+  --vga-gain=<db>    Baseband gain, 0-62 dB in 2 dB steps. Defaults to 20.
+  --amp              Enable the RF amplifier.
+  --min-db=<db>      Power at the bottom of the spectrum view. [default: 0]
+  --max-db=<db>      Power at the top of the spectrum view. [default: 50]
+  --average=<n>      Smooth the spectrum with a running average over about <n>
+                     spectra. [default: 1]
+  --threshold=<db>   Draw a line at <db> across the spectrum and flash the
+                     status bar while anything is above it, for leaving a band
+                     watched. t toggles it, < and > move it.
+  --alert-bell       Ring the terminal bell when something goes above the
+                     threshold.
+  --alert-command=<cmd>  Run <cmd> with sh when something goes above the
+                         threshold, with ALERT_FREQ_HZ and ALERT_POWER_DB set
+                         to the strongest bin.
+  --probe=<mode>     Without a bandwidth, spend a couple of seconds measuring
+                     the band at 10 MHz to pick the gains, power range and
+                     averaging: apply, suggest (print the options and exit)
+                     or off. Gains given with --lna-gain or --vga-gain are
+                     kept. [default: apply]
+  --dwell=<secs>     Time spent measuring each gain-sweep setting. [default: 0.5]
+  --palette=<name>   Waterfall colors: classic, viridis, inferno, turbo or
+                     grayscale. [default: classic]
+  --ascii            Draw with plain ASCII characters, for terminals without
+                     braille glyphs or 256 colors.
+  --glyphs=<set>     Draw the spectrum with braille, or with eighth blocks for
+                     fonts that render braille poorly. [default: braille]
+  --braille-waterfall  Draw the waterfall with braille dots, at full frequency
+                       resolution, instead of half blocks.
+  --kitty            Draw the waterfall as an image with the kitty graphics
+                     protocol, a row of pixels per spectrum, in kitty, ghostty
+                     and other terminals that answer they support it.
+  --normalize-rows   Scale each waterfall row by its own range, to bring out
+                     weak signals next to a strong carrier.
+  --persistence      Show where the spectrum has been, colored by how often and
+                     fading over time, instead of the newest spectrum, to catch
+                     transients and frequency hoppers.
+  --compress-history  Average older waterfall rows together, more the further
+                      back they are, to show a longer stretch of time.
+  --history=<rows>   Spectra kept for scrolling back through the waterfall while
+                     paused, and for exporting. [default: 4096]
+  --history-spill=<dir>  Keep all but the newest 4096 of them in a file under
+                         <dir>, removed as soon as it's made, for a history
+                         deeper than memory allows.
+  --scroll-up        Add waterfall rows at the bottom, scrolling it up as SDR#
+                     does, instead of at the top.
+  --timestamps       Print the time every few waterfall rows, in a gutter on
+                     its right, to line what's seen up with the clock.
+  --bookmarks=<file>  Label the frequencies listed in <file>, one
+                     '<freq-hz> <name>' per line, above the waterfall.
+  --wrap=<n>         Wrap the span across <n> stacked rows, for more
+                     frequency resolution on wide terminals. [default: 1]
+  --record-session=<file>  Record the displayed spectra and key presses so the
+                           session can be replayed later.
+  --record=<file>    Record the raw samples to <file> from the start, as r
+                     does. A name ending in .sigmf-data also gets SigMF
+                     metadata.
+  --trigger=<range>  Only record raw samples while the strongest signal between
+                     <low-hz>:<high-hz> is above --trigger-db, each burst to
+                     its own file, for leaving a run going unattended.
+  --trigger-db=<db>  Power that starts a triggered recording. [default: 30]
+  --trigger-pre=<secs>  Samples kept from before each trigger. [default: 1]
+  --trigger-hold=<secs>  Keep recording this long after the power drops back.
+                         [default: 1]
+  --csv=<file>       Append every spectrum to <file> in rtl_power's CSV format,
+                     paused or not. '-' writes to stdout, with --headless.
+  --json=<file>      Append every spectrum to <file> as a line of JSON, with its
+                     time and tuning, for jq and the like. '-' writes to
+                     stdout, with --headless.
+  --waterfall-png=<file>  Add every spectrum as a row of pixels at the bottom
+                          of <file>, a PNG colored like the waterfall, with
+                          the --palette from --min-db to --max-db, for
+                          looking back over a long run as one tall image. It
+                          stays whole as it grows.
+  --fast             With play, go through the recording once as fast as it can
+                     be processed instead of at its sample rate, keeping all of
+                     it to scroll back through once it's paused at the end, or
+                     writing it out with --waterfall-png.
+  --loop             Play recordings over and over, instead of pausing at the
+                     end, for a short capture to set the display up with, or a
+                     demo.
+  --udp-format=<fmt>  How udp's datagrams are laid out: cs8, cs16 or cf32 for
+                      interleaved I and Q samples, the bigger ones little
+                      endian, or vita49 for VITA-49 and DIFI packets, whose
+                      context packets set the frequency and bandwidth.
+                      [default: cs16]
+  --zmq=<endpoint>   Show the complex floats a GNU Radio ZMQ PUB Sink publishes at
+                     <endpoint>, like tcp://localhost:5555, in place of the
+                     radio, to see what a flowgraph makes. The stream doesn't
+                     say where it's tuned, so give <freq-hz> and
+                     <bandwidth-hz>, or --freq and --rate.
+  --kiwi=<addr>      Show the IQ of the KiwiSDR at <addr>, like
+                     kiwi.example.org:8073, at <freq-hz>, in place of the
+                     radio, for HF from anywhere. Most send about 12 kHz.
+  --source=<spec>    Take the samples from <spec> in place of the radio.
+                     synth:<signals> makes them up, for trying the program
+                     without one: a comma separated list of tone=<offset-hz>,
+                     chirp=<from-hz>:<to-hz>[:<secs>], am=<offset-hz>[:<tone-hz>],
+                     fm=<offset-hz>[:<deviation-hz>[:<tone-hz>]] and noise,
+                     each with an optional @<dbfs> level, like
+                     synth:tone=100k,fm=300k,noise. Just synth is some of each.
+  --compare=<input>  Show a second input in a pane of its own, at the same
+                     frequencies, to compare antennas or a signal before and
+                     after a filter: an IQ recording, synth[:<signals>] as
+                     for --source, udp:<port>, a tcp:// endpoint as for --zmq,
+                     or hackrf:<serial> for a second HackRF, tuned along with
+                     the first. devices lists the serials.
+  --cross            With --compare, add a pane of how far the second input
+                     is behind the first in phase in each bin, from -180
+                     degrees at the bottom of the colors to 180 at the top,
+                     for inputs sampled on one clock, as for direction finding
+                     with two antennas. Bins where the two aren't coherent
+                     stay dark. The two are processed in step, holding up
+                     the samples as --backpressure=block does.
+  --layout=<layout>  Where the panes of --compare and --cross go: stacked
+                     below the first, or in columns beside it.
+                     [default: stacked]
+  --freq=<hz>        The same as <freq-hz>.
+  --rate=<hz>        The same as <bandwidth-hz>, the sample rate.
+  --serve-ws=<port>  Serve every spectrum over WebSocket on <port>, as the
+                     same JSON objects as --json, for mirroring the display.
+  --serve-http=<port>  Serve a web page on <port> mirroring the spectrum and
+                       waterfall, for watching from a browser. It's fed over
+                       WebSocket, on the port after <port> unless --serve-ws
+                       gives one.
+  --serve-addr=<ip>  The address --serve-ws and --serve-http listen on. Only
+                     this machine can connect to 127.0.0.1; 0.0.0.0 shows the
+                     spectra to anyone who can reach any of its interfaces.
+                     [default: 127.0.0.1]
+  --serve-metrics=<port>  Serve Prometheus metrics on <port> at /metrics: the
+                          power, noise floor and band powers of the newest
+                          spectrum, and counts of samples, frames and drops.
+  --metrics-bands=<n>  Split the span into <n> bands for the band power
+                       metric. [default: 8]
+  --rigctl=<port>    Answer hamlib rigctld commands on <port>, usually 4532,
+                     from this machine, so logging programs and other SDR
+                     tools can read the frequency and retune.
+  --follow=<addr>    Keep the span centered on wherever the rig at <addr>,
+                     like localhost:4532 for rigctld or localhost:7356 for
+                     GQRX, is tuned, shading its passband on the spectrum.
+  --headless         Run without the terminal UI until interrupted, writing
+                     spectra to --csv, --json, --waterfall-png, --serve-ws or
+                     the metrics, or as CSV to stdout if none are given.
+  --bins=<n>         Number of FFT bins when headless. [default: 1024]
+  --duration=<time>  Stop after <time>, like 30s, 5m or 2h, stopping the radio
+                     and finishing the outputs, for scripted runs.
+  --frames=<n>       Stop after <n> spectra, like --duration.
+  --no-mouse         Leave the mouse to the terminal, for selecting text,
+                     instead of clicking to place the marker and tune.
+  --max-fps=<n>      Redraw at most <n> times a second, and show an estimate
+                     of the terminal output in the status bar, for slow
+                     links like SSH over a mobile connection.
+  --report=<file>    On exit, write a sorted summary of the signals seen and
+                     the noise floor at each tuning to <file>, which can be
+                     diffed against the report of another run.
+  --events=<file>    Log each signal seen to <file> as it goes away, with
+                     its start and end time, frequency and peak power, as CSV.
+  --audio-device=<name>  Play the audio of the marker's signal on <name>, one of
+                         the outputs the devices command lists, instead of
+                         the default output. Not supported on macOS.
+  --on-detect=<cmd>  Run <cmd> with sh for each signal as it's first seen,
+                     the same signals as --events logs, with {freq} replaced
+                     by its frequency in Hz and {power} by its power in dB,
+                     like 'notify-send \"{freq} Hz at {power} dB\"'.
+  --config=<file>    Read presets from <file> instead of
+                     ~/.config/terminal_spectrograph/config.toml.
+  --preset=<name>    Start with the frequency, bandwidth and gains of the
+                     [preset.<name>] section of the config. Anything given on
+                     the command line takes precedence.
+  --scan=<freqs>     Step through <freqs>, a comma separated list or a
+                     start:stop:step range like 88M:108M:200k, instead of
+                     staying on <freq-hz>. The waterfall is labeled where it
+                     retunes.
+  --scan-dwell=<secs>  Time spent on each scanned frequency. [default: 1]
+  --scan-squelch=<db>  While scanning, stay on a frequency as long as its
+                       strongest signal is above <db>, and the dwell time after.
+  --stall-timeout=<secs>  Reopen the radio when no samples have arrived for
+                          <secs>, marking the outage on the waterfall.
+                          [default: 5]
+
+Commands:
+  live        Show the radio's spectrum and waterfall, what's run without a
+              command too.
+  play        Show an IQ recording (.cs8 or .iq, as written by r or
+              hackrf_transfer) at <bandwidth-hz>, as if live, pausing at the
+              end, or going back to the start with --loop. No radio is
+              needed. Without <freq-hz> the frequencies are relative to the
+              recording's center.
+  record      Like live, recording the raw samples to <iq-file> from the
+              start, as --record does.
+  scan        Like live, stepping through <freqs> as --scan does.
+  devices     List the connected HackRFs, and except on macOS the audio
+              outputs for --audio-device.
+  gain-sweep  Step through the gain settings, printing the noise floor and
+              the SNR of the strongest signal at each one.
+  replay      Replay a session recorded with --record-session.
+  self-test   Check the radio, signal processing and rendering, printing a
+              pass/fail report.
+
+Frequencies and bandwidths are in Hz, and take a k, M or G suffix or an
+exponent, like 100M, 2.45G, 250k or 2.4e9.
+
+Started without any arguments, a setup screen asks for the input, frequency,
+span and gains.
+
+Keys:
+  q  Quit, as does Ctrl-C.
+  p  Toggle peak labels.
+  c  Cycle through the waterfall palettes.
+  b  Toggle the braille waterfall.
+  n  Toggle scaling each waterfall row by its own range.
+  h  Toggle compressing older waterfall rows, as --compress-history does.
+  o  Toggle the persistence spectrum, as --persistence does.
+  C  Show or hide the IQ scope, the newest samples plotted I across and Q up
+     at full scale in the top right corner, with their DC offset, how much
+     stronger I is than Q, and the share clipped, for spotting at a glance
+     what the spectrum hides.
+  T  Toggle the waterfall timestamps, as --timestamps does.
+  I  Flip the spectrum left to right, or back, as --invert does.
+  u  Switch the waterfall between scrolling down and up, as --scroll-up does.
+  S  Cycle the live spectrum's style: braille dots, dots colored by each
+     column's height with the waterfall's palette, solid, and solid colored
+     by height.
+  1  Show or hide the live spectrum, 2 its average, 3 its max hold and 4 its
+     min hold, each in its own color over the others.
+  P  Switch to the next preset in the config.
+  i  Pick the input, the radio or an IQ recording (.cs8 or .iq, as written by
+     hackrf_transfer) in the current directory, keeping the display settings.
+  f  Tune to a center frequency typed in, like 433.92M.
+  [  Tune to the next bookmark down, ] to the next one up, with its span if it
+     gives one.
+  B  Bookmark the marker's frequency, under a name typed in, in the config.
+  r  Start or stop recording the raw samples to a file named after the time.
+  g  Save the spectrum and the waterfall as a PNG named after the time, with
+     frequency and power axes, drawn from the spectra rather than the screen.
+     G saves the screen itself as text with its colors, to cat later.
+  +  Raise the FFT rate, and with it the waterfall speed. - lowers it.
+  m  Place a marker on the strongest signal, or remove it. Left/Right move it.
+  t  Show or hide the threshold line, < and > move it down and up by 1 dB.
+  y  Copy the marker's frequency to the clipboard, Y with its power too.
+  a  Listen to the marker in AM, then NFM, WFM, USB, LSB and CW, then stop,
+     played through paplay, aplay or sox's play. In SSB and CW the marker is
+     the carrier. In NFM the status bar shows the CTCSS tone, if there's one,
+     in WFM the station's name and radiotext from RDS, and in CW a pane shows
+     the Morse read from the keying.
+  w  Cycle the width of the channel listened to, 6k, 12.5k or 25k, in WFM
+     150k or 200k, in SSB 2.4k, 2.7k or 3k, or in CW 250, 500 or 2.4k.
+  v  Turn the audio down, V turns it up. M mutes or unmutes it.
+  A  Start or stop recording the audio to a WAV file named after the time.
+  k  Pause or go on playing the recording, with play or a recording picked
+     with i. j and l seek back and on 10 seconds, J and L a minute, and ( and
+     ) halve and double the speed it plays at. The waterfall is marked with
+     where in the recording it is every 10 seconds of it and at each change.
+  ?  Show the keys and current settings.
+  space  Pause, while paused PageUp/PageDown scroll the waterfall.
+  s      While paused, select a region of the waterfall with the arrow keys.
+         Enter zooms to it, x exports it to a CSV file, Esc goes back.
+  tab    Move the focus, which pausing and scrolling apply to, to the next pane.
+
+Mouse, unless --no-mouse is given:
+  Clicking the spectrum places the marker there, double-clicking tunes there.
+  The wheel moves the dB scale up and down.
 ";
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
+/// How long the UI sleeps when there's nothing to do. Only bounds how late a
+/// terminal resize or a stalled radio is noticed, anything else wakes the UI
+/// right away.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long after the last spectrum of a recording played fast it's taken
+/// to be the last.
+const FAST_SETTLE_TIME: Duration = Duration::from_millis(250);
+
+/// Bins whose coherence is below this are left dark in the --cross pane.
+const CROSS_MIN_COHERENCE: f32 = 0.5;
+
+const DEFAULT_LNA_GAIN_DB: u32 = 16;
+const DEFAULT_VGA_GAIN_DB: u32 = 20;
+
+/// What the radio is set to, which presets can change while running.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Tuning {
+    freq_hz: u64,
+    sample_rate_hz: f64,
+    lna_gain_db: u32,
+    vga_gain_db: u32,
+    amp: bool,
+}
+
+impl Tuning {
+    /// Takes whatever `preset` sets, keeping the rest.
+    fn apply_preset(&mut self, preset: &Preset) {
+        self.freq_hz = preset.freq_hz.unwrap_or(self.freq_hz);
+        self.sample_rate_hz = preset.bandwidth_hz.unwrap_or(self.sample_rate_hz);
+        self.lna_gain_db = preset.lna_gain_db.unwrap_or(self.lna_gain_db);
+        self.vga_gain_db = preset.vga_gain_db.unwrap_or(self.vga_gain_db);
+        self.amp = preset.amp.unwrap_or(self.amp);
+    }
+
+    fn set_radio(&self, radio: &mut HackRF) {
+        if let Err(setting) = self.try_set_radio(radio) {
+            panic!("Invalid {}", setting);
+        }
+    }
+
+    /// Like `set_radio`, returning the setting that failed instead.
+    fn try_set_radio(&self, radio: &mut HackRF) -> Result<(), &'static str> {
+        try!(radio.set_frequency(self.freq_hz).map_err(|_| "frequency"));
+        try!(radio.set_sample_rate(self.sample_rate_hz).map_err(|_| "bandwidth"));
+        try!(radio.set_amp_enable(self.amp).map_err(|_| "amp setting"));
+        try!(radio.set_lna_gain(self.lna_gain_db).map_err(|_| "LNA gain"));
+        radio.set_vga_gain(self.vga_gain_db).map_err(|_| "VGA gain")
+    }
+
+    fn radio_settings(&self, fft_rate_hz: u32) -> RadioSettings {
+        RadioSettings {
+            fft_rate_hz: fft_rate_hz,
+            lna_gain_db: self.lna_gain_db,
+            vga_gain_db: self.vga_gain_db,
+            amp: self.amp,
+        }
+    }
+}
+
+/// When a run given `--duration` or `--frames` is over.
+struct RunLimit {
+    started: Instant,
+    duration: Option<Duration>,
+    frames_left: Option<u64>,
+}
+
+impl RunLimit {
+    /// Starts counting from now.
+    fn start(args: &Args) -> Self {
+        // main has checked the duration already
+        let duration = args.flag_duration.as_ref().and_then(|d| parse_duration(d));
+        RunLimit {
+            started: Instant::now(),
+            duration: duration,
+            frames_left: args.flag_frames,
+        }
+    }
+
+    /// True if every spectrum has to be taken, for counting them.
+    fn counts_frames(&self) -> bool {
+        self.frames_left.is_some()
+    }
+
+    fn count_frame(&mut self) {
+        if let Some(ref mut n) = self.frames_left {
+            *n = n.saturating_sub(1);
+        }
+    }
+
+    fn is_over(&self) -> bool {
+        self.frames_left == Some(0) || self.time_left() == Some(Duration::from_secs(0))
+    }
+
+    fn time_left(&self) -> Option<Duration> {
+        self.duration.map(|d| d.checked_sub(self.started.elapsed())
+                               .unwrap_or(Duration::from_secs(0)))
+    }
+}
+
+/// The files every spectrum is written to, whether it's displayed or not, and
+/// the session and raw sample recordings.
+struct Outputs {
+    session: Option<SessionWriter>,
+    iq: IqRecorder,
+    trigger: Option<Trigger>,
+    /// Set while the raw sample recording is one the trigger started.
+    triggered: bool,
+    report: Option<Report>,
+    events: Option<EventLog>,
+    /// For --on-detect, with its command.
+    detector: Option<(Detector, String)>,
+    csv: Option<RtlPowerWriter>,
+    json: Option<JsonWriter>,
+    waterfall_png: Option<WaterfallPng>,
+    ws: Option<ws::Broadcaster>,
+    metrics: Option<metrics::Exporter>,
+}
+
+impl Outputs {
+    /// Opens the outputs given in `args`, apart from the session, starting
+    /// the raw sample recording at `tuning`. The metrics count what's in
+    /// `stats`.
+    fn open(args: &Args, tuning: &Tuning, session: Option<SessionWriter>, stats: &Arc<Stats>)
+            -> Self {
+        let iq = IqRecorder::new();
+        if let Some(ref path) = args.flag_record {
+            iq.start(Path::new(path), tuning.freq_hz as f64, tuning.sample_rate_hz)
+              .expect("Error creating IQ recording");
+        }
+        // main has checked the range already
+        let range_hz = args.flag_trigger.as_ref().and_then(|r| trigger::parse_range(r).ok());
+        let trigger = range_hz.map(|range_hz| {
+            iq.keep_recent((args.flag_trigger_pre * tuning.sample_rate_hz) as usize);
+            let hold = Duration::from_millis((args.flag_trigger_hold * 1000.0) as u64);
+            Trigger::new(range_hz, args.flag_trigger_db, hold)
+        });
+        if let Some(port) = args.flag_serve_http {
+            web::serve(serve_addr(args), port, ws_port(args).unwrap(), args.flag_min_db,
+                       args.flag_max_db, args.flag_palette)
+                .expect("Error starting the web server");
+        }
+        Outputs {
+            session: session,
+            iq: iq,
+            trigger: trigger,
+            triggered: false,
+            report: args.flag_report.as_ref().map(|path| {
+                Report::create(path).expect("Error creating report file")
+            }),
+            events: args.flag_events.as_ref().map(|path| {
+                EventLog::create(path).expect("Error creating event log")
+            }),
+            detector: args.flag_on_detect.as_ref().map(|command| {
+                (Detector::new(), command.clone())
+            }),
+            csv: args.flag_csv.as_ref().map(|path| {
+                RtlPowerWriter::open(path).expect("Error opening CSV file")
+            }),
+            json: args.flag_json.as_ref().map(|path| {
+                JsonWriter::open(path).expect("Error opening JSON file")
+            }),
+            waterfall_png: args.flag_waterfall_png.as_ref().map(|path| {
+                WaterfallPng::create(path, args.flag_min_db, args.flag_max_db, args.flag_palette)
+                    .expect("Error creating waterfall PNG")
+            }),
+            ws: ws_port(args).map(|port| {
+                ws::Broadcaster::listen(serve_addr(args), port)
+                    .expect("Error starting the WebSocket server")
+            }),
+            metrics: args.flag_serve_metrics.map(|port| {
+                metrics::Exporter::listen(port, args.flag_metrics_bands, stats.clone())
+                    .expect("Error starting the metrics server")
+            }),
+        }
+    }
+
+    /// True if the outputs need every spectrum, even while the UI is paused.
+    fn wants_every_spectrum(&self) -> bool {
+        self.report.is_some() || self.events.is_some() || self.detector.is_some() ||
+        self.csv.is_some() || self.json.is_some() || self.waterfall_png.is_some() ||
+        self.ws.is_some() || self.trigger.is_some() || self.metrics.is_some()
+    }
+
+    /// Writes a spectrum to each output, returning what the trigger did, or
+    /// that the --on-detect command couldn't run, for the status bar.
+    fn write_spectrum(&mut self, spec: &[num::Complex<f32>], tuning: &Tuning)
+                      -> Option<String> {
+        let (center_freq_hz, sample_rate_hz) = (tuning.freq_hz as f64, tuning.sample_rate_hz);
+        if let Some(ref mut session) = self.session {
+            session.write_spectrum(spec).expect("Error writing session file");
+        }
+        if let Some(ref mut report) = self.report {
+            report.add_spectrum(spec, center_freq_hz, sample_rate_hz);
+        }
+        if let Some(ref mut events) = self.events {
+            events.add_spectrum(spec, center_freq_hz, sample_rate_hz)
+                  .expect("Error writing event log");
+        }
+        let mut failed = None;
+        if let Some((ref mut detector, ref command)) = self.detector {
+            for (freq_hz, power_db) in detector.add_spectrum(spec, center_freq_hz, sample_rate_hz) {
+                let command = alert::fill_in(command, freq_hz, power_db);
+                if let Err(e) = alert::run_command(&command, freq_hz, power_db) {
+                    failed = Some(format!("Couldn't run the --on-detect command: {}", e));
+                }
+            }
+        }
+        if let Some(ref mut csv) = self.csv {
+            csv.write_spectrum(spec, center_freq_hz, sample_rate_hz)
+               .expect("Error writing CSV file");
+        }
+        if let Some(ref mut json) = self.json {
+            json.write_spectrum(spec, center_freq_hz, sample_rate_hz)
+                .expect("Error writing JSON file");
+        }
+        if let Some(ref mut waterfall_png) = self.waterfall_png {
+            waterfall_png.write_spectrum(spec).expect("Error writing waterfall PNG");
+        }
+        let ws_failed = self.ws.as_ref().and_then(|ws| {
+            ws.send_spectrum(spec, center_freq_hz, sample_rate_hz).err()
+        });
+        if let Some(e) = ws_failed {
+            // said once, the spectra going nowhere from then on
+            self.ws = None;
+            failed = Some(format!("Stopped serving over WebSocket: {}", e));
+        }
+        if let Some(ref metrics) = self.metrics {
+            metrics.add_spectrum(spec, center_freq_hz, sample_rate_hz);
+        }
+        let edge = match self.trigger {
+            Some(ref mut trigger) => trigger.observe(spec, center_freq_hz, sample_rate_hz),
+            None => None,
+        };
+        match edge {
+            // a recording started with r keeps going
+            Some(Edge::Start) if !self.iq.is_recording() => {
+                let started = start_recording(&self.iq, "burst", tuning);
+                self.triggered = started.is_ok();
+                Some(started.unwrap_or_else(|e| e))
+            }
+            Some(Edge::Stop) if self.triggered => {
+                self.triggered = false;
+                stop_recording(&self.iq, tuning)
+            }
+            _ => failed,
+        }
+    }
+
+    /// Sets the count of sample buffers dropped on the way from the radio,
+    /// for the metrics.
+    fn set_overruns(&self, overruns: usize) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.set_overruns(overruns);
+        }
+    }
+
+    fn write_tuning(&mut self, tuning: &Tuning) {
+        self.iq.retune(tuning.freq_hz as f64);
+        // what's seen at the next frequency is a different signal
+        if let Some(ref mut events) = self.events {
+            events.end_all().expect("Error writing event log");
+        }
+        if let Some((ref mut detector, _)) = self.detector {
+            detector.clear();
+        }
+        if let Some(ref mut session) = self.session {
+            session.write_tuning(tuning.freq_hz as f64, tuning.sample_rate_hz)
+                   .expect("Error writing session file");
+        }
+    }
+
+    fn finish(self) {
+        self.iq.stop().expect("Error writing IQ recording");
+        if let Some(mut events) = self.events {
+            events.end_all().expect("Error writing event log");
+        }
+        if let Some(report) = self.report {
+            report.finish().expect("Error writing report file");
+        }
+    }
+}
+
+/// What's left for the caller of `handle_key` to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Action {
+    Continue,
+    Quit,
+    NextPreset,
+    PickSource,
+    ToggleRecording,
+    /// Up or down a step.
+    StepFftRate(isize),
+    /// Index into the list the source picker was shown with.
+    SwitchSource(usize),
+    /// A center frequency typed into the prompt, in Hz.
+    Tune(u64),
+    /// To the next bookmark up or down.
+    StepBookmark(isize),
+    /// The marker's frequency, under the name typed into the prompt.
+    SaveBookmark(String),
+    /// Through off and the demodulators.
+    CycleListenMode,
+    CycleListenWidth,
+    /// Up or down a step.
+    StepVolume(i32),
+    ToggleMute,
+    ToggleAudioRecording,
+    ToggleScope,
+    Playback(Playback),
+}
+
+/// What's asked of a recording being played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Playback {
+    TogglePause,
+    /// By this many seconds, back if it's below 0.
+    Seek(i32),
+    /// Twice as fast or half, for below 0.
+    StepSpeed(isize),
+}
+
 #[derive(Debug, RustcDecodable)]
 struct Args {
-    arg_freq_hz: Option<u64>,
-    arg_bandwidth_hz: Option<f64>,
+    cmd_live: bool,
+    cmd_play: bool,
+    cmd_record: bool,
+    cmd_udp: bool,
+    cmd_scan: bool,
+    cmd_devices: bool,
+    cmd_gain_sweep: bool,
+    cmd_replay: bool,
+    cmd_self_test: bool,
+    arg_session_file: Option<String>,
+    arg_iq_file: Option<String>,
+    arg_udp_port: Option<u16>,
+    arg_freqs: Option<String>,
+    arg_freq_hz: Option<String>,
+    arg_bandwidth_hz: Option<String>,
     flag_fft_rate: u32,
+    flag_backpressure: Backpressure,
+    flag_threads: usize,
+    flag_exclude_dc: bool,
+    flag_freq_offset: String,
+    flag_invert: bool,
+    flag_peaks: usize,
+    flag_lna_gain: Option<u32>,
+    flag_vga_gain: Option<u32>,
+    flag_amp: bool,
+    flag_min_db: f32,
+    flag_max_db: f32,
+    flag_average: usize,
+    flag_threshold: Option<f32>,
+    flag_alert_bell: bool,
+    flag_alert_command: Option<String>,
+    flag_probe: ProbeMode,
+    flag_dwell: f64,
+    flag_record_session: Option<String>,
+    flag_report: Option<String>,
+    flag_events: Option<String>,
+    flag_on_detect: Option<String>,
+    flag_audio_device: Option<String>,
+    flag_csv: Option<String>,
+    flag_json: Option<String>,
+    flag_waterfall_png: Option<String>,
+    flag_serve_ws: Option<u16>,
+    flag_fast: bool,
+    flag_loop: bool,
+    flag_udp_format: udp::Format,
+    flag_zmq: Option<String>,
+    flag_kiwi: Option<String>,
+    flag_source: Option<String>,
+    flag_compare: Option<String>,
+    flag_cross: bool,
+    flag_layout: Layout,
+    flag_freq: Option<String>,
+    flag_rate: Option<String>,
+    flag_serve_http: Option<u16>,
+    flag_serve_addr: String,
+    flag_serve_metrics: Option<u16>,
+    flag_metrics_bands: usize,
+    flag_rigctl: Option<u16>,
+    flag_follow: Option<String>,
+    flag_headless: bool,
+    flag_record: Option<String>,
+    flag_trigger: Option<String>,
+    flag_trigger_db: f32,
+    flag_trigger_pre: f64,
+    flag_trigger_hold: f64,
+    flag_max_fps: Option<f64>,
+    flag_bins: usize,
+    flag_duration: Option<String>,
+    flag_frames: Option<u64>,
+    flag_palette: Palette,
+    flag_ascii: bool,
+    flag_glyphs: Glyphs,
+    flag_no_mouse: bool,
+    flag_braille_waterfall: bool,
+    flag_kitty: bool,
+    flag_normalize_rows: bool,
+    flag_compress_history: bool,
+    flag_history: usize,
+    flag_history_spill: Option<String>,
+    flag_scroll_up: bool,
+    flag_timestamps: bool,
+    flag_persistence: bool,
+    flag_bookmarks: Option<String>,
+    flag_wrap: usize,
+    flag_config: Option<String>,
+    flag_preset: Option<String>,
+    flag_scan: Option<String>,
+    flag_scan_dwell: f64,
+    flag_scan_squelch: Option<f32>,
+    flag_stall_timeout: f64,
     flag_version: bool,
 }
 
 fn main() {
-    let args: Args = Docopt::new(USAGE)
-                                .and_then(|d| d.decode())
-                                .unwrap_or_else(|e| e.exit());
+    let mut args: Args = Docopt::new(USAGE)
+                                    .and_then(|d| d.decode())
+                                    .unwrap_or_else(|e| e.exit());
 
     if args.flag_version {
         println!("{}", VERSION);
         return;
     }
 
-    let mut radio = HackRF::open().expect("Error opening HackRF");
+    if args.cmd_self_test {
+        let passed = self_test::run();
+        std::process::exit(if passed { 0 } else { 1 });
+    }
 
-    let mut canvas = Canvas::new().expect("Error opening terminal");
-    let fft_len = Arc::new(Mutex::new(canvas.get_spectrum_width()));
+    if args.cmd_devices {
+        list_devices();
+        return;
+    }
+    // record and scan stand in for their options
+    if args.cmd_record {
+        if args.flag_record.is_some() {
+            exit_with_error("record already records to <iq-file>, leave out --record.");
+        }
+        args.flag_record = args.arg_iq_file.take();
+    }
+    if args.cmd_scan {
+        if args.flag_scan.is_some() {
+            exit_with_error("scan already steps through <freqs>, leave out --scan.");
+        }
+        args.flag_scan = args.arg_freqs.take();
+    }
+    if args.cmd_play {
+        let path = args.arg_iq_file.as_ref().map_or("", |p| &p[..]);
+        if !Path::new(path).is_file() {
+            exit_with_error(&format!("Can't play '{}', it isn't a file.", path));
+        }
+        if args.flag_headless || args.flag_scan.is_some() {
+            exit_with_error("play shows a recording in the terminal UI, leave out \
+                             --headless and --scan.");
+        }
+    }
+    if args.flag_audio_device.is_some() && !demod::CHOOSES_OUTPUT {
+        exit_with_error("--audio-device isn't supported on macOS, the audio goes to the \
+                         default output.");
+    }
+    if args.flag_fast && !args.cmd_play {
+        exit_with_error("--fast goes through a recording, it needs play.");
+    }
+    if args.flag_fast && args.flag_loop {
+        exit_with_error("--fast goes through the recording once, leave out --loop.");
+    }
+    // and so do these for their arguments
+    if args.flag_freq.is_some() {
+        if args.arg_freq_hz.is_some() {
+            exit_with_error("Give either <freq-hz> or --freq, not both.");
+        }
+        args.arg_freq_hz = args.flag_freq.take();
+    }
+    if args.flag_rate.is_some() {
+        if args.arg_bandwidth_hz.is_some() {
+            exit_with_error("Give either <bandwidth-hz> or --rate, not both.");
+        }
+        args.arg_bandwidth_hz = args.flag_rate.take();
+    }
+    let given_source = args.flag_source.as_ref().map(|spec| {
+        Source::parse(spec).unwrap_or_else(|msg| exit_with_error(&format!("Invalid --source: {}",
+                                                                          msg)))
+    });
+    let input_flags = [("--zmq", args.flag_zmq.is_some()), ("--kiwi", args.flag_kiwi.is_some()),
+                       ("--source", given_source.is_some())];
+    let mut input_flags = input_flags.iter().filter(|&&(_, given)| given).map(|&(flag, _)| flag);
+    if let Some(flag) = input_flags.next() {
+        if input_flags.next().is_some() {
+            exit_with_error("Give only one of --zmq, --kiwi and --source.");
+        }
+        if args.cmd_play || args.cmd_udp || args.cmd_live {
+            exit_with_error(&format!("{} is an input of its own, leave out play, udp and \
+                                      live.", flag));
+        }
+        if args.flag_headless || args.flag_scan.is_some() {
+            exit_with_error(&format!("{} shows the stream in the terminal UI, leave out \
+                                      --headless and --scan.", flag));
+        }
+    }
+    let compare = args.flag_compare.as_ref().map(|spec| {
+        parse_comparison(spec, args.flag_udp_format).unwrap_or_else(|msg| {
+            exit_with_error(&format!("Invalid --compare: {}", msg))
+        })
+    });
+    if compare.is_some() && (args.flag_headless || args.flag_scan.is_some() || args.flag_fast) {
+        exit_with_error("--compare shows both inputs in the terminal UI, leave out --headless, \
+                         --scan and --fast.");
+    }
+    if args.flag_cross && compare.is_none() {
+        exit_with_error("--cross needs --compare, for the input to take the phase against.");
+    }
+    if args.cmd_udp && (args.flag_headless || args.flag_scan.is_some()) {
+        exit_with_error("udp shows the stream in the terminal UI, leave out --headless and \
+                         --scan.");
+    }
+
+    let arg_freq_hz = args.arg_freq_hz.as_ref().map(|s| {
+        let hz = parse_hz_arg("<freq-hz>", s);
+        // a stream's can be any
+        if !args.cmd_udp && args.flag_zmq.is_none() && args.flag_kiwi.is_none() &&
+           given_source.is_none() && !is_tunable(hz) {
+            exit_with_error(&format!("<freq-hz> should be from {}Hz to {}Hz, not {}.",
+                                     format_frequency(MIN_FREQ_HZ),
+                                     format_frequency(MAX_FREQ_HZ), s));
+        }
+        hz.round() as u64
+    });
+    let arg_bandwidth_hz = args.arg_bandwidth_hz.as_ref().map(|s| {
+        parse_hz_arg("<bandwidth-hz>", s)
+    });
+    if args.flag_max_fps.map_or(false, |fps| !(fps > 0.0)) {
+        exit_with_error("--max-fps must be above 0.");
+    }
+    if args.flag_serve_ws.is_none() && args.flag_serve_http == Some(::std::u16::MAX) {
+        exit_with_error("--serve-http needs --serve-ws to give the WebSocket port.");
+    }
+    if args.flag_serve_addr.parse::<IpAddr>().is_err() {
+        exit_with_error("--serve-addr must be an IP address, like 127.0.0.1 or 0.0.0.0.");
+    }
+    if args.flag_metrics_bands == 0 {
+        exit_with_error("--metrics-bands must be at least 1.");
+    }
+    if args.flag_history == 0 {
+        exit_with_error("--history must be at least 1.");
+    }
+    if let Some(Err(msg)) = args.flag_trigger.as_ref().map(|r| trigger::parse_range(r)) {
+        exit_with_error(&format!("Invalid --trigger: {}", msg));
+    }
+    if args.flag_duration.as_ref().map_or(false, |d| parse_duration(d).is_none()) {
+        exit_with_error("Invalid --duration, expected a time like 30s, 5m or 2h.");
+    }
+
+    if args.cmd_replay {
+        replay(&args);
+        return;
+    }
+
+    let config = config::load(args.flag_config.as_ref().map(|path| &path[..]))
+                        .expect("Error reading config");
+    let mut preset_index = args.flag_preset.as_ref().map(|name| {
+        config.presets.iter().position(|p| &p.name == name).unwrap_or_else(|| {
+            let names: Vec<_> = config.presets.iter().map(|p| &p.name[..]).collect();
+            exit_with_error(&format!("Unknown preset '{}', the config has: {}", name,
+                                     names.join(", ")))
+        })
+    });
+    let preset = preset_index.map(|i| &config.presets[i]);
+
+    // started bare, ask for the settings rather than complain about them
+    let mut setup_canvas = None;
+    let setup = if std::env::args().len() == 1 {
+        wait::catch_stop_signals();
+        let mut canvas = Canvas::new().expect("Error opening terminal");
+        let setup = match wizard::run(&mut canvas, DEFAULT_LNA_GAIN_DB, DEFAULT_VGA_GAIN_DB) {
+            Some(setup) => setup,
+            None => return,
+        };
+        setup_canvas = Some(canvas);
+        Some(setup)
+    } else {
+        None
+    };
+
+    let radioless = args.cmd_play || args.cmd_udp || args.flag_zmq.is_some() ||
+                    args.flag_kiwi.is_some() || given_source.is_some();
+    let mut scanner = args.flag_scan.as_ref().map(|freqs| {
+        let channels = scan::parse_channels(freqs).unwrap_or_else(|msg| {
+            exit_with_error(&format!("Invalid --scan: {}", msg))
+        });
+        let dwell = Duration::from_millis((args.flag_scan_dwell * 1000.0) as u64);
+        Scanner::new(channels, dwell, args.flag_scan_squelch)
+    });
+    let freq_hz = scanner.as_ref().map(Scanner::current)
+                         .or(arg_freq_hz)
+                         .or(preset.and_then(|p| p.freq_hz))
+                         .or(setup.as_ref().map(|s| s.freq_hz))
+                                                  // but a Kiwi has to be told
+                         .or(Some(0).filter(|_| radioless && args.flag_kiwi.is_none()))
+                         .unwrap_or_else(|| {
+                             exit_with_error("Give a frequency, or a --preset with one.")
+                         });
+    let bandwidth_hz = arg_bandwidth_hz.or(preset.and_then(|p| p.bandwidth_hz))
+                                       .or(setup.as_ref().map(|s| s.bandwidth_hz));
+    let mut tuning = Tuning {
+        freq_hz: freq_hz,
+        sample_rate_hz: bandwidth_hz.unwrap_or(probe::DEFAULT_SAMPLE_RATE_HZ),
+        lna_gain_db: args.flag_lna_gain.or(preset.and_then(|p| p.lna_gain_db))
+                                       .or(setup.as_ref().map(|s| s.lna_gain_db))
+                                       .unwrap_or(DEFAULT_LNA_GAIN_DB),
+        vga_gain_db: args.flag_vga_gain.or(preset.and_then(|p| p.vga_gain_db))
+                                       .or(setup.as_ref().map(|s| s.vga_gain_db))
+                                       .unwrap_or(DEFAULT_VGA_GAIN_DB),
+        amp: args.flag_amp || preset.and_then(|p| p.amp)
+                                    .or(setup.as_ref().map(|s| s.amp))
+                                    .unwrap_or(false),
+    };
+
+    // a recording or a stream plays without one
+    let compare_serial = match compare {
+        Some(Comparison::Radio(ref serial)) => Some(&serial[..]),
+        _ => None,
+    };
+    let mut radio = if radioless {
+        None
+    } else {
+        Some(open_radio(compare_serial).expect("Error opening HackRF"))
+    };
+    if let Some(ref mut radio) = radio {
+        tuning.set_radio(radio);
+        if args.cmd_gain_sweep {
+            let dwell = Duration::from_millis((args.flag_dwell * 1000.0) as u64);
+            gain_sweep::run(radio, tuning.sample_rate_hz as u32, processing_threads(&args), dwell);
+            return;
+        }
+    }
+
+    if args.flag_headless {
+        if args.flag_record_session.is_some() {
+            exit_with_error("--record-session needs the terminal UI, leave out --headless.");
+        }
+        if args.flag_rigctl.is_some() || args.flag_follow.is_some() {
+            exit_with_error("--rigctl and --follow need the terminal UI, leave out --headless.");
+        }
+        let radio = radio.expect("play doesn't run headless");
+        headless(&args, radio, tuning, bandwidth_hz.is_none(), scanner);
+        return;
+    }
+    // stdout is the screen
+    if args.flag_csv.as_ref().map_or(false, |p| p == "-") ||
+       args.flag_json.as_ref().map_or(false, |p| p == "-") {
+        exit_with_error("Writing spectra to stdout needs --headless, or give a file instead.");
+    }
+
+    // from here a signal has to go through the loop, to restore the terminal
+    wait::catch_stop_signals();
+    let mut canvas = setup_canvas.unwrap_or_else(|| {
+        Canvas::new().expect("Error opening terminal")
+    });
+    if let Some(ref compare) = compare {
+        canvas.set_layout(args.flag_layout);
+        canvas.add_pane(format!("B {}", compare));
+    }
+    let mut cross = if args.flag_cross {
+        let pane = canvas.add_pane("A-B phase, -180 to 180 degrees".to_string());
+        Some((CrossSpectrum::new(), pane))
+    } else {
+        None
+    };
+    if args.flag_fast {
+        // room for every spectrum of the recording
+        let path = args.arg_iq_file.as_ref().map_or("", |p| &p[..]);
+        let num_samples = fs::metadata(path).map_or(0, |m| m.len() / 2);
+        let rows = num_samples as f64 / tuning.sample_rate_hz * args.flag_fft_rate as f64;
+        args.flag_history = max(args.flag_history, rows.ceil() as usize + 1);
+    }
+    configure_canvas(&mut canvas, &args);
+    canvas.set_tuning(tuning.freq_hz as f64, tuning.sample_rate_hz);
+    canvas.add_bookmarks(config.bookmarks.clone());
+    let config_path = args.flag_config.as_ref().map(PathBuf::from).or_else(config::default_path);
+    if !args.flag_no_mouse {
+        if let Err(e) = canvas.enable_mouse() {
+            canvas.set_notice(Some(format!("Couldn't turn on the mouse: {}", e)));
+        }
+    }
+    if args.flag_kitty && !args.flag_ascii {
+        match canvas.enable_kitty() {
+            Ok(true) => {}
+            Ok(false) => canvas.set_notice(Some(
+                "The terminal doesn't support the kitty graphics protocol".to_string())),
+            Err(e) => canvas.set_notice(Some(
+                format!("Couldn't ask the terminal about kitty graphics: {}", e))),
+        }
+    }
+
+    let probing = bandwidth_hz.is_none() && args.flag_probe != ProbeMode::Off;
+    if let Some(radio) = radio.as_mut().filter(|_| probing) {
+        canvas.show_message("Probing the band for suitable settings... q quits");
+        let fft_len = canvas.get_spectrum_width();
+        let mut quit = false;
+        let suggestion = probe::run(radio, tuning.sample_rate_hz as u32, fft_len,
+                                    processing_threads(&args), args.flag_lna_gain,
+                                    args.flag_vga_gain, || {
+            while let Ok(Some(key)) = read_key(canvas.get_term(), Duration::from_secs(0)) {
+                quit |= key == Key::Char('q') || key == INTERRUPT;
+            }
+            quit |= wait::stop_requested();
+            quit
+        });
+        if quit {
+            return;
+        }
+        if args.flag_probe == ProbeMode::Suggest {
+            drop(canvas);
+            print_suggestion(suggestion.as_ref());
+            return;
+        }
+
+        if let Some(ref s) = suggestion {
+            canvas.set_db_range(s.min_db, s.max_db);
+            canvas.set_average(s.average);
+            tuning.lna_gain_db = s.lna_gain_db;
+            tuning.vga_gain_db = s.vga_gain_db;
+        }
+        // the probe leaves the radio at whichever gains it tried last
+        tuning.set_radio(radio);
+    }
+    canvas.set_radio_settings(tuning.radio_settings(args.flag_fft_rate));
+
+    let session = args.flag_record_session.as_ref().map(|path| {
+        let header = Header {
+            center_freq_hz: tuning.freq_hz as f64,
+            sample_rate_hz: tuning.sample_rate_hz,
+            cols: canvas.size().0,
+            rows: canvas.size().1,
+        };
+        SessionWriter::create(path, &header).expect("Error creating session file")
+    });
+    let stats = Arc::new(Stats::default());
+    canvas.set_stats(stats.clone());
+    let mut outputs = Outputs::open(&args, &tuning, session, &stats);
+    let params = Arc::new(Mutex::new(Params {
+        fft_len: canvas.get_spectrum_width(),
+        sample_rate_hz: tuning.sample_rate_hz as u32,
+        fft_rate_hz: args.flag_fft_rate,
+        threads: processing_threads(&args),
+    }));
 
-    radio.set_frequency(args.arg_freq_hz.unwrap()).unwrap();
-    radio.set_sample_rate(args.arg_bandwidth_hz.unwrap()).unwrap();
     let (spec_send, spec_recv) = sync_channel(1);
-    let recv = radio.start_rx();
+    let (mut wait_set, waker) = WaitSet::new().expect("Error opening terminal");
+    let stall_timeout = Duration::from_millis((args.flag_stall_timeout * 1000.0) as u64);
+    let (mut watchdog, recv) = Watchdog::new(stall_timeout);
+    if let Some(ref mut radio) = radio {
+        watchdog.watch(radio.start_rx());
+    }
 
-    let len = fft_len.clone();
-    std::thread::spawn(move || {
-        process_signal(recv, spec_send, len, args.flag_fft_rate,
-                       args.arg_bandwidth_hz.unwrap() as u32);
+    let thread_params = params.clone();
+    let thread_stats = stats.clone();
+    let recv = outputs.iq.tee(recv, watchdog.overrun_counter());
+    let listener = Listener::new(args.flag_audio_device.clone());
+    let recv = listener.tee(recv, watchdog.overrun_counter());
+    let scope = Scope::new();
+    let recv = scope.tee(recv, watchdog.overrun_counter());
+    let (mut listen_mode, mut listen_width) = (None, 0);
+    let mut fft_rate = args.flag_fft_rate;
+    canvas.render_in_background(waker.try_clone().expect("Error creating waker"));
+    let rigctl = args.flag_rigctl.and_then(|port| {
+        let waker = waker.try_clone().expect("Error creating waker");
+        match rigctl::Server::listen(port, waker) {
+            Ok(server) => Some(server),
+            Err(e) => {
+                canvas.set_notice(Some(format!("Couldn't start the rigctl server: {}", e)));
+                None
+            }
+        }
+    });
+    let follower = args.flag_follow.as_ref().map(|addr| {
+        Follower::start(addr.clone(), waker.try_clone().expect("Error creating waker"))
+    });
+    // none of a recording played fast is dropped, it's held up instead
+    let backpressure = if args.flag_fast { Backpressure::Block } else { args.flag_backpressure };
+    // a recording's samples aren't handed out from a pool
+    let pools = Pools::new(radio.as_ref().map_or_else(BufferPool::new, HackRF::buffer_pool));
+    let thread_pools = pools.clone();
+    let compare_waker = waker.try_clone().expect("Error creating waker");
+
+    // the second input's spectra go through a pipeline of their own
+    let (compare_spec_send, compare_spec_recv) = sync_channel(1);
+    let compare_params = Arc::new(Mutex::new(*params.lock().unwrap()));
+    let mut compare_radio = None;
+    let mut compare_feed = None;
+    let mut compare_tuning = tuning;
+    let opened = compare.as_ref().map(|compare| {
+        open_comparison(compare, &tuning, args.flag_loop, &mut compare_radio, &mut compare_feed)
+    });
+    let compare_pools = Pools::new(compare_radio.as_ref()
+                                                .map_or_else(BufferPool::new, HackRF::buffer_pool));
+    let first = PairedInput {
+        recv: recv,
+        send: spec_send,
+        waker: waker,
+        stats: thread_stats,
+        pools: thread_pools,
+    };
+    let paired_with = match opened {
+        // or along with the first's, for their spectra to pair up
+        Some(Ok(compare_recv)) if args.flag_cross => Some(PairedInput {
+            recv: compare_recv,
+            send: compare_spec_send,
+            waker: compare_waker,
+            stats: Arc::new(Stats::default()),
+            pools: compare_pools.clone(),
+        }),
+        Some(Ok(compare_recv)) => {
+            let thread_params = compare_params.clone();
+            let thread_pools = compare_pools.clone();
+            std::thread::spawn(move || {
+                process_signal(compare_recv, compare_spec_send, compare_waker, thread_params,
+                               Arc::new(Stats::default()), backpressure, thread_pools);
+            });
+            None
+        }
+        Some(Err(e)) => {
+            let compare = compare.as_ref().unwrap();
+            canvas.set_notice(Some(format!("Couldn't open {}: {}", compare, e)));
+            None
+        }
+        None => None,
+    };
+    std::thread::spawn(move || match paired_with {
+        Some(second) => process_pair(first, second, thread_params),
+        None => process_signal(first.recv, first.send, first.waker, thread_params, first.stats,
+                               backpressure, first.pools),
+    });
+
+    // so the time spent starting up doesn't count towards the first dwell
+    if let Some(ref mut scanner) = scanner {
+        scanner.restart();
+    }
+    let mut limit = RunLimit::start(&args);
+    let mut scan_holding = false;
+    let mut source = Source::Radio;
+    let mut feed: Option<Feed> = None;
+    // until a recording played fast is all in
+    let mut rendering_fast = args.flag_fast;
+    let mut last_spectrum = Instant::now();
+    // the recording's position when last looked at
+    let mut position_mark = None;
+    // what the source picker is showing
+    let mut sources = Vec::new();
+    let first = setup.map(|s| s.source).or_else(|| {
+        args.arg_iq_file.as_ref().filter(|_| args.cmd_play).map(|p| Source::File(p.into()))
+            .or(args.arg_udp_port.map(|port| Source::Udp(port, args.flag_udp_format)))
+            .or(args.flag_zmq.as_ref().map(|endpoint| Source::Zmq(endpoint.clone())))
+            .or(args.flag_kiwi.as_ref().map(|addr| Source::Kiwi(addr.clone())))
+            .or(given_source)
     });
+    let fast = Some(stats.clone()).filter(|_| args.flag_fast);
+    if let Some(next) = first.filter(|s| *s != Source::Radio) {
+        match switch_source(&source, &next, &mut radio, &tuning, &mut feed, args.flag_loop,
+                            fast) {
+            Ok(recv) => {
+                watchdog.watch(recv);
+                source = next;
+            }
+            Err(e) => canvas.set_notice(Some(format!("Couldn't open {}: {}", next, e))),
+        }
+    }
 
-    for spec in spec_recv.iter() {
-        canvas.add_spectrum(spec);
-        if let Ok(Some(Event::Key('q'))) = canvas.get_term().get_event(Duration::from_secs(0)) {
+    'ui: loop {
+        // While paused there's no need to wake for new spectra, unless they're
+        // being recorded; the processing thread keeps draining the radio and
+        // drops what the UI doesn't take. The renderer wakes the same way when
+        // a waterfall is ready.
+        let recording = outputs.wants_every_spectrum() || limit.counts_frames();
+        let mut timeout = scanner.as_ref()
+                                 .map_or(IDLE_TIMEOUT, |s| min(s.time_to_next(), IDLE_TIMEOUT));
+        for t in canvas.time_to_next_frame().into_iter().chain(limit.time_left()) {
+            timeout = min(timeout, t);
+        }
+        if rendering_fast {
+            timeout = min(timeout, FAST_SETTLE_TIME);
+        }
+        // rigctl clients and the followed rig wake the loop the same way
+        let want_data = !canvas.is_paused() || recording || canvas.is_rendering() ||
+                        rigctl.is_some() || follower.is_some();
+        let wakeup = wait_set.wait(want_data, timeout).expect("Error waiting for input");
+        match wakeup {
+            Wakeup::Data => loop {
+                canvas.set_scope(scope.samples());
+                while let Ok(spec) = compare_spec_recv.try_recv() {
+                    canvas.add_spectrum_to(1, &spec);
+                    add_cross(&mut canvas, cross.as_mut(), 1, &spec);
+                    compare_pools.spectra.give(spec);
+                }
+                if limit.is_over() {
+                    break;
+                }
+                let spec = match spec_recv.try_recv() {
+                    Ok(spec) => spec,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => break 'ui,
+                };
+                limit.count_frame();
+                last_spectrum = Instant::now();
+                if let Some(notice) = outputs.write_spectrum(&spec, &tuning) {
+                    canvas.set_notice(Some(notice));
+                }
+                if let Some(ref mut scanner) = scanner {
+                    scanner.observe(&spec);
+                }
+                let radio_overruns = radio.as_ref().map_or(0, HackRF::overruns);
+                let overruns = watchdog.overruns() + radio_overruns;
+                canvas.set_overruns(overruns);
+                outputs.set_overruns(overruns);
+                let alerting = canvas.alert().is_some();
+                canvas.add_spectrum(&spec);
+                add_cross(&mut canvas, cross.as_mut(), 0, &spec);
+                pools.spectra.give(spec);
+                match canvas.alert() {
+                    Some((freq_hz, power_db)) if !alerting => {
+                        raise_alert(&mut canvas, &args, freq_hz, power_db)
+                    }
+                    _ => {}
+                }
+            },
+            Wakeup::Input => {
+                while let Ok(Some(key)) = read_key(canvas.get_term(), Duration::from_secs(0)) {
+                    if let Some(ref mut session) = outputs.session {
+                        session.write_key(key).expect("Error writing session file");
+                    }
+                    match handle_key(&mut canvas, key) {
+                        Action::Continue => {}
+                        Action::Quit => break 'ui,
+                        Action::NextPreset => {
+                            if config.presets.is_empty() {
+                                canvas.set_notice(Some("The config has no presets".to_string()));
+                                continue;
+                            }
+                            let i = preset_index.map_or(0, |i| (i + 1) % config.presets.len());
+                            preset_index = Some(i);
+                            switch_preset(&config.presets[i], &mut tuning, radio.as_mut(),
+                                          &mut canvas, fft_rate);
+                            params.lock().unwrap().sample_rate_hz = tuning.sample_rate_hz as u32;
+                            outputs.write_tuning(&tuning);
+                        }
+                        Action::ToggleRecording => {
+                            let notice = toggle_recording(&outputs.iq, &tuning);
+                            canvas.set_notice(Some(notice));
+                        }
+                        Action::StepFftRate(step) => {
+                            fft_rate = step_fft_rate(fft_rate, step);
+                            params.lock().unwrap().fft_rate_hz = fft_rate;
+                            canvas.set_radio_settings(tuning.radio_settings(fft_rate));
+                            canvas.set_notice(Some(format!("{} FFT/s", fft_rate)));
+                        }
+                        Action::PickSource => {
+                            sources = source::available();
+                            let names = sources.iter().map(|s| s.to_string()).collect();
+                            let current = sources.iter().position(|s| *s == source).unwrap_or(0);
+                            canvas.show_picker("Inputs", names, current,
+                                               "Up/Down choose, Enter switches, Esc cancels.");
+                        }
+                        Action::SwitchSource(i) => {
+                            let next = sources[i].clone();
+                            if next == source {
+                                continue;
+                            }
+                            match switch_source(&source, &next, &mut radio, &tuning, &mut feed,
+                                                args.flag_loop, None) {
+                                Ok(recv) => {
+                                    watchdog.watch(recv);
+                                    position_mark = None;
+                                    canvas.mark_waterfall(format!("input {}", next));
+                                    canvas.set_notice(Some(format!("Switched to {}", next)));
+                                    source = next;
+                                }
+                                Err(e) => {
+                                    let notice = format!("Couldn't open {}: {}", next, e);
+                                    canvas.set_notice(Some(notice));
+                                }
+                            }
+                        }
+                        Action::StepBookmark(step) => {
+                            // bookmarks are of frequencies as shown
+                            let offset_hz = canvas.freq_offset();
+                            let freq_hz = tuning.freq_hz as f64 - offset_hz;
+                            let bookmark = match bookmarks::step(canvas.get_bookmarks(), freq_hz,
+                                                                 step) {
+                                Some(bookmark) => bookmark.clone(),
+                                None => {
+                                    let notice = "No bookmarks yet, B saves the marker as one";
+                                    canvas.set_notice(Some(notice.to_string()));
+                                    continue;
+                                }
+                            };
+                            let radio_hz = bookmark.freq_hz + offset_hz;
+                            if !is_tunable(radio_hz) {
+                                let notice = format!("Can't tune to {}", bookmark.name);
+                                canvas.set_notice(Some(notice));
+                                continue;
+                            }
+                            let preset = Preset {
+                                name: bookmark.name.clone(),
+                                freq_hz: Some(radio_hz.round() as u64),
+                                bandwidth_hz: bookmark.bandwidth_hz,
+                                ..Preset::default()
+                            };
+                            switch_preset(&preset, &mut tuning, radio.as_mut(), &mut canvas,
+                                          fft_rate);
+                            params.lock().unwrap().sample_rate_hz = tuning.sample_rate_hz as u32;
+                            outputs.write_tuning(&tuning);
+                            canvas.set_notice(Some(format!("Bookmark {}", bookmark.name)));
+                        }
+                        Action::SaveBookmark(name) => {
+                            let path = config_path.as_ref().map(PathBuf::as_path);
+                            let notice = save_bookmark(&mut canvas, path, name);
+                            canvas.set_notice(Some(notice));
+                        }
+                        Action::Tune(freq_hz) => {
+                            tune_to(freq_hz, &mut tuning, radio.as_mut(), &mut canvas,
+                                    &mut outputs);
+                        }
+                        Action::CycleListenMode => {
+                            if canvas.marker_reading().is_none() {
+                                let notice = "Place the marker to listen to it";
+                                canvas.set_notice(Some(notice.to_string()));
+                                continue;
+                            }
+                            listen_mode = Mode::cycle(listen_mode);
+                            let notice = match listen_mode {
+                                Some(mode) => {
+                                    listen_width = mode.default_bandwidth();
+                                    format!("Listening in {}", mode)
+                                }
+                                // nothing more would be written to it
+                                None => stop_audio_recording(&listener)
+                                            .unwrap_or_else(|| "Stopped listening".to_string()),
+                            };
+                            canvas.set_notice(Some(notice));
+                        }
+                        Action::CycleListenWidth => match listen_mode {
+                            Some(mode) => {
+                                listen_width = (listen_width + 1) % mode.bandwidths_hz().len();
+                                let width = format_frequency(mode.bandwidths_hz()[listen_width]);
+                                canvas.set_notice(Some(format!("Channel {}Hz wide", width)));
+                            }
+                            None => {
+                                let notice = "Press a to listen to the marker first";
+                                canvas.set_notice(Some(notice.to_string()));
+                            }
+                        },
+                        Action::StepVolume(steps) => {
+                            let volume = listener.step_volume(steps);
+                            let notice = format!("Volume {:.0}%", 100.0 * volume);
+                            canvas.set_notice(Some(notice));
+                        }
+                        Action::ToggleAudioRecording => {
+                            let notice = toggle_audio_recording(&listener, listen_mode.is_some());
+                            canvas.set_notice(Some(notice));
+                        }
+                        Action::Playback(playback) => match feed {
+                            Some(Feed::File(ref player)) if !args.flag_fast => {
+                                let notice = control_playback(player, playback);
+                                position_mark = Some(player.position_secs());
+                                canvas.mark_waterfall(playback_mark(player));
+                                canvas.set_notice(Some(notice));
+                            }
+                            _ => {
+                                let notice = "Only a recording played back can be paused, \
+                                              sped up or seeked";
+                                canvas.set_notice(Some(notice.to_string()));
+                            }
+                        },
+                        Action::ToggleMute => {
+                            let muted = listener.toggle_mute();
+                            canvas.set_notice(Some(if muted { "Muted" } else { "Unmuted" }
+                                                       .to_string()));
+                        }
+                        Action::ToggleScope => {
+                            scope.toggle();
+                            canvas.set_scope(scope.samples());
+                        }
+                    }
+                }
+            }
+            Wakeup::Timeout => {}
+        }
+        if let Some(ref server) = rigctl {
+            answer_rigctl(server, &mut tuning, radio.as_mut(), &mut canvas, &mut outputs);
+        }
+        if let Some(ref follower) = follower {
+            follow_rig(follower, &mut tuning, radio.as_mut(), &mut canvas, &mut outputs);
+        }
+        if let Some(ref feed) = feed {
+            if follow_stream(feed, &mut tuning, &mut canvas, &mut outputs, fft_rate) {
+                params.lock().unwrap().sample_rate_hz = tuning.sample_rate_hz as u32;
+            }
+        }
+        // the second input goes along with the first
+        *compare_params.lock().unwrap() = *params.lock().unwrap();
+        if let Some(ref mut compare) = compare_radio.as_mut().filter(|_| compare_tuning != tuning) {
+            compare_tuning = tuning;
+            if let Err(setting) = tuning.try_set_radio(compare) {
+                canvas.set_notice(Some(format!("Couldn't set the second HackRF's {}", setting)));
+            }
+        }
+        let (played_through, held) = match feed {
+            Some(Feed::File(ref player)) => {
+                // the position it's got to, every so often and back at the start
+                let position = player.position_secs();
+                let interval = |secs: f64| (secs / POSITION_MARK_SECS).floor();
+                if position_mark.map_or(true, |last| position < last ||
+                                                     interval(position) != interval(last)) {
+                    canvas.mark_waterfall(playback_mark(player));
+                }
+                position_mark = Some(position);
+                (player.is_finished(), player.is_finished() || player.is_paused())
+            }
+            _ => (false, false),
+        };
+        if held {
+            watchdog.excuse();
+        }
+        // its last spectra follow its last samples through the processing
+        if rendering_fast && played_through && last_spectrum.elapsed() >= FAST_SETTLE_TIME {
+            rendering_fast = false;
+            if !canvas.is_paused() {
+                canvas.toggle_pause();
+            }
+            canvas.set_notice(Some(format!("All of {} is in, PgUp/PgDn scroll through it",
+                                           source)));
+        }
+        // follows the marker, and the tuning, wherever they've gone
+        if let Err(e) = listen_at_marker(&listener, &mut canvas, listen_mode, listen_width,
+                                         &tuning) {
+            listen_mode = None;
+            canvas.set_notice(Some(format!("Couldn't play the audio: {}", e)));
+        }
+        if limit.is_over() || wait::stop_requested() {
             break;
         }
+        canvas.collect_rendered();
+        canvas.draw_pending_frame();
+
+        match watchdog.poll() {
+            Some(Event::Stalled) if source != Source::Radio => {
+                canvas.set_notice(Some(format!("No samples from {}", source)));
+            }
+            Some(Event::Stalled) => if let Some(ref mut radio) = radio {
+                let notice = match restart_radio(radio, &tuning) {
+                    Ok(recv) => {
+                        watchdog.watch(recv);
+                        format!("No samples for {}s, reopened the radio", args.flag_stall_timeout)
+                    }
+                    Err(e) => format!("No samples for {}s, couldn't reopen the radio: {}",
+                                      args.flag_stall_timeout, e),
+                };
+                canvas.set_notice(Some(notice));
+            },
+            Some(Event::Recovered(outage)) => {
+                let secs = outage.as_secs() as f64 + outage.subsec_nanos() as f64 / 1e9;
+                let outage = format!("no samples for {:.1}s", secs);
+                canvas.set_notice(Some(format!("The radio is back after {}", outage)));
+                canvas.mark_waterfall(outage);
+            }
+            None => {}
+        }
+
+        if let Some(ref mut scanner) = scanner {
+            if scanner.is_holding() != scan_holding {
+                scan_holding = !scan_holding;
+                canvas.set_notice(if scan_holding {
+                    let freq = format_frequency(tuning.freq_hz as f64);
+                    Some(format!("Holding the scan on {}", freq))
+                } else {
+                    None
+                });
+            }
+            if let Some(freq_hz) = scanner.poll() {
+                tuning.freq_hz = freq_hz;
+                if let Some(ref mut radio) = radio {
+                    radio.set_frequency(freq_hz).expect("Invalid frequency");
+                }
+                canvas.retune(tuning.freq_hz as f64, tuning.sample_rate_hz);
+                outputs.write_tuning(&tuning);
+            }
+        }
+
+        params.lock().unwrap().fft_len = canvas.get_spectrum_width();
+    }
+
+    if let Some(radio) = radio.as_mut().filter(|_| source == Source::Radio) {
+        radio.stop_rx().expect("Couldn't stop receiving");
+    }
+    listener.stop_recording().expect("Error writing audio recording");
+    outputs.finish();
+}
+
+/// Runs the radio and processing without a terminal, until interrupted, with
+/// messages going to stderr. `probe` is set when no bandwidth was given.
+fn headless(args: &Args, mut radio: HackRF, mut tuning: Tuning, probe: bool,
+            mut scanner: Option<Scanner>) {
+    wait::catch_stop_signals();
+    if probe && args.flag_probe != ProbeMode::Off {
+        eprintln!("Probing the band for suitable settings...");
+        let suggestion = probe::run(&mut radio, tuning.sample_rate_hz as u32, args.flag_bins,
+                                    processing_threads(args), args.flag_lna_gain,
+                                    args.flag_vga_gain,
+                                    wait::stop_requested);
+        if wait::stop_requested() {
+            return;
+        }
+        if args.flag_probe == ProbeMode::Suggest {
+            print_suggestion(suggestion.as_ref());
+            return;
+        }
+        if let Some(ref s) = suggestion {
+            tuning.lna_gain_db = s.lna_gain_db;
+            tuning.vga_gain_db = s.vga_gain_db;
+        }
+        tuning.set_radio(&mut radio);
+    }
+
+    let stats = Arc::new(Stats::default());
+    let mut outputs = Outputs::open(args, &tuning, None, &stats);
+    if args.flag_csv.is_none() && args.flag_json.is_none() && args.flag_waterfall_png.is_none() &&
+       ws_port(args).is_none() && args.flag_serve_metrics.is_none() {
+        outputs.csv = Some(RtlPowerWriter::open("-").expect("Error opening stdout"));
+    }
+
+    let params = Arc::new(Mutex::new(Params {
+        fft_len: args.flag_bins,
+        sample_rate_hz: tuning.sample_rate_hz as u32,
+        fft_rate_hz: args.flag_fft_rate,
+        threads: processing_threads(args),
+    }));
+    let (spec_send, spec_recv) = sync_channel(1);
+    let (mut wait_set, waker) = WaitSet::without_tty().expect("Error creating wait set");
+    let stall_timeout = Duration::from_millis((args.flag_stall_timeout * 1000.0) as u64);
+    let (mut watchdog, recv) = Watchdog::new(stall_timeout);
+    watchdog.watch(radio.start_rx());
+
+    let recv = outputs.iq.tee(recv, watchdog.overrun_counter());
+    let backpressure = args.flag_backpressure;
+    let pools = Pools::new(radio.buffer_pool());
+    let thread_pools = pools.clone();
+    std::thread::spawn(move || {
+        process_signal(recv, spec_send, waker, params, stats, backpressure, thread_pools);
+    });
+
+    if let Some(ref mut scanner) = scanner {
+        scanner.restart();
+    }
+    let mut limit = RunLimit::start(args);
+    let mut overruns = 0;
+    'run: while !wait::stop_requested() && !limit.is_over() {
+        let mut timeout = scanner.as_ref()
+                                 .map_or(IDLE_TIMEOUT, |s| min(s.time_to_next(), IDLE_TIMEOUT));
+        if let Some(t) = limit.time_left() {
+            timeout = min(timeout, t);
+        }
+        match wait_set.wait(true, timeout) {
+            Ok(Wakeup::Data) => loop {
+                if limit.is_over() {
+                    break;
+                }
+                let spec = match spec_recv.try_recv() {
+                    Ok(spec) => spec,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => break 'run,
+                };
+                limit.count_frame();
+                if let Some(msg) = outputs.write_spectrum(&spec, &tuning) {
+                    eprintln!("{}", msg);
+                }
+                if let Some(ref mut scanner) = scanner {
+                    scanner.observe(&spec);
+                }
+                pools.spectra.give(spec);
+            },
+            Ok(_) => {}
+            Err(e) => panic!("Error waiting for samples: {}", e),
+        }
 
-        *fft_len.lock().unwrap() = canvas.get_spectrum_width();
+        match watchdog.poll() {
+            Some(Event::Stalled) => match restart_radio(&mut radio, &tuning) {
+                Ok(recv) => {
+                    watchdog.watch(recv);
+                    eprintln!("No samples for {}s, reopened the radio", args.flag_stall_timeout);
+                }
+                Err(e) => eprintln!("No samples for {}s, couldn't reopen the radio: {}",
+                                    args.flag_stall_timeout, e),
+            },
+            Some(Event::Recovered(outage)) => {
+                let secs = outage.as_secs() as f64 + outage.subsec_nanos() as f64 / 1e9;
+                eprintln!("The radio is back after no samples for {:.1}s", secs);
+            }
+            None => {}
+        }
+        let num_overruns = watchdog.overruns() + radio.overruns();
+        if num_overruns > overruns {
+            eprintln!("Dropped {} buffers of samples, processing fell behind",
+                      num_overruns - overruns);
+            overruns = num_overruns;
+            outputs.set_overruns(overruns);
+        }
+
+        if let Some(ref mut scanner) = scanner {
+            if let Some(freq_hz) = scanner.poll() {
+                tuning.freq_hz = freq_hz;
+                radio.set_frequency(freq_hz).expect("Invalid frequency");
+                outputs.write_tuning(&tuning);
+            }
+        }
     }
 
     radio.stop_rx().expect("Couldn't stop receiving");
+    outputs.finish();
+}
+
+/// Stops the raw sample recording, or starts one named after the time,
+/// returning what happened for the status bar.
+fn toggle_recording(iq: &IqRecorder, tuning: &Tuning) -> String {
+    if iq.is_recording() {
+        return stop_recording(iq, tuning).unwrap_or_default();
+    }
+    match start_recording(iq, "iq", tuning) {
+        Ok(notice) => format!("{}, r stops", notice),
+        Err(notice) => notice,
+    }
+}
+
+/// `<prefix>-<date>-<time>.<extension>`, for a recording started now.
+fn timestamped_name(prefix: &str, extension: &str) -> String {
+    let (date, time) = export::local_date_time();
+    format!("{}-{}-{}.{}", prefix, date.replace("-", ""), time.replace(":", ""), extension)
+}
+
+/// Starts a raw sample recording named `<prefix>-<date>-<time>.cs8`,
+/// returning what happened.
+fn start_recording(iq: &IqRecorder, prefix: &str, tuning: &Tuning) -> Result<String, String> {
+    let path = timestamped_name(prefix, "cs8");
+    match iq.start(Path::new(&path), tuning.freq_hz as f64, tuning.sample_rate_hz) {
+        Ok(()) => Ok(format!("Recording to {}", path)),
+        Err(e) => Err(format!("Couldn't create {}: {}", path, e)),
+    }
+}
+
+/// Stops the raw sample recording, if there is one, returning what happened.
+fn stop_recording(iq: &IqRecorder, tuning: &Tuning) -> Option<String> {
+    match iq.stop() {
+        Ok(Some((path, num_samples))) => {
+            let secs = num_samples as f64 / tuning.sample_rate_hz;
+            Some(format!("Recorded {:.1}s to {}", secs, path.display()))
+        }
+        Ok(None) => None,
+        Err(e) => Some(format!("Error recording: {}", e)),
+    }
+}
+
+/// Starts or stops recording what's being listened to, to a WAV file named
+/// after the time, returning what happened.
+fn toggle_audio_recording(listener: &Listener, listening: bool) -> String {
+    if listener.is_recording() {
+        return stop_audio_recording(listener).unwrap_or_default();
+    }
+    if !listening {
+        return "Press a to listen to the marker first".to_string();
+    }
+    let path = timestamped_name("audio", "wav");
+    match listener.start_recording(Path::new(&path)) {
+        Ok(()) => format!("Recording the audio to {}, A stops", path),
+        Err(e) => format!("Couldn't create {}: {}", path, e),
+    }
+}
+
+/// Stops the audio recording, if there is one, returning what happened.
+fn stop_audio_recording(listener: &Listener) -> Option<String> {
+    match listener.stop_recording() {
+        Ok(Some((path, secs))) => Some(format!("Recorded {:.1}s of audio to {}", secs,
+                                               path.display())),
+        Ok(None) => None,
+        Err(e) => Some(format!("Error recording the audio: {}", e)),
+    }
+}
+
+/// Starts receiving from `next` instead of `current`, stopping `current` only
+/// once `next` has started.
+fn switch_source(current: &Source, next: &Source, radio: &mut Option<HackRF>, tuning: &Tuning,
+                 feed: &mut Option<Feed>, looped: bool, fast: Option<Arc<Stats>>)
+                 -> Result<Receiver<Vec<num::Complex<i8>>>, String> {
+    let (next_feed, recv) = match (next, radio.as_mut()) {
+        (&Source::Radio, Some(radio)) => (None, radio.start_rx()),
+        (&Source::Radio, None) => return Err("no HackRF was opened, for play".to_string()),
+        _ => {
+            let (next_feed, recv) = try!(open_feed(next, tuning, looped, fast));
+            (Some(next_feed), recv)
+        }
+    };
+    if let Some(radio) = radio.as_mut().filter(|_| *current == Source::Radio) {
+        radio.stop_rx().expect("Couldn't stop receiving");
+    }
+    // dropping the old feed stops it
+    *feed = next_feed;
+    Ok(recv)
+}
+
+/// Starts taking samples from anything but the radio.
+fn open_feed(source: &Source, tuning: &Tuning, looped: bool, fast: Option<Arc<Stats>>)
+             -> Result<(Feed, Receiver<Vec<num::Complex<i8>>>), String> {
+    match *source {
+        Source::Radio => Err("the HackRF isn't opened as a feed".to_string()),
+        Source::File(ref path) => {
+            let (p, recv) = try!(FilePlayer::start(path, tuning.sample_rate_hz, looped, fast)
+                                            .map_err(|e| e.to_string()));
+            Ok((Feed::File(p), recv))
+        }
+        Source::Udp(port, format) => {
+            let (udp, recv) = try!(UdpReceiver::start(port, format).map_err(|e| e.to_string()));
+            Ok((Feed::Udp(udp), recv))
+        }
+        Source::Zmq(ref endpoint) => {
+            let (subscriber, recv) = try!(Subscriber::start(endpoint));
+            Ok((Feed::Zmq(subscriber), recv))
+        }
+        Source::Kiwi(ref addr) => {
+            let (kiwi, recv) = try!(KiwiClient::start(addr, tuning.freq_hz as f64));
+            Ok((Feed::Kiwi(kiwi), recv))
+        }
+        Source::Synth(ref signals) => {
+            let (generator, recv) = Generator::start(signals.clone(), tuning.sample_rate_hz);
+            Ok((Feed::Synth(generator), recv))
+        }
+    }
+}
+
+/// What --compare shows beside the first input.
+enum Comparison {
+    /// A second HackRF, by its serial number.
+    Radio(String),
+    Source(Source),
+}
+
+impl std::fmt::Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Comparison::Radio(ref serial) => write!(f, "HackRF {}", serial),
+            Comparison::Source(ref source) => write!(f, "{}", source),
+        }
+    }
+}
+
+fn parse_comparison(spec: &str, udp_format: udp::Format) -> Result<Comparison, String> {
+    if Path::new(spec).is_file() {
+        return Ok(Comparison::Source(Source::File(spec.into())));
+    }
+    if spec.starts_with("tcp://") {
+        return Ok(Comparison::Source(Source::Zmq(spec.to_string())));
+    }
+    match spec.find(':').map_or((spec, ""), |i| (&spec[..i], &spec[i + 1..])) {
+        ("hackrf", serial) if !serial.is_empty() => Ok(Comparison::Radio(serial.to_string())),
+        ("udp", port) => match port.parse() {
+            Ok(port) => Ok(Comparison::Source(Source::Udp(port, udp_format))),
+            Err(_) => Err(format!("'{}' has a bad port", spec)),
+        },
+        ("synth", _) => Source::parse(spec).map(Comparison::Source),
+        _ => Err(format!("'{}' isn't a file, synth, udp:<port>, tcp:// endpoint or \
+                          hackrf:<serial>", spec)),
+    }
+}
+
+/// Starts taking samples from the input to compare with, leaving the radio
+/// or the feed it takes them from in `radio` or `feed`.
+fn open_comparison(compare: &Comparison, tuning: &Tuning, looped: bool,
+                   radio: &mut Option<HackRF>, feed: &mut Option<Feed>)
+                   -> Result<Receiver<Vec<num::Complex<i8>>>, String> {
+    match *compare {
+        Comparison::Radio(ref serial) => {
+            let opened = try!(HackRF::open_serial(serial).map_err(|_| {
+                format!("no HackRF with serial {} could be opened", serial)
+            }));
+            // started where it stays, as receiving keeps a pointer into it
+            *radio = Some(opened);
+            let radio = radio.as_mut().unwrap();
+            try!(tuning.try_set_radio(radio).map_err(|setting| format!("invalid {}", setting)));
+            Ok(radio.start_rx())
+        }
+        Comparison::Source(ref source) => {
+            let (opened, recv) = try!(open_feed(source, tuning, looped, None));
+            *feed = Some(opened);
+            Ok(recv)
+        }
+    }
+}
+
+/// The HackRF, or with a second one to compare with, the first of the others.
+fn open_radio(other_than: Option<&str>) -> Result<HackRF, ()> {
+    let other_than = match other_than {
+        Some(serial) => serial,
+        None => return HackRF::open(),
+    };
+    let devices = try!(radio::hackrf::list_devices());
+    match devices.iter().filter_map(|d| d.serial.as_ref()).find(|s| *s != other_than) {
+        Some(serial) => HackRF::open_serial(serial),
+        None => Err(()),
+    }
+}
+
+/// Where the WebSocket server listens, if anything asks for it.
+fn ws_port(args: &Args) -> Option<u16> {
+    args.flag_serve_ws.or_else(|| args.flag_serve_http.map(|port| port + 1))
+}
+
+/// Where the WebSocket and web page servers listen.
+fn serve_addr(args: &Args) -> IpAddr {
+    // main has checked it's an address already
+    args.flag_serve_addr.parse().unwrap()
+}
+
+/// The threads `--threads` asks for, 0 being one a core.
+fn processing_threads(args: &Args) -> usize {
+    match args.flag_threads {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+}
+
+/// Reopens a radio that stopped streaming and sets it up as it was.
+fn restart_radio(radio: &mut HackRF, tuning: &Tuning)
+                 -> Result<Receiver<Vec<num::Complex<i8>>>, String> {
+    // it's already broken, so stopping can fail
+    let _ = radio.stop_rx();
+    try!(radio.reopen().map_err(|_| "the device didn't open".to_string()));
+    try!(tuning.try_set_radio(radio).map_err(|setting| format!("setting the {} failed", setting)));
+    Ok(radio.start_rx())
+}
+
+/// Parses the positional argument `name`, exiting with what's expected if it
+/// isn't a frequency above 0.
+fn parse_hz_arg(name: &str, s: &str) -> f64 {
+    match parse_frequency(s) {
+        Some(hz) if hz > 0.0 && hz.is_finite() => hz,
+        _ => exit_with_error(&format!("Invalid {} '{}', expected Hz with an optional k, M \
+                                       or G suffix, like 100M or 2.4e9.", name, s)),
+    }
+}
+
+/// Whether the radio tunes to `freq_hz`.
+fn is_tunable(freq_hz: f64) -> bool {
+    freq_hz >= MIN_FREQ_HZ && freq_hz <= MAX_FREQ_HZ
+}
+
+/// Parses --freq-offset, which can be below 0.
+fn parse_offset_arg(s: &str) -> f64 {
+    match parse_frequency(s) {
+        Some(hz) if hz.is_finite() => hz,
+        _ => exit_with_error(&format!("Invalid --freq-offset '{}', expected Hz with an optional \
+                                       k, M or G suffix, like 120M or -9.75G.", s)),
+    }
+}
+
+fn exit_with_error(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    std::process::exit(1);
+}
+
+/// Tunes the radio to `freq_hz`, or only relabels a recording, with a
+/// notice saying so. Returns whether it could.
+fn tune_to(freq_hz: u64, tuning: &mut Tuning, radio: Option<&mut HackRF>, canvas: &mut Canvas,
+           outputs: &mut Outputs) -> bool {
+    let freq = format_frequency(freq_hz as f64 - canvas.freq_offset());
+    if radio.map_or(false, |r| r.set_frequency(freq_hz).is_err()) {
+        canvas.set_notice(Some(format!("Couldn't tune to {}Hz", freq)));
+        return false;
+    }
+    tuning.freq_hz = freq_hz;
+    canvas.retune(tuning.freq_hz as f64, tuning.sample_rate_hz);
+    outputs.write_tuning(tuning);
+    canvas.set_notice(Some(format!("Tuned to {}Hz", freq)));
+    true
+}
+
+/// Answers what rigctl clients have asked since the last look. Their
+/// frequencies are as shown, like bookmarks'.
+fn answer_rigctl(server: &rigctl::Server, tuning: &mut Tuning, mut radio: Option<&mut HackRF>,
+                 canvas: &mut Canvas, outputs: &mut Outputs) {
+    while let Some(pending) = server.next_request() {
+        let offset_hz = canvas.freq_offset();
+        let reply = match pending.request {
+            Request::GetFrequency => Reply::Frequency(tuning.freq_hz as f64 - offset_hz),
+            Request::SetFrequency(freq_hz) => {
+                let radio_hz = freq_hz + offset_hz;
+                if is_tunable(radio_hz) &&
+                   tune_to(radio_hz.round() as u64, tuning, radio.as_mut().map(|r| &mut **r),
+                           canvas, outputs) {
+                    Reply::Done
+                } else {
+                    Reply::Refused
+                }
+            }
+        };
+        pending.answer(reply);
+    }
+}
+
+/// Retunes to wherever the followed rig has gone since the last look,
+/// shading its passband. Its frequencies are as shown too.
+fn follow_rig(follower: &Follower, tuning: &mut Tuning, mut radio: Option<&mut HackRF>,
+              canvas: &mut Canvas, outputs: &mut Outputs) {
+    while let Some(event) = follower.next_event() {
+        match event {
+            RigEvent::Tuned(rig) => {
+                let radio_hz = rig.freq_hz + canvas.freq_offset();
+                // the same Hz for comparing as for tuning, so a rig just short
+                // of one isn't retuned to on every look
+                let tune_hz = radio_hz.round() as u64;
+                if !is_tunable(radio_hz) {
+                    canvas.set_notice(Some(format!("The rig's on {}Hz, which the radio can't tune",
+                                                   format_frequency(rig.freq_hz))));
+                } else if tune_hz != tuning.freq_hz {
+                    tune_to(tune_hz, tuning, radio.as_mut().map(|r| &mut **r), canvas, outputs);
+                }
+                canvas.set_passband(Some(rig.passband));
+            }
+            RigEvent::Lost(error) => {
+                canvas.set_passband(None);
+                canvas.set_notice(Some(format!("Can't reach the rig: {}", error)));
+            }
+        }
+    }
+}
+
+/// Takes up the frequency and bandwidth the stream has given since the last
+/// look, returning whether it gave any.
+fn follow_stream(feed: &Feed, tuning: &mut Tuning, canvas: &mut Canvas, outputs: &mut Outputs,
+                 fft_rate_hz: u32) -> bool {
+    let mut retuned = false;
+    while let Some(event) = feed.next_event() {
+        let context = match event {
+            FeedEvent::Tuned(context) => context,
+            FeedEvent::Lost(error) => {
+                canvas.set_notice(Some(format!("Lost the stream: {}", error)));
+                continue;
+            }
+            FeedEvent::Ended => {
+                let notice = "The recording's over, k plays it again, --loop repeats it";
+                canvas.set_notice(Some(notice.to_string()));
+                continue;
+            }
+        };
+        retuned = true;
+        tuning.apply_preset(&Preset {
+            freq_hz: context.freq_hz.map(|hz| hz.round() as u64),
+            bandwidth_hz: context.sample_rate_hz.filter(|&hz| hz > 0.0),
+            ..Preset::default()
+        });
+        canvas.retune(tuning.freq_hz as f64, tuning.sample_rate_hz);
+        canvas.set_radio_settings(tuning.radio_settings(fft_rate_hz));
+        outputs.write_tuning(tuning);
+        canvas.set_notice(Some(format!("The stream is at {}Hz, {}Hz wide",
+                                       format_frequency(tuning.freq_hz as f64),
+                                       format_frequency(tuning.sample_rate_hz))));
+    }
+    retuned
+}
+
+/// Retunes the radio and the canvas to `preset` while running.
+fn switch_preset(preset: &Preset, tuning: &mut Tuning, radio: Option<&mut HackRF>,
+                 canvas: &mut Canvas, fft_rate_hz: u32) {
+    tuning.apply_preset(preset);
+    let failed = radio.and_then(|radio| tuning.try_set_radio(radio).err());
+    canvas.retune(tuning.freq_hz as f64, tuning.sample_rate_hz);
+    canvas.set_radio_settings(tuning.radio_settings(fft_rate_hz));
+    canvas.set_notice(Some(match failed {
+        Some(setting) => format!("Preset {} has an invalid {} for the radio", preset.name,
+                                 setting),
+        None => format!("Preset {}", preset.name),
+    }));
+}
+
+/// Saves the marker's frequency as a bookmark called `name`, to the config
+/// file and the canvas, returning what happened.
+fn save_bookmark(canvas: &mut Canvas, config_path: Option<&Path>, name: String) -> String {
+    let freq_hz = match canvas.marker_reading() {
+        Some((freq_hz, _)) => freq_hz.round(),
+        None => return "The marker has gone".to_string(),
+    };
+    if canvas.get_bookmarks().iter().any(|b| b.name == name) {
+        return format!("There's already a bookmark called {}", name);
+    }
+    let path = match config_path {
+        Some(path) => path,
+        None => return "There's nowhere to save bookmarks, give a --config".to_string(),
+    };
+    let bookmark = Bookmark { name: name, freq_hz: freq_hz, bandwidth_hz: None };
+    match config::append_bookmark(path, &bookmark) {
+        Ok(()) => {
+            let notice = format!("Bookmarked {}Hz as {}", format_frequency(freq_hz),
+                                 bookmark.name);
+            canvas.add_bookmarks(vec![bookmark]);
+            notice
+        }
+        Err(e) => format!("Couldn't save the bookmark to {}: {}", path.display(), e),
+    }
+}
+
+/// Points the listener at the marker, in `mode` if any, and shows what it's
+/// listening to. There's nothing to listen to without a marker.
+fn listen_at_marker(listener: &Listener, canvas: &mut Canvas, mode: Option<Mode>,
+                    width: usize, tuning: &Tuning) -> std::io::Result<()> {
+    let channel = match (mode, canvas.marker_reading()) {
+        (Some(mode), Some((freq_hz, _))) => Some(Channel {
+            mode: mode,
+            offset_hz: freq_hz + canvas.freq_offset() - tuning.freq_hz as f64,
+            bandwidth_hz: mode.bandwidths_hz()[width],
+            sample_rate_hz: tuning.sample_rate_hz,
+        }),
+        _ => None,
+    };
+    let tone = listener.tone_hz().map_or(String::new(), |hz| format!(" CTCSS {:.1}", hz));
+    let rds: String = listener.station_name().into_iter().chain(listener.radiotext())
+                          .map(|text| format!(" {}", text)).collect();
+    let muted = if listener.is_muted() { " muted" } else { "" };
+    canvas.set_listening(channel.map(|c| {
+        format!("{} {}Hz{}{}{}", c.mode, format_frequency(c.bandwidth_hz), tone, muted, rds)
+    }));
+    canvas.set_decoded(listener.morse_text().map(|text| ("CW".to_string(), text)));
+    listener.listen(channel)
+}
+
+/// Adds a spectrum of input `input`, 0 for the first or 1 for --compare's,
+/// to the cross spectrum and its pane, if --cross asked for them.
+fn add_cross(canvas: &mut Canvas, cross: Option<&mut (CrossSpectrum, usize)>, input: usize,
+             spec: &[num::Complex<f32>]) {
+    let (cross, pane) = match cross {
+        Some(&mut (ref mut cross, pane)) => (cross, pane),
+        None => return,
+    };
+    if !cross.add(input, spec) {
+        return;
+    }
+    let coherence = cross.coherence();
+    let levels: Vec<f32> = cross.phase_differences().iter().zip(&coherence).map(|(p, &c)| {
+        if c < CROSS_MIN_COHERENCE {
+            0.0
+        } else {
+            (p / ::std::f32::consts::PI + 1.0) / 2.0
+        }
+    }).collect();
+    canvas.add_levels_to(pane, &levels);
+}
+
+/// Rings the bell and runs the command asked for, for something that's just
+/// gone above the threshold.
+fn raise_alert(canvas: &mut Canvas, args: &Args, freq_hz: f64, power_db: f32) {
+    if args.flag_alert_bell {
+        // not worth a notice, the status bar flashes anyway
+        let _ = alert::ring_bell();
+    }
+    if let Some(ref command) = args.flag_alert_command {
+        if let Err(e) = alert::run_command(command, freq_hz, power_db) {
+            canvas.set_notice(Some(format!("Couldn't run the alert command: {}", e)));
+        }
+    }
+}
+
+fn configure_canvas(canvas: &mut Canvas, args: &Args) {
+    canvas.set_exclude_dc(args.flag_exclude_dc);
+    canvas.set_freq_offset(parse_offset_arg(&args.flag_freq_offset));
+    canvas.set_invert(args.flag_invert);
+    canvas.set_num_peaks(args.flag_peaks);
+    canvas.set_palette(args.flag_palette);
+    canvas.set_ascii(args.flag_ascii);
+    canvas.set_glyphs(args.flag_glyphs);
+    canvas.set_braille_waterfall(args.flag_braille_waterfall);
+    canvas.set_normalize_rows(args.flag_normalize_rows);
+    canvas.set_compress_history(args.flag_compress_history);
+    let spill_dir = args.flag_history_spill.as_ref().map(Path::new);
+    if let Err(e) = canvas.set_history(args.flag_history, spill_dir) {
+        canvas.set_notice(Some(format!("Couldn't make the history file: {}", e)));
+        let _ = canvas.set_history(args.flag_history, None);
+    }
+    canvas.set_scroll_up(args.flag_scroll_up);
+    canvas.set_timestamps(args.flag_timestamps);
+    canvas.set_persistence(args.flag_persistence);
+    canvas.set_num_bands(args.flag_wrap);
+    canvas.set_db_range(args.flag_min_db, args.flag_max_db);
+    canvas.set_average(args.flag_average);
+    if let Some(threshold_db) = args.flag_threshold {
+        canvas.set_threshold(threshold_db);
+    }
+    if let Some(fps) = args.flag_max_fps {
+        canvas.set_max_fps(fps);
+    }
+    if let Some(ref path) = args.flag_bookmarks {
+        canvas.set_bookmarks(bookmarks::load(path).expect("Error reading bookmarks"));
+    }
+}
+
+fn list_devices() {
+    match radio::hackrf::list_devices() {
+        Ok(ref devices) if devices.is_empty() => println!("No HackRF is connected."),
+        Ok(devices) => for (i, device) in devices.iter().enumerate() {
+            let serial = device.serial.as_ref().map_or("no serial", |s| &s[..]);
+            println!("{}  {}  {}", i, device.board, serial);
+        },
+        // the audio outputs are still worth listing
+        Err(_) => println!("Couldn't list the HackRFs, is libhackrf working?"),
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    print_audio_outputs();
+}
+
+#[cfg(not(target_os = "macos"))]
+fn print_audio_outputs() {
+    println!();
+    match demod::list_outputs() {
+        Ok(ref outputs) if outputs.is_empty() => println!("No audio outputs were found."),
+        Ok(outputs) => {
+            println!("Audio outputs:");
+            for output in outputs {
+                println!("  {}", output);
+            }
+        }
+        Err(e) => println!("Couldn't list the audio outputs: {}", e),
+    }
+}
+
+fn print_suggestion(suggestion: Option<&Suggestion>) {
+    let s = match suggestion {
+        Some(s) => s,
+        None => {
+            println!("No samples were received while probing.");
+            return;
+        }
+    };
+    println!("Noise floor {:.1} dB, strongest signal {:.1} dB ({:.1} dB SNR), {:.2}% clipped.",
+             s.level.noise_floor_db, s.level.peak_db, s.level.snr_db(),
+             100.0 * s.level.clipped_fraction);
+    if s.is_clipping() {
+        println!("The input clips at every gain tried, an attenuator may help.");
+    }
+    println!("Suggested options: {}", s.options());
+}
+
+/// The keys `handle_key` understands, for the help overlay.
+const KEYS: [(&'static str, &'static str); 45] = [
+    ("q", "Quit"),
+    ("p", "Toggle peak labels"),
+    ("c", "Cycle the waterfall palette"),
+    ("b", "Toggle the braille waterfall"),
+    ("n", "Toggle per row waterfall scaling"),
+    ("h", "Toggle compressing older waterfall rows"),
+    ("o", "Toggle the persistence spectrum"),
+    ("C", "Show or hide the IQ scope"),
+    ("T", "Toggle the waterfall timestamps"),
+    ("u", "Switch the waterfall scroll direction"),
+    ("I", "Flip the spectrum left to right"),
+    ("S", "Cycle the spectrum style"),
+    ("1-4", "Show or hide the live, average, max and min traces"),
+    ("P", "Switch to the next preset"),
+    ("i", "Pick the input source"),
+    ("f", "Tune to a typed frequency"),
+    ("[/]", "Tune to the previous/next bookmark"),
+    ("B", "Bookmark the marker"),
+    ("r", "Start or stop recording raw samples"),
+    ("g", "Save a PNG of the spectrum and waterfall"),
+    ("G", "Save the screen as ANSI text"),
+    ("+/-", "Raise or lower the FFT rate"),
+    ("space", "Pause the focused pane"),
+    ("PgUp/PgDn", "Scroll back while paused"),
+    ("s", "Select a region while paused"),
+    ("Enter", "Zoom to the selection"),
+    ("x", "Export the selection or zoom"),
+    ("tab", "Focus the next pane"),
+    ("m", "Place or remove the marker"),
+    ("Left/Right", "Move the marker"),
+    ("y/Y", "Copy the marker frequency/and power"),
+    ("a", "Listen to the marker, AM, NFM, WFM, SSB or CW"),
+    ("w", "Cycle the channel width listened to"),
+    ("v/V", "Turn the audio down/up"),
+    ("M", "Mute or unmute the audio"),
+    ("A", "Start or stop recording the audio"),
+    ("k", "Pause or play the recording"),
+    ("j/l J/L", "Seek back/on 10s, or a minute"),
+    ("(/)", "Play the recording at half/twice the speed"),
+    ("t", "Show or hide the threshold line"),
+    ("</>", "Move the threshold down/up"),
+    ("click", "Place the marker"),
+    ("double-click", "Tune there"),
+    ("wheel", "Move the dB scale"),
+    ("?", "Show this help"),
+];
+
+/// How far j and l, and J and L, move through a recording.
+const SEEK_STEP_SECS: i32 = 10;
+const LONG_SEEK_STEP_SECS: i32 = 60;
+
+/// How far apart in a recording the waterfall marks where it's got to.
+const POSITION_MARK_SECS: f64 = 10.0;
+
+/// How far a turn of the mouse wheel moves the dB scale.
+const WHEEL_STEP_DB: f32 = 5.0;
+
+const TUNE_TITLE: &'static str = "Center frequency";
+const BOOKMARK_TITLE: &'static str = "Bookmark name";
+const BOOKMARK_HINT: &'static str = "Enter saves it to the config, Esc cancels.";
+const TUNE_HINT: &'static str = "Type a frequency like 433.92M, Enter tunes, Esc cancels.";
+
+/// Applies a key press to the canvas, returning what's left for the caller.
+fn handle_key(canvas: &mut Canvas, key: Key) -> Action {
+    if key == INTERRUPT {
+        return Action::Quit;
+    }
+    // any key closes the help, without doing anything else
+    if canvas.is_help_shown() {
+        canvas.hide_help();
+        return Action::Continue;
+    }
+    if canvas.is_picker_shown() {
+        match key {
+            Key::Up => canvas.move_picker(-1),
+            Key::Down => canvas.move_picker(1),
+            Key::Enter => {
+                if let Some(i) = canvas.hide_picker() {
+                    return Action::SwitchSource(i);
+                }
+            }
+            Key::Esc | Key::Char('q') => {
+                canvas.hide_picker();
+            }
+            _ => {}
+        }
+        return Action::Continue;
+    }
+    if let Some(naming) = canvas.prompt_title().map(|title| title == BOOKMARK_TITLE) {
+        match key {
+            Key::Char(c) if naming && !c.is_control() => canvas.edit_prompt(Some(c)),
+            Key::Char(c) if !naming && (c.is_digit(10) || ".ekMG".contains(c)) => {
+                canvas.edit_prompt(Some(c))
+            }
+            Key::Backspace => canvas.edit_prompt(None),
+            Key::Enter if naming => {
+                let text = canvas.hide_prompt().unwrap_or_default();
+                if config::is_valid_name(&text) {
+                    return Action::SaveBookmark(text);
+                }
+                canvas.show_prompt(BOOKMARK_TITLE, &text,
+                                   "Give a name without [, ], # or \" in it.");
+            }
+            Key::Enter => {
+                let text = canvas.hide_prompt().unwrap_or_default();
+                // typed as shown, ahead of any converter
+                let offset_hz = canvas.freq_offset();
+                match parse_frequency(&text).map(|hz| hz + offset_hz) {
+                    Some(hz) if is_tunable(hz) => {
+                        return Action::Tune(hz.round() as u64);
+                    }
+                    _ => {
+                        let hint = format!("Give a frequency from {}Hz to {}Hz, like 100M.",
+                                           format_frequency((MIN_FREQ_HZ - offset_hz).max(0.0)),
+                                           format_frequency(MAX_FREQ_HZ - offset_hz));
+                        canvas.show_prompt(TUNE_TITLE, &text, &hint);
+                    }
+                }
+            }
+            Key::Esc => {
+                canvas.hide_prompt();
+            }
+            _ => {}
+        }
+        return Action::Continue;
+    }
+    // a notice is about the key before
+    canvas.set_notice(None);
+
+    match key {
+        Key::Char('q') => return Action::Quit,
+        Key::Char('P') => return Action::NextPreset,
+        Key::Char('i') => return Action::PickSource,
+        Key::Char('f') => canvas.show_prompt(TUNE_TITLE, "", TUNE_HINT),
+        Key::Char('[') => return Action::StepBookmark(-1),
+        Key::Char(']') => return Action::StepBookmark(1),
+        Key::Char('B') => {
+            if canvas.marker_reading().is_some() {
+                canvas.show_prompt(BOOKMARK_TITLE, "", BOOKMARK_HINT);
+            } else {
+                canvas.set_notice(Some("Place the marker to bookmark its frequency".to_string()));
+            }
+        }
+        Key::Char('r') => return Action::ToggleRecording,
+        Key::Char('+') | Key::Char('=') => return Action::StepFftRate(1),
+        Key::Char('-') => return Action::StepFftRate(-1),
+        Key::Char('p') => canvas.toggle_peaks(),
+        Key::Char('c') => canvas.cycle_palette(),
+        Key::Char('b') => canvas.toggle_braille_waterfall(),
+        Key::Char('n') => canvas.toggle_normalize_rows(),
+        Key::Char('h') => canvas.toggle_compress_history(),
+        Key::Char('o') => canvas.toggle_persistence(),
+        Key::Char('C') => return Action::ToggleScope,
+        Key::Char('I') => {
+            let notice = if canvas.toggle_invert() { "Spectrum inverted" } else {
+                "Spectrum upright"
+            };
+            canvas.set_notice(Some(notice.to_string()));
+        }
+        Key::Char('u') => {
+            let direction = if canvas.toggle_scroll_up() { "up" } else { "down" };
+            canvas.set_notice(Some(format!("Waterfall scrolls {}", direction)));
+        }
+        Key::Char('T') => {
+            let shown = if canvas.toggle_timestamps() { "shown" } else { "hidden" };
+            canvas.set_notice(Some(format!("Timestamps {}", shown)));
+        }
+        Key::Char('S') => {
+            let style = canvas.cycle_spectrum_style();
+            canvas.set_notice(Some(format!("Spectrum style {}", style)));
+        }
+        Key::Char(' ') => canvas.toggle_pause(),
+        Key::Char('\t') => canvas.cycle_focus(),
+        Key::Char('?') => canvas.show_help(&KEYS),
+        Key::Char('m') => canvas.toggle_marker(),
+        Key::Char(c) if "1234".contains(c) => {
+            let trace = [Trace::Live, Trace::Average, Trace::MaxHold, Trace::MinHold]
+                            [c.to_digit(10).unwrap() as usize - 1];
+            let shown = if canvas.toggle_trace(trace) { "shown" } else { "hidden" };
+            canvas.set_notice(Some(format!("{} trace {}", trace, shown)));
+        }
+        Key::Char('t') => {
+            let notice = if canvas.toggle_threshold() { "Threshold on" } else { "Threshold off" };
+            canvas.set_notice(Some(notice.to_string()));
+        }
+        Key::Char('<') | Key::Char('>') => {
+            let step_db = if key == Key::Char('<') { -1.0 } else { 1.0 };
+            let threshold_db = canvas.move_threshold(step_db);
+            canvas.set_notice(Some(format!("Threshold {:.0} dB", threshold_db)));
+        }
+        Key::Char('a') => return Action::CycleListenMode,
+        Key::Char('w') => return Action::CycleListenWidth,
+        Key::Char('v') => return Action::StepVolume(-1),
+        Key::Char('V') => return Action::StepVolume(1),
+        Key::Char('M') => return Action::ToggleMute,
+        Key::Char('k') => return Action::Playback(Playback::TogglePause),
+        Key::Char('j') => return Action::Playback(Playback::Seek(-SEEK_STEP_SECS)),
+        Key::Char('l') => return Action::Playback(Playback::Seek(SEEK_STEP_SECS)),
+        Key::Char('J') => return Action::Playback(Playback::Seek(-LONG_SEEK_STEP_SECS)),
+        Key::Char('L') => return Action::Playback(Playback::Seek(LONG_SEEK_STEP_SECS)),
+        Key::Char('(') => return Action::Playback(Playback::StepSpeed(-1)),
+        Key::Char(')') => return Action::Playback(Playback::StepSpeed(1)),
+        Key::Char('A') => return Action::ToggleAudioRecording,
+        Key::Char('y') => copy_marker(canvas, false),
+        Key::Char('Y') => copy_marker(canvas, true),
+        Key::Char('s') => {
+            if !canvas.start_selection() {
+                canvas.set_notice(Some("Pause with space to select a region".to_string()));
+            }
+        }
+        Key::Char('x') => export_region(canvas),
+        Key::Char('g') => save_screenshot(canvas),
+        Key::Char('G') => save_screen(canvas),
+        Key::Enter => canvas.zoom_to_selection(),
+        Key::Esc => canvas.cancel_selection(),
+        Key::Left | Key::Right | Key::Up | Key::Down if canvas.is_selecting() => {
+            let (cols, rows) = match key {
+                Key::Left => (-1, 0),
+                Key::Right => (1, 0),
+                Key::Up => (0, -1),
+                _ => (0, 1),
+            };
+            canvas.move_selection(cols, rows);
+        }
+        Key::Left => canvas.move_marker(-1),
+        Key::Right => canvas.move_marker(1),
+        Key::Mouse(Mouse::Click, col, row) => {
+            canvas.place_marker_at(col, row);
+        }
+        Key::Mouse(Mouse::DoubleClick, col, row) => match canvas.frequency_at(col, row) {
+            Some(hz) if is_tunable(hz + canvas.freq_offset()) => {
+                return Action::Tune((hz + canvas.freq_offset()).round() as u64)
+            }
+            Some(hz) => {
+                let notice = format!("Can't tune to {}Hz", format_frequency(hz));
+                canvas.set_notice(Some(notice));
+            }
+            None => {}
+        },
+        Key::Mouse(wheel, _, _) => {
+            let step_db = if wheel == Mouse::WheelUp { WHEEL_STEP_DB } else { -WHEEL_STEP_DB };
+            canvas.shift_db_range(step_db);
+            let (min_db, max_db) = canvas.get_db_range();
+            canvas.set_notice(Some(format!("Scale {:.0} to {:.0} dB", min_db, max_db)));
+        }
+        Key::PageUp => canvas.scroll_waterfall(1),
+        Key::PageDown => canvas.scroll_waterfall(-1),
+        _ => {}
+    }
+    Action::Continue
+}
+
+/// Applies a playback key to the recording, returning what to say of it.
+fn control_playback(player: &FilePlayer, playback: Playback) -> String {
+    match playback {
+        Playback::TogglePause => {
+            if player.toggle_paused() { "Playback paused" } else { "Playing" }.to_string()
+        }
+        Playback::Seek(secs) => format!("At {}", format_position(player.seek_by(secs as f64))),
+        Playback::StepSpeed(step) => format!("Playing at {}x", player.step_speed(step)),
+    }
+}
+
+/// Where a recording is, and how it's playing, for marking on the waterfall.
+fn playback_mark(player: &FilePlayer) -> String {
+    let position = format_position(player.position_secs());
+    if player.is_paused() {
+        format!("{} paused", position)
+    } else if player.speed() != 1.0 {
+        format!("{} {}x", position, player.speed())
+    } else {
+        position
+    }
+}
+
+/// Like 1:05.3, minutes and seconds into a recording.
+fn format_position(secs: f64) -> String {
+    let tenths = (secs * 10.0).round() as u64;
+    format!("{}:{:02}.{}", tenths / 600, tenths / 10 % 60, tenths % 10)
+}
+
+/// Copies the marker's frequency, in Hz, to the clipboard, and its power if
+/// `with_power` is set.
+fn copy_marker(canvas: &mut Canvas, with_power: bool) {
+    let (freq_hz, power_db) = match canvas.marker_reading() {
+        Some(reading) => reading,
+        None => {
+            canvas.set_notice(Some("No marker to copy, press m to place one".to_string()));
+            return;
+        }
+    };
+
+    let text = if with_power {
+        format!("{:.0} Hz {:.1} dB", freq_hz, power_db)
+    } else {
+        format!("{:.0}", freq_hz)
+    };
+    let notice = match clipboard::copy(&text) {
+        Ok(Method::Native(tool)) => format!("Copied {} with {}", text, tool),
+        Ok(Method::Osc52) => format!("Copied {} with OSC 52", text),
+        Err(e) => format!("Couldn't copy to the clipboard: {}", e),
+    };
+    canvas.set_notice(Some(notice));
+}
+
+/// Writes the selected or zoomed region out to a CSV file in the current
+/// directory.
+fn export_region(canvas: &mut Canvas) {
+    let region = match canvas.region_data() {
+        Some(region) => region,
+        None => {
+            canvas.set_notice(Some("Select a region with s to export it".to_string()));
+            return;
+        }
+    };
+
+    let secs = std::time::SystemTime::now()
+                   .duration_since(std::time::UNIX_EPOCH)
+                   .map(|d| d.as_secs())
+                   .unwrap_or(0);
+    let path = format!("region-{}.csv", secs);
+    let notice = match export::write_region_csv(&path, &region) {
+        Ok(()) => format!("Exported {} spectra to {}", region.spectra.len(), path),
+        Err(e) => format!("Couldn't write {}: {}", path, e),
+    };
+    canvas.set_notice(Some(notice));
+}
+
+/// Saves the focused pane's spectrum and waterfall as a PNG in the current
+/// directory.
+fn save_screenshot(canvas: &mut Canvas) {
+    let snapshot = match canvas.snapshot(screenshot::WATERFALL_SPECTRA) {
+        Some(snapshot) => snapshot,
+        None => {
+            canvas.set_notice(Some("Nothing to save until the first spectrum".to_string()));
+            return;
+        }
+    };
+    let path = timestamped_name("spectrum", "png");
+    let (date, time) = export::local_date_time();
+    let notice = match screenshot::save(&path, &snapshot, &format!("{} {}", date, time)) {
+        Ok(()) => format!("Saved {}", path),
+        Err(e) => format!("Couldn't write {}: {}", path, e),
+    };
+    canvas.set_notice(Some(notice));
+}
+
+/// Saves what's on the screen as text with ANSI colors in the current
+/// directory.
+fn save_screen(canvas: &mut Canvas) {
+    let path = timestamped_name("screen", "txt");
+    let notice = {
+        let (cells, cols) = canvas.screen();
+        match export::write_screen_ansi(&path, cells, cols) {
+            Ok(()) => format!("Saved {}, cat shows it", path),
+            Err(e) => format!("Couldn't write {}: {}", path, e),
+        }
+    };
+    canvas.set_notice(Some(notice));
+}
+
+/// Plays back a recorded session with its original timing. Pressing `q`
+/// stops the replay; the recorded key presses are applied as they happened.
+fn replay(args: &Args) {
+    let path = args.arg_session_file.as_ref().unwrap();
+    let mut session = SessionReader::open(path).expect("Error opening session file");
+
+    wait::catch_stop_signals();
+    let mut canvas = Canvas::new().expect("Error opening terminal");
+    configure_canvas(&mut canvas, args);
+    canvas.set_tuning(session.header.center_freq_hz, session.header.sample_rate_hz);
+    let size = canvas.size();
+    let mut tuning = (session.header.center_freq_hz, session.header.sample_rate_hz);
+    let mut report = args.flag_report.as_ref().map(|path| {
+        Report::create(path).expect("Error creating report file")
+    });
+
+    let start = Instant::now();
+    let mut quit = false;
+    'records: loop {
+        let record = match session.next_record() {
+            Ok(Some(record)) => record,
+            Ok(None) => break,
+            Err(e) => {
+                drop(canvas);
+                exit_with_error(&format!("Error reading session file: {}", e));
+            }
+        };
+
+        let time = match record {
+            Record::Spectrum(time, _) | Record::Key(time, _) | Record::Tuning(time, _, _) => time,
+        };
+        while let Some(wait) = time.checked_sub(start.elapsed()) {
+            match read_key(canvas.get_term(), min(wait, IDLE_TIMEOUT)) {
+                Ok(Some(Key::Char('q'))) | Ok(Some(INTERRUPT)) => {
+                    quit = true;
+                    break 'records;
+                }
+                _ if wait::stop_requested() => {
+                    quit = true;
+                    break 'records;
+                }
+                _ => {}
+            }
+        }
+
+        match record {
+            Record::Spectrum(_, spec) => {
+                if let Some(ref mut report) = report {
+                    report.add_spectrum(&spec, tuning.0, tuning.1);
+                }
+                canvas.add_spectrum(&spec);
+            }
+            // presets were switched and scans stepped live, which shows up as
+            // a tuning record
+            Record::Key(_, key) => {
+                if handle_key(&mut canvas, key) == Action::Quit {
+                    break;
+                }
+            }
+            Record::Tuning(_, center_freq_hz, sample_rate_hz) => {
+                tuning = (center_freq_hz, sample_rate_hz);
+                canvas.retune(center_freq_hz, sample_rate_hz);
+            }
+        }
+        canvas.draw_pending_frame();
+    }
+
+    if let Some(report) = report {
+        report.finish().expect("Error writing report file");
+    }
+    if quit {
+        return;
+    }
+
+    // leave the final frame up until the user quits
+    loop {
+        canvas.draw_pending_frame();
+        let timeout = canvas.time_to_next_frame().map_or(IDLE_TIMEOUT, |t| min(t, IDLE_TIMEOUT));
+        match read_key(canvas.get_term(), timeout) {
+            Ok(Some(Key::Char('q'))) | Ok(Some(INTERRUPT)) | Err(_) => break,
+            Ok(_) if wait::stop_requested() => break,
+            Ok(_) => {}
+        }
+    }
+    drop(canvas);
+
+    if size != (session.header.cols, session.header.rows) {
+        println!("Note: the session was recorded on a {}x{} terminal, this one is {}x{}.",
+                 session.header.cols, session.header.rows, size.0, size.1);
+    }
 }
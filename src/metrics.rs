@@ -0,0 +1,180 @@
+//! Measurements of the band for Prometheus to scrape, at `/metrics` over
+//! HTTP, so a long unattended run can be graphed and alerted on. Powers are
+//! of the newest spectrum, in the same dB as the spectrum view, and the
+//! counts are since the start, from the pipeline's `Stats`.
+
+use std::fmt::Write;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+use num::Complex;
+
+use export::shifted_powers_db;
+use http::{self, Response};
+use stats::Stats;
+
+/// What's known of the newest spectrum, and the radio.
+#[derive(Debug, Clone, Default)]
+struct Gauges {
+    center_freq_hz: f64,
+    sample_rate_hz: f64,
+    /// `None` until the first spectrum.
+    measured: Option<Measured>,
+    overruns: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Measured {
+    power_db: f32,
+    noise_floor_db: f32,
+    /// The lowest and highest frequency of each band, in Hz, and its power.
+    bands: Vec<(f64, f64, f32)>,
+}
+
+pub struct Exporter {
+    gauges: Arc<Mutex<Gauges>>,
+    num_bands: usize,
+}
+
+impl Exporter {
+    /// Starts serving the metrics on `port` of every interface, with the channel power of each
+    /// of `num_bands` equal bands across the span, and the counts in `stats`.
+    pub fn listen(port: u16, num_bands: usize, stats: Arc<Stats>) -> io::Result<Self> {
+        let gauges = Arc::new(Mutex::new(Gauges::default()));
+        let shown = gauges.clone();
+        let every_interface = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
+        try!(http::serve(every_interface, port, move |path: &str| match path {
+            "/metrics" | "/" => {
+                let gauges = shown.lock().unwrap().clone();
+                Response::ok("text/plain; version=0.0.4", render(&gauges, &stats).into_bytes())
+            }
+            _ => Response::not_found(),
+        }));
+        Ok(Exporter { gauges: gauges, num_bands: num_bands })
+    }
+
+    /// Measures an unshifted FFT output taken at the given tuning.
+    pub fn add_spectrum(&self, spec: &[Complex<f32>], center_freq_hz: f64,
+                        sample_rate_hz: f64) {
+        let powers = shifted_powers_db(spec);
+        let measured = measure(&powers, center_freq_hz, sample_rate_hz, self.num_bands);
+        let mut gauges = self.gauges.lock().unwrap();
+        gauges.center_freq_hz = center_freq_hz;
+        gauges.sample_rate_hz = sample_rate_hz;
+        if measured.is_some() {
+            gauges.measured = measured;
+        }
+    }
+
+    /// Sets the count of sample buffers dropped on the way from the radio.
+    pub fn set_overruns(&self, overruns: usize) {
+        self.gauges.lock().unwrap().overruns = overruns;
+    }
+}
+
+/// The total power of `powers`, a shifted spectrum in dB, its noise floor,
+/// taken as the median bin, and the power of each band.
+fn measure(powers: &[f32], center_freq_hz: f64, sample_rate_hz: f64, num_bands: usize)
+           -> Option<Measured> {
+    if powers.is_empty() {
+        return None;
+    }
+    let mut sorted = powers.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+    let low_hz = center_freq_hz - sample_rate_hz / 2.0;
+    let bands = (0..num_bands).filter_map(|i| {
+        let (start, end) = (i * powers.len() / num_bands, (i + 1) * powers.len() / num_bands);
+        if start == end {
+            return None;
+        }
+        let freq = |bin: usize| low_hz + bin as f64 * sample_rate_hz / powers.len() as f64;
+        Some((freq(start), freq(end), total_db(&powers[start..end])))
+    }).collect();
+    Some(Measured {
+        power_db: total_db(powers),
+        noise_floor_db: sorted[sorted.len() / 2],
+        bands: bands,
+    })
+}
+
+/// The sum of bins in dB, in dB.
+fn total_db(powers: &[f32]) -> f32 {
+    10.0 * powers.iter().map(|p| 10f32.powf(p / 10.0)).sum::<f32>().log10()
+}
+
+/// The metrics in Prometheus' text format.
+fn render(gauges: &Gauges, stats: &Stats) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+        let _ = writeln!(out, "# HELP tspec_{} {}\n# TYPE tspec_{} {}", name, help, name, kind);
+        for &(ref labels, ref value) in samples {
+            let _ = writeln!(out, "tspec_{}{} {}", name, labels, value);
+        }
+    };
+    // unlabeled
+    let value = |x: String| vec![(String::new(), x)];
+
+    metric("center_frequency_hz", "gauge", "Frequency at the middle of the span.",
+           &value(gauges.center_freq_hz.to_string()));
+    metric("sample_rate_hz", "gauge", "Width of the span.",
+           &value(gauges.sample_rate_hz.to_string()));
+    if let Some(ref measured) = gauges.measured {
+        metric("power_db", "gauge", "Total power of the newest spectrum.",
+               &value(measured.power_db.to_string()));
+        metric("noise_floor_db", "gauge", "Median bin of the newest spectrum.",
+               &value(measured.noise_floor_db.to_string()));
+        let bands: Vec<_> = measured.bands.iter().map(|&(low, high, power_db)| {
+            (format!("{{low_hz=\"{}\",high_hz=\"{}\"}}", low, high), power_db.to_string())
+        }).collect();
+        metric("band_power_db", "gauge", "Power of each band of the newest spectrum.", &bands);
+    }
+
+    let counts = stats.snapshot();
+    metric("samples_received_total", "counter", "Samples that reached the processing.",
+           &value(counts.samples_received.to_string()));
+    metric("samples_discarded_total", "counter",
+           "Samples skipped between FFTs to keep to the FFT rate.",
+           &value(counts.samples_discarded.to_string()));
+    metric("ffts_total", "counter", "FFTs taken.", &value(counts.ffts.to_string()));
+    metric("frames_drawn_total", "counter", "Frames put on the terminal.",
+           &value(counts.frames_drawn.to_string()));
+    metric("frames_dropped_total", "counter",
+           "Spectra dropped or averaged together while the drawing was behind.",
+           &value(counts.frames_dropped.to_string()));
+    metric("overruns_total", "counter",
+           "Sample buffers dropped on the way from the radio while processing was behind.",
+           &value(gauges.overruns.to_string()));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use stats::Stats;
+    use super::{measure, render, Gauges};
+
+    #[test]
+    fn test_metrics() {
+        // four bins of 0 dB but one of 10 dB, over 100 to 104 Hz
+        let measured = measure(&[0.0, 0.0, 10.0, 0.0], 102.0, 4.0, 2).unwrap();
+        assert_eq!(measured.noise_floor_db, 0.0);
+        assert!((measured.power_db - 10.0 * 13f32.log10()).abs() < 1e-4);
+        assert_eq!(measured.bands.len(), 2);
+        assert_eq!((measured.bands[0].0, measured.bands[0].1), (100.0, 102.0));
+        assert!((measured.bands[1].2 - 10.0 * 11f32.log10()).abs() < 1e-4);
+
+        let stats = Stats::default();
+        stats.add_buffer(100, 10, 1);
+        let gauges = Gauges {
+            center_freq_hz: 102.0,
+            sample_rate_hz: 4.0,
+            measured: Some(measured),
+            overruns: 3,
+        };
+        let text = render(&gauges, &stats);
+        assert!(text.contains("# TYPE tspec_power_db gauge\n"));
+        assert!(text.contains("\ntspec_noise_floor_db 0\n"));
+        assert!(text.contains("\ntspec_band_power_db{low_hz=\"100\",high_hz=\"102\"} 3.0103"));
+        assert!(text.contains("\ntspec_samples_received_total 100\n"));
+        assert!(text.ends_with("\ntspec_overruns_total 3\n"));
+    }
+}
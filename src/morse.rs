@@ -0,0 +1,200 @@
+//! Reading Morse from the level of a carrier keyed on and off, at whatever
+//! speed it's sent. The dot length is learnt from the marks as they come, so
+//! a beacon at 12 WPM and a contest at 30 read the same.
+
+/// The level is averaged over this long at a time, in seconds, about a tenth
+/// of a dot at 50 WPM.
+const STEP_SECS: f64 = 0.002;
+
+/// How quickly the levels of the carrier on and off follow, per step, and
+/// how quickly the on level rises to a louder mark and the off level falls to
+/// a quieter gap.
+const LEVEL_SMOOTHING: f32 = 0.02;
+const LEVEL_ATTACK: f32 = 0.5;
+
+/// Keyed on above this fraction of the way from the off level to the on
+/// level, and off below the other, so noise on an edge isn't a mark.
+const ON_FRACTION: f32 = 0.6;
+const OFF_FRACTION: f32 = 0.4;
+
+/// The on level has to be this many times the off level for there to be
+/// keying at all, rather than just noise.
+const MIN_CONTRAST: f32 = 2.0;
+
+/// The dot length to start with, 20 WPM, the shortest and longest taken,
+/// and how quickly it follows the marks.
+const START_DOT_SECS: f64 = 0.06;
+const MIN_DOT_SECS: f64 = 0.02;
+const MAX_DOT_SECS: f64 = 0.3;
+const DOT_SMOOTHING: f64 = 0.3;
+
+/// Decoded text kept, the oldest dropped first.
+const MAX_TEXT_CHARS: usize = 500;
+
+/// The characters and their dots and dashes.
+const CODE: &'static [(&'static str, char)] = &[
+    (".-", 'A'), ("-...", 'B'), ("-.-.", 'C'), ("-..", 'D'), (".", 'E'), ("..-.", 'F'),
+    ("--.", 'G'), ("....", 'H'), ("..", 'I'), (".---", 'J'), ("-.-", 'K'), (".-..", 'L'),
+    ("--", 'M'), ("-.", 'N'), ("---", 'O'), (".--.", 'P'), ("--.-", 'Q'), (".-.", 'R'),
+    ("...", 'S'), ("-", 'T'), ("..-", 'U'), ("...-", 'V'), (".--", 'W'), ("-..-", 'X'),
+    ("-.--", 'Y'), ("--..", 'Z'),
+    ("-----", '0'), (".----", '1'), ("..---", '2'), ("...--", '3'), ("....-", '4'),
+    (".....", '5'), ("-....", '6'), ("--...", '7'), ("---..", '8'), ("----.", '9'),
+    (".-.-.-", '.'), ("--..--", ','), ("..--..", '?'), ("-..-.", '/'), ("-...-", '='),
+    (".-.-.", '+'), ("-....-", '-'), (".--.-.", '@'), ("---...", ':'),
+];
+
+/// Takes the carrier's level at `level_rate_hz`.
+pub struct Decoder {
+    /// Levels are averaged `step_len` at a time.
+    step_len: usize,
+    sum: f32,
+    num_summed: usize,
+    /// The carrier's level on and off, `None` until the first step.
+    levels: Option<(f32, f32)>,
+    keyed: bool,
+    /// How long it's been keyed on or off, in seconds.
+    run_secs: f64,
+    dot_secs: f64,
+    /// The dots and dashes of the character being sent.
+    symbols: String,
+    /// Whether a gap between words came after the last character.
+    space_pending: bool,
+    text: String,
+}
+
+impl Decoder {
+    pub fn new(level_rate_hz: f64) -> Self {
+        Decoder {
+            step_len: ((STEP_SECS * level_rate_hz) as usize).max(1),
+            sum: 0.0,
+            num_summed: 0,
+            levels: None,
+            keyed: false,
+            run_secs: 0.0,
+            dot_secs: START_DOT_SECS,
+            symbols: String::new(),
+            space_pending: false,
+            text: String::new(),
+        }
+    }
+
+    pub fn add_level(&mut self, level: f32) {
+        self.sum += level;
+        self.num_summed += 1;
+        if self.num_summed < self.step_len {
+            return;
+        }
+        let level = self.sum / self.num_summed as f32;
+        self.sum = 0.0;
+        self.num_summed = 0;
+
+        let (mut on, mut off) = self.levels.unwrap_or((level, level));
+        // each follows the level while keyed its way
+        if level > on || self.keyed {
+            on += (level - on) * if level > on { LEVEL_ATTACK } else { LEVEL_SMOOTHING };
+        }
+        if level < off || !self.keyed {
+            off += (level - off) * if level < off { LEVEL_ATTACK } else { LEVEL_SMOOTHING };
+        }
+        self.levels = Some((on, off));
+        let keyed = on > MIN_CONTRAST * off && if self.keyed {
+            level > off + OFF_FRACTION * (on - off)
+        } else {
+            level > off + ON_FRACTION * (on - off)
+        };
+
+        if keyed == self.keyed {
+            self.run_secs += STEP_SECS;
+            if !keyed {
+                self.check_gap();
+            }
+            return;
+        }
+        if self.keyed {
+            self.end_mark();
+        }
+        self.keyed = keyed;
+        self.run_secs = STEP_SECS;
+    }
+
+    /// What's been read so far.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Calls a mark a dot or a dash, against twice the dot length, and learns
+    /// the dot length from it.
+    fn end_mark(&mut self) {
+        let (symbol, dot_secs) = if self.run_secs < 2.0 * self.dot_secs {
+            ('.', self.run_secs)
+        } else {
+            ('-', self.run_secs / 3.0)
+        };
+        self.symbols.push(symbol);
+        self.dot_secs += (dot_secs - self.dot_secs) * DOT_SMOOTHING;
+        self.dot_secs = self.dot_secs.max(MIN_DOT_SECS).min(MAX_DOT_SECS);
+    }
+
+    /// Ends the character after a gap of 3 dots, and the word after 7, taking
+    /// the gaps at 2 and 5 for sloppy keying.
+    fn check_gap(&mut self) {
+        if !self.symbols.is_empty() && self.run_secs >= 2.0 * self.dot_secs {
+            let c = CODE.iter().find(|&&(code, _)| code == self.symbols).map_or('*', |&(_, c)| c);
+            self.symbols.clear();
+            if self.space_pending && !self.text.is_empty() {
+                self.text.push(' ');
+            }
+            self.space_pending = false;
+            self.text.push(c);
+            let num_chars = self.text.len();
+            if num_chars > MAX_TEXT_CHARS {
+                self.text.drain(..num_chars - MAX_TEXT_CHARS);
+            }
+        } else if self.symbols.is_empty() && self.run_secs >= 5.0 * self.dot_secs {
+            self.space_pending = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decoder, CODE};
+
+    /// Levels at 8 kHz for `text` keyed with dots of `dot_secs`, between
+    /// half a second off before and a second after.
+    fn keyed(text: &str, dot_secs: f64) -> Vec<f32> {
+        let mut units = Vec::new();
+        for c in text.chars() {
+            if c == ' ' {
+                // on top of the gap after the last character
+                units.extend(&[false; 4]);
+                continue;
+            }
+            let &(code, _) = CODE.iter().find(|&&(_, x)| x == c).unwrap();
+            for symbol in code.chars() {
+                let len = if symbol == '.' { 1 } else { 3 };
+                units.extend(vec![true; len]);
+                units.push(false);
+            }
+            units.extend(&[false; 2]);
+        }
+        let per_unit = (dot_secs * 8000.0) as usize;
+        let mut levels = vec![0.02; 4000];
+        levels.extend(units.iter().flat_map(|&on| vec![if on { 1.0 } else { 0.02 }; per_unit]));
+        levels.extend(vec![0.02; 8000]);
+        levels
+    }
+
+    #[test]
+    fn test_decode() {
+        // at the speed it starts with, then slower and faster
+        for &dot_secs in &[0.06, 0.1, 0.04] {
+            let mut decoder = Decoder::new(8000.0);
+            for level in keyed("CQ TEST DE N0CALL", dot_secs) {
+                decoder.add_level(level);
+            }
+            assert_eq!(decoder.text(), "CQ TEST DE N0CALL");
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::fmt;
+use std::str::FromStr;
+
+use cells::Color;
+
+/// Color maps for the waterfall, rendered with the xterm 256-color palette or
+/// in true colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, RustcDecodable)]
+pub enum Palette {
+    Classic,
+    Viridis,
+    Inferno,
+    Turbo,
+    Grayscale,
+}
+
+pub const PALETTES: [Palette; 5] = [Palette::Classic, Palette::Viridis, Palette::Inferno,
+                                    Palette::Turbo, Palette::Grayscale];
+
+/// The original blue-green-yellow mapping, as xterm color indices.
+const CLASSIC: [u8; 20] = [16, 17, 18, 19, 21, 27, 33, 39, 45, 51,
+                           50, 49, 48, 47, 46, 82, 118, 154, 190, 226];
+
+// Evenly spaced RGB stops along each color map.
+const VIRIDIS: [(u8, u8, u8); 5] = [(68, 1, 84), (59, 82, 139), (33, 145, 140),
+                                    (94, 201, 98), (253, 231, 37)];
+const INFERNO: [(u8, u8, u8); 5] = [(0, 0, 4), (87, 16, 110), (188, 55, 84),
+                                    (249, 142, 9), (252, 255, 164)];
+const TURBO: [(u8, u8, u8); 7] = [(48, 18, 59), (70, 134, 251), (27, 229, 181),
+                                  (164, 252, 60), (251, 185, 56), (228, 70, 10),
+                                  (122, 4, 3)];
+
+/// Intensity levels of the xterm 6x6x6 color cube.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl Palette {
+    /// Maps `f`, between 0 and 1, to an xterm color index. Anything outside of
+    /// this range is clamped.
+    pub fn color(&self, f: f32) -> u8 {
+        let f = if f.is_nan() { 0.0 } else { f.max(0.0).min(1.0) };
+        match *self {
+            Palette::Classic => {
+                let idx = (f * CLASSIC.len() as f32) as usize;
+                CLASSIC[if idx >= CLASSIC.len() { CLASSIC.len() - 1 } else { idx }]
+            }
+            Palette::Viridis => interpolate(&VIRIDIS, f),
+            Palette::Inferno => interpolate(&INFERNO, f),
+            Palette::Turbo => interpolate(&TURBO, f),
+            // xterm's 24 step gray ramp
+            Palette::Grayscale => 232 + (f * 23.0).round() as u8,
+        }
+    }
+
+    /// Maps `f` like `color`, but to a true color, for drawing pixels rather
+    /// than cells. The maps given as stops aren't held to the color cube.
+    pub fn rgb(&self, f: f32) -> (u8, u8, u8) {
+        let f = if f.is_nan() { 0.0 } else { f.max(0.0).min(1.0) };
+        let stops: &[(u8, u8, u8)] = match *self {
+            Palette::Classic | Palette::Grayscale => return xterm_to_rgb(self.color(f)),
+            Palette::Viridis => &VIRIDIS,
+            Palette::Inferno => &INFERNO,
+            Palette::Turbo => &TURBO,
+        };
+        let (r, g, b) = interpolate_rgb(stops, f);
+        (r.round() as u8, g.round() as u8, b.round() as u8)
+    }
+
+    /// Maps `f` to a cell's color: an xterm index for the maps made of them,
+    /// and a true color for the others, which the backend puts in 256 colors
+    /// if it has to.
+    pub fn cell_color(&self, f: f32) -> Color {
+        match *self {
+            Palette::Classic | Palette::Grayscale => Color::Byte(self.color(f)),
+            _ => {
+                let (r, g, b) = self.rgb(f);
+                Color::Rgb(r, g, b)
+            }
+        }
+    }
+
+    /// The palette after this one, wrapping around.
+    pub fn next(&self) -> Palette {
+        let idx = PALETTES.iter().position(|p| p == self).unwrap();
+        PALETTES[(idx + 1) % PALETTES.len()]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Palette::Classic => "classic",
+            Palette::Viridis => "viridis",
+            Palette::Inferno => "inferno",
+            Palette::Turbo => "turbo",
+            Palette::Grayscale => "grayscale",
+        }
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        PALETTES.iter()
+                .find(|p| p.name() == s)
+                .cloned()
+                .ok_or_else(|| {
+                    let names: Vec<_> = PALETTES.iter().map(|p| p.name()).collect();
+                    format!("Unknown palette '{}', expected one of: {}", s, names.join(", "))
+                })
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+fn interpolate(stops: &[(u8, u8, u8)], f: f32) -> u8 {
+    let (r, g, b) = interpolate_rgb(stops, f);
+    rgb_to_xterm(r, g, b)
+}
+
+fn interpolate_rgb(stops: &[(u8, u8, u8)], f: f32) -> (f32, f32, f32) {
+    let pos = f * (stops.len() - 1) as f32;
+    let i = (pos as usize).min(stops.len() - 2);
+    let t = pos - i as f32;
+    let lerp = |a: u8, b: u8| a as f32 + (b as f32 - a as f32) * t;
+    let (r0, g0, b0) = stops[i];
+    let (r1, g1, b1) = stops[i + 1];
+    (lerp(r0, r1), lerp(g0, g1), lerp(b0, b1))
+}
+
+/// The color of an xterm index from the cube or the gray ramp, the only
+/// ones the palettes use.
+fn xterm_to_rgb(index: u8) -> (u8, u8, u8) {
+    if index >= 232 {
+        let level = 8 + 10 * (index - 232);
+        return (level, level, level);
+    }
+    let i = index.saturating_sub(16) as usize;
+    (CUBE_LEVELS[i / 36], CUBE_LEVELS[i / 6 % 6], CUBE_LEVELS[i % 6])
+}
+
+/// Nearest color in the xterm 6x6x6 color cube.
+fn rgb_to_xterm(r: f32, g: f32, b: f32) -> u8 {
+    let level = |x: f32| {
+        let mut best = 0;
+        for (i, &l) in CUBE_LEVELS.iter().enumerate() {
+            if (l as f32 - x).abs() < (CUBE_LEVELS[best] as f32 - x).abs() {
+                best = i;
+            }
+        }
+        best as u8
+    };
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use cells::Color;
+    use super::{Palette, PALETTES};
+
+    #[test]
+    fn test_palette_endpoints() {
+        assert_eq!(Palette::Classic.color(0.0), 16);
+        assert_eq!(Palette::Classic.color(2.0), 226);
+        assert_eq!(Palette::Grayscale.color(0.0), 232);
+        assert_eq!(Palette::Grayscale.color(1.0), 255);
+        // inferno starts black and ends a pale yellow
+        assert_eq!(Palette::Inferno.color(-1.0), 16);
+        assert_eq!(Palette::Inferno.color(1.0), 229);
+
+        // the classic map's yellow, and the stops of the others exactly
+        assert_eq!(Palette::Classic.rgb(1.0), (255, 255, 0));
+        assert_eq!(Palette::Grayscale.rgb(0.0), (8, 8, 8));
+        assert_eq!(Palette::Inferno.rgb(1.0), (252, 255, 164));
+
+        assert_eq!(Palette::Classic.cell_color(1.0), Color::Byte(226));
+        assert_eq!(Palette::Inferno.cell_color(1.0), Color::Rgb(252, 255, 164));
+        assert_eq!(Palette::Inferno.cell_color(1.0).as_byte(), 229);
+    }
+
+    #[test]
+    fn test_palette_names() {
+        for p in PALETTES.iter() {
+            assert_eq!(p.name().parse::<Palette>(), Ok(*p));
+        }
+        assert!("nope".parse::<Palette>().is_err());
+        assert_eq!(Palette::Grayscale.next(), Palette::Classic);
+    }
+}
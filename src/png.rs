@@ -0,0 +1,258 @@
+//! Writing RGB images as PNG files, a row at a time, or adding rows to one as
+//! they come.
+//!
+//! The image data is stored rather than compressed, which keeps this short and
+//! needs no dependencies. The files are bigger for it, but any viewer opens
+//! them and a tool like optipng shrinks them afterwards.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// The most a stored deflate block holds.
+const MAX_BLOCK_LEN: usize = 65535;
+
+/// The zlib header, for deflate with a 32K window.
+const ZLIB_HEADER: [u8; 2] = [0x78, 0x01];
+
+/// Where the header chunk starts, after the signature.
+const HEADER_AT: u64 = 8;
+
+/// Writes a PNG of a known size, row by row from the top.
+pub struct Encoder<W: Write> {
+    out: W,
+    width: usize,
+    rows_left: usize,
+    /// The running Adler-32 of the image data, in its two halves.
+    adler: (u32, u32),
+    started: bool,
+}
+
+impl<W: Write> Encoder<W> {
+    /// Starts a PNG `width` by `height` pixels, neither of which can be 0.
+    pub fn new(mut out: W, width: usize, height: usize) -> io::Result<Self> {
+        assert!(width > 0 && height > 0);
+        try!(out.write_all(&SIGNATURE));
+        try!(write_header(&mut out, width, height));
+        Ok(Encoder { out: out, width: width, rows_left: height, adler: (1, 0), started: false })
+    }
+
+    /// Adds the next row, `3 * width` bytes of RGB.
+    pub fn write_row(&mut self, rgb: &[u8]) -> io::Result<()> {
+        assert_eq!(rgb.len(), 3 * self.width);
+        if self.rows_left == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "too many rows"));
+        }
+        self.rows_left -= 1;
+
+        let row = unfiltered(rgb);
+        self.adler = adler32(self.adler, &row);
+        let mut data = Vec::with_capacity(row.len() + 16);
+        if !self.started {
+            data.extend(&ZLIB_HEADER);
+            self.started = true;
+        }
+        add_stored_blocks(&mut data, &row, self.rows_left == 0);
+        if self.rows_left == 0 {
+            data.extend(&adler_bytes(self.adler));
+        }
+        write_chunk(&mut self.out, b"IDAT", &data)
+    }
+
+    /// Ends the file once every row is in, handing back the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.rows_left > 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "rows missing"));
+        }
+        try!(write_chunk(&mut self.out, b"IEND", &[]));
+        try!(self.out.flush());
+        Ok(self.out)
+    }
+}
+
+/// A PNG that rows are added to the bottom of, one at a time, for as long as
+/// it's wanted. It's left whole after each, so it can be looked at while it
+/// grows and nothing's lost if the program is killed.
+pub struct Appender<F: Write + Seek> {
+    out: F,
+    width: usize,
+    height: usize,
+    adler: (u32, u32),
+}
+
+impl<F: Write + Seek> Appender<F> {
+    /// Starts a PNG `width` pixels wide, which is only whole once there's a
+    /// row in it.
+    pub fn new(mut out: F, width: usize) -> io::Result<Self> {
+        assert!(width > 0);
+        try!(out.write_all(&SIGNATURE));
+        try!(write_header(&mut out, width, 0));
+        try!(write_chunk(&mut out, b"IDAT", &ZLIB_HEADER));
+        let adler = (1, 0);
+        try!(write_end(&mut out, adler));
+        Ok(Appender { out: out, width: width, height: 0, adler: adler })
+    }
+
+    /// Adds a row, `3 * width` bytes of RGB, at the bottom.
+    pub fn add_row(&mut self, rgb: &[u8]) -> io::Result<()> {
+        assert_eq!(rgb.len(), 3 * self.width);
+        let row = unfiltered(rgb);
+        self.adler = adler32(self.adler, &row);
+        let mut data = Vec::with_capacity(row.len() + 8);
+        add_stored_blocks(&mut data, &row, false);
+
+        // over the end of the last row, then the height
+        try!(self.out.seek(SeekFrom::End(-(END_LEN as i64))));
+        try!(write_chunk(&mut self.out, b"IDAT", &data));
+        try!(write_end(&mut self.out, self.adler));
+        self.height += 1;
+        try!(self.out.seek(SeekFrom::Start(HEADER_AT)));
+        try!(write_header(&mut self.out, self.width, self.height));
+        self.out.flush()
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+}
+
+/// Bytes `write_end` writes.
+const END_LEN: usize = 12 + 9 + 12;
+
+/// Ends the image data, with an empty final block and the Adler-32, then the
+/// file.
+fn write_end<W: Write>(out: &mut W, adler: (u32, u32)) -> io::Result<()> {
+    let mut data = vec![1, 0, 0, 0xff, 0xff];
+    data.extend(&adler_bytes(adler));
+    try!(write_chunk(out, b"IDAT", &data));
+    write_chunk(out, b"IEND", &[])
+}
+
+/// Writes `rgb`, `width` pixels to a row, as a PNG at `path`.
+pub fn write_file(path: &str, width: usize, rgb: &[u8]) -> io::Result<()> {
+    let height = rgb.len() / (3 * width);
+    let mut encoder = try!(Encoder::new(BufWriter::new(try!(File::create(path))), width,
+                                        height));
+    for row in rgb.chunks(3 * width) {
+        try!(encoder.write_row(row));
+    }
+    encoder.finish().map(|_| ())
+}
+
+fn write_header<W: Write>(out: &mut W, width: usize, height: usize) -> io::Result<()> {
+    let mut header = Vec::with_capacity(13);
+    header.extend(&be32(width as u32));
+    header.extend(&be32(height as u32));
+    // 8 bits per channel of RGB, no interlacing
+    header.extend(&[8, 2, 0, 0, 0]);
+    write_chunk(out, b"IHDR", &header)
+}
+
+/// `rgb` as a row of image data, which starts with its filter.
+fn unfiltered(rgb: &[u8]) -> Vec<u8> {
+    let mut row = Vec::with_capacity(rgb.len() + 1);
+    row.push(0);
+    row.extend(rgb);
+    row
+}
+
+/// Adds `row` to `data` as stored deflate blocks, the last of them final if
+/// `last` is set.
+fn add_stored_blocks(data: &mut Vec<u8>, row: &[u8], last: bool) {
+    let mut blocks = row.chunks(MAX_BLOCK_LEN).peekable();
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        data.push(if last && blocks.peek().is_none() { 1 } else { 0 });
+        data.extend(&[len as u8, (len >> 8) as u8, !len as u8, (!len >> 8) as u8]);
+        data.extend(block);
+    }
+}
+
+/// Carries the Adler-32 `adler`, in its two halves, over `data`.
+fn adler32((mut a, mut b): (u32, u32), data: &[u8]) -> (u32, u32) {
+    for &x in data {
+        a = (a + x as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (a, b)
+}
+
+fn adler_bytes((a, b): (u32, u32)) -> [u8; 4] {
+    be32(b << 16 | a)
+}
+
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    try!(out.write_all(&be32(data.len() as u32)));
+    try!(out.write_all(kind));
+    try!(out.write_all(data));
+    let crc = crc32(crc32(!0, kind), data);
+    out.write_all(&be32(!crc))
+}
+
+fn be32(x: u32) -> [u8; 4] {
+    [(x >> 24) as u8, (x >> 16) as u8, (x >> 8) as u8, x as u8]
+}
+
+/// Carries the CRC-32 `crc`, not yet inverted, over `data`.
+fn crc32(mut crc: u32, data: &[u8]) -> u32 {
+    for &x in data {
+        crc ^= x as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::{Appender, Encoder};
+
+    #[test]
+    fn test_encode() {
+        let mut encoder = Encoder::new(Vec::new(), 1, 2).unwrap();
+        encoder.write_row(&[255, 0, 0]).unwrap();
+        encoder.write_row(&[0, 0, 255]).unwrap();
+        let png = encoder.finish().unwrap();
+
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[8..33], &[0, 0, 0, 13, b'I', b'H', b'D', b'R', 0, 0, 0, 1, 0, 0, 0, 2,
+                                  8, 2, 0, 0, 0, 0x16, 0xe3, 0x21, 0x70][..]);
+        // a stored block for each row, the last one final, then the Adler-32
+        let first = &png[33..];
+        assert_eq!(&first[..8], b"\0\0\0\x0bIDAT");
+        assert_eq!(&first[8..19], &[0x78, 0x01, 0, 4, 0, 0xfb, 0xff, 0, 255, 0, 0][..]);
+        let second = &first[23..];
+        assert_eq!(&second[..8], b"\0\0\0\x0dIDAT");
+        assert_eq!(&second[8..21],
+                   &[1, 4, 0, 0xfb, 0xff, 0, 0, 0, 255, 0x08, 0x00, 0x01, 0xff][..]);
+        assert_eq!(&png[png.len() - 12..], b"\0\0\0\0IEND\xae\x42\x60\x82");
+    }
+
+    #[test]
+    fn test_append() {
+        let mut appender = Appender::new(Cursor::new(Vec::new()), 1).unwrap();
+        appender.add_row(&[255, 0, 0]).unwrap();
+        let one_row = appender.out.get_ref().clone();
+        appender.add_row(&[0, 0, 255]).unwrap();
+        let png = appender.out.into_inner();
+
+        // the same header as written in one go
+        let mut encoder = Encoder::new(Vec::new(), 1, 2).unwrap();
+        encoder.write_row(&[255, 0, 0]).unwrap();
+        encoder.write_row(&[0, 0, 255]).unwrap();
+        assert_eq!(&png[..33], &encoder.finish().unwrap()[..33]);
+
+        // the zlib header on its own, a chunk for each row, then the end
+        assert_eq!(&png[33..47], b"\0\0\0\x02IDAT\x78\x01\xec\x1a\x7e\xd2");
+        assert_eq!(&png[47..55], b"\0\0\0\x09IDAT");
+        assert_eq!(&png[55..64], &[0, 4, 0, 0xfb, 0xff, 0, 255, 0, 0][..]);
+        assert_eq!(png.len(), one_row.len() + 12 + 9);
+        assert_eq!(&png[png.len() - 25..png.len() - 16],
+                   &[1, 0, 0, 0xff, 0xff, 0x08, 0x00, 0x01, 0xff][..]);
+        assert_eq!(&png[png.len() - 12..], b"\0\0\0\0IEND\xae\x42\x60\x82");
+    }
+}
@@ -0,0 +1,65 @@
+//! Buffers handed back once used, for the next to be filled, so the samples
+//! and spectra going through at full rate don't each need allocating.
+
+use std::sync::{Arc, Mutex};
+
+/// Most buffers kept for reuse. More come back only while something's fallen
+/// behind, and are let go.
+const MAX_KEPT: usize = 64;
+
+/// Shared by whatever fills the buffers and whatever's last to use them.
+/// Clones share the same buffers.
+pub struct BufferPool<T> {
+    spare: Arc<Mutex<Vec<Vec<T>>>>,
+}
+
+impl<T> BufferPool<T> {
+    pub fn new() -> Self {
+        BufferPool { spare: Arc::new(Mutex::new(Vec::new())) }
+    }
+
+    /// An empty buffer, one handed back if there is one.
+    pub fn take(&self) -> Vec<T> {
+        self.spare.lock().unwrap().pop().unwrap_or_default()
+    }
+
+    /// Hands `buffer` back for reuse.
+    pub fn give(&self, mut buffer: Vec<T>) {
+        let mut spare = self.spare.lock().unwrap();
+        if spare.len() < MAX_KEPT {
+            buffer.clear();
+            spare.push(buffer);
+        }
+    }
+}
+
+impl<T> Clone for BufferPool<T> {
+    fn clone(&self) -> Self {
+        BufferPool { spare: self.spare.clone() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BufferPool, MAX_KEPT};
+
+    #[test]
+    fn test_buffer_pool() {
+        let pool = BufferPool::new();
+        let mut buffer = pool.take();
+        buffer.extend_from_slice(&[1, 2, 3]);
+        let capacity = buffer.capacity();
+        pool.clone().give(buffer);
+
+        // the same one back, emptied
+        let buffer = pool.take();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.capacity(), capacity);
+        assert_eq!(pool.take().capacity(), 0);
+
+        for _ in 0..MAX_KEPT + 1 {
+            pool.give(vec![0; 4]);
+        }
+        assert_eq!(pool.spare.lock().unwrap().len(), MAX_KEPT);
+    }
+}
@@ -0,0 +1,118 @@
+//! A quick look at the band on startup, when only a frequency is given, to
+//! pick the gains, the displayed power range and the averaging for it.
+
+use std::time::Duration;
+
+use gain_sweep::{measure, Level};
+use radio::hackrf::{HackRF, LNA_GAINS_DB};
+
+/// Sample rate used when no bandwidth is given.
+pub const DEFAULT_SAMPLE_RATE_HZ: f64 = 10e6;
+
+/// Time spent measuring each gain setting. The probe tries every LNA gain with
+/// each of `VGA_GAINS_DB`, so this keeps it to a couple of seconds.
+const DWELL: Duration = Duration::from_millis(100);
+const VGA_GAINS_DB: [u32; 4] = [8, 20, 32, 44];
+
+/// Settings clipping more than this fraction of samples are only picked if
+/// they all do.
+const MAX_CLIPPED_FRACTION: f32 = 0.001;
+
+/// Room left below the noise floor and above the strongest signal, in dB.
+const RANGE_MARGIN_DB: f32 = 5.0;
+
+/// Spectra are averaged when nothing stands further than this above the noise,
+/// since whatever is there is easier to see once the noise is smoothed out.
+const WEAK_SIGNAL_SNR_DB: f32 = 15.0;
+const WEAK_SIGNAL_AVERAGE: usize = 4;
+
+/// What to do with the probe's findings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, RustcDecodable)]
+pub enum ProbeMode {
+    /// Start with the suggested settings.
+    Apply,
+    /// Print the suggested settings and exit.
+    Suggest,
+    Off,
+}
+
+pub struct Suggestion {
+    pub lna_gain_db: u32,
+    pub vga_gain_db: u32,
+    pub min_db: f32,
+    pub max_db: f32,
+    pub average: usize,
+    /// What was measured at the suggested gains.
+    pub level: Level,
+}
+
+impl Suggestion {
+    /// True if even the suggested gains clip, so the signal needs attenuating.
+    pub fn is_clipping(&self) -> bool {
+        self.level.clipped_fraction > MAX_CLIPPED_FRACTION
+    }
+
+    /// The command line options giving these settings.
+    pub fn options(&self) -> String {
+        format!("--lna-gain={} --vga-gain={} --min-db={} --max-db={} --average={}",
+                self.lna_gain_db, self.vga_gain_db, self.min_db, self.max_db, self.average)
+    }
+}
+
+/// Tries a spread of gain settings and suggests the one with the best SNR that
+/// doesn't clip, with a power range from just below its noise floor to just
+/// above its strongest signal. `fft_len` should be the display's, so the range
+/// matches what the spectrum view shows, and the FFTs are shared out between
+/// `threads` threads. `cancelled` is checked between
+/// settings, so the caller can keep handling keys. A gain given as `lna_db`
+/// or `vga_db` is kept, only the other being tried. Returns `None` if no
+/// samples arrived, or once `cancelled` returns true.
+pub fn run<F>(radio: &mut HackRF, sample_rate_hz: u32, fft_len: usize, threads: usize,
+              lna_db: Option<u32>, vga_db: Option<u32>, mut cancelled: F)
+              -> Option<Suggestion>
+    where F: FnMut() -> bool
+{
+    let lna_gains = lna_db.map_or(LNA_GAINS_DB.to_vec(), |db| vec![db]);
+    let vga_gains = vga_db.map_or(VGA_GAINS_DB.to_vec(), |db| vec![db]);
+    let recv = radio.start_rx();
+    let clips = |l: &Level| l.clipped_fraction > MAX_CLIPPED_FRACTION;
+
+    let mut best: Option<(u32, u32, Level)> = None;
+    'settings: for &lna in lna_gains.iter() {
+        for &vga in vga_gains.iter() {
+            if cancelled() {
+                best = None;
+                break 'settings;
+            }
+            radio.set_lna_gain(lna).expect("Couldn't set LNA gain");
+            radio.set_vga_gain(vga).expect("Couldn't set VGA gain");
+
+            let level = match measure(&recv, sample_rate_hz, fft_len, threads, DWELL) {
+                Some(level) => level,
+                None => continue,
+            };
+            let better = match best {
+                None => true,
+                Some((_, _, ref b)) if clips(b) != clips(&level) => clips(b),
+                Some((_, _, ref b)) => level.snr_db() > b.snr_db(),
+            };
+            if better {
+                best = Some((lna, vga, level));
+            }
+        }
+    }
+
+    radio.stop_rx().expect("Couldn't stop receiving");
+
+    best.map(|(lna, vga, level)| Suggestion {
+        lna_gain_db: lna,
+        vga_gain_db: vga,
+        // rounded outwards to a multiple of the margin, to keep the options short
+        min_db: ((level.noise_floor_db - RANGE_MARGIN_DB) / RANGE_MARGIN_DB).floor() *
+                RANGE_MARGIN_DB,
+        max_db: ((level.peak_db + RANGE_MARGIN_DB) / RANGE_MARGIN_DB).ceil() * RANGE_MARGIN_DB,
+        average: if level.snr_db() < WEAK_SIGNAL_SNR_DB { WEAK_SIGNAL_AVERAGE } else { 1 },
+        level: level,
+    })
+}
+
@@ -1,83 +1,1125 @@
 use std::sync::mpsc::{Receiver, SyncSender, TrySendError};
 use std::sync::{Arc, Mutex};
-use num::Complex;
+use std::cmp::{max, min, Ordering};
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::fmt;
+use std::iter;
+use std::marker::PhantomData;
+use std::mem;
+use std::str::FromStr;
+use num::{Complex, Float, FromPrimitive, Signed};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use rayon::prelude::*;
+use rustc_serialize::{Decodable, Decoder};
 use rustfft::FFT;
 
-struct SignalProcessor {
-    fft: FFT<f32>,
-    signal: Vec<Complex<f32>>,
+use pool::BufferPool;
+use stats::Stats;
+use wait::Waker;
+
+/// FFTs of lengths used before kept for switching back, since setting one up
+/// takes longer than several transforms.
+const MAX_CACHED_FFTS: usize = 4;
+
+/// Fewest samples or bins a thread takes at a time, so short FFTs aren't
+/// split up finer than is worth it.
+const PARALLEL_MIN_LEN: usize = 4096;
+
+/// Rates the FFT rate keys step through, in FFTs per second.
+const FFT_RATES_HZ: [u32; 9] = [1, 2, 5, 10, 15, 20, 30, 50, 100];
+
+/// About how many pairs of spectra the cross spectrum is averaged over.
+const CROSS_AVERAGE_SPECTRA: f32 = 8.0;
+
+/// Spectra of one input kept waiting for the other's, the oldest going
+/// first when one stops.
+const CROSS_WAITING_LEN: usize = 8;
+
+/// A float the FFTs can be done in: `f32`, or `f64` for captures with more
+/// dynamic range than single precision keeps.
+pub trait FFTnum: Float + FromPrimitive + Signed + Send + Sync {}
+
+impl FFTnum for f32 {}
+impl FFTnum for f64 {}
+
+/// A sample `SignalProcessor` takes, as a complex `T`. Each kind keeps its
+/// own scale, so the powers of 16 bit samples, say, come out 48 dB above
+/// those of 8 bit ones of the same signal.
+pub trait IntoComplex<T>: Copy {
+    fn into_complex(self) -> Complex<T>;
+}
+
+impl<T: FFTnum> IntoComplex<T> for Complex<i8> {
+    fn into_complex(self) -> Complex<T> {
+        Complex::new(T::from_i8(self.re).unwrap(), T::from_i8(self.im).unwrap())
+    }
+}
+
+impl<T: FFTnum> IntoComplex<T> for Complex<i16> {
+    fn into_complex(self) -> Complex<T> {
+        Complex::new(T::from_i16(self.re).unwrap(), T::from_i16(self.im).unwrap())
+    }
+}
+
+impl<T: FFTnum> IntoComplex<T> for Complex<f32> {
+    fn into_complex(self) -> Complex<T> {
+        Complex::new(T::from_f32(self.re).unwrap(), T::from_f32(self.im).unwrap())
+    }
+}
+
+impl IntoComplex<f64> for Complex<f64> {
+    fn into_complex(self) -> Complex<f64> {
+        self
+    }
+}
+
+/// A real sample, such as audio, whose spectrum mirrors itself about DC.
+impl<T: FFTnum> IntoComplex<T> for i16 {
+    fn into_complex(self) -> Complex<T> {
+        Complex::new(T::from_i16(self).unwrap(), T::zero())
+    }
+}
+
+/// Shaping applied to each block of samples before its FFT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    /// None at all, for the narrowest peaks, though strong ones leak into
+    /// the bins around them.
+    Rectangular,
+    /// Rounds the ends of the block off, for far less leakage at the cost of
+    /// wider peaks, 6 dB lower.
+    Hann,
+}
+
+impl Window {
+    fn coefficients<T: FFTnum>(self, len: usize) -> Vec<T> {
+        match self {
+            Window::Rectangular => vec![T::one(); len],
+            Window::Hann => (0..len).map(|n| {
+                T::from_f64(0.5 - 0.5 * (2.0 * PI * n as f64 / len as f64).cos()).unwrap()
+            }).collect(),
+        }
+    }
+}
+
+/// Turns blocks of samples into spectra, at a set rate, dropping the samples
+/// between blocks. The FFTs are done in `T`.
+pub struct SignalProcessor<T: FFTnum = f32> {
+    fft: FFT<T>,
+    /// Set up for other lengths, most recently used first.
+    cached_ffts: Vec<(usize, FFT<T>)>,
+    /// One for each thread of `workers` but the first, for the current length.
+    worker_ffts: Vec<FFT<T>>,
+    /// The threads the windowing, FFTs and averaging are shared out between,
+    /// if more than one.
+    workers: Option<ThreadPool>,
+    /// The blocks of the buffer being taken in, then the start of the next,
+    /// windowed as they're transformed.
+    signal: Vec<Complex<T>>,
     fft_rate_hz: u32,
     sample_rate_hz: u32,
     pub fft_len: usize,
     num_samples_discarded: u32,
+    window: Window,
+    /// `window`'s coefficients, one for each sample of a block.
+    window_coefficients: Vec<T>,
+    /// Spectra averaged into each one returned, 1 for none.
+    average: usize,
+    /// Sum of the powers of the spectra so far toward the next average.
+    power_sum: Vec<T>,
+    num_averaged: usize,
+    /// Where the spectra are made in, if they're handed back once used.
+    spectrum_pool: Option<BufferPool<Complex<T>>>,
+    /// Where the samples and FFTs are counted, if anywhere.
+    stats: Option<Arc<Stats>>,
 }
 
 impl SignalProcessor {
-    fn new(sample_rate_hz: u32, fft_rate_hz: u32, fft_len: usize) -> Self {
-        SignalProcessor {
-            fft: FFT::new(fft_len, false),
-            signal: Vec::with_capacity(fft_len),
-            fft_rate_hz: fft_rate_hz,
-            sample_rate_hz: sample_rate_hz,
-            fft_len: fft_len,
-            num_samples_discarded: 0,
-        }
+    /// A processor taking `fft_rate_hz` FFTs a second of `fft_len` samples
+    /// each, unwindowed and unaveraged, from samples at `sample_rate_hz`.
+    pub fn new(sample_rate_hz: u32, fft_rate_hz: u32, fft_len: usize) -> Self {
+        SignalProcessor::builder(sample_rate_hz).fft_rate_hz(fft_rate_hz).fft_len(fft_len).build()
     }
 
-    fn new_fft_len(&mut self, fft_len: usize) {
-        self.fft = FFT::new(fft_len, false);
+    /// Starts setting up a processor for samples at `sample_rate_hz`, in
+    /// single precision. `SignalProcessorBuilder::new` sets up others.
+    pub fn builder(sample_rate_hz: u32) -> SignalProcessorBuilder<f32> {
+        SignalProcessorBuilder::new(sample_rate_hz)
+    }
+}
+
+impl<T: FFTnum> SignalProcessor<T> {
+    /// Changes the FFT length, dropping any block or average part way done.
+    /// It's at least 1.
+    pub fn set_fft_len(&mut self, fft_len: usize) {
+        let fft_len = max(fft_len, 1);
+        let fft = match self.cached_ffts.iter().position(|&(len, _)| len == fft_len) {
+            Some(i) => self.cached_ffts.remove(i).1,
+            None => FFT::new(fft_len, false),
+        };
+        let old_fft = mem::replace(&mut self.fft, fft);
+        self.cached_ffts.insert(0, (self.fft_len, old_fft));
+        self.cached_ffts.truncate(MAX_CACHED_FFTS);
+        self.worker_ffts = (0..self.worker_ffts.len()).map(|_| FFT::new(fft_len, false))
+                                                      .collect();
+        self.signal.clear();
         self.signal.reserve(fft_len);
         self.fft_len = fft_len;
+        self.window_coefficients = self.window.coefficients(fft_len);
+        self.power_sum.clear();
+        self.num_averaged = 0;
     }
 
-    fn add_signal_buffer(&mut self, buff: Vec<Complex<i8>>) -> Vec<Vec<Complex<f32>>> {
-        let num_samples_to_discard = (self.sample_rate_hz -
-            self.fft_rate_hz * self.fft_len as u32) / self.fft_rate_hz;
-        let mut spectra = Vec::new();
-        for x in buff {
+    /// Changes how many FFTs are taken a second, from the next block on.
+    /// It's at least 1.
+    pub fn set_fft_rate(&mut self, fft_rate_hz: u32) {
+        self.fft_rate_hz = max(fft_rate_hz, 1);
+    }
+
+    /// Takes in `buff`, returning the spectra finished with it, if any, each
+    /// `fft_len` bins from DC up and then the negative frequencies. Averaged
+    /// spectra keep only the magnitudes.
+    pub fn add_signal_buffer<S: IntoComplex<T>>(&mut self, buff: Vec<S>) -> Vec<Vec<Complex<T>>> {
+        self.add_signal_slice(&buff)
+    }
+
+    /// `add_signal_buffer`, leaving the samples with the caller, such as to
+    /// hand the buffer back for reuse.
+    pub fn add_signal_slice<S: IntoComplex<T>>(&mut self, buff: &[S]) -> Vec<Vec<Complex<T>>> {
+        // a rate the sample rate can't keep up with takes every sample
+        let fft_rate_hz = self.fft_rate_hz as u64;
+        let num_samples_to_discard = ((self.sample_rate_hz as u64)
+            .saturating_sub(fft_rate_hz.saturating_mul(self.fft_len as u64)) / fft_rate_hz) as u32;
+        let mut num_discarded = 0;
+        for &x in buff {
             if self.num_samples_discarded >= num_samples_to_discard {
-                self.signal.push(Complex::new(x.re as f32, x.im as f32));
+                self.signal.push(x.into_complex());
 
-                if self.signal.len() >= self.fft_len {
-                    let mut spectrum = vec![Complex::new(0.0, 0.0); self.fft_len];
-                    self.fft.process(&self.signal[..], &mut spectrum[..]);
-                    self.signal.clear();
+                if self.signal.len().is_multiple_of(self.fft_len) {
                     self.num_samples_discarded = 0;
-                    spectra.push(spectrum);
                 }
             } else {
                 // discard these samples to maintain the desired FFT rate.
                 self.num_samples_discarded += 1;
+                num_discarded += 1;
+            }
+        }
+
+        let transformed = self.process_blocks();
+        if let Some(ref stats) = self.stats {
+            stats.add_buffer(buff.len(), num_discarded, transformed.len());
+        }
+        let mut spectra = Vec::new();
+        for spectrum in transformed {
+            if let Some(spectrum) = self.add_to_average(spectrum) {
+                spectra.push(spectrum);
             }
         }
         spectra
     }
+
+    /// The spectra of every whole block taken in, all at once, leaving the
+    /// start of the next block. With more than one thread the blocks are
+    /// shared out between them, and a long one windowed a stretch on each.
+    fn process_blocks(&mut self) -> Vec<Vec<Complex<T>>> {
+        let len = self.fft_len;
+        let num_blocks = self.signal.len() / len;
+        let window = &self.window_coefficients;
+        let pool = self.spectrum_pool.as_ref();
+        let spectra = {
+            let blocks = &mut self.signal[..num_blocks * len];
+            match self.workers {
+                Some(ref workers) if num_blocks > 0 => {
+                    let per_thread = num_blocks.div_ceil(self.worker_ffts.len() + 1);
+                    let ffts: Vec<_> = iter::once(&mut self.fft).chain(self.worker_ffts.iter_mut())
+                                                                 .collect();
+                    workers.install(|| {
+                        blocks.par_chunks_mut(len).for_each(|block| {
+                            block.par_iter_mut().zip(&window[..]).with_min_len(PARALLEL_MIN_LEN)
+                                 .for_each(|(x, &w)| *x = *x * w);
+                        });
+                        let spectra: Vec<_> = blocks.par_chunks_mut(per_thread * len).zip(ffts)
+                            .map(|(blocks, fft)| transform_blocks(fft, blocks, len, pool))
+                            .collect();
+                        spectra.into_iter().flatten().collect()
+                    })
+                }
+                _ => {
+                    for block in blocks.chunks_mut(len) {
+                        for (x, &w) in block.iter_mut().zip(window) {
+                            *x = *x * w;
+                        }
+                    }
+                    transform_blocks(&mut self.fft, blocks, len, pool)
+                }
+            }
+        };
+        self.signal.drain(..num_blocks * len);
+        spectra
+    }
+
+    fn new_spectrum(&self) -> Vec<Complex<T>> {
+        self.spectrum_pool.as_ref().map_or_else(Vec::new, BufferPool::take)
+    }
+
+    /// Adds `spectrum` to the average, returning it once it's complete.
+    fn add_to_average(&mut self, spectrum: Vec<Complex<T>>) -> Option<Vec<Complex<T>>> {
+        if self.average <= 1 {
+            return Some(spectrum);
+        }
+        if self.power_sum.len() != spectrum.len() {
+            self.power_sum = vec![T::zero(); spectrum.len()];
+        }
+        let power_sum = &mut self.power_sum;
+        match self.workers {
+            Some(ref workers) => workers.install(|| {
+                power_sum.par_iter_mut().zip(&spectrum[..]).with_min_len(PARALLEL_MIN_LEN)
+                         .for_each(|(sum, x)| *sum = *sum + x.norm_sqr());
+            }),
+            None => for (sum, x) in power_sum.iter_mut().zip(&spectrum) {
+                *sum = *sum + x.norm_sqr();
+            },
+        }
+        if let Some(ref pool) = self.spectrum_pool {
+            pool.give(spectrum);
+        }
+        self.num_averaged += 1;
+        if self.num_averaged < self.average {
+            return None;
+        }
+
+        let n = T::from_usize(self.num_averaged).unwrap();
+        let rms = |&sum: &T| Complex::new((sum / n).sqrt(), T::zero());
+        let mut averaged = self.new_spectrum();
+        let power_sum = &self.power_sum;
+        match self.workers {
+            Some(ref workers) => workers.install(|| {
+                averaged.par_extend(power_sum.par_iter().with_min_len(PARALLEL_MIN_LEN).map(rms));
+            }),
+            None => averaged.extend(power_sum.iter().map(rms)),
+        }
+        for sum in self.power_sum.iter_mut() {
+            *sum = T::zero();
+        }
+        self.num_averaged = 0;
+        Some(averaged)
+    }
 }
 
-pub fn process_signal(recv: Receiver<Vec<Complex<i8>>>, send: SyncSender<Vec<Complex<f32>>>,
-                      fft_len: Arc<Mutex<usize>>, fft_rate: u32, sample_rate_hz: u32) {
-    let mut processor = {
-        let len = fft_len.lock().unwrap();
-        SignalProcessor::new(sample_rate_hz, fft_rate, *len)
-    };
+/// Transforms each of `blocks`, already windowed and `len` samples each, by
+/// `fft`, the spectra in buffers from `pool` if there is one.
+fn transform_blocks<T: FFTnum>(fft: &mut FFT<T>, blocks: &mut [Complex<T>], len: usize,
+                               pool: Option<&BufferPool<Complex<T>>>)
+                               -> Vec<Vec<Complex<T>>> {
+    blocks.chunks_mut(len).map(|block| {
+        let mut spectrum = pool.map_or_else(Vec::new, BufferPool::take);
+        spectrum.resize(len, Complex::new(T::zero(), T::zero()));
+        fft.process(block, &mut spectrum[..]);
+        spectrum
+    }).collect()
+}
 
-    for buff in recv.iter() {
-        {
-            let len = fft_len.lock().unwrap();
-            if *len != processor.fft_len {
-                processor.new_fft_len(*len);
+/// Sets up a `SignalProcessor`, from `SignalProcessor::builder`. Unless
+/// changed it takes 10 FFTs a second of 1024 samples, unwindowed, each
+/// returned on its own.
+#[derive(Clone)]
+pub struct SignalProcessorBuilder<T: FFTnum> {
+    sample_rate_hz: u32,
+    fft_rate_hz: u32,
+    fft_len: usize,
+    window: Window,
+    average: usize,
+    threads: usize,
+    spectrum_pool: Option<BufferPool<Complex<T>>>,
+    stats: Option<Arc<Stats>>,
+    float: PhantomData<T>,
+}
+
+impl<T: FFTnum> SignalProcessorBuilder<T> {
+    /// Starts setting up a processor for samples at `sample_rate_hz`, doing
+    /// its FFTs in `T`.
+    pub fn new(sample_rate_hz: u32) -> Self {
+        SignalProcessorBuilder {
+            sample_rate_hz: sample_rate_hz,
+            fft_rate_hz: 10,
+            fft_len: 1024,
+            window: Window::Rectangular,
+            average: 1,
+            threads: 1,
+            spectrum_pool: None,
+            stats: None,
+            float: PhantomData,
+        }
+    }
+
+    pub fn sample_rate_hz(mut self, sample_rate_hz: u32) -> Self {
+        self.sample_rate_hz = sample_rate_hz;
+        self
+    }
+
+    /// FFTs taken a second, at least 1.
+    pub fn fft_rate_hz(mut self, fft_rate_hz: u32) -> Self {
+        self.fft_rate_hz = max(fft_rate_hz, 1);
+        self
+    }
+
+    /// Samples in each FFT, at least 1.
+    pub fn fft_len(mut self, fft_len: usize) -> Self {
+        self.fft_len = max(fft_len, 1);
+        self
+    }
+
+    pub fn window(mut self, window: Window) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Averages the powers of every `num_spectra` spectra into one, for a
+    /// steadier noise floor at a lower rate.
+    pub fn average(mut self, num_spectra: usize) -> Self {
+        self.average = max(num_spectra, 1);
+        self
+    }
+
+    /// Shares the windowing, FFTs and averaging of each buffer out between a
+    /// pool of `threads` threads, for FFT lengths and sample rates high
+    /// enough that one core falls behind.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = max(threads, 1);
+        self
+    }
+
+    /// Makes the spectra in buffers from `pool`, for handing them back once
+    /// they've been used rather than allocating each.
+    pub fn spectrum_pool(mut self, pool: BufferPool<Complex<T>>) -> Self {
+        self.spectrum_pool = Some(pool);
+        self
+    }
+
+    /// Counts the samples taken in, and the FFTs taken of them, in `stats`.
+    pub fn stats(mut self, stats: Arc<Stats>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    pub fn build(self) -> SignalProcessor<T> {
+        // one thread after all, if the pool can't be had
+        let workers = if self.threads > 1 {
+            ThreadPoolBuilder::new().num_threads(self.threads).build().ok()
+        } else {
+            None
+        };
+        let num_worker_ffts = if workers.is_some() { self.threads - 1 } else { 0 };
+        SignalProcessor {
+            fft: FFT::new(self.fft_len, false),
+            cached_ffts: Vec::new(),
+            worker_ffts: (0..num_worker_ffts).map(|_| FFT::new(self.fft_len, false)).collect(),
+            workers: workers,
+            signal: Vec::with_capacity(self.fft_len),
+            fft_rate_hz: self.fft_rate_hz,
+            sample_rate_hz: self.sample_rate_hz,
+            fft_len: self.fft_len,
+            num_samples_discarded: 0,
+            window: self.window,
+            window_coefficients: self.window.coefficients(self.fft_len),
+            average: self.average,
+            power_sum: Vec::new(),
+            num_averaged: 0,
+            spectrum_pool: self.spectrum_pool,
+            stats: self.stats,
+        }
+    }
+}
+
+/// Settings the UI can change while `process_signal` is running.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Params {
+    pub fft_len: usize,
+    pub sample_rate_hz: u32,
+    pub fft_rate_hz: u32,
+    /// Threads taking the FFTs, which stays as it starts.
+    pub threads: usize,
+}
+
+/// The next of the FFT rate keys' steps above `rate_hz` for a positive `step`,
+/// or below it for a negative one, staying at the ends.
+pub fn step_fft_rate(rate_hz: u32, step: isize) -> u32 {
+    if step > 0 {
+        FFT_RATES_HZ.iter().cloned().find(|&r| r > rate_hz).unwrap_or(rate_hz)
+    } else {
+        FFT_RATES_HZ.iter().cloned().rev().find(|&r| r < rate_hz).unwrap_or(rate_hz)
+    }
+}
+
+/// What happens to a spectrum the drawing isn't ready for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Waits for the drawing, holding up the samples behind it.
+    Block,
+    /// Drops the new spectrum, keeping the one already waiting.
+    DropNewest,
+    /// Drops the spectrum waiting for the new one, so the newest is shown.
+    DropOldest,
+    /// Averages the spectra waiting into one.
+    CoalesceByAveraging,
+}
+
+const BACKPRESSURES: [Backpressure; 4] = [Backpressure::Block, Backpressure::DropNewest,
+                                          Backpressure::DropOldest,
+                                          Backpressure::CoalesceByAveraging];
+
+impl Backpressure {
+    pub fn name(self) -> &'static str {
+        match self {
+            Backpressure::Block => "block",
+            Backpressure::DropNewest => "drop-newest",
+            Backpressure::DropOldest => "drop-oldest",
+            Backpressure::CoalesceByAveraging => "coalesce",
+        }
+    }
+}
+
+impl FromStr for Backpressure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        BACKPRESSURES.iter()
+                     .find(|b| b.name() == s)
+                     .cloned()
+                     .ok_or_else(|| {
+                         let names: Vec<_> = BACKPRESSURES.iter().map(|b| b.name()).collect();
+                         format!("Unknown backpressure '{}', expected one of: {}", s,
+                                 names.join(", "))
+                     })
+    }
+}
+
+impl Decodable for Backpressure {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Self, D::Error> {
+        let name = try!(d.read_str());
+        name.parse().map_err(|e: String| d.error(&e))
+    }
+}
+
+impl fmt::Display for Backpressure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Spectra on their way to the drawing, held back as `backpressure` says
+/// while it isn't ready for them.
+struct Outbox {
+    send: SyncSender<Vec<Complex<f32>>>,
+    waker: Waker,
+    backpressure: Backpressure,
+    /// Counts the spectra that didn't reach the drawing on their own.
+    stats: Arc<Stats>,
+    /// Kept back until there's room for it, with the number of spectra
+    /// averaged into it.
+    waiting: Option<(Vec<Complex<f32>>, usize)>,
+}
+
+impl Outbox {
+    /// Hands `spectrum` over, or holds it back, returning false once the
+    /// drawing has gone.
+    fn add(&mut self, spectrum: Vec<Complex<f32>>) -> bool {
+        match self.backpressure {
+            Backpressure::Block => {
+                if self.send.send(spectrum).is_err() {
+                    return false;
+                }
+                self.waker.wake();
+                true
+            }
+            Backpressure::DropNewest => match self.send.try_send(spectrum) {
+                Ok(()) => {
+                    self.waker.wake();
+                    true
+                }
+                Err(TrySendError::Full(_)) => {
+                    self.stats.add_frame_dropped();
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            },
+            Backpressure::DropOldest => {
+                if self.waiting.is_some() {
+                    self.stats.add_frame_dropped();
+                }
+                self.waiting = Some((spectrum, 1));
+                self.flush()
+            }
+            Backpressure::CoalesceByAveraging => {
+                self.waiting = Some(match self.waiting.take() {
+                    Some((average, n)) => {
+                        self.stats.add_frame_dropped();
+                        (average_powers(&average, n, &spectrum), n + 1)
+                    }
+                    None => (spectrum, 1),
+                });
+                self.flush()
+            }
+        }
+    }
+
+    /// Hands over the spectrum held back, if the drawing has room for it now,
+    /// returning false once the drawing has gone.
+    fn flush(&mut self) -> bool {
+        if let Some((spectrum, n)) = self.waiting.take() {
+            match self.send.try_send(spectrum) {
+                Ok(()) => self.waker.wake(),
+                Err(TrySendError::Full(spectrum)) => self.waiting = Some((spectrum, n)),
+                Err(TrySendError::Disconnected(_)) => return false,
+            }
+        }
+        true
+    }
+}
+
+/// `spectrum` averaged by power into `average`, the average of `n` others,
+/// keeping only the magnitudes.
+fn average_powers(average: &[Complex<f32>], n: usize, spectrum: &[Complex<f32>])
+    -> Vec<Complex<f32>>
+{
+    let n = n as f32;
+    average.iter().zip(spectrum).map(|(a, x)| {
+        Complex::new(((a.norm_sqr() * n + x.norm_sqr()) / (n + 1.0)).sqrt(), 0.0)
+    }).collect()
+}
+
+/// The buffers `process_signal` hands the samples back in, once done with
+/// them, and makes the spectra in, for whoever's last to use them to hand
+/// back.
+#[derive(Clone)]
+pub struct Pools {
+    pub samples: BufferPool<Complex<i8>>,
+    pub spectra: BufferPool<Complex<f32>>,
+}
+
+impl Pools {
+    /// Pools for samples `samples` is handed back to, like a radio's.
+    pub fn new(samples: BufferPool<Complex<i8>>) -> Self {
+        Pools { samples: samples, spectra: BufferPool::new() }
+    }
+}
+
+/// One input's spectra on their way from the samples to `Outbox`, with the
+/// settings its processor was last set up for.
+struct Pipeline {
+    processor: SignalProcessor,
+    current: Params,
+    outbox: Outbox,
+    pools: Pools,
+    stats: Arc<Stats>,
+}
+
+impl Pipeline {
+    fn new(send: SyncSender<Vec<Complex<f32>>>, waker: Waker, params: Params, stats: Arc<Stats>,
+           backpressure: Backpressure, pools: Pools) -> Self {
+        Pipeline {
+            processor: new_processor(&params, &pools, &stats),
+            current: params,
+            outbox: Outbox {
+                send: send,
+                waker: waker,
+                backpressure: backpressure,
+                stats: stats.clone(),
+                waiting: None,
+            },
+            pools: pools,
+            stats: stats,
+        }
+    }
+
+    /// Catches up with `latest`, the settings as the UI has them now.
+    fn set_params(&mut self, latest: Params) {
+        if latest.sample_rate_hz != self.current.sample_rate_hz {
+            self.processor = new_processor(&latest, &self.pools, &self.stats);
+        } else if latest.fft_len != self.current.fft_len {
+            self.processor.set_fft_len(latest.fft_len);
+        }
+        self.processor.set_fft_rate(latest.fft_rate_hz);
+        self.current = latest;
+    }
+
+    /// Turns `samples` into spectra and hands them over, returning false once
+    /// the drawing has gone.
+    fn add(&mut self, samples: &[Complex<i8>]) -> bool {
+        // the one held back may fit now, even with no new ones
+        if !self.outbox.flush() {
+            return false;
+        }
+        for spectrum in self.processor.add_signal_slice(samples) {
+            if !self.outbox.add(spectrum) {
+                return false;
             }
         }
+        true
+    }
+}
+
+fn new_processor(params: &Params, pools: &Pools, stats: &Arc<Stats>) -> SignalProcessor {
+    SignalProcessor::builder(params.sample_rate_hz).fft_rate_hz(params.fft_rate_hz)
+                                                   .fft_len(params.fft_len)
+                                                   .threads(params.threads)
+                                                   .spectrum_pool(pools.spectra.clone())
+                                                   .stats(stats.clone())
+                                                   .build()
+}
 
-        let spectra = processor.add_signal_buffer(buff);
+/// Turns the buffers from `recv` into spectra for `send`, as `params` says,
+/// counting in `stats` the samples, the FFTs, and the spectra that don't get
+/// there on their own.
+pub fn process_signal(recv: Receiver<Vec<Complex<i8>>>, send: SyncSender<Vec<Complex<f32>>>,
+                      waker: Waker, params: Arc<Mutex<Params>>, stats: Arc<Stats>,
+                      backpressure: Backpressure, pools: Pools) {
+    let current = *params.lock().unwrap();
+    let mut pipeline = Pipeline::new(send, waker, current, stats, backpressure, pools);
+    for buff in recv.iter() {
+        pipeline.set_params(*params.lock().unwrap());
+        if !pipeline.add(&buff) {
+            return;
+        }
+        pipeline.pools.samples.give(buff);
+    }
+}
+
+/// One of the inputs `process_pair` takes: where its samples come from and
+/// its spectra go, and the stats and pools `process_signal` would be given.
+pub struct PairedInput {
+    pub recv: Receiver<Vec<Complex<i8>>>,
+    pub send: SyncSender<Vec<Complex<f32>>>,
+    pub waker: Waker,
+    pub stats: Arc<Stats>,
+    pub pools: Pools,
+}
 
-        for spectrum in spectra {
-            // This will implicitly drop spectra when the printing end of the channel
-            // isn't ready.
-            // TODO should notify the user that we're dropping frames.
-            if let Err(TrySendError::Disconnected(_)) = send.try_send(spectrum) {
+/// `process_signal` for two inputs sampled together, for the cross spectrum.
+/// The same number of samples goes to each in turn, so their FFTs start on
+/// the same samples and a change of `params` comes in at the same sample for
+/// both, and the spectra are held up rather than dropped, so each of A's is
+/// paired with the B spectrum of the same samples. Samples lost before they
+/// get here, which show as overruns, still throw the pairs off.
+pub fn process_pair(a: PairedInput, b: PairedInput, params: Arc<Mutex<Params>>) {
+    let current = *params.lock().unwrap();
+    let mut sides: Vec<PairedSide> = vec![a, b].into_iter().map(|input| {
+        PairedSide {
+            recv: input.recv,
+            pipeline: Pipeline::new(input.send, input.waker, current, input.stats,
+                                    Backpressure::Block, input.pools),
+            buff: Vec::new(),
+            taken: 0,
+        }
+    }).collect();
+    loop {
+        for side in sides.iter_mut() {
+            if side.taken == side.buff.len() {
+                let next = match side.recv.recv() {
+                    Ok(next) => next,
+                    Err(_) => return,
+                };
+                side.pipeline.pools.samples.give(mem::replace(&mut side.buff, next));
+                side.taken = 0;
+            }
+        }
+        let len = min(sides[0].buff.len() - sides[0].taken, sides[1].buff.len() - sides[1].taken);
+        let latest = *params.lock().unwrap();
+        for side in sides.iter_mut() {
+            side.pipeline.set_params(latest);
+            if !side.pipeline.add(&side.buff[side.taken..side.taken + len]) {
                 return;
             }
+            side.taken += len;
+        }
+    }
+}
+
+/// One of `process_pair`'s inputs, with its newest buffer and how far into
+/// it the other input has got.
+struct PairedSide {
+    recv: Receiver<Vec<Complex<i8>>>,
+    pipeline: Pipeline,
+    buff: Vec<Complex<i8>>,
+    taken: usize,
+}
+
+/// The cross spectrum of two inputs sampled together, like two receivers on
+/// one clock, from their spectra paired off in the order `process_pair`
+/// hands them over: how far apart in phase they are in each bin, as for
+/// finding which way a signal comes from with two antennas, and how
+/// coherent, which says whether that means anything.
+pub struct CrossSpectrum {
+    /// Spectra of each input waiting for one of the other's.
+    waiting: [VecDeque<Vec<Complex<f32>>>; 2],
+    /// Running averages of A B*, and of the powers of A and B.
+    cross: Vec<Complex<f32>>,
+    powers: [Vec<f32>; 2],
+}
+
+impl CrossSpectrum {
+    pub fn new() -> Self {
+        CrossSpectrum {
+            waiting: [VecDeque::new(), VecDeque::new()],
+            cross: Vec::new(),
+            powers: [Vec::new(), Vec::new()],
+        }
+    }
+
+    /// Adds a spectrum of input `input`, 0 for A or 1 for B, returning
+    /// whether it made a pair with one of the other's, changing the averages.
+    pub fn add(&mut self, input: usize, spec: &[Complex<f32>]) -> bool {
+        let theirs = match self.waiting[1 - input].pop_front() {
+            Some(theirs) => theirs,
+            None => {
+                if self.waiting[input].len() == CROSS_WAITING_LEN {
+                    self.waiting[input].pop_front();
+                }
+                self.waiting[input].push_back(spec.to_vec());
+                return false;
+            }
+        };
+        let (a, b) = if input == 0 { (spec, &theirs[..]) } else { (&theirs[..], spec) };
+        // spectra either side of an FFT length change don't go together
+        if a.len() != b.len() {
+            return false;
+        }
+        // starting again at a new length
+        if self.cross.len() != a.len() {
+            self.cross = vec![Complex::new(0.0, 0.0); a.len()];
+            self.powers = [vec![0.0; a.len()], vec![0.0; a.len()]];
+        }
+        let weight = 1.0 / CROSS_AVERAGE_SPECTRA;
+        for (i, (x, y)) in a.iter().zip(b).enumerate() {
+            self.cross[i] = self.cross[i] + (x * y.conj() - self.cross[i]) * weight;
+            self.powers[0][i] += (x.norm_sqr() - self.powers[0][i]) * weight;
+            self.powers[1][i] += (y.norm_sqr() - self.powers[1][i]) * weight;
+        }
+        true
+    }
+
+    /// How far B is behind A in each bin, in radians from -pi to pi, in the
+    /// FFTs' order.
+    pub fn phase_differences(&self) -> Vec<f32> {
+        self.cross.iter().map(|x| x.arg()).collect()
+    }
+
+    /// The magnitude squared coherence of each bin, from 0 where A and B
+    /// have nothing to do with each other to 1 where one's the other but
+    /// for its gain and phase.
+    pub fn coherence(&self) -> Vec<f32> {
+        self.cross.iter().zip(self.powers[0].iter().zip(&self.powers[1])).map(|(x, (a, b))| {
+            if a * b > 0.0 { (x.norm_sqr() / (a * b)).min(1.0) } else { 0.0 }
+        }).collect()
+    }
+}
+
+/// A local maximum found by `find_peaks`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Peak {
+    pub bin: usize,
+    pub power: f32,
+}
+
+/// Cell-averaging CFAR peak detector.
+///
+/// A bin is a peak if it's a local maximum and exceeds the average of the
+/// `training` bins on either side of it (skipping `guard` bins next to it)
+/// by more than `threshold`. `threshold` is in the same units as `spec`,
+/// which is expected to be logarithmic. Returns at most `max_peaks` peaks,
+/// strongest first.
+pub fn find_peaks(spec: &[f32], guard: usize, training: usize, threshold: f32,
+                  max_peaks: usize) -> Vec<Peak> {
+    let mut peaks = Vec::new();
+    for (i, &x) in spec.iter().enumerate() {
+        let left = if i > 0 { spec[i - 1] } else { x };
+        let right = if i + 1 < spec.len() { spec[i + 1] } else { x };
+        if x < left || x < right {
+            continue;
+        }
+
+        let lower = i.saturating_sub(guard + training)..i.saturating_sub(guard);
+        let upper = (i + guard + 1).min(spec.len())..(i + guard + training + 1).min(spec.len());
+        let (sum, count) = lower.chain(upper)
+                                .fold((0.0, 0), |(sum, count), j| (sum + spec[j], count + 1));
+        if count == 0 {
+            continue;
+        }
+
+        if x - sum / count as f32 > threshold {
+            peaks.push(Peak { bin: i, power: x });
+        }
+    }
+
+    peaks.sort_by(|a, b| b.power.partial_cmp(&a.power).unwrap_or(Ordering::Equal));
+    peaks.truncate(max_peaks);
+    peaks
+}
+
+#[cfg(test)]
+mod tests {
+    use num::Complex;
+    use std::f64::consts::PI;
+    use std::sync::Arc;
+    use std::sync::mpsc::sync_channel;
+    use pool::BufferPool;
+    use stats::Stats;
+    use wait::WaitSet;
+    use std::sync::Mutex;
+    use std::thread;
+    use super::{find_peaks, process_pair, step_fft_rate, Backpressure, CrossSpectrum, Outbox,
+                PairedInput, Params, Pools, SignalProcessor, SignalProcessorBuilder, Window,
+                PARALLEL_MIN_LEN};
+
+    #[test]
+    fn test_find_peaks() {
+        let mut spec = vec![0.0; 64];
+        spec[10] = 20.0;
+        spec[11] = 10.0;
+        spec[40] = 30.0;
+        spec[50] = 1.0;
+
+        let peaks = find_peaks(&spec, 2, 8, 6.0, 5);
+        assert_eq!(peaks.len(), 2);
+        assert_eq!(peaks[0].bin, 40);
+        assert_eq!(peaks[1].bin, 10);
+
+        assert_eq!(find_peaks(&spec, 2, 8, 6.0, 1).len(), 1);
+    }
+
+    #[test]
+    fn test_signal_processor() {
+        // every sample taken, DC at 100
+        let dc = vec![Complex::new(100i8, 0i8); 64];
+        let mut processor = SignalProcessor::builder(160).fft_rate_hz(10).fft_len(16).build();
+        let spectra = processor.add_signal_buffer(dc.clone());
+        assert_eq!(spectra.len(), 4);
+        assert_eq!(spectra[0][0], Complex::new(1600.0, 0.0));
+        // the same, a block and a half at a time
+        let mut processor = SignalProcessor::builder(160).fft_rate_hz(10).fft_len(16).build();
+        let halves: Vec<_> = dc.chunks(24).flat_map(|c| processor.add_signal_slice(c)).collect();
+        assert_eq!(halves, spectra);
+    }
+
+    #[test]
+    fn test_set_fft_len() {
+        let dc = vec![Complex::new(100i8, 0i8); 64];
+        let builder = SignalProcessor::builder(160).fft_len(16);
+        let spectra = builder.clone().build().add_signal_buffer(dc.clone());
+        // the same after switching length and back again, the first kept
+        let mut processor = builder.build();
+        processor.set_fft_len(8);
+        processor.set_fft_len(16);
+        assert_eq!(processor.add_signal_buffer(dc), spectra);
+        assert_eq!(processor.cached_ffts.len(), 1);
+    }
+
+    #[test]
+    fn test_threads() {
+        // the same shared out between threads, windowed or not
+        let dc = vec![Complex::new(100i8, 0i8); 64];
+        for &window in &[Window::Rectangular, Window::Hann] {
+            let builder = SignalProcessor::builder(160).fft_len(16).window(window);
+            let one = builder.clone().build().add_signal_buffer(dc.clone());
+            let three = builder.threads(3).build().add_signal_buffer(dc.clone());
+            assert_eq!(three, one);
+        }
+        // and a block long enough to be windowed and averaged a stretch on each
+        let long = vec![Complex::new(100i8, 0i8); 4 * PARALLEL_MIN_LEN];
+        let builder = SignalProcessor::builder(160).fft_len(2 * PARALLEL_MIN_LEN)
+                                                   .window(Window::Hann).average(2);
+        let one = builder.clone().build().add_signal_buffer(long.clone());
+        assert_eq!(builder.threads(3).build().add_signal_buffer(long), one);
+    }
+
+    #[test]
+    fn test_average() {
+        // half the height through a Hann window, and two spectra in each
+        let dc = vec![Complex::new(100i8, 0i8); 64];
+        let mut processor = SignalProcessor::builder(160).fft_len(16).window(Window::Hann)
+                                                         .average(2).build();
+        let spectra = processor.add_signal_buffer(dc);
+        assert_eq!(spectra.len(), 2);
+        assert!((spectra[1][0].re - 800.0).abs() < 1e-2);
+        assert!(spectra[1][4].re < 1e-2);
+    }
+
+    #[test]
+    fn test_sample_types() {
+        // the other kinds of samples, at their own scales
+        let mut processor = SignalProcessor::builder(160).fft_len(16).build();
+        let spectra = processor.add_signal_buffer(vec![Complex::new(1000i16, -1000i16); 16]);
+        assert_eq!(spectra[0][0], Complex::new(16000.0, -16000.0));
+        let spectra = processor.add_signal_buffer(vec![Complex::new(0.5f32, 0.0); 16]);
+        assert_eq!(spectra[0][0], Complex::new(8.0, 0.0));
+        let spectra = processor.add_signal_buffer(vec![-300i16; 16]);
+        assert_eq!(spectra[0][0], Complex::new(-4800.0, 0.0));
+    }
+
+    #[test]
+    fn test_spectrum_pool() {
+        // made in the buffers handed back, not fresh ones
+        let pool = BufferPool::new();
+        pool.give(Vec::with_capacity(100));
+        let mut processor = SignalProcessor::builder(160).fft_len(16).spectrum_pool(pool)
+                                                         .build();
+        let spectra = processor.add_signal_buffer(vec![Complex::new(1i8, 0i8); 16]);
+        assert_eq!(spectra[0].capacity(), 100);
+    }
+
+    #[test]
+    fn test_stats() {
+        // 16 samples skipped before each block, for 5 FFTs a second
+        let stats = Arc::new(Stats::default());
+        let mut processor = SignalProcessor::builder(160).fft_rate_hz(5).fft_len(16)
+                                                         .stats(stats.clone()).build();
+        assert_eq!(processor.add_signal_buffer(vec![Complex::new(1i8, 0i8); 64]).len(), 2);
+        let snapshot = stats.snapshot();
+        assert_eq!((snapshot.samples_received, snapshot.samples_discarded, snapshot.ffts),
+                   (64, 32, 2));
+    }
+
+    #[test]
+    fn test_f64() {
+        // in double precision, a tone 1e-10 of the noise is still there
+        let mut processor = SignalProcessorBuilder::<f64>::new(160).fft_len(16).build();
+        let signal: Vec<Complex<f64>> = (0..16).map(|n| {
+            Complex::new(1.0 + 1e-10 * (2.0 * PI * n as f64 / 4.0).cos(), 0.0)
+        }).collect();
+        let spectra = processor.add_signal_buffer(signal);
+        assert!((spectra[0][4].norm() - 8e-10).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_zero_rate_and_len() {
+        // taken as 1 rather than dividing by them
+        let mut processor = SignalProcessor::builder(160).fft_rate_hz(0).fft_len(0).build();
+        assert!(processor.add_signal_buffer(vec![Complex::new(1i8, 0i8); 4]).is_empty());
+        processor.set_fft_len(0);
+        processor.set_fft_rate(0);
+        assert!(processor.add_signal_buffer(vec![Complex::new(1i8, 0i8); 4]).is_empty());
+        // and a rate the FFT length would overflow takes every sample
+        let mut processor = SignalProcessor::builder(160).fft_rate_hz(u32::max_value())
+                                                         .fft_len(16).build();
+        assert_eq!(processor.add_signal_buffer(vec![Complex::new(1i8, 0i8); 16]).len(), 1);
+    }
+
+    #[test]
+    fn test_outbox() {
+        let spectrum = |x: f32| vec![Complex::new(x, 0.0)];
+        for &(backpressure, received, dropped) in &[(Backpressure::DropNewest, None, 3),
+                                                    (Backpressure::DropOldest, Some(4.0), 2),
+                                                    (Backpressure::CoalesceByAveraging,
+                                                     Some(3.0), 2)] {
+            let (send, recv) = sync_channel(1);
+            let (_wait_set, waker) = WaitSet::without_tty().unwrap();
+            let mut outbox = Outbox {
+                send: send,
+                waker: waker,
+                backpressure: backpressure,
+                stats: Arc::new(Stats::default()),
+                waiting: None,
+            };
+            // the first gets through, the rest wait for room
+            for &x in &[1.0, 2.0, 7.0f32.sqrt(), 4.0] {
+                assert!(outbox.add(spectrum(x)));
+            }
+            assert_eq!(recv.try_recv().unwrap(), spectrum(1.0));
+            assert!(outbox.flush());
+            let next = recv.try_recv().ok().map(|s| s[0].norm());
+            assert_eq!(next.map(|x| (x * 1e4).round() / 1e4), received, "{}", backpressure);
+            assert_eq!(outbox.stats.snapshot().frames_dropped, dropped);
+        }
+        assert_eq!("coalesce".parse(), Ok(Backpressure::CoalesceByAveraging));
+        assert!("latest".parse::<Backpressure>().is_err());
+    }
+
+    #[test]
+    fn test_cross_spectrum() {
+        // a tone a quarter of the way up in each, B's a quarter turn behind,
+        // with noise of their own
+        let mut rng = 1u32;
+        let mut noise = || {
+            rng = rng.wrapping_mul(1664525).wrapping_add(1013904223);
+            (rng >> 8) as f32 / (1 << 24) as f32 - 0.5
+        };
+        let mut inputs = vec![Vec::new(), Vec::new()];
+        for n in 0..30000 {
+            let tone = Complex::from_polar(&0.5, &(2.0 * PI as f32 * n as f32 / 4.0));
+            inputs[0].push(tone + Complex::new(noise(), noise()));
+            inputs[1].push(tone * Complex::new(0.0, -1.0) + Complex::new(noise(), noise()));
+        }
+        let spectra: Vec<Vec<Vec<Complex<f32>>>> = inputs.into_iter().map(|input| {
+            SignalProcessor::builder(1000000).fft_rate_hz(1000).fft_len(1000).build()
+                                             .add_signal_buffer(input)
+        }).collect();
+
+        // B's come a spectrum late, and each pairs with the A before it
+        let mut cross = CrossSpectrum::new();
+        assert!(!cross.add(0, &spectra[0][0]));
+        for i in 1..spectra[0].len() {
+            assert!(!cross.add(0, &spectra[0][i]));
+            assert!(cross.add(1, &spectra[1][i - 1]));
         }
+        let phases = cross.phase_differences();
+        assert!((phases[250] - PI as f32 / 2.0).abs() < 0.05, "{}", phases[250]);
+        let mut coherence = cross.coherence();
+        assert!(coherence[250] > 0.95, "{}", coherence[250]);
+        coherence.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert!(coherence[500] < 0.3, "{}", coherence[500]);
+
+        // the last A doesn't go with a B of another length, and a new length
+        // starts again
+        let short = [Complex::new(1.0, 0.0); 4];
+        assert!(!cross.add(1, &short));
+        assert!(!cross.add(0, &short));
+        assert!(cross.add(1, &short));
+        assert_eq!(cross.phase_differences().len(), 4);
+    }
+
+    #[test]
+    fn test_process_pair() {
+        // the same samples in buffers of different sizes, some skipped for
+        // the FFT rate, still come out as the same spectra
+        let signal: Vec<Complex<i8>> = (0..400).map(|i| Complex::new((i % 101) as i8, 3))
+                                               .collect();
+        let params = Params { fft_len: 16, sample_rate_hz: 160, fft_rate_hz: 5, threads: 1 };
+        let (_wait_set, waker) = WaitSet::without_tty().unwrap();
+        let mut spectra = Vec::new();
+        let mut inputs = Vec::new();
+        for &buff_len in &[40, 24] {
+            let (send, recv) = sync_channel(64);
+            for buff in signal.chunks(buff_len) {
+                send.send(buff.to_vec()).unwrap();
+            }
+            let (spec_send, spec_recv) = sync_channel(64);
+            spectra.push(spec_recv);
+            inputs.push(PairedInput {
+                recv: recv,
+                send: spec_send,
+                waker: waker.try_clone().unwrap(),
+                stats: Arc::new(Stats::default()),
+                pools: Pools::new(BufferPool::new()),
+            });
+        }
+        let (b, a) = (inputs.pop().unwrap(), inputs.pop().unwrap());
+        thread::spawn(move || process_pair(a, b, Arc::new(Mutex::new(params)))).join().unwrap();
+        let a: Vec<_> = spectra[0].try_iter().collect();
+        let b: Vec<_> = spectra[1].try_iter().collect();
+        assert_eq!(a.len(), 12);
+        assert_eq!(a, b);
+        assert!(a[0] != a[1]);
+    }
+
+    #[test]
+    fn test_step_fft_rate() {
+        assert_eq!(step_fft_rate(10, 1), 15);
+        assert_eq!(step_fft_rate(10, -1), 5);
+        // rates given with --fft-rate between the steps
+        assert_eq!(step_fft_rate(12, 1), 15);
+        assert_eq!(step_fft_rate(12, -1), 10);
+        assert_eq!(step_fft_rate(100, 1), 100);
+        assert_eq!(step_fft_rate(1, -1), 1);
     }
 }
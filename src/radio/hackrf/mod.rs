@@ -1,14 +1,37 @@
-use std::sync::{Once, ONCE_INIT};
-use std::sync::mpsc::{channel, Sender, Receiver};
+use std::sync::{Arc, Once, ONCE_INIT};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread::{self, Thread};
+use std::time::Duration;
+use std::ffi::{CStr, CString};
 use std::ptr;
 use std::mem;
 use std::slice;
+#[cfg(feature = "futures")]
+use std::pin::Pin;
+#[cfg(feature = "futures")]
+use std::task::{Context, Poll};
+#[cfg(feature = "futures")]
+use futures::Stream;
 use libc::c_int;
 use num::Complex;
 
+use pool::BufferPool;
+use ring::{ring, Producer, PushError};
+#[cfg(feature = "futures")]
+use ring::Consumer;
+
+/// Transfers the callback can get ahead of the thread taking them by, past
+/// which they're dropped as overruns.
+const RX_BUFFERS: usize = 32;
+
+/// Longest the thread taking the transfers sleeps between looking, in case
+/// receiving has stopped and there's no callback to wake it.
+const IDLE_WAIT: Duration = Duration::from_millis(100);
+
 #[allow(dead_code, non_camel_case_types)]
 mod ffi {
-    use libc::{c_void, c_int};
+    use libc::{c_char, c_void, c_int};
 
     pub type hackrf_device = c_void;
     pub type callback = unsafe extern "C" fn(*mut Transfer) -> c_int;
@@ -40,14 +63,32 @@ mod ffi {
         pub tx_ctx: *mut c_void,
     }
 
+    #[repr(C)]
+    pub struct DeviceList {
+        pub serial_numbers: *mut *mut c_char,
+        pub usb_board_ids: *mut c_int,
+        pub usb_device_index: *mut c_int,
+        pub devicecount: c_int,
+        pub usb_devices: *mut *mut c_void,
+        pub usb_devicecount: c_int,
+    }
+
     #[link(name = "hackrf")]
     extern "C" {
         pub fn hackrf_init() -> Return;
         pub fn hackrf_exit() -> Return;
+        pub fn hackrf_device_list() -> *mut DeviceList;
+        pub fn hackrf_device_list_free(list: *mut DeviceList);
+        pub fn hackrf_usb_board_id_name(usb_board_id: c_int) -> *const c_char;
         pub fn hackrf_open(dev: *mut *mut hackrf_device) -> Return;
+        pub fn hackrf_open_by_serial(desired_serial_number: *const c_char,
+                                     dev: *mut *mut hackrf_device) -> Return;
         pub fn hackrf_close(dev: *mut hackrf_device) -> Return;
         pub fn hackrf_set_freq(dev: *mut hackrf_device, freq_hz: u64) -> Return;
         pub fn hackrf_set_sample_rate(dev: *mut hackrf_device, freq_hz: f64) -> Return;
+        pub fn hackrf_set_lna_gain(dev: *mut hackrf_device, value: u32) -> Return;
+        pub fn hackrf_set_vga_gain(dev: *mut hackrf_device, value: u32) -> Return;
+        pub fn hackrf_set_amp_enable(dev: *mut hackrf_device, value: u8) -> Return;
         pub fn hackrf_start_rx(dev: *mut hackrf_device, callback: callback,
                                rx_ctx: *mut c_void) -> Return;
         pub fn hackrf_stop_rx(dev: *mut hackrf_device) -> Return;
@@ -70,40 +111,137 @@ fn init() -> Result<(), ()> {
     }
 }
 
+/// Where the callback puts the samples of each transfer.
+struct Rx {
+    producer: Producer<Vec<Complex<i8>>>,
+    /// The thread taking them, to wake up, unless it's a task the ring wakes.
+    consumer: Option<Thread>,
+    pool: BufferPool<Complex<i8>>,
+    overruns: Arc<AtomicUsize>,
+}
+
 unsafe extern "C" fn rx_callback(transfer: *mut ffi::Transfer) -> c_int {
-    let sender: &Option<Sender<Vec<Complex<i8>>>> = mem::transmute((*transfer).rx_ctx);
+    let rx: &Option<Rx> = mem::transmute((*transfer).rx_ctx);
 
-    match sender {
-        &Some(ref rx_send) => {
+    match rx {
+        &Some(ref rx) => {
             assert_eq!((*transfer).valid_length & 0x01, 0);
-            let buffer = slice::from_raw_parts(
+            let mut buffer = rx.pool.take();
+            buffer.extend_from_slice(slice::from_raw_parts(
                 mem::transmute((*transfer).buffer),
                 (*transfer).valid_length as usize / 2
-            ).to_vec();
-            match rx_send.send(buffer) {
-                Ok(()) => 0,
-                Err(_) => -1,
+            ));
+            match rx.producer.push(buffer) {
+                Ok(()) => {
+                    if let Some(ref consumer) = rx.consumer {
+                        consumer.unpark();
+                    }
+                    0
+                }
+                Err(PushError::Full(buffer)) => {
+                    rx.overruns.fetch_add(1, Ordering::Relaxed);
+                    rx.pool.give(buffer);
+                    0
+                }
+                Err(PushError::Disconnected(_)) => -1,
             }
         },
         &None => -1,
     }
 }
 
+unsafe fn to_string(s: *const libc::c_char) -> Option<String> {
+    if s.is_null() {
+        return None;
+    }
+    Some(CStr::from_ptr(s).to_string_lossy().into_owned())
+}
+
+/// A connected HackRF, as libhackrf lists it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub board: String,
+    /// Older firmware doesn't report one.
+    pub serial: Option<String>,
+}
+
+/// The HackRFs connected over USB.
+pub fn list_devices() -> Result<Vec<Device>, ()> {
+    try!(init());
+
+    unsafe {
+        let list = ffi::hackrf_device_list();
+        if list.is_null() {
+            return Err(());
+        }
+        let mut devices = Vec::new();
+        for i in 0..(*list).devicecount.max(0) as isize {
+            let board_id = *(*list).usb_board_ids.offset(i);
+            devices.push(Device {
+                board: to_string(ffi::hackrf_usb_board_id_name(board_id))
+                           .unwrap_or_else(|| "HackRF".to_string()),
+                serial: to_string(*(*list).serial_numbers.offset(i)),
+            });
+        }
+        ffi::hackrf_device_list_free(list);
+        Ok(devices)
+    }
+}
+
+pub const LNA_GAINS_DB: [u32; 6] = [0, 8, 16, 24, 32, 40];
+pub const VGA_GAIN_MAX_DB: u32 = 62;
 
 pub struct HackRF {
     dev: *mut ffi::hackrf_device,
-    rx: Option<Sender<Vec<Complex<i8>>>>,
+    rx: Option<Rx>,
+    /// The samples are received into these, which outlast reopening.
+    pool: BufferPool<Complex<i8>>,
+    /// Transfers dropped for the samples not being taken fast enough.
+    overruns: Arc<AtomicUsize>,
+    /// Of the one asked for, when there's more than one connected.
+    serial: Option<CString>,
 }
 
 impl HackRF {
     pub fn open() -> Result<Self, ()> {
+        HackRF::open_device(None)
+    }
+
+    /// Opens the one with serial number `serial`, as `list_devices` gives it.
+    pub fn open_serial(serial: &str) -> Result<Self, ()> {
+        HackRF::open_device(Some(try!(CString::new(serial).map_err(|_| ()))))
+    }
+
+    fn open_device(serial: Option<CString>) -> Result<Self, ()> {
         try!(init());
 
         let mut dev: *mut ffi::hackrf_device = ptr::null_mut();
+        match open_raw(serial.as_ref(), &mut dev) {
+            ffi::Return::SUCCESS => Ok(HackRF {
+                dev: dev,
+                rx: None,
+                pool: BufferPool::new(),
+                overruns: Arc::new(AtomicUsize::new(0)),
+                serial: serial,
+            }),
+            _ => Err(()),
+        }
+    }
+
+    /// Closes the device and opens it again, to recover from it no longer
+    /// streaming. Its settings need setting again after, and receiving
+    /// restarting.
+    pub fn reopen(&mut self) -> Result<(), ()> {
         unsafe {
-            match ffi::hackrf_open(&mut dev) {
-                ffi::Return::SUCCESS => Ok(HackRF{dev: dev, rx: None}),
-                _ => Err(()),
+            ffi::hackrf_close(self.dev);
+        }
+        self.dev = ptr::null_mut();
+        self.rx = None;
+        match open_raw(self.serial.as_ref(), &mut self.dev) {
+            ffi::Return::SUCCESS => Ok(()),
+            _ => {
+                self.dev = ptr::null_mut();
+                Err(())
             }
         }
     }
@@ -126,17 +264,95 @@ impl HackRF {
         }
     }
 
+    /// Sets the IF (LNA) gain. Valid values are 0-40 dB in 8 dB steps.
+    pub fn set_lna_gain(&mut self, gain_db: u32) -> Result<(), ()> {
+        unsafe {
+            match ffi::hackrf_set_lna_gain(self.dev, gain_db) {
+                ffi::Return::SUCCESS => Ok(()),
+                _ => Err(()),
+            }
+        }
+    }
+
+    /// Sets the baseband (VGA) gain. Valid values are 0-62 dB in 2 dB steps.
+    pub fn set_vga_gain(&mut self, gain_db: u32) -> Result<(), ()> {
+        unsafe {
+            match ffi::hackrf_set_vga_gain(self.dev, gain_db) {
+                ffi::Return::SUCCESS => Ok(()),
+                _ => Err(()),
+            }
+        }
+    }
+
+    pub fn set_amp_enable(&mut self, enable: bool) -> Result<(), ()> {
+        unsafe {
+            match ffi::hackrf_set_amp_enable(self.dev, enable as u8) {
+                ffi::Return::SUCCESS => Ok(()),
+                _ => Err(()),
+            }
+        }
+    }
+
+    /// Starts receiving, the samples of each transfer coming in a buffer of
+    /// their own. Any that aren't taken in time are dropped and counted, so
+    /// the callback never waits.
     pub fn start_rx(&mut self) -> Receiver<Vec<Complex<i8>>> {
-        let (rx_send, rx_rec) = channel::<Vec<Complex<i8>>>();
-        self.rx = Some(rx_send);
+        let (producer, consumer) = ring(RX_BUFFERS);
+        // the ring is where the transfers queue up, so only one waits here
+        let (rx_send, rx_rec) = sync_channel(1);
+        let forwarder = thread::spawn(move || loop {
+            // what was pushed before the callback's end went is still there
+            let stopped = consumer.is_disconnected();
+            match consumer.pop() {
+                Some(buffer) => if rx_send.send(buffer).is_err() {
+                    return;
+                },
+                None if stopped => return,
+                None => thread::park_timeout(IDLE_WAIT),
+            }
+        });
+        self.start_rx_into(producer, Some(forwarder.thread().clone()));
+        return rx_rec;
+    }
+
+    /// `start_rx` for an async task, the samples coming as a stream straight
+    /// from the callback, with no thread of their own in between. It ends
+    /// when receiving next starts again, or the radio is dropped.
+    #[cfg(feature = "futures")]
+    pub fn start_rx_stream(&mut self) -> RxStream {
+        let (producer, consumer) = ring(RX_BUFFERS);
+        self.start_rx_into(producer, None);
+        RxStream(consumer)
+    }
+
+    fn start_rx_into(&mut self, producer: Producer<Vec<Complex<i8>>>, consumer: Option<Thread>) {
+        self.rx = Some(Rx {
+            producer: producer,
+            consumer: consumer,
+            pool: self.pool.clone(),
+            overruns: self.overruns.clone(),
+        });
         unsafe {
             // TODO this can return an error
             ffi::hackrf_start_rx(self.dev, rx_callback, mem::transmute(&self.rx));
         };
-        return rx_rec;
+    }
+
+    /// The buffers the samples come in, for handing them back once used.
+    pub fn buffer_pool(&self) -> BufferPool<Complex<i8>> {
+        self.pool.clone()
+    }
+
+    /// Transfers dropped so far, since the radio was opened, for the samples
+    /// not being taken fast enough.
+    pub fn overruns(&self) -> usize {
+        self.overruns.load(Ordering::Relaxed)
     }
 
     pub fn stop_rx(&mut self) -> Result<(), ()> {
+        if self.dev.is_null() {
+            return Ok(());
+        }
         unsafe {
             match ffi::hackrf_stop_rx(self.dev) {
                 ffi::Return::SUCCESS => {
@@ -149,8 +365,36 @@ impl HackRF {
     }
 }
 
+/// The samples `HackRF::start_rx_stream` receives, in a buffer for each
+/// transfer, for handing back to `buffer_pool` once used.
+#[cfg(feature = "futures")]
+pub struct RxStream(Consumer<Vec<Complex<i8>>>);
+
+#[cfg(feature = "futures")]
+impl Stream for RxStream {
+    type Item = Vec<Complex<i8>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Vec<Complex<i8>>>> {
+        self.0.poll_pop(cx)
+    }
+}
+
+/// The first HackRF libhackrf finds, or the one with `serial`.
+fn open_raw(serial: Option<&CString>, dev: &mut *mut ffi::hackrf_device) -> ffi::Return {
+    unsafe {
+        match serial {
+            Some(serial) => ffi::hackrf_open_by_serial(serial.as_ptr(), dev),
+            None => ffi::hackrf_open(dev),
+        }
+    }
+}
+
 impl Drop for HackRF {
     fn drop(&mut self) {
+        // a failed reopen leaves nothing to close
+        if self.dev.is_null() {
+            return;
+        }
         unsafe {
             match ffi::hackrf_close(self.dev) {
                 ffi::Return::SUCCESS => (),
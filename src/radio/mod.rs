@@ -1 +1,2 @@
 pub mod hackrf;
+pub mod synth;
@@ -0,0 +1,273 @@
+//! A made-up radio: tones, chirps, noise and AM and FM carriers, added up
+//! at a chosen sample rate. It's for trying the program without hardware,
+//! and for tests of the whole pipeline, as what it makes comes out the
+//! same every time, noise and all.
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use num::Complex;
+
+use units::{parse_duration, parse_frequency};
+
+/// Samples made and sent on at a time.
+const BLOCK_LEN: usize = 65536;
+
+/// Levels, in dB below full scale, of what isn't given one.
+const DEFAULT_LEVEL_DB: f64 = -10.0;
+const DEFAULT_NOISE_DB: f64 = -30.0;
+
+/// How deep AM carriers are modulated.
+const AM_DEPTH: f64 = 0.5;
+
+/// What plain `synth` makes: something of each, spread over 2 MHz.
+const DEMO: &'static str = "tone=200k,am=-500k,fm=650k:25k,chirp=-900k:-700k:2s,noise";
+
+/// The seed of the noise, the same each time.
+const SEED: u64 = 0x853c49e6748fea9b;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Kind {
+    Tone { offset_hz: f64 },
+    /// Sweeping from one frequency to another, again every period.
+    Chirp { from_hz: f64, to_hz: f64, period_secs: f64 },
+    /// White, across the whole span.
+    Noise,
+    /// A carrier with a tone on it.
+    Am { offset_hz: f64, tone_hz: f64 },
+    Fm { offset_hz: f64, deviation_hz: f64, tone_hz: f64 },
+}
+
+/// One of the signals made, with frequencies relative to the middle of the
+/// span.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signal {
+    pub kind: Kind,
+    /// At its peak, in dB below full scale.
+    pub level_db: f64,
+}
+
+/// The signals of a list like `tone=100k,fm=300k:5k,noise@-40`, each a
+/// kind with its arguments, in Hz or seconds, and maybe `@` its level. An
+/// empty one is the demo.
+pub fn parse(list: &str) -> Result<Vec<Signal>, String> {
+    let list = if list.is_empty() { DEMO } else { list };
+    list.split(',').map(|item| {
+        let mut parts = item.splitn(2, '@');
+        let body = parts.next().unwrap_or("");
+        let level_db = match parts.next().map(|db| db.parse::<f64>()) {
+            Some(Ok(db)) => Some(db),
+            Some(Err(_)) => return Err(format!("'{}' has a bad level", item)),
+            None => None,
+        };
+        let mut parts = body.splitn(2, '=');
+        let name = parts.next().unwrap_or("");
+        let args: Vec<&str> = parts.next().map_or(vec![], |args| args.split(':').collect());
+        let hz = |i: usize, default: Option<f64>| match args.get(i) {
+            Some(arg) => parse_frequency(arg).ok_or_else(|| format!("'{}' has a bad frequency",
+                                                                   item)),
+            None => default.ok_or_else(|| format!("'{}' needs more arguments", item)),
+        };
+        let max_args = match name {
+            "noise" => 0,
+            "tone" => 1,
+            "am" => 2,
+            "chirp" | "fm" => 3,
+            _ => return Err(format!("Unknown signal '{}', expected tone, chirp, noise, am or fm",
+                                    item)),
+        };
+        if args.len() > max_args {
+            return Err(format!("'{}' has too many arguments", item));
+        }
+        let kind = match name {
+            "tone" => Kind::Tone { offset_hz: try!(hz(0, None)) },
+            "chirp" => Kind::Chirp {
+                from_hz: try!(hz(0, None)),
+                to_hz: try!(hz(1, None)),
+                period_secs: match args.get(2).map(|secs| parse_duration(secs)) {
+                    Some(Some(period)) if period > Duration::from_secs(0) => {
+                        period.as_secs() as f64 + period.subsec_nanos() as f64 / 1e9
+                    }
+                    Some(_) => return Err(format!("'{}' has a bad period", item)),
+                    None => 1.0,
+                },
+            },
+            "noise" => Kind::Noise,
+            "am" => Kind::Am { offset_hz: try!(hz(0, None)), tone_hz: try!(hz(1, Some(1e3))) },
+            "fm" => Kind::Fm {
+                offset_hz: try!(hz(0, None)),
+                deviation_hz: try!(hz(1, Some(5e3))),
+                tone_hz: try!(hz(2, Some(1e3))),
+            },
+            _ => unreachable!(),
+        };
+        let default_db = if kind == Kind::Noise { DEFAULT_NOISE_DB } else { DEFAULT_LEVEL_DB };
+        Ok(Signal { kind: kind, level_db: level_db.unwrap_or(default_db) })
+    }).collect()
+}
+
+/// Makes the samples of some signals, in order.
+pub struct Synth {
+    signals: Vec<Signal>,
+    /// Of each signal's carrier, in cycles.
+    phases: Vec<f64>,
+    sample_rate_hz: f64,
+    /// Samples made so far.
+    n: u64,
+    rng: u64,
+}
+
+impl Synth {
+    pub fn new(signals: Vec<Signal>, sample_rate_hz: f64) -> Self {
+        Synth {
+            phases: vec![0.0; signals.len()],
+            signals: signals,
+            sample_rate_hz: sample_rate_hz,
+            n: 0,
+            rng: SEED,
+        }
+    }
+
+    pub fn next_block(&mut self, len: usize) -> Vec<Complex<i8>> {
+        let mut block = Vec::with_capacity(len);
+        for _ in 0..len {
+            let t = self.n as f64 / self.sample_rate_hz;
+            let mut sum = Complex::new(0.0, 0.0);
+            for (signal, phase) in self.signals.iter().zip(self.phases.iter_mut()) {
+                let amplitude = 10f64.powf(signal.level_db / 20.0);
+                let carrier = || Complex::from_polar(&amplitude, &(2.0 * PI * *phase));
+                let freq_hz = match signal.kind {
+                    Kind::Tone { offset_hz } => {
+                        sum += carrier();
+                        offset_hz
+                    }
+                    Kind::Chirp { from_hz, to_hz, period_secs } => {
+                        sum += carrier();
+                        from_hz + (to_hz - from_hz) * (t / period_secs).fract()
+                    }
+                    Kind::Noise => {
+                        let (i, q) = gaussians(&mut self.rng);
+                        sum += Complex::new(i, q) * (amplitude / 2f64.sqrt());
+                        0.0
+                    }
+                    Kind::Am { offset_hz, tone_hz } => {
+                        let envelope = (1.0 + AM_DEPTH * (2.0 * PI * tone_hz * t).cos()) /
+                                       (1.0 + AM_DEPTH);
+                        sum += carrier() * envelope;
+                        offset_hz
+                    }
+                    Kind::Fm { offset_hz, deviation_hz, tone_hz } => {
+                        sum += carrier();
+                        offset_hz + deviation_hz * (2.0 * PI * tone_hz * t).cos()
+                    }
+                };
+                *phase = (*phase + freq_hz / self.sample_rate_hz).fract();
+            }
+            // out of range casts saturate
+            block.push(Complex::new((sum.re * 127.0).round() as i8,
+                                    (sum.im * 127.0).round() as i8));
+            self.n += 1;
+        }
+        block
+    }
+}
+
+/// Two independent normally distributed numbers, by Box-Muller, from an
+/// xorshift generator.
+fn gaussians(rng: &mut u64) -> (f64, f64) {
+    let mut uniform = || {
+        *rng ^= *rng >> 12;
+        *rng ^= *rng << 25;
+        *rng ^= *rng >> 27;
+        // in (0, 1], as it's logged
+        ((rng.wrapping_mul(0x2545f4914f6cdd1d) >> 11) + 1) as f64 / (1u64 << 53) as f64
+    };
+    let r = (-2.0 * uniform().ln()).sqrt();
+    let theta = 2.0 * PI * uniform();
+    (r * theta.cos(), r * theta.sin())
+}
+
+/// Makes samples at their sample rate, as the radio would send them, until
+/// dropped.
+pub struct Generator {
+    stop: Arc<AtomicBool>,
+}
+
+impl Generator {
+    pub fn start(signals: Vec<Signal>, sample_rate_hz: f64)
+                 -> (Generator, Receiver<Vec<Complex<i8>>>) {
+        let (send, recv) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        thread::spawn(move || {
+            let mut synth = Synth::new(signals, sample_rate_hz);
+            let start = Instant::now();
+            while !thread_stop.load(Ordering::Relaxed) {
+                if send.send(synth.next_block(BLOCK_LEN)).is_err() {
+                    return;
+                }
+                let due_ms = synth.n as f64 / sample_rate_hz * 1000.0;
+                let due = Duration::from_millis(due_ms as u64);
+                if let Some(wait) = due.checked_sub(start.elapsed()) {
+                    thread::sleep(wait);
+                }
+            }
+        });
+        (Generator { stop: stop }, recv)
+    }
+}
+
+impl Drop for Generator {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use export::shifted_powers_db;
+    use processing::SignalProcessor;
+    use super::{parse, Kind, Signal, Synth};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("tone=100k@-20,noise").unwrap(), vec![
+            Signal { kind: Kind::Tone { offset_hz: 100e3 }, level_db: -20.0 },
+            Signal { kind: Kind::Noise, level_db: -30.0 },
+        ]);
+        assert_eq!(parse("fm=-1M:10k").unwrap()[0].kind,
+                   Kind::Fm { offset_hz: -1e6, deviation_hz: 10e3, tone_hz: 1e3 });
+        assert_eq!(parse("chirp=1k:2k:0.5s").unwrap()[0].kind,
+                   Kind::Chirp { from_hz: 1e3, to_hz: 2e3, period_secs: 0.5 });
+        assert_eq!(parse("").unwrap().len(), 5);
+        for bad in &["tone", "tone=1k:2k:3k", "hum=50", "noise@loud", "chirp=1k"] {
+            assert!(parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_pipeline() {
+        // a tone a quarter of the way up, well above the noise, through the
+        // FFTs as the display gets them
+        let signals = parse("tone=250k,noise@-40").unwrap();
+        let mut synth = Synth::new(signals.clone(), 1e6);
+        let samples = synth.next_block(4000);
+        assert_eq!(Synth::new(signals, 1e6).next_block(4000), samples);
+        let mut processor = SignalProcessor::builder(1000000).fft_rate_hz(1000).fft_len(1000)
+                                                             .build();
+        let spectra = processor.add_signal_buffer(samples);
+        assert_eq!(spectra.len(), 4);
+        for spec in &spectra {
+            let powers = shifted_powers_db(spec);
+            let peak = (0..powers.len()).max_by(|&a, &b| powers[a].partial_cmp(&powers[b])
+                                                                  .unwrap()).unwrap();
+            assert_eq!(peak, 750);
+            let mut sorted = powers.clone();
+            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert!(powers[peak] - sorted[500] > 20.0, "{:?}", &powers[740..760]);
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//! Reading the station's name and radiotext from RDS, the data an FM
+//! broadcast carries on a subcarrier at 57 kHz, from the demodulated
+//! multiplex.
+//!
+//! The subcarrier is mixed down to 0 Hz and filtered, and its phase followed
+//! with a Costas loop. The bits are biphase symbols at 1187.5 a second,
+//! differentially coded so it doesn't matter which way up the loop locks.
+//! They come in blocks of 16 bits and a checkword, which also tells which
+//! block of its group of 4 each is, and groups 0A and 0B carry the name, and
+//! 2A and 2B the radiotext, a few characters at a time.
+
+use std::f32::consts::PI;
+use num::Complex;
+
+use demod::low_pass_taps;
+
+const SUBCARRIER_HZ: f64 = 57e3;
+const BIT_RATE_HZ: f64 = 1187.5;
+
+/// The subcarrier is brought to this many samples a bit, to find the bits'
+/// timing among.
+const SAMPLES_PER_BIT: usize = 16;
+
+/// Passed either side of the subcarrier, where nearly all of its power is,
+/// and the length of the filter, in taps per width at its rate.
+const BANDWIDTH_HZ: f64 = 2.4e3;
+const TAPS_PER_WIDTH: f64 = 26.0;
+
+/// How quickly the Costas loop moves the phase, per sample and per radian
+/// off.
+const LOOP_GAIN: f32 = 0.02;
+
+/// How quickly the bits' timing follows, per bit, slow enough not to be
+/// thrown by a noisy one.
+const TIMING_SMOOTHING: f32 = 0.02;
+
+/// Block length, checkword included, and the checkword's generator,
+/// x^10 + x^8 + x^7 + x^5 + x^4 + x^3 + 1.
+const BLOCK_BITS: usize = 26;
+const CHECK_POLY: u32 = 0x5b9;
+
+/// The offset words added to the checkwords of blocks A, B, C, C' and D,
+/// which are what's left dividing a whole block by the generator.
+const OFFSETS: [u32; 5] = [0x0fc, 0x198, 0x168, 0x350, 0x1b4];
+
+/// Bad blocks in a row before looking for the start of a block again.
+const MAX_BAD_BLOCKS: u32 = 10;
+
+/// Takes the multiplex at `mpx_rate_hz`, full scale at 75 kHz deviation.
+pub struct Decoder {
+    /// Mixes the subcarrier down to 0 Hz, turning by `step` every sample.
+    phasor: Complex<f32>,
+    step: Complex<f32>,
+    /// Samples are averaged `decimation` at a time before the filter, as in
+    /// the demodulator.
+    decimation: usize,
+    sum: Complex<f32>,
+    num_summed: usize,
+    taps: Vec<f32>,
+    /// The newest decimated samples, as a ring starting at `next`.
+    recent: Vec<Complex<f32>>,
+    next: usize,
+    /// Filtered samples per sample at `SAMPLES_PER_BIT`, where the next one
+    /// falls after the last filtered sample, and that sample.
+    resample_step: f64,
+    resample_at: f64,
+    last: Complex<f32>,
+    /// The Costas loop's phase, and how much it turns by each sample.
+    phase: f32,
+    turn: f32,
+    /// The last two bits' worth of symbol samples, oldest first, and how many
+    /// have come since the last bit.
+    window: Vec<f32>,
+    num_new: usize,
+    /// How strongly a bit starts at each sample of the window, on average.
+    strengths: [f32; SAMPLES_PER_BIT],
+    last_symbol: bool,
+    blocks: Blocks,
+}
+
+impl Decoder {
+    pub fn new(mpx_rate_hz: f64) -> Self {
+        let symbol_rate_hz = SAMPLES_PER_BIT as f64 * BIT_RATE_HZ;
+        let decimation = ((mpx_rate_hz / symbol_rate_hz) as usize).max(1);
+        let filter_rate_hz = mpx_rate_hz / decimation as f64;
+        let turn = -2.0 * PI * (SUBCARRIER_HZ / mpx_rate_hz) as f32;
+        let num_taps = (TAPS_PER_WIDTH * filter_rate_hz / (2.0 * BANDWIDTH_HZ)) as usize | 1;
+        let taps = low_pass_taps(num_taps, (BANDWIDTH_HZ / filter_rate_hz) as f32);
+        Decoder {
+            phasor: Complex::new(1.0, 0.0),
+            step: Complex::new(turn.cos(), turn.sin()),
+            decimation: decimation,
+            sum: Complex::new(0.0, 0.0),
+            num_summed: 0,
+            recent: vec![Complex::new(0.0, 0.0); taps.len()],
+            taps: taps,
+            next: 0,
+            resample_step: filter_rate_hz / symbol_rate_hz,
+            resample_at: 0.0,
+            last: Complex::new(0.0, 0.0),
+            phase: 0.0,
+            turn: 0.0,
+            window: vec![0.0; 2 * SAMPLES_PER_BIT],
+            num_new: 0,
+            strengths: [0.0; SAMPLES_PER_BIT],
+            last_symbol: false,
+            blocks: Blocks::new(),
+        }
+    }
+
+    pub fn add_mpx(&mut self, mpx: f32) {
+        self.sum = self.sum + self.phasor * mpx;
+        self.phasor = self.phasor * self.step;
+        self.num_summed += 1;
+        if self.num_summed < self.decimation {
+            return;
+        }
+        // rounding would otherwise let it drift away from the unit circle
+        self.phasor = self.phasor / self.phasor.norm();
+        let sample = self.sum / self.decimation as f32;
+        self.sum = Complex::new(0.0, 0.0);
+        self.num_summed = 0;
+
+        self.recent[self.next] = sample;
+        self.next = (self.next + 1) % self.recent.len();
+        let (older, newer) = self.recent.split_at(self.next);
+        let filtered = newer.iter().chain(older).zip(&self.taps)
+            .fold(Complex::new(0.0, 0.0), |acc, (&x, &tap)| acc + x * tap);
+        while self.resample_at < 1.0 {
+            let t = self.resample_at as f32;
+            let sample = self.last + (filtered - self.last) * t;
+            self.add_symbol_sample(sample);
+            self.resample_at += self.resample_step;
+        }
+        self.resample_at -= 1.0;
+        self.last = filtered;
+    }
+
+    /// The station's name, as much of it as has come.
+    pub fn station_name(&self) -> Option<String> {
+        self.blocks.station_name()
+    }
+
+    /// The radiotext, as much of it as has come.
+    pub fn radiotext(&self) -> Option<String> {
+        self.blocks.radiotext()
+    }
+
+    fn add_symbol_sample(&mut self, sample: Complex<f32>) {
+        let sample = sample * Complex::new(self.phase.cos(), -self.phase.sin());
+        // sin(2 * off) / 2, the same whichever way up the symbol is and
+        // whatever the level
+        let off = sample.re * sample.im / (sample.norm_sqr() + 1e-12);
+        self.phase += self.turn + LOOP_GAIN * off;
+        self.turn += LOOP_GAIN * LOOP_GAIN / 4.0 * off;
+        self.phase %= 2.0 * PI;
+
+        self.window.remove(0);
+        self.window.push(sample.re);
+        self.num_new += 1;
+        if self.num_new < SAMPLES_PER_BIT {
+            return;
+        }
+        self.num_new = 0;
+        // a symbol is a half bit one way then a half bit the other
+        let half = SAMPLES_PER_BIT / 2;
+        let mut best = (0, 0.0);
+        for start in 0..SAMPLES_PER_BIT {
+            let first: f32 = self.window[start..start + half].iter().sum();
+            let second: f32 = self.window[start + half..start + SAMPLES_PER_BIT].iter().sum();
+            let symbol = first - second;
+            self.strengths[start] += (symbol.abs() - self.strengths[start]) * TIMING_SMOOTHING;
+            if start == 0 || self.strengths[start] > self.strengths[best.0] {
+                best = (start, symbol);
+            }
+        }
+        let symbol = best.1 > 0.0;
+        self.blocks.add_bit(symbol != self.last_symbol);
+        self.last_symbol = symbol;
+    }
+}
+
+/// Finds the blocks in the bits and puts together the name and radiotext
+/// from the groups.
+struct Blocks {
+    /// The last 26 bits, newest in the lowest bit.
+    bits: u32,
+    num_bits: usize,
+    /// Once a block's been found, the next is looked for every 26 bits.
+    synced: bool,
+    bad_blocks: u32,
+    /// Where in the group the next block is, and the data of the group's
+    /// blocks so far, `None` if they were bad.
+    position: usize,
+    group: [Option<u16>; 4],
+    name: [u8; 8],
+    name_seen: bool,
+    text: [u8; 64],
+    text_seen: bool,
+    /// Flips when the station starts a new radiotext.
+    text_flag: Option<bool>,
+}
+
+impl Blocks {
+    fn new() -> Self {
+        Blocks {
+            bits: 0,
+            num_bits: 0,
+            synced: false,
+            bad_blocks: 0,
+            position: 0,
+            group: [None; 4],
+            name: [b' '; 8],
+            name_seen: false,
+            text: [b' '; 64],
+            text_seen: false,
+            text_flag: None,
+        }
+    }
+
+    fn add_bit(&mut self, bit: bool) {
+        self.bits = ((self.bits << 1) | bit as u32) & ((1 << BLOCK_BITS) - 1);
+        self.num_bits += 1;
+        if self.num_bits < BLOCK_BITS {
+            return;
+        }
+        let offset = OFFSETS.iter().position(|&offset| offset == syndrome(self.bits));
+        if !self.synced {
+            if let Some(offset) = offset {
+                self.synced = true;
+                self.bad_blocks = 0;
+                self.group = [None; 4];
+                self.add_block(offset);
+            }
+            return;
+        }
+        self.num_bits = 0;
+        match offset {
+            Some(offset) if block_position(offset) == self.position => {
+                self.bad_blocks = 0;
+                self.add_block(offset);
+            }
+            _ => {
+                self.bad_blocks += 1;
+                if self.bad_blocks == MAX_BAD_BLOCKS {
+                    self.synced = false;
+                } else {
+                    self.group[self.position] = None;
+                    self.end_block();
+                }
+            }
+        }
+    }
+
+    fn add_block(&mut self, offset: usize) {
+        self.num_bits = 0;
+        self.position = block_position(offset);
+        self.group[self.position] = Some((self.bits >> 10) as u16);
+        self.end_block();
+    }
+
+    fn end_block(&mut self) {
+        if self.position == 3 {
+            self.decode_group();
+            self.group = [None; 4];
+        }
+        self.position = (self.position + 1) % 4;
+    }
+
+    fn decode_group(&mut self) {
+        let b = match self.group[1] {
+            Some(b) => b,
+            None => return,
+        };
+        let version_b = b & 0x800 != 0;
+        let (c, d) = (self.group[2], self.group[3]);
+        match b >> 12 {
+            0 => if let Some(d) = d {
+                let at = 2 * (b & 0x3) as usize;
+                self.name[at] = (d >> 8) as u8;
+                self.name[at + 1] = d as u8;
+                self.name_seen = true;
+            },
+            2 => {
+                let flag = b & 0x10 != 0;
+                if self.text_flag != Some(flag) {
+                    self.text = [b' '; 64];
+                    self.text_flag = Some(flag);
+                }
+                let segment = (b & 0xf) as usize;
+                let chars: Vec<u16> = match (version_b, c, d) {
+                    (false, Some(c), Some(d)) => vec![c, d],
+                    (true, _, Some(d)) => vec![d],
+                    _ => return,
+                };
+                let at = 2 * chars.len() * segment;
+                for (i, word) in chars.iter().enumerate() {
+                    self.text[at + 2 * i] = (word >> 8) as u8;
+                    self.text[at + 2 * i + 1] = *word as u8;
+                }
+                self.text_seen = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn station_name(&self) -> Option<String> {
+        if self.name_seen { printable(&self.name) } else { None }
+    }
+
+    fn radiotext(&self) -> Option<String> {
+        if !self.text_seen {
+            return None;
+        }
+        // a carriage return ends a text shorter than 64
+        let end = self.text.iter().position(|&c| c == b'\r').unwrap_or(self.text.len());
+        printable(&self.text[..end])
+    }
+}
+
+/// Where in its group a block with its checkword offset by `OFFSETS[offset]`
+/// goes. C' takes C's place in version B groups.
+fn block_position(offset: usize) -> usize {
+    [0, 1, 2, 2, 3][offset]
+}
+
+/// What's left dividing a block by the checkword's generator.
+fn syndrome(block: u32) -> u32 {
+    let mut left = block;
+    for bit in (10..BLOCK_BITS).rev() {
+        if left & (1 << bit) != 0 {
+            left ^= CHECK_POLY << (bit - 10);
+        }
+    }
+    left
+}
+
+/// RDS characters as text, the ASCII ones as they are and the rest of its
+/// character set as `?`, trimmed, or `None` if there's nothing left.
+fn printable(chars: &[u8]) -> Option<String> {
+    let text: String = chars.iter()
+        .map(|&c| if c >= 0x20 && c < 0x7f { c as char } else { '?' })
+        .collect();
+    let text = text.trim();
+    if text.is_empty() { None } else { Some(text.to_string()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+    use super::{syndrome, Decoder, BIT_RATE_HZ, OFFSETS};
+
+    /// The bits of a group with blocks `blocks`, checkwords and all.
+    fn group_bits(blocks: [u16; 4]) -> Vec<bool> {
+        let mut bits = Vec::new();
+        for (i, &data) in blocks.iter().enumerate() {
+            let offset = OFFSETS[[0, 1, 2, 4][i]];
+            let block = (data as u32) << 10;
+            let block = block | (syndrome(block) ^ offset);
+            bits.extend((0..26).rev().map(|bit| block & (1 << bit) != 0));
+        }
+        bits
+    }
+
+    #[test]
+    fn test_decode() {
+        let name = b"TEST FM ";
+        let text = b"HELLO WORLD\r";
+        let mut bits = Vec::new();
+        for _ in 0..3 {
+            for segment in 0..4 {
+                let chars = ((name[2 * segment] as u16) << 8) | name[2 * segment + 1] as u16;
+                bits.extend(group_bits([0x1234, segment as u16, 0xe0cd, chars]));
+            }
+            for segment in 0..3 {
+                let word = |i: usize| ((text[4 * segment + i] as u16) << 8)
+                                          | text[4 * segment + i + 1] as u16;
+                bits.extend(group_bits([0x1234, 0x2000 | segment as u16, word(0), word(2)]));
+            }
+        }
+
+        // the multiplex at 250 kHz, with audio, the pilot and the subcarrier
+        // at an odd phase, biphase coded
+        let mpx_rate_hz = 250e3;
+        let mut coded = vec![false];
+        for &bit in &bits {
+            let last = *coded.last().unwrap();
+            coded.push(last != bit);
+        }
+        let num_samples = (bits.len() as f64 / BIT_RATE_HZ * mpx_rate_hz) as usize;
+        let mut decoder = Decoder::new(mpx_rate_hz);
+        for i in 0..num_samples {
+            let t = i as f64 / mpx_rate_hz;
+            let at = t * BIT_RATE_HZ;
+            let first_half = at.fract() < 0.5;
+            let symbol = if coded[at as usize + 1] == first_half { 1.0 } else { -1.0 };
+            let mpx = 0.5 * (2.0 * PI * 1e3 * t).sin() + 0.1 * (2.0 * PI * 19e3 * t).sin()
+                      + 0.05 * symbol * (2.0 * PI * 57e3 * t + 1.0).cos();
+            decoder.add_mpx(mpx as f32);
+        }
+        assert_eq!(decoder.station_name(), Some("TEST FM".to_string()));
+        assert_eq!(decoder.radiotext(), Some("HELLO WORLD".to_string()));
+    }
+}
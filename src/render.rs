@@ -0,0 +1,189 @@
+//! Drawing the waterfall on a background thread.
+//!
+//! Zooming, scrolling back and changing the palette or the row scaling all
+//! redraw every visible row of the waterfall from the history. The `Renderer`
+//! does that on its own thread and hands back the finished grids, and until
+//! they arrive the UI keeps showing the last ones, so it never waits on a
+//! redraw.
+
+use std::borrow::Cow;
+use std::cmp::{max, min};
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+use cells::Widget;
+use drawing::{draw_waterfall, draw_waterfall_ascii, draw_waterfall_braille, normalize_row};
+use history::Row;
+use kitty::{Image, SPECTRA_PER_ROW};
+use palette::Palette;
+use wait::Waker;
+
+/// How the waterfall is drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Style {
+    pub palette: Palette,
+    pub ascii: bool,
+    pub braille: bool,
+    /// Leaves the grids empty for images to go under.
+    pub kitty: bool,
+    pub normalize_rows: bool,
+    /// Puts the newest spectrum at the bottom, the waterfall scrolling up.
+    pub newest_at_bottom: bool,
+}
+
+/// A region of the history stretched over the whole waterfall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Zoom {
+    /// First and last bin of the region.
+    pub bins: (usize, usize),
+    /// Spectra, and bins in each, to stretch the region to.
+    pub num_spectra: usize,
+    pub width: usize,
+}
+
+/// Everything needed to draw the waterfall of one pane.
+pub struct Job {
+    pub pane: usize,
+    /// Spectra to draw, newest first. With a zoom, those of its region.
+    pub rows: Vec<Arc<Row>>,
+    pub zoom: Option<Zoom>,
+    /// Columns of the waterfall of every band, and the rows of each.
+    pub cols: usize,
+    pub band_rows: Vec<usize>,
+    /// Spectra drawn in each row of cells.
+    pub spectra_per_row: usize,
+    pub style: Style,
+}
+
+/// Draws the waterfall of each band of `job`.
+pub fn render(job: &Job) -> Vec<Widget> {
+    let rows = visible_rows(job);
+    job.band_rows.iter().enumerate().map(|(i, &band_rows)| {
+        let mut grid = Widget::new(job.cols, band_rows);
+        let spectra = band_spectra(job, &rows, i).into_iter();
+        if job.style.kitty {
+            // left empty, the images go under it
+        } else if job.style.ascii {
+            draw_waterfall_ascii(&mut grid, spectra);
+        } else if job.style.braille {
+            draw_waterfall_braille(&mut grid, spectra, job.style.palette);
+        } else {
+            draw_waterfall(&mut grid, spectra, job.style.palette);
+        }
+        grid
+    }).collect()
+}
+
+/// Draws the waterfall of each band of `job` as an image, for the kitty
+/// graphics protocol.
+pub fn render_images(job: &Job) -> Vec<Image> {
+    let rows = visible_rows(job);
+    job.band_rows.iter().enumerate().map(|(i, &band_rows)| {
+        Image::from_spectra(band_spectra(job, &rows, i).into_iter(), 2 * job.cols,
+                            band_rows * SPECTRA_PER_ROW, job.style.palette)
+    }).collect()
+}
+
+/// The spectra of `job` as they're drawn, stretched to its zoom and
+/// rescaled, newest first.
+fn visible_rows<'a>(job: &'a Job) -> Vec<Cow<'a, [f32]>> {
+    let visible: Vec<Cow<[f32]>> = match job.zoom {
+        Some(zoom) => stretch(&job.rows, zoom).into_iter().map(Cow::Owned).collect(),
+        None => job.rows.iter().map(|row| Cow::Owned(row.powers())).collect(),
+    };
+    // only the waterfall is rescaled, the history keeps the real powers
+    if job.style.normalize_rows {
+        visible.iter().map(|row| Cow::Owned(normalize_row(row))).collect()
+    } else {
+        visible
+    }
+}
+
+/// The part of each of `rows` in band `i`, two bins per column, in the order
+/// they're drawn from the top. With the newest at the bottom, empty ones go
+/// above the oldest until the band is full.
+fn band_spectra<'a>(job: &Job, rows: &'a [Cow<[f32]>], i: usize) -> Vec<&'a [f32]> {
+    let width = 2 * job.cols;
+    let mut spectra: Vec<&[f32]> = rows.iter().map(|row| {
+        // rows from before a resize may be shorter than the current width
+        let start = min(i * width, row.len());
+        &row[start..min(start + width, row.len())]
+    }).collect();
+    if job.style.newest_at_bottom {
+        let num_slots = job.band_rows[i] * job.spectra_per_row;
+        spectra.truncate(num_slots);
+        spectra.reverse();
+        let empty: &[f32] = &[];
+        let mut padded = vec![empty; num_slots - spectra.len()];
+        padded.extend(spectra);
+        spectra = padded;
+    }
+    spectra
+}
+
+/// `rows` stretched, by repeating spectra and bins, to the size of `zoom`.
+fn stretch(rows: &[Arc<Row>], zoom: Zoom) -> Vec<Vec<f32>> {
+    let (b0, b1) = zoom.bins;
+    (0..zoom.num_spectra).map(|k| {
+        let row = &rows[k * rows.len() / zoom.num_spectra];
+        (0..zoom.width).map(|j| {
+            row.power(b0 + j * (b1 - b0 + 1) / zoom.width).unwrap_or(0.0)
+        }).collect()
+    }).collect()
+}
+
+/// Runs jobs on a background thread, waking the UI when they're done.
+pub struct Renderer {
+    jobs: Sender<(u64, Job)>,
+    done: Receiver<(u64, usize, Vec<Widget>)>,
+    /// Ids of the last job submitted and the last one finished.
+    submitted: u64,
+    finished: u64,
+}
+
+impl Renderer {
+    pub fn new(waker: Waker) -> Self {
+        let (jobs, job_recv) = channel::<(u64, Job)>();
+        let (done_send, done) = channel();
+        thread::spawn(move || {
+            while let Ok(first) = job_recv.recv() {
+                // only the newest job for each pane is worth drawing
+                let mut pending = vec![first];
+                while let Ok(job) = job_recv.try_recv() {
+                    pending.retain(|&(_, ref p)| p.pane != job.1.pane);
+                    pending.push(job);
+                }
+                for (id, job) in pending {
+                    if done_send.send((id, job.pane, render(&job))).is_err() {
+                        return;
+                    }
+                }
+                waker.wake();
+            }
+        });
+        Renderer { jobs: jobs, done: done, submitted: 0, finished: 0 }
+    }
+
+    pub fn submit(&mut self, job: Job) {
+        self.submitted += 1;
+        // the thread only stops once this is dropped
+        let _ = self.jobs.send((self.submitted, job));
+    }
+
+    /// True until the last job submitted has been drawn.
+    pub fn is_busy(&self) -> bool {
+        self.finished < self.submitted
+    }
+
+    /// The pane and grids of each job finished since the last call, oldest
+    /// first.
+    pub fn take_finished(&mut self) -> Vec<(usize, Vec<Widget>)> {
+        let mut finished = Vec::new();
+        while let Ok((id, pane, grids)) = self.done.try_recv() {
+            self.finished = max(self.finished, id);
+            finished.push((pane, grids));
+        }
+        finished
+    }
+}
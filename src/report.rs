@@ -0,0 +1,195 @@
+//! A plain text summary of the signals seen during a run, written at exit.
+//!
+//! Nothing in it depends on when things happened, and every section is sorted
+//! by frequency, so the reports of two runs over the same band can be diffed to
+//! spot new emitters. Each line starts with what it describes and its
+//! frequency in Hz, with the measurements after:
+//!
+//! ```text
+//! tuning <center-hz> <sample-rate-hz> <noise-floor-db>
+//! signal <freq-hz> <width-hz> <peak-db> <mean-db> <seen-percent>
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use num::Complex;
+
+use export::shifted_powers_db;
+use processing::find_peaks;
+
+/// Detection settings, matching the peak labels of the spectrum view.
+const THRESHOLD_DB: f32 = 10.0;
+const GUARD_BINS: usize = 2;
+const TRAINING_BINS: usize = 16;
+const MAX_PEAKS: usize = 32;
+
+/// Bins detected in fewer of the spectra covering them than this are taken to
+/// be noise.
+const MIN_SEEN_PERCENT: usize = 10;
+
+/// One bin's detections, keyed by its frequency rounded to the bin width.
+#[derive(Debug, Clone, Copy)]
+struct Detection {
+    bin_width_hz: u64,
+    count: usize,
+    peak_db: f32,
+    sum_db: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TuningStats {
+    num_spectra: usize,
+    sum_noise_floor_db: f64,
+}
+
+pub struct Report {
+    out: BufWriter<File>,
+    detections: BTreeMap<u64, Detection>,
+    /// Keyed by center frequency and sample rate, in Hz.
+    tunings: BTreeMap<(u64, u64), TuningStats>,
+}
+
+impl Report {
+    /// Creates the report file right away, so a bad path shows up before the
+    /// run rather than after it.
+    pub fn create(path: &str) -> io::Result<Self> {
+        Ok(Report {
+            out: BufWriter::new(try!(File::create(path))),
+            detections: BTreeMap::new(),
+            tunings: BTreeMap::new(),
+        })
+    }
+
+    /// Looks for signals in an unshifted FFT output taken at the given tuning.
+    pub fn add_spectrum(&mut self, spec: &[Complex<f32>], center_freq_hz: f64,
+                        sample_rate_hz: f64) {
+        let powers = shifted_powers_db(spec);
+        add_powers(&mut self.detections, &mut self.tunings, &powers, center_freq_hz,
+                   sample_rate_hz);
+    }
+
+    /// Writes the report out.
+    pub fn finish(mut self) -> io::Result<()> {
+        try!(writeln!(self.out, "# terminal_spectrograph report"));
+        for line in report_lines(&self.detections, &self.tunings) {
+            try!(writeln!(self.out, "{}", line));
+        }
+        self.out.flush()
+    }
+}
+
+/// Adds a shifted spectrum of powers in dB.
+fn add_powers(detections: &mut BTreeMap<u64, Detection>,
+              tunings: &mut BTreeMap<(u64, u64), TuningStats>, powers: &[f32],
+              center_freq_hz: f64, sample_rate_hz: f64) {
+    if powers.is_empty() {
+        return;
+    }
+    let bin_width_hz = sample_rate_hz / powers.len() as f64;
+    let dc = powers.len() / 2;
+
+    let mut sorted = powers.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+    let stats = tunings.entry((center_freq_hz as u64, sample_rate_hz as u64))
+                       .or_insert(TuningStats { num_spectra: 0, sum_noise_floor_db: 0.0 });
+    stats.num_spectra += 1;
+    stats.sum_noise_floor_db += sorted[sorted.len() / 2] as f64;
+
+    for peak in find_peaks(powers, GUARD_BINS, TRAINING_BINS, THRESHOLD_DB, MAX_PEAKS) {
+        // the LO leakage isn't a signal
+        if peak.bin == dc {
+            continue;
+        }
+        let freq_hz = center_freq_hz + (peak.bin as f64 - dc as f64) * bin_width_hz;
+        // rounded to the bin width, so the key is the same on every run
+        let key = ((freq_hz / bin_width_hz).round() * bin_width_hz) as u64;
+        let d = detections.entry(key).or_insert(Detection {
+            bin_width_hz: bin_width_hz as u64,
+            count: 0,
+            peak_db: ::std::f32::MIN,
+            sum_db: 0.0,
+        });
+        d.count += 1;
+        d.peak_db = d.peak_db.max(peak.power);
+        d.sum_db += peak.power as f64;
+    }
+}
+
+fn report_lines(detections: &BTreeMap<u64, Detection>,
+                tunings: &BTreeMap<(u64, u64), TuningStats>) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (&(center, rate), stats) in tunings {
+        lines.push(format!("tuning {} {} {:.0}", center, rate,
+                           stats.sum_noise_floor_db / stats.num_spectra as f64));
+    }
+
+    // spectra taken at a tuning covering `freq_hz`
+    let coverage = |freq_hz: u64| -> usize {
+        tunings.iter()
+               .filter(|&(&(center, rate), _)| {
+                   freq_hz + rate / 2 >= center && freq_hz <= center + rate / 2
+               })
+               .map(|(_, stats)| stats.num_spectra)
+               .sum()
+    };
+    let seen_percent = |freq_hz: u64, d: &Detection| 100 * d.count / coverage(freq_hz).max(1);
+
+    // neighbouring bins are one signal, reported at the bin seen most often
+    let mut group: Vec<(u64, Detection)> = Vec::new();
+    let kept = detections.iter().filter(|&(&f, d)| seen_percent(f, d) >= MIN_SEEN_PERCENT);
+    for (&freq_hz, &d) in kept {
+        let adjacent = group.last().map_or(false, |&(last, ref ld)| {
+            freq_hz - last <= ld.bin_width_hz.max(d.bin_width_hz) * 3 / 2
+        });
+        if !adjacent && !group.is_empty() {
+            lines.push(signal_line(&group, &seen_percent));
+            group.clear();
+        }
+        group.push((freq_hz, d));
+    }
+    if !group.is_empty() {
+        lines.push(signal_line(&group, &seen_percent));
+    }
+    lines
+}
+
+fn signal_line<F>(group: &[(u64, Detection)], seen_percent: &F) -> String
+    where F: Fn(u64, &Detection) -> usize
+{
+    // the first of the most often seen, so ties go the same way every time
+    let &(freq_hz, best) = group.iter()
+                                .fold(&group[0], |b, g| if g.1.count > b.1.count { g } else { b });
+    let first = group[0];
+    let last = group[group.len() - 1];
+    let width_hz = last.0 - first.0 + last.1.bin_width_hz;
+    let peak_db = group.iter().fold(::std::f32::MIN, |m, g| m.max(g.1.peak_db));
+    format!("signal {} {} {:.0} {:.0} {}", freq_hz, width_hz, peak_db,
+            best.sum_db / best.count as f64, seen_percent(freq_hz, &best).min(100))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use super::{add_powers, report_lines};
+
+    #[test]
+    fn test_report_lines() {
+        let mut detections = BTreeMap::new();
+        let mut tunings = BTreeMap::new();
+        for i in 0..20 {
+            let mut powers = vec![0.0; 64];
+            // a signal two bins wide at 1 kHz per bin, drifting by a bin
+            powers[40 + i % 2] = 30.0;
+            // a blip, seen too rarely to count
+            if i == 0 {
+                powers[10] = 30.0;
+            }
+            add_powers(&mut detections, &mut tunings, &powers, 1_000_000.0, 64_000.0);
+        }
+
+        assert_eq!(report_lines(&detections, &tunings),
+                   vec!["tuning 1000000 64000 0".to_string(),
+                        "signal 1008000 2000 30 30 50".to_string()]);
+    }
+}
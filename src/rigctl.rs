@@ -0,0 +1,415 @@
+//! A small server speaking hamlib's rigctld protocol, so logging programs
+//! and other SDR tools can read the frequency and retune as they would a
+//! rig. Clients talk to it as rig model 2, "Hamlib NET rigctl".
+//!
+//! Each connection has its own thread answering what doesn't depend on the
+//! tuning, and handing the rest to the UI through `Server::next_request`,
+//! waking it to answer.
+//!
+//! The other way round, a `Follower` asks another receiver's rigctld, or
+//! GQRX's remote control, which speaks the same protocol, where it's tuned,
+//! for the spectrograph to follow it.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use wait::Waker;
+use wizard::{MAX_FREQ_HZ, MIN_FREQ_HZ};
+
+/// How long a client waits on the UI for an answer, which it only takes
+/// this long to give if it's stuck.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often a follower asks where the rig's tuned, and how long it waits
+/// before connecting again once the rig's gone.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(3);
+
+/// How long a follower waits on the rig for each answer.
+const RIG_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// hamlib's error codes, sent back as `RPRT <code>`.
+const RIG_OK: i32 = 0;
+const RIG_EINVAL: i32 = -1;
+const RIG_ENIMPL: i32 = -4;
+const RIG_ETIMEOUT: i32 = -5;
+const RIG_ERJCTED: i32 = -9;
+
+/// What a client asks of the UI.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Request {
+    /// The frequency shown at the middle of the span, in Hz.
+    GetFrequency,
+    /// Retuning so the span's middle shows this frequency, in Hz.
+    SetFrequency(f64),
+}
+
+/// The UI's answer to a request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reply {
+    Frequency(f64),
+    Done,
+    /// The request couldn't be carried out, like a frequency out of range.
+    Refused,
+}
+
+/// A request waiting for the UI's reply.
+pub struct Pending {
+    pub request: Request,
+    reply: Sender<Reply>,
+}
+
+impl Pending {
+    pub fn answer(self, reply: Reply) {
+        // the client may have hung up or given up waiting
+        let _ = self.reply.send(reply);
+    }
+}
+
+pub struct Server {
+    requests: Receiver<Pending>,
+}
+
+impl Server {
+    /// Starts listening for clients on `port`, only from this machine, as
+    /// GQRX's remote control does, since anyone connecting can retune.
+    pub fn listen(port: u16, waker: Waker) -> io::Result<Self> {
+        let listener = try!(TcpListener::bind(("127.0.0.1", port)));
+        Ok(Server::serve(listener, waker))
+    }
+
+    fn serve(listener: TcpListener, waker: Waker) -> Self {
+        let (send, requests) = channel();
+        // shared by every connection's thread
+        let waker = Arc::new(waker);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let (send, waker) = (send.clone(), waker.clone());
+                thread::spawn(move || {
+                    // a client that's gone away just ends its thread
+                    let _ = serve_client(stream, &send, &waker);
+                });
+            }
+        });
+        Server { requests: requests }
+    }
+
+    /// A request waiting to be answered, if any.
+    pub fn next_request(&self) -> Option<Pending> {
+        self.requests.try_recv().ok()
+    }
+}
+
+/// Answers `stream`'s commands, one a line, until it quits or hangs up.
+fn serve_client(stream: TcpStream, requests: &Sender<Pending>, waker: &Waker)
+                -> io::Result<()> {
+    let mut reader = BufReader::new(try!(stream.try_clone()));
+    let mut stream = stream;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if try!(reader.read_line(&mut line)) == 0 {
+            return Ok(());
+        }
+        let command = parse_command(&line);
+        let request = match command {
+            Command::Quit => return Ok(()),
+            Command::Request(request) => request,
+            Command::Answer(answer) => {
+                try!(stream.write_all(answer.as_bytes()));
+                continue;
+            }
+        };
+        let (send, recv) = channel();
+        if requests.send(Pending { request: request, reply: send }).is_err() {
+            // the UI has gone
+            return Ok(());
+        }
+        waker.wake();
+        let answer = match recv.recv_timeout(REPLY_TIMEOUT) {
+            Ok(Reply::Frequency(freq_hz)) => format!("{:.0}\n", freq_hz),
+            Ok(Reply::Done) => report(RIG_OK),
+            Ok(Reply::Refused) => report(RIG_ERJCTED),
+            Err(RecvTimeoutError::Timeout) => report(RIG_ETIMEOUT),
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        };
+        try!(stream.write_all(answer.as_bytes()));
+    }
+}
+
+/// What to do about a line from a client.
+#[derive(Debug, PartialEq)]
+enum Command {
+    /// Sends this back straight away.
+    Answer(String),
+    /// Asks the UI, sending back its reply.
+    Request(Request),
+    Quit,
+}
+
+/// The command on `line`, by its short name, like `F 14074000`, or its long
+/// one, like `\set_freq 14074000`.
+fn parse_command(line: &str) -> Command {
+    let mut words = line.split_whitespace();
+    let name = match words.next() {
+        Some(name) => name,
+        None => return Command::Answer(report(RIG_EINVAL)),
+    };
+    let answer = |text: &str| Command::Answer(text.to_string());
+    match name {
+        "f" | "\\get_freq" => Command::Request(Request::GetFrequency),
+        "F" | "\\set_freq" => match words.next().and_then(|s| s.parse::<f64>().ok()) {
+            Some(freq_hz) => Command::Request(Request::SetFrequency(freq_hz)),
+            None => Command::Answer(report(RIG_EINVAL)),
+        },
+        // one VFO, never transmitting
+        "v" | "\\get_vfo" => answer("VFOA\n"),
+        "V" | "\\set_vfo" => Command::Answer(report(RIG_OK)),
+        "t" | "\\get_ptt" => answer("0\n"),
+        "s" | "\\get_split_vfo" => answer("0\nVFOA\n"),
+        "\\chk_vfo" => answer("0\n"),
+        "\\dump_state" => Command::Answer(dump_state()),
+        "q" | "Q" => Command::Quit,
+        _ => Command::Answer(report(RIG_ENIMPL)),
+    }
+}
+
+fn report(code: i32) -> String {
+    format!("RPRT {}\n", code)
+}
+
+/// The rig's capabilities, as hamlib's network backend reads them when it
+/// connects: a receive range of what the radio tunes, every mode, nothing
+/// to transmit with and none of the rig's functions or levels.
+fn dump_state() -> String {
+    let mut state = String::new();
+    // protocol version, rig model and ITU region
+    state.push_str("0\n2\n2\n");
+    state.push_str(&format!("{:.6} {:.6} 0x1ff -1 -1 0x10000003 0x3\n", MIN_FREQ_HZ,
+                            MAX_FREQ_HZ));
+    // the end of the receive ranges, then no transmit ones
+    state.push_str("0 0 0 0 0 0 0\n0 0 0 0 0 0 0\n");
+    // tuning steps and filters, each list ending in zeros
+    state.push_str("0x1ff 1\n0x1ff 0\n0 0\n");
+    state.push_str("0x1e 2400\n0x2 500\n0x1 8000\n0x1 2400\n0x20 15000\n0x20 8000\n\
+                    0x40 230000\n0 0\n");
+    // RIT, XIT, IF shift and announcements, then preamps and attenuators
+    state.push_str("0\n0\n0\n0\n\n\n");
+    // functions, levels and parameters got and set
+    state.push_str("0x0\n0x0\n0x0\n0x0\n0x0\n0x0\n");
+    state
+}
+
+/// Where a followed rig is tuned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RigTuning {
+    pub freq_hz: f64,
+    /// Lowest and highest frequency it's listening to, in Hz, from its mode
+    /// and passband. Just the frequency if it has no passband, or won't say.
+    pub passband: (f64, f64),
+}
+
+/// What a follower has found out since last asked.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RigEvent {
+    /// The rig's tuning changed, or it's been reached for the first time.
+    Tuned(RigTuning),
+    /// The rig can't be reached, and why, once each time it's lost.
+    Lost(String),
+}
+
+/// Keeps asking the rig at an address like `localhost:7356` where it's
+/// tuned, on its own thread, waking the UI whenever that changes.
+pub struct Follower {
+    events: Receiver<RigEvent>,
+}
+
+impl Follower {
+    pub fn start(addr: String, waker: Waker) -> Self {
+        let (send, events) = channel();
+        thread::spawn(move || {
+            let mut last = None;
+            let mut reported = false;
+            loop {
+                let error = match follow(&addr, &mut last, &send, &waker) {
+                    Ok(()) => return,
+                    Err(e) => e,
+                };
+                // once each time it's lost, not each time it's tried again
+                if last.take().is_some() || !reported {
+                    reported = true;
+                    if send.send(RigEvent::Lost(error.to_string())).is_err() {
+                        return;
+                    }
+                    waker.wake();
+                }
+                thread::sleep(RECONNECT_INTERVAL);
+            }
+        });
+        Follower { events: events }
+    }
+
+    /// What's happened since the last look, if anything.
+    pub fn next_event(&self) -> Option<RigEvent> {
+        self.events.try_recv().ok()
+    }
+}
+
+/// Connects to the rig and asks it where it's tuned until either end goes
+/// away, sending whatever's changed since `last`. `Ok` once the UI's gone.
+fn follow(addr: &str, last: &mut Option<RigTuning>, events: &Sender<RigEvent>, waker: &Waker)
+          -> io::Result<()> {
+    let stream = try!(TcpStream::connect(addr));
+    try!(stream.set_read_timeout(Some(RIG_TIMEOUT)));
+    let mut lines = BufReader::new(try!(stream.try_clone())).lines();
+    let mut stream = stream;
+    let mut read_line = || match lines.next() {
+        Some(line) => line,
+        None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the rig hung up")),
+    };
+    loop {
+        try!(stream.write_all(b"f\n"));
+        let freq = try!(read_line());
+        let freq_hz = match freq.trim().parse::<f64>() {
+            Ok(freq_hz) => freq_hz,
+            Err(_) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          format!("unexpected answer '{}'", freq.trim())));
+            }
+        };
+        try!(stream.write_all(b"m\n"));
+        let mode = try!(read_line());
+        // an error, for rigs without modes, is all there is to the answer
+        let passband_hz = if mode.starts_with("RPRT") {
+            0.0
+        } else {
+            try!(read_line()).trim().parse().unwrap_or(0.0)
+        };
+        let tuning = RigTuning {
+            freq_hz: freq_hz,
+            passband: passband(freq_hz, mode.trim(), passband_hz),
+        };
+        if last.as_ref() != Some(&tuning) {
+            if events.send(RigEvent::Tuned(tuning.clone())).is_err() {
+                return Ok(());
+            }
+            waker.wake();
+            *last = Some(tuning);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Where a rig tuned to `freq_hz` in `mode` listens, `width_hz` wide: above
+/// the frequency for upper sideband, below it for lower, and around it for
+/// anything else.
+fn passband(freq_hz: f64, mode: &str, width_hz: f64) -> (f64, f64) {
+    match mode {
+        "USB" | "PKTUSB" | "ECSSUSB" => (freq_hz, freq_hz + width_hz),
+        "LSB" | "PKTLSB" | "ECSSLSB" => (freq_hz - width_hz, freq_hz),
+        _ => (freq_hz - width_hz / 2.0, freq_hz + width_hz / 2.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+    use wait::WaitSet;
+    use super::{Follower, Reply, Request, RigEvent, RigTuning, Server};
+
+    #[test]
+    fn test_server() {
+        let (_wait_set, waker) = WaitSet::without_tty().unwrap();
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::serve(listener, waker);
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        let mut lines = BufReader::new(client.try_clone().unwrap()).lines();
+        let mut ask = |command: &str| {
+            client.write_all(command.as_bytes()).unwrap();
+            lines.next().unwrap().unwrap()
+        };
+        // answered without the UI
+        assert_eq!(ask("v\n"), "VFOA");
+        assert_eq!(ask("\\get_level STRENGTH\n"), "RPRT -4");
+        assert_eq!(ask("F 1e6x\n"), "RPRT -1");
+
+        // the UI answers the rest, wherever it takes them from
+        let ui = ::std::thread::spawn(move || {
+            let mut answered = Vec::new();
+            while answered.len() < 3 {
+                match server.next_request() {
+                    Some(pending) => {
+                        let reply = match pending.request {
+                            Request::GetFrequency => Reply::Frequency(100.5e6),
+                            Request::SetFrequency(hz) if hz > 6e9 => Reply::Refused,
+                            Request::SetFrequency(_) => Reply::Done,
+                        };
+                        answered.push(pending.request);
+                        pending.answer(reply);
+                    }
+                    None => ::std::thread::yield_now(),
+                }
+            }
+            answered
+        });
+        assert_eq!(ask("\\set_freq 14074000\n"), "RPRT 0");
+        assert_eq!(ask("F 7000000000\n"), "RPRT -9");
+        assert_eq!(ask("f\n"), "100500000");
+        assert_eq!(ui.join().unwrap(), vec![Request::SetFrequency(14074000.0),
+                                            Request::SetFrequency(7e9),
+                                            Request::GetFrequency]);
+    }
+
+    #[test]
+    fn test_follower() {
+        let (_wait_set, waker) = WaitSet::without_tty().unwrap();
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        // a rig on upper sideband, then retuned, then gone
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut client = stream.try_clone().unwrap();
+            let answers = ["14074000\n", "USB\n2400\n", "14074000\n", "USB\n2400\n",
+                           "7074000\n", "RPRT -11\n"];
+            for (line, answer) in BufReader::new(stream).lines().zip(&answers) {
+                line.unwrap();
+                client.write_all(answer.as_bytes()).unwrap();
+            }
+        });
+
+        let follower = Follower::start(addr.to_string(), waker);
+        let mut events = Vec::new();
+        while events.len() < 3 {
+            match follower.next_event() {
+                Some(event) => events.push(event),
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+        // only the changes
+        assert_eq!(events[0], RigEvent::Tuned(RigTuning {
+            freq_hz: 14074000.0,
+            passband: (14074000.0, 14076400.0),
+        }));
+        assert_eq!(events[1], RigEvent::Tuned(RigTuning {
+            freq_hz: 7074000.0,
+            passband: (7074000.0, 7074000.0),
+        }));
+        match events[2] {
+            RigEvent::Lost(_) => {}
+            ref event => panic!("{:?}", event),
+        }
+    }
+}
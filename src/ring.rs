@@ -0,0 +1,208 @@
+//! A bounded queue from one thread to one other that neither waits on nor
+//! allocates for, so the radio's callback can hand its buffers over without
+//! ever blocking libhackrf's transfer thread. Once it's full, what's pushed
+//! is handed back rather than queued, for the pusher to count and drop.
+//!
+//! With the `futures` feature the popping side can be an async task, which
+//! the pusher wakes, rather than a thread.
+
+use std::cell::{Cell, UnsafeCell};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+#[cfg(feature = "futures")]
+use std::task::{Context, Poll};
+#[cfg(feature = "futures")]
+use futures::task::AtomicWaker;
+
+/// Why `Producer::push` handed its item back.
+#[derive(Debug, PartialEq)]
+pub enum PushError<T> {
+    /// The consumer has fallen behind by the whole capacity.
+    Full(T),
+    /// The consumer is gone, so nothing pushed will be taken.
+    Disconnected(T),
+}
+
+struct Shared<T> {
+    slots: Vec<UnsafeCell<Option<T>>>,
+    /// Items taken so far, only ever changed by the consumer.
+    head: AtomicUsize,
+    /// Items pushed so far, only ever changed by the producer.
+    tail: AtomicUsize,
+    producer_alive: AtomicBool,
+    consumer_alive: AtomicBool,
+    /// The task waiting in `Consumer::poll_pop`, if any.
+    #[cfg(feature = "futures")]
+    waker: AtomicWaker,
+}
+
+// each slot is only touched by one side at a time, as head and tail say
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+/// One end each, which can be sent to another thread but not shared with
+/// one, as only one thread may push and only one pop.
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+    not_sync: PhantomData<Cell<()>>,
+}
+
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+    not_sync: PhantomData<Cell<()>>,
+}
+
+/// The two ends of a queue with room for `capacity` items.
+pub fn ring<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    assert!(capacity > 0);
+    let shared = Arc::new(Shared {
+        slots: (0..capacity).map(|_| UnsafeCell::new(None)).collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+        producer_alive: AtomicBool::new(true),
+        consumer_alive: AtomicBool::new(true),
+        #[cfg(feature = "futures")]
+        waker: AtomicWaker::new(),
+    });
+    (Producer { shared: shared.clone(), not_sync: PhantomData },
+     Consumer { shared: shared, not_sync: PhantomData })
+}
+
+impl<T> Producer<T> {
+    pub fn push(&self, item: T) -> Result<(), PushError<T>> {
+        let shared = &*self.shared;
+        if !shared.consumer_alive.load(Ordering::Relaxed) {
+            return Err(PushError::Disconnected(item));
+        }
+        let tail = shared.tail.load(Ordering::Relaxed);
+        let head = shared.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == shared.slots.len() {
+            return Err(PushError::Full(item));
+        }
+        unsafe {
+            *shared.slots[tail % shared.slots.len()].get() = Some(item);
+        }
+        shared.tail.store(tail.wrapping_add(1), Ordering::Release);
+        #[cfg(feature = "futures")]
+        shared.waker.wake();
+        Ok(())
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        self.shared.producer_alive.store(false, Ordering::Release);
+        #[cfg(feature = "futures")]
+        self.shared.waker.wake();
+    }
+}
+
+impl<T> Consumer<T> {
+    /// The oldest item, if there's one waiting.
+    pub fn pop(&self) -> Option<T> {
+        let shared = &*self.shared;
+        let head = shared.head.load(Ordering::Relaxed);
+        let tail = shared.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let item = unsafe { (*shared.slots[head % shared.slots.len()].get()).take() };
+        shared.head.store(head.wrapping_add(1), Ordering::Release);
+        item
+    }
+
+    /// Whether the producer is gone, so once empty nothing more will come.
+    pub fn is_disconnected(&self) -> bool {
+        !self.shared.producer_alive.load(Ordering::Acquire)
+    }
+
+    /// `pop` for an async task, which is woken by the next push, or `None`
+    /// once the producer's gone and everything it pushed has been taken.
+    #[cfg(feature = "futures")]
+    pub fn poll_pop(&self, cx: &mut Context) -> Poll<Option<T>> {
+        // registered first, so a push after looking still wakes it
+        self.shared.waker.register(cx.waker());
+        let stopped = self.is_disconnected();
+        match self.pop() {
+            Some(item) => Poll::Ready(Some(item)),
+            None if stopped => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        self.shared.consumer_alive.store(false, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use super::{ring, PushError};
+
+    #[test]
+    fn test_ring() {
+        let (producer, consumer) = ring(2);
+        assert_eq!(consumer.pop(), None);
+        producer.push(1).unwrap();
+        producer.push(2).unwrap();
+        assert_eq!(producer.push(3), Err(PushError::Full(3)));
+        assert_eq!(consumer.pop(), Some(1));
+        // round past the end of the slots
+        producer.push(3).unwrap();
+        assert_eq!(consumer.pop(), Some(2));
+        assert_eq!(consumer.pop(), Some(3));
+        assert_eq!(consumer.pop(), None);
+
+        drop(producer);
+        assert!(consumer.is_disconnected());
+        let (producer, consumer) = ring(1);
+        drop(consumer);
+        assert_eq!(producer.push(1), Err(PushError::Disconnected(1)));
+
+        // everything that goes in comes out, in order, from another thread
+        let (producer, consumer) = ring(4);
+        let pusher = thread::spawn(move || {
+            for i in 0..10000 {
+                let mut item = i;
+                while let Err(PushError::Full(back)) = producer.push(item) {
+                    item = back;
+                    thread::yield_now();
+                }
+            }
+        });
+        let mut next = 0;
+        while next < 10000 {
+            match consumer.pop() {
+                Some(i) => {
+                    assert_eq!(i, next);
+                    next += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+        pusher.join().unwrap();
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn test_poll_pop() {
+        use futures::executor::block_on;
+        use futures::stream::{self, StreamExt};
+
+        let (producer, consumer) = ring(4);
+        let pusher = thread::spawn(move || {
+            for i in 0..100 {
+                while producer.push(i).is_err() {
+                    thread::yield_now();
+                }
+            }
+        });
+        // woken for each, and at the end
+        let taken: Vec<i32> = block_on(stream::poll_fn(|cx| consumer.poll_pop(cx)).collect());
+        assert_eq!(taken, (0..100).collect::<Vec<_>>());
+        pusher.join().unwrap();
+    }
+}
@@ -0,0 +1,135 @@
+//! Stepping the radio through a list of frequencies, for watching a band wider
+//! than the radio's bandwidth.
+
+use std::time::{Duration, Instant};
+use num::Complex;
+
+use units::parse_frequency;
+
+/// Spectra this soon after a retune may still hold samples from the previous
+/// channel, so they don't count towards holding on the new one.
+const SETTLE_TIME: Duration = Duration::from_millis(100);
+
+/// Parses a comma separated list of frequencies, or a `start:stop:step` range
+/// including both ends, all in Hz.
+pub fn parse_channels(s: &str) -> Result<Vec<u64>, String> {
+    let parse = |x: &str| match parse_frequency(x) {
+        Some(hz) if hz >= 0.0 => Ok(hz),
+        _ => Err(format!("'{}' isn't a frequency", x.trim())),
+    };
+
+    let range: Vec<&str> = s.split(':').collect();
+    if range.len() == 3 {
+        let (start, stop, step) = (try!(parse(range[0])), try!(parse(range[1])),
+                                   try!(parse(range[2])));
+        if step <= 0.0 || stop < start {
+            return Err("expected start:stop:step with stop >= start and step > 0".to_string());
+        }
+        let count = ((stop - start) / step).floor() as u64 + 1;
+        return Ok((0..count).map(|i| (start + i as f64 * step) as u64).collect());
+    } else if range.len() != 1 {
+        return Err("expected a list of frequencies or start:stop:step".to_string());
+    }
+
+    let mut channels = Vec::new();
+    for x in s.split(',') {
+        channels.push(try!(parse(x)) as u64);
+    }
+    Ok(channels)
+}
+
+/// Strongest bin power in dB, skipping the DC bin, on the same scale as the
+/// spectrum view.
+pub fn peak_power_db(spec: &[Complex<f32>]) -> f32 {
+    spec.iter()
+        .skip(1)
+        .map(|x| 10.0 * x.norm().log10())
+        .fold(::std::f32::MIN, f32::max)
+}
+
+/// Decides when to move to the next channel. It stays on each one for the
+/// dwell time, or with a squelch, for as long as there's a signal above it
+/// and the dwell time after.
+pub struct Scanner {
+    channels: Vec<u64>,
+    index: usize,
+    dwell: Duration,
+    squelch_db: Option<f32>,
+    retuned_at: Instant,
+    /// When a spectrum last broke the squelch on this channel.
+    active_at: Option<Instant>,
+}
+
+impl Scanner {
+    pub fn new(channels: Vec<u64>, dwell: Duration, squelch_db: Option<f32>) -> Self {
+        assert!(!channels.is_empty());
+        Scanner {
+            channels: channels,
+            index: 0,
+            dwell: dwell,
+            squelch_db: squelch_db,
+            retuned_at: Instant::now(),
+            active_at: None,
+        }
+    }
+
+    /// Starts the dwell on the current channel over.
+    pub fn restart(&mut self) {
+        self.retuned_at = Instant::now();
+        self.active_at = None;
+    }
+
+    pub fn current(&self) -> u64 {
+        self.channels[self.index]
+    }
+
+    /// Checks a spectrum from the current channel against the squelch.
+    pub fn observe(&mut self, spec: &[Complex<f32>]) {
+        if let Some(squelch_db) = self.squelch_db {
+            if self.retuned_at.elapsed() >= SETTLE_TIME && peak_power_db(spec) >= squelch_db {
+                self.active_at = Some(Instant::now());
+            }
+        }
+    }
+
+    /// True while a signal is holding the scan on the current channel.
+    pub fn is_holding(&self) -> bool {
+        self.active_at.map_or(false, |t| t.elapsed() < self.dwell)
+    }
+
+    /// How long until `poll` will move on, at the earliest.
+    pub fn time_to_next(&self) -> Duration {
+        let since = self.active_at.map_or(self.retuned_at, |t| if t > self.retuned_at {
+            t
+        } else {
+            self.retuned_at
+        });
+        self.dwell.checked_sub(since.elapsed()).unwrap_or(Duration::from_secs(0))
+    }
+
+    /// Moves on to the next channel if it's time, returning its frequency.
+    pub fn poll(&mut self) -> Option<u64> {
+        if self.time_to_next() > Duration::from_secs(0) {
+            return None;
+        }
+        self.index = (self.index + 1) % self.channels.len();
+        self.restart();
+        Some(self.current())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_channels;
+
+    #[test]
+    fn test_parse_channels() {
+        assert_eq!(parse_channels("88e6:92e6:2e6"), Ok(vec![88_000_000, 90_000_000, 92_000_000]));
+        assert_eq!(parse_channels("144.39e6, 145.8e6"), Ok(vec![144_390_000, 145_800_000]));
+        assert_eq!(parse_channels("88M:88.4M:200k"),
+                   Ok(vec![88_000_000, 88_200_000, 88_400_000]));
+        assert!(parse_channels("92e6:88e6:1e6").is_err());
+        assert!(parse_channels("1:2").is_err());
+        assert!(parse_channels("fm").is_err());
+    }
+}
@@ -0,0 +1,91 @@
+//! A tap on the samples for the IQ scope, keeping the newest while it's
+//! shown for the UI to plot. Clipping, a DC offset and I and Q out of
+//! balance show there at a glance, as they don't in the spectrum.
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::Receiver;
+use num::Complex;
+
+use watchdog;
+
+/// Samples kept, from the end of the newest buffer.
+const SCOPE_LEN: usize = 2048;
+
+/// Shared between the UI, which shows or hides the scope, and the thread
+/// passing the samples on.
+pub struct Scope {
+    shown: Arc<AtomicBool>,
+    newest: Arc<Mutex<Vec<Complex<i8>>>>,
+}
+
+impl Scope {
+    pub fn new() -> Self {
+        Scope { shown: Arc::new(AtomicBool::new(false)), newest: Arc::new(Mutex::new(Vec::new())) }
+    }
+
+    /// Passes the samples from `recv` on, keeping the newest while shown,
+    /// counting the buffers dropped for falling behind in `overruns`.
+    pub fn tee(&self, recv: Receiver<Vec<Complex<i8>>>, overruns: Arc<AtomicUsize>)
+               -> Receiver<Vec<Complex<i8>>> {
+        let shown = self.shown.clone();
+        let newest = self.newest.clone();
+        watchdog::tee(recv, overruns, move |buff| {
+            if shown.load(Ordering::Relaxed) {
+                let start = buff.len().saturating_sub(SCOPE_LEN);
+                *newest.lock().unwrap() = buff[start..].to_vec();
+            }
+        })
+    }
+
+    /// Shows the scope, or hides it, returning whether it's shown now.
+    pub fn toggle(&self) -> bool {
+        let shown = !self.shown.load(Ordering::Relaxed);
+        self.shown.store(shown, Ordering::Relaxed);
+        if !shown {
+            self.newest.lock().unwrap().clear();
+        }
+        shown
+    }
+
+    /// The newest samples, while shown.
+    pub fn samples(&self) -> Option<Vec<Complex<i8>>> {
+        if self.shown.load(Ordering::Relaxed) {
+            Some(self.newest.lock().unwrap().clone())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::channel;
+    use num::Complex;
+    use super::{Scope, SCOPE_LEN};
+
+    #[test]
+    fn test_scope() {
+        let scope = Scope::new();
+        let (send, recv) = channel();
+        let overruns = Arc::new(AtomicUsize::new(0));
+        let recv = scope.tee(recv, overruns.clone());
+        let buff: Vec<Complex<i8>> = (0..SCOPE_LEN + 2).map(|i| Complex::new(i as i8, 0))
+                                                      .collect();
+
+        // passed on either way, but only kept while shown
+        send.send(buff.clone()).unwrap();
+        assert_eq!(recv.recv().unwrap(), buff);
+        assert_eq!(scope.samples(), None);
+        assert!(scope.toggle());
+        assert_eq!(scope.samples(), Some(Vec::new()));
+        send.send(buff.clone()).unwrap();
+        assert_eq!(recv.recv().unwrap(), buff);
+        assert_eq!(scope.samples().unwrap(), &buff[2..]);
+        assert!(!scope.toggle());
+        assert_eq!(scope.samples(), None);
+        assert_eq!(overruns.load(Ordering::Relaxed), 0);
+    }
+}
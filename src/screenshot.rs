@@ -0,0 +1,248 @@
+//! Saving the spectrum and the waterfall as a PNG, with frequency and power
+//! axes and the time, for sharing what was seen. It's drawn from the history,
+//! a column of pixels per bin and a row per spectrum, not from the cells, so
+//! it looks the same whatever the terminal.
+
+use std::cmp::{max, min};
+use std::io;
+
+use drawing::Snapshot;
+use png;
+use units::format_frequency;
+
+/// Spectra in the waterfall, the newest, at most.
+pub const WATERFALL_SPECTRA: usize = 300;
+
+/// Height of the spectrum plot.
+const SPECTRUM_HEIGHT: usize = 160;
+
+/// Bins are widened to more pixels until the plots are at least this wide.
+const MIN_PLOT_WIDTH: usize = 800;
+
+/// Room around the plots for the labels.
+const LEFT: usize = 48;
+const TOP: usize = 24;
+const RIGHT: usize = 24;
+const BOTTOM: usize = 24;
+
+/// About how far apart the frequency labels go.
+const LABEL_SPACING: usize = 100;
+
+/// Each pixel of the 3x5 font is drawn this many pixels square.
+const FONT_SCALE: usize = 2;
+
+type Rgb = (u8, u8, u8);
+
+const BACKGROUND: Rgb = (0, 0, 0);
+const GRID: Rgb = (56, 56, 56);
+const TEXT: Rgb = (220, 220, 220);
+const TRACE: Rgb = (255, 255, 255);
+
+/// The characters the font has, lower case being drawn as upper case, and
+/// their rows from the top, the high bit of each on the left.
+const FONT_CHARS: &'static str = "0123456789.-:/ ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const FONT: [[u8; 5]; 41] = [
+    [7, 5, 5, 5, 7], [2, 6, 2, 2, 7], [7, 1, 7, 4, 7], [7, 1, 7, 1, 7], [5, 5, 7, 1, 1],
+    [7, 4, 7, 1, 7], [7, 4, 7, 5, 7], [7, 1, 1, 1, 1], [7, 5, 7, 5, 7], [7, 5, 7, 1, 7],
+    [0, 0, 0, 0, 2], [0, 0, 7, 0, 0], [0, 2, 0, 2, 0], [1, 1, 2, 4, 4], [0, 0, 0, 0, 0],
+    [2, 5, 7, 5, 5], [6, 5, 6, 5, 6], [3, 4, 4, 4, 3], [6, 5, 5, 5, 6], [7, 4, 6, 4, 7],
+    [7, 4, 6, 4, 4], [3, 4, 5, 5, 3], [5, 5, 7, 5, 5], [7, 2, 2, 2, 7], [1, 1, 1, 5, 2],
+    [5, 5, 6, 5, 5], [4, 4, 4, 4, 7], [5, 7, 7, 5, 5], [6, 5, 5, 5, 5], [2, 5, 5, 5, 2],
+    [6, 5, 6, 4, 4], [2, 5, 5, 6, 3], [6, 5, 6, 5, 5], [3, 4, 2, 1, 6], [7, 2, 2, 2, 2],
+    [5, 5, 5, 5, 7], [5, 5, 5, 5, 2], [5, 5, 7, 7, 5], [5, 5, 2, 5, 5], [5, 5, 2, 2, 2],
+    [7, 1, 2, 4, 7],
+];
+
+/// Drawn for anything the font doesn't have.
+const UNKNOWN_GLYPH: [u8; 5] = [7, 7, 7, 7, 7];
+
+/// Pixels of RGB, row by row from the top.
+struct Picture {
+    width: usize,
+    height: usize,
+    rgb: Vec<u8>,
+}
+
+impl Picture {
+    fn new(width: usize, height: usize) -> Self {
+        let (r, g, b) = BACKGROUND;
+        let rgb = (0..width * height).flat_map(|_| vec![r, g, b]).collect();
+        Picture { width: width, height: height, rgb: rgb }
+    }
+
+    /// Anything off the picture is left out.
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, (r, g, b): Rgb) {
+        for y in y..min(y + height, self.height) {
+            for x in x..min(x + width, self.width) {
+                let i = 3 * (y * self.width + x);
+                self.rgb[i..i + 3].copy_from_slice(&[r, g, b]);
+            }
+        }
+    }
+
+    /// Writes `text` with its top left at `x`, `y`.
+    fn text(&mut self, x: usize, y: usize, text: &str, color: Rgb) {
+        for (i, c) in text.chars().enumerate() {
+            let c = c.to_ascii_uppercase();
+            let glyph = FONT_CHARS.find(c).map_or(UNKNOWN_GLYPH, |i| FONT[i]);
+            let left = x + i * 4 * FONT_SCALE;
+            for (row, &bits) in glyph.iter().enumerate() {
+                for col in 0..3 {
+                    if bits & (4 >> col) != 0 {
+                        self.fill(left + col * FONT_SCALE, y + row * FONT_SCALE, FONT_SCALE,
+                                  FONT_SCALE, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn text_width(text: &str) -> usize {
+    (4 * text.chars().count()).saturating_sub(1) * FONT_SCALE
+}
+
+/// Saves `snapshot` as a PNG at `path`, titled with `time`.
+pub fn save(path: &str, snapshot: &Snapshot, time: &str) -> io::Result<()> {
+    let picture = render(snapshot, time);
+    png::write_file(path, picture.width, &picture.rgb)
+}
+
+fn render(snapshot: &Snapshot, time: &str) -> Picture {
+    let num_bins = max(snapshot.spectra[0].len(), 1);
+    let bin_width = max(MIN_PLOT_WIDTH / num_bins, 1);
+    let plot_width = num_bins * bin_width;
+    let spectrum_bottom = TOP + SPECTRUM_HEIGHT;
+    // a line between the plots
+    let waterfall_top = spectrum_bottom + 1;
+    let waterfall_bottom = waterfall_top + snapshot.spectra.len();
+    let mut picture = Picture::new(LEFT + plot_width + RIGHT, waterfall_bottom + BOTTOM);
+    let glyph_height = 5 * FONT_SCALE;
+
+    // power lines and labels
+    let range_db = snapshot.max_db - snapshot.min_db;
+    let step_db = nice_step(range_db as f64 / 4.0) as f32;
+    let mut db = (snapshot.min_db / step_db).ceil() * step_db;
+    while db <= snapshot.max_db {
+        let y = TOP + ((snapshot.max_db - db) / range_db * (SPECTRUM_HEIGHT - 1) as f32)
+                          .round() as usize;
+        picture.fill(LEFT, y, plot_width, 1, GRID);
+        let label = if step_db >= 1.0 { format!("{:.0}", db) } else { format!("{:.1}", db) };
+        picture.text(LEFT.saturating_sub(text_width(&label) + 6),
+                     y.saturating_sub(glyph_height / 2), &label, TEXT);
+        db += step_db;
+    }
+
+    // frequency lines, ticks and labels
+    let low_hz = snapshot.center_freq_hz - snapshot.sample_rate_hz / 2.0;
+    let step_hz = nice_step(snapshot.sample_rate_hz * LABEL_SPACING as f64 / plot_width as f64);
+    let mut freq_hz = (low_hz / step_hz).ceil() * step_hz;
+    while freq_hz < low_hz + snapshot.sample_rate_hz {
+        let x = LEFT + ((freq_hz - low_hz) / snapshot.sample_rate_hz * plot_width as f64)
+                           as usize;
+        picture.fill(x, TOP, 1, SPECTRUM_HEIGHT, GRID);
+        picture.fill(x, waterfall_bottom, 1, 4, TEXT);
+        let label = format_frequency(freq_hz);
+        let label_x = min(x.saturating_sub(text_width(&label) / 2),
+                          picture.width - text_width(&label));
+        picture.text(label_x, waterfall_bottom + 8, &label, TEXT);
+        freq_hz += step_hz;
+    }
+    picture.fill(LEFT, spectrum_bottom, plot_width, 1, GRID);
+
+    // the newest spectrum, filled in under it, joined up from bin to bin
+    let mut last_y = None;
+    for (bin, &h) in snapshot.spectra[0].iter().enumerate() {
+        let h = h.max(0.0).min(1.0);
+        let y = TOP + ((1.0 - h) * (SPECTRUM_HEIGHT - 1) as f32).round() as usize;
+        let x = LEFT + bin * bin_width;
+        let (r, g, b) = snapshot.palette.rgb(h);
+        picture.fill(x, y, bin_width, spectrum_bottom - y, (r / 2, g / 2, b / 2));
+        let (top, bottom) = match last_y {
+            Some(last_y) => (min(y, last_y), max(y, last_y)),
+            None => (y, y),
+        };
+        picture.fill(x, top, 1, bottom - top + 1, TRACE);
+        picture.fill(x, y, bin_width, 1, TRACE);
+        last_y = Some(y);
+    }
+
+    for (row, spec) in snapshot.spectra.iter().enumerate() {
+        for (bin, &h) in spec.iter().take(num_bins).enumerate() {
+            picture.fill(LEFT + bin * bin_width, waterfall_top + row, bin_width, 1,
+                         snapshot.palette.rgb(h));
+        }
+    }
+
+    let title = format!("{}   {}Hz   span {}Hz   {} to {} dB", time,
+                        format_frequency(snapshot.center_freq_hz),
+                        format_frequency(snapshot.sample_rate_hz), snapshot.min_db,
+                        snapshot.max_db);
+    picture.text(LEFT, (TOP - glyph_height) / 2, &title, TEXT);
+    picture
+}
+
+/// 1, 2 or 5 times a power of ten, the smallest at least `x`.
+fn nice_step(x: f64) -> f64 {
+    if x.is_nan() || x <= 0.0 {
+        return 1.0;
+    }
+    let power = 10.0f64.powf(x.log10().floor());
+    let mantissa = x / power;
+    power * if mantissa <= 1.0 {
+        1.0
+    } else if mantissa <= 2.0 {
+        2.0
+    } else if mantissa <= 5.0 {
+        5.0
+    } else {
+        10.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use drawing::Snapshot;
+    use palette::Palette;
+    use super::{nice_step, render, Picture, Rgb, BACKGROUND, LEFT, SPECTRUM_HEIGHT, TEXT, TOP,
+                TRACE};
+
+    fn pixel(picture: &Picture, x: usize, y: usize) -> Rgb {
+        let i = 3 * (y * picture.width + x);
+        (picture.rgb[i], picture.rgb[i + 1], picture.rgb[i + 2])
+    }
+
+    #[test]
+    fn test_render() {
+        let snapshot = Snapshot {
+            center_freq_hz: 100e6,
+            sample_rate_hz: 2e6,
+            min_db: 0.0,
+            max_db: 50.0,
+            palette: Palette::Inferno,
+            spectra: vec![Arc::new(vec![0.0, 1.0, 0.5, 0.0]), Arc::new(vec![1.0, 0.0])],
+        };
+        let picture = render(&snapshot, "2026-10-14 12:00:00");
+        // each bin 200 pixels wide
+        assert_eq!(picture.width, LEFT + 800 + 24);
+        assert_eq!(picture.height, TOP + SPECTRUM_HEIGHT + 1 + 2 + 24);
+
+        // the trace at the top in the second bin, filled in below
+        assert_eq!(pixel(&picture, LEFT + 300, TOP), TRACE);
+        let (r, g, b) = Palette::Inferno.rgb(1.0);
+        assert_eq!(pixel(&picture, LEFT + 300, TOP + 50), (r / 2, g / 2, b / 2));
+        // the waterfall, the older spectrum shorter than the rest
+        let waterfall_top = TOP + SPECTRUM_HEIGHT + 1;
+        assert_eq!(pixel(&picture, LEFT + 300, waterfall_top), Palette::Inferno.rgb(1.0));
+        assert_eq!(pixel(&picture, LEFT, waterfall_top + 1), Palette::Inferno.rgb(1.0));
+        assert_eq!(pixel(&picture, LEFT + 500, waterfall_top + 1), BACKGROUND);
+        // the title's first 2
+        assert_eq!(pixel(&picture, LEFT, (TOP - 10) / 2), TEXT);
+
+        assert_eq!(nice_step(12.5), 20.0);
+        assert_eq!(nice_step(0.3), 0.5);
+        assert_eq!(nice_step(5000.0), 5000.0);
+        assert_eq!(nice_step(0.0), 1.0);
+    }
+}
@@ -0,0 +1,142 @@
+//! The `self-test` command, for checking an install on a new machine.
+
+use std::f32::consts::PI;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+use num::Complex;
+
+use cells::{CellAccessor, Widget};
+use drawing::{draw_spectrum, draw_waterfall};
+use palette::Palette;
+use processing::SignalProcessor;
+use radio::hackrf::HackRF;
+
+const SAMPLE_RATE_HZ: u32 = 2_000_000;
+const CAPTURE_TIME: Duration = Duration::from_millis(500);
+
+/// Runs every check and prints a report. Returns true if they all passed.
+pub fn run() -> bool {
+    let mut results = Vec::new();
+    results.push(("HackRF capture", check_hackrf()));
+    results.push(("FFT pipeline", check_fft()));
+    results.push(("renderer", check_renderer()));
+
+    for &(name, ref result) in &results {
+        match *result {
+            Ok(ref detail) => println!("[PASS] {}: {}", name, detail),
+            Err(ref detail) => println!("[FAIL] {}: {}", name, detail),
+        }
+    }
+
+    let failures = results.iter().filter(|&&(_, ref r)| r.is_err()).count();
+    println!("\n{} passed, {} failed", results.len() - failures, failures);
+    failures == 0
+}
+
+/// Opens the device, captures briefly and checks the samples look like a
+/// working receiver: something arrived, it isn't all zeros, and it isn't
+/// clipping.
+fn check_hackrf() -> Result<String, String> {
+    let mut radio = try!(HackRF::open().map_err(|_| "couldn't open a HackRF".to_string()));
+    try!(radio.set_frequency(100_000_000).map_err(|_| "couldn't set the frequency".to_string()));
+    try!(radio.set_sample_rate(SAMPLE_RATE_HZ as f64)
+              .map_err(|_| "couldn't set the sample rate".to_string()));
+
+    let recv = radio.start_rx();
+    let (mut count, mut sum, mut sum_sq, mut clipped) = (0u64, Complex::new(0.0, 0.0), 0.0, 0u64);
+    let start = Instant::now();
+    while start.elapsed() < CAPTURE_TIME {
+        let buff = match recv.recv_timeout(CAPTURE_TIME) {
+            Ok(buff) => buff,
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break,
+        };
+        for x in buff {
+            let c = Complex::new(x.re as f64, x.im as f64);
+            count += 1;
+            sum = sum + c;
+            sum_sq += c.norm_sqr();
+            if x.re == -128 || x.re == 127 || x.im == -128 || x.im == 127 {
+                clipped += 1;
+            }
+        }
+    }
+    try!(radio.stop_rx().map_err(|_| "couldn't stop receiving".to_string()));
+
+    if count == 0 {
+        return Err("no samples received".to_string());
+    }
+    let mean = sum / count as f64;
+    let rms = (sum_sq / count as f64).sqrt();
+    let clipped_fraction = clipped as f64 / count as f64;
+    let detail = format!("{} samples, DC offset {:.1}{:+.1}i, RMS {:.1}, {:.2}% clipped",
+                         count, mean.re, mean.im, rms, 100.0 * clipped_fraction);
+
+    if rms < 0.5 {
+        Err(format!("samples are all zero ({})", detail))
+    } else if clipped_fraction > 0.01 {
+        Err(format!("input is clipping, try lowering the gain ({})", detail))
+    } else {
+        Ok(detail)
+    }
+}
+
+/// Feeds tones at known bins through the signal processor and checks each
+/// one comes out in the right bin.
+fn check_fft() -> Result<String, String> {
+    const FFT_LEN: usize = 256;
+    let bins = [1, 17, 100, 200];
+
+    for &bin in bins.iter() {
+        let tone: Vec<Complex<i8>> = (0..FFT_LEN).map(|n| {
+            let phase = 2.0 * PI * (bin * n) as f32 / FFT_LEN as f32;
+            Complex::new((100.0 * phase.cos()) as i8, (100.0 * phase.sin()) as i8)
+        }).collect();
+
+        let mut processor = SignalProcessor::new(SAMPLE_RATE_HZ, 10, FFT_LEN);
+        // The processor discards samples between FFTs to hit its rate, so
+        // keep feeding the tone until a spectrum comes out.
+        let mut spectra = Vec::new();
+        for _ in 0..(SAMPLE_RATE_HZ as usize / FFT_LEN) {
+            spectra = processor.add_signal_buffer(tone.clone());
+            if !spectra.is_empty() {
+                break;
+            }
+        }
+        let spectrum = try!(spectra.first().ok_or("no spectrum produced".to_string()));
+
+        let peak = spectrum.iter()
+                           .enumerate()
+                           .max_by(|a, b| a.1.norm().partial_cmp(&b.1.norm()).unwrap())
+                           .map(|(i, _)| i)
+                           .unwrap();
+        if peak != bin {
+            return Err(format!("tone at bin {} came out at bin {}", bin, peak));
+        }
+    }
+    Ok(format!("{} tones found in the right bins", bins.len()))
+}
+
+/// Renders into an in-memory grid and checks the cells.
+fn check_renderer() -> Result<String, String> {
+    let mut grid = Widget::new(4, 2);
+
+    // full height in column 0, nothing in column 3
+    draw_spectrum(&mut grid, &[1.0, 1.0, 0.5, 0.5, 0.25, 0.25, 0.0, 0.0]);
+    let column = |grid: &Widget, col| -> String {
+        (0..2).map(|row| grid.get(col, row).unwrap().ch()).collect()
+    };
+    if column(&grid, 0) != "⣿⣿" || column(&grid, 1) != "⣀⣿" {
+        return Err(format!("unexpected spectrum cells {:?} {:?}",
+                           column(&grid, 0), column(&grid, 1)));
+    }
+
+    let rows = vec![vec![1.0; 8], vec![0.0; 8]];
+    draw_waterfall(&mut grid, rows.iter().map(|r| &r[..]), Palette::Classic);
+    let cell = grid.get(0, 0).unwrap();
+    if cell.ch() != '▀' || cell.fg().as_byte() != Palette::Classic.color(1.0) ||
+        cell.bg().as_byte() != Palette::Classic.color(0.0) {
+        return Err(format!("unexpected waterfall cell {:?}", cell));
+    }
+
+    Ok("spectrum and waterfall cells match".to_string())
+}
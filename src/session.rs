@@ -0,0 +1,283 @@
+//! Recording and replaying of UI sessions.
+//!
+//! A session file holds every spectrum handed to the `Canvas` along with the
+//! keys pressed, each stamped with the time since the start of the session, so
+//! a rendering bug can be reproduced exactly by replaying it.
+//!
+//! The format is a header (`TSPS`, a version byte, center frequency and sample
+//! rate as little endian f64s, terminal cols and rows as u32s) followed by
+//! records of a tag byte, a u64 millisecond timestamp and a payload. Version 2
+//! added the tuning record, for retuning partway through a session.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::time::{Duration, Instant};
+use num::Complex;
+
+use input::{Key, Mouse};
+
+const MAGIC: &'static [u8; 4] = b"TSPS";
+const VERSION: u8 = 2;
+
+const TAG_SPECTRUM: u8 = 0;
+const TAG_KEY: u8 = 1;
+const TAG_TUNING: u8 = 2;
+
+pub struct Header {
+    pub center_freq_hz: f64,
+    pub sample_rate_hz: f64,
+    pub cols: usize,
+    pub rows: usize,
+}
+
+pub enum Record {
+    Spectrum(Duration, Vec<Complex<f32>>),
+    Key(Duration, Key),
+    /// New center frequency and sample rate, in Hz.
+    Tuning(Duration, f64, f64),
+}
+
+pub struct SessionWriter {
+    out: BufWriter<File>,
+    start: Instant,
+}
+
+impl SessionWriter {
+    pub fn create(path: &str, header: &Header) -> io::Result<Self> {
+        let mut out = BufWriter::new(try!(File::create(path)));
+        try!(out.write_all(MAGIC));
+        try!(out.write_all(&[VERSION]));
+        try!(write_u64(&mut out, header.center_freq_hz.to_bits()));
+        try!(write_u64(&mut out, header.sample_rate_hz.to_bits()));
+        try!(write_u32(&mut out, header.cols as u32));
+        try!(write_u32(&mut out, header.rows as u32));
+        Ok(SessionWriter { out: out, start: Instant::now() })
+    }
+
+    fn write_record_start(&mut self, tag: u8) -> io::Result<()> {
+        let elapsed = self.start.elapsed();
+        let ms = elapsed.as_secs() * 1000 + (elapsed.subsec_nanos() / 1_000_000) as u64;
+        try!(self.out.write_all(&[tag]));
+        write_u64(&mut self.out, ms)
+    }
+
+    pub fn write_spectrum(&mut self, spec: &[Complex<f32>]) -> io::Result<()> {
+        try!(self.write_record_start(TAG_SPECTRUM));
+        try!(write_u32(&mut self.out, spec.len() as u32));
+        for x in spec {
+            try!(write_u32(&mut self.out, x.re.to_bits()));
+            try!(write_u32(&mut self.out, x.im.to_bits()));
+        }
+        Ok(())
+    }
+
+    pub fn write_key(&mut self, key: Key) -> io::Result<()> {
+        try!(self.write_record_start(TAG_KEY));
+        let (code, value) = encode_key(key);
+        try!(self.out.write_all(&[code]));
+        write_u32(&mut self.out, value)
+    }
+
+    pub fn write_tuning(&mut self, center_freq_hz: f64, sample_rate_hz: f64) -> io::Result<()> {
+        try!(self.write_record_start(TAG_TUNING));
+        try!(write_u64(&mut self.out, center_freq_hz.to_bits()));
+        write_u64(&mut self.out, sample_rate_hz.to_bits())
+    }
+}
+
+pub struct SessionReader {
+    input: BufReader<File>,
+    pub header: Header,
+}
+
+impl SessionReader {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let mut input = BufReader::new(try!(File::open(path)));
+        let mut magic = [0; 5];
+        try!(input.read_exact(&mut magic));
+        // version 1 files are the same, without tuning records
+        if &magic[..4] != MAGIC || magic[4] < 1 || magic[4] > VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a session file"));
+        }
+
+        let header = Header {
+            center_freq_hz: f64::from_bits(try!(read_u64(&mut input))),
+            sample_rate_hz: f64::from_bits(try!(read_u64(&mut input))),
+            cols: try!(read_u32(&mut input)) as usize,
+            rows: try!(read_u32(&mut input)) as usize,
+        };
+        Ok(SessionReader { input: input, header: header })
+    }
+
+    /// Reads the next record, or `None` at the end of the file. A record cut
+    /// off partway, as the last one is when tspec was killed while writing
+    /// it, is taken as the end too.
+    pub fn next_record(&mut self) -> io::Result<Option<Record>> {
+        let mut tag = [0];
+        if try!(self.input.read(&mut tag)) == 0 {
+            return Ok(None);
+        }
+        match self.read_record(tag[0]) {
+            Ok(record) => Ok(Some(record)),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_record(&mut self, tag: u8) -> io::Result<Record> {
+        let time = Duration::from_millis(try!(read_u64(&mut self.input)));
+
+        match tag {
+            TAG_SPECTRUM => {
+                let len = try!(read_u32(&mut self.input)) as usize;
+                let mut spec = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let re = f32::from_bits(try!(read_u32(&mut self.input)));
+                    let im = f32::from_bits(try!(read_u32(&mut self.input)));
+                    spec.push(Complex::new(re, im));
+                }
+                Ok(Record::Spectrum(time, spec))
+            }
+            TAG_KEY => {
+                let mut code = [0];
+                try!(self.input.read_exact(&mut code));
+                let c = try!(read_u32(&mut self.input));
+                match decode_key(code[0], c) {
+                    Some(key) => Ok(Record::Key(time, key)),
+                    None => Err(io::Error::new(io::ErrorKind::InvalidData, "bad key record")),
+                }
+            }
+            TAG_TUNING => {
+                let center_freq_hz = f64::from_bits(try!(read_u64(&mut self.input)));
+                let sample_rate_hz = f64::from_bits(try!(read_u64(&mut self.input)));
+                Ok(Record::Tuning(time, center_freq_hz, sample_rate_hz))
+            }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "unknown record")),
+        }
+    }
+}
+
+/// A code for the kind of key, and its character, or for the mouse the
+/// column and row packed into 16 bits each.
+fn encode_key(key: Key) -> (u8, u32) {
+    match key {
+        Key::Char(c) => (0, c as u32),
+        Key::Up => (1, 0),
+        Key::Down => (2, 0),
+        Key::Left => (3, 0),
+        Key::Right => (4, 0),
+        Key::PageUp => (5, 0),
+        Key::PageDown => (6, 0),
+        Key::Home => (7, 0),
+        Key::End => (8, 0),
+        Key::Enter => (9, 0),
+        Key::Backspace => (10, 0),
+        Key::Esc => (11, 0),
+        Key::Mouse(mouse, col, row) => {
+            let code = match mouse {
+                Mouse::Click => 12,
+                Mouse::DoubleClick => 13,
+                Mouse::WheelUp => 14,
+                Mouse::WheelDown => 15,
+            };
+            (code, (col.min(0xffff) << 16 | row.min(0xffff)) as u32)
+        }
+    }
+}
+
+fn decode_key(code: u8, c: u32) -> Option<Key> {
+    let (col, row) = ((c >> 16) as usize, (c & 0xffff) as usize);
+    Some(match code {
+        0 => Key::Char(match ::std::char::from_u32(c) {
+            Some(c) => c,
+            None => return None,
+        }),
+        1 => Key::Up,
+        2 => Key::Down,
+        3 => Key::Left,
+        4 => Key::Right,
+        5 => Key::PageUp,
+        6 => Key::PageDown,
+        7 => Key::Home,
+        8 => Key::End,
+        9 => Key::Enter,
+        10 => Key::Backspace,
+        11 => Key::Esc,
+        12 => Key::Mouse(Mouse::Click, col, row),
+        13 => Key::Mouse(Mouse::DoubleClick, col, row),
+        14 => Key::Mouse(Mouse::WheelUp, col, row),
+        15 => Key::Mouse(Mouse::WheelDown, col, row),
+        _ => return None,
+    })
+}
+
+fn write_u32<W: Write>(out: &mut W, x: u32) -> io::Result<()> {
+    let bytes = [x as u8, (x >> 8) as u8, (x >> 16) as u8, (x >> 24) as u8];
+    out.write_all(&bytes)
+}
+
+fn write_u64<W: Write>(out: &mut W, x: u64) -> io::Result<()> {
+    try!(write_u32(out, x as u32));
+    write_u32(out, (x >> 32) as u32)
+}
+
+fn read_u32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut b = [0; 4];
+    try!(input.read_exact(&mut b));
+    Ok(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24)
+}
+
+fn read_u64<R: Read>(input: &mut R) -> io::Result<u64> {
+    let lo = try!(read_u32(input)) as u64;
+    let hi = try!(read_u32(input)) as u64;
+    Ok(lo | hi << 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, OpenOptions};
+    use num::Complex;
+    use super::{encode_key, decode_key, Header, Record, SessionReader, SessionWriter};
+    use input::{Key, Mouse};
+
+    #[test]
+    fn test_truncated_session() {
+        let path = env::temp_dir().join(format!("tspec-session-{}", ::std::process::id()));
+        let path = path.to_str().unwrap();
+        let header = Header { center_freq_hz: 100e6, sample_rate_hz: 2e6, cols: 80, rows: 24 };
+        {
+            let mut session = SessionWriter::create(path, &header).unwrap();
+            session.write_key(Key::Char('z')).unwrap();
+            session.write_tuning(101e6, 4e6).unwrap();
+            session.write_spectrum(&[Complex::new(1.0, -2.0); 3]).unwrap();
+        }
+        // cut off partway through the spectrum
+        let len = fs::metadata(path).unwrap().len();
+        OpenOptions::new().write(true).open(path).unwrap().set_len(len - 5).unwrap();
+
+        let mut session = SessionReader::open(path).unwrap();
+        assert_eq!((session.header.center_freq_hz, session.header.cols), (100e6, 80));
+        match session.next_record().unwrap() {
+            Some(Record::Key(_, key)) => assert_eq!(key, Key::Char('z')),
+            _ => panic!("expected the key"),
+        }
+        match session.next_record().unwrap() {
+            Some(Record::Tuning(_, center_freq_hz, sample_rate_hz)) => {
+                assert_eq!((center_freq_hz, sample_rate_hz), (101e6, 4e6));
+            }
+            _ => panic!("expected the tuning"),
+        }
+        assert!(session.next_record().unwrap().is_none());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_key_round_trip() {
+        for &key in &[Key::Char('q'), Key::Char('é'), Key::PageUp, Key::Esc,
+                      Key::Mouse(Mouse::DoubleClick, 300, 70)] {
+            let (code, value) = encode_key(key);
+            assert_eq!(decode_key(code, value), Some(key));
+        }
+    }
+}
@@ -0,0 +1,344 @@
+//! Where samples come from: the radio, an IQ recording played back as if it
+//! were live, a stream over UDP, from GNU Radio or from a KiwiSDR, or signals
+//! made up on the spot.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use num::Complex;
+
+use kiwi::KiwiClient;
+use radio::synth::{self, Generator, Signal};
+use stats::Stats;
+use udp::{self, Context, UdpReceiver};
+use zmq::Subscriber;
+
+/// Extensions of the recordings `available` lists, interleaved signed 8 bit
+/// I and Q samples as written by `hackrf_transfer -r`.
+const FILE_EXTENSIONS: [&'static str; 2] = ["cs8", "iq"];
+
+/// Bytes read and sent on at a time, the size of the radio's own buffers.
+const BUFFER_LEN: usize = 262144;
+
+/// Samples a recording played fast gets ahead of the processing by, and how
+/// often it looks how far that's got.
+const FAST_AHEAD_LEN: u64 = 8 * BUFFER_LEN as u64 / 2;
+const FAST_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// How often a paused recording looks whether it's been played again.
+const PAUSED_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How slow and fast a recording can be played, as multiples of its sample
+/// rate.
+const MIN_SPEED: f64 = 0.125;
+const MAX_SPEED: f64 = 16.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Radio,
+    File(PathBuf),
+    /// Datagrams on a port.
+    Udp(u16, udp::Format),
+    /// A ZMQ PUB Sink's endpoint.
+    Zmq(String),
+    /// A KiwiSDR's address.
+    Kiwi(String),
+    Synth(Vec<Signal>),
+}
+
+impl Source {
+    /// The source `--source` names: `synth`, or `synth:` and the signals.
+    pub fn parse(spec: &str) -> Result<Source, String> {
+        match spec.find(':').map_or((spec, ""), |i| (&spec[..i], &spec[i + 1..])) {
+            ("synth", signals) => synth::parse(signals).map(Source::Synth),
+            _ => Err(format!("Unknown source '{}', expected synth or synth:<signals>", spec)),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Source::Radio => write!(f, "HackRF"),
+            Source::File(ref path) => write!(f, "{}", path.display()),
+            Source::Udp(port, _) => write!(f, "UDP port {}", port),
+            Source::Zmq(ref endpoint) => write!(f, "{}", endpoint),
+            Source::Kiwi(ref addr) => write!(f, "KiwiSDR {}", addr),
+            Source::Synth(_) => write!(f, "synthesized signals"),
+        }
+    }
+}
+
+/// The radio, then the recordings in the current directory, by name.
+pub fn available() -> Vec<Source> {
+    let mut files: Vec<PathBuf> = fs::read_dir(".")
+        .map(|entries| {
+            entries.filter_map(|entry| entry.ok().map(|e| e.path()))
+                   .filter(|path| {
+                       path.extension()
+                           .and_then(|ext| ext.to_str())
+                           .map_or(false, |ext| FILE_EXTENSIONS.contains(&ext))
+                   })
+                   .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+
+    let mut sources = vec![Source::Radio];
+    sources.extend(files.into_iter().map(Source::File));
+    sources
+}
+
+/// What's giving the samples in place of the radio, stopped once dropped.
+pub enum Feed {
+    // only kept until it's dropped
+    File(FilePlayer),
+    Udp(UdpReceiver),
+    Zmq(Subscriber),
+    Kiwi(KiwiClient),
+    Synth(Generator),
+}
+
+/// What a stream says of itself.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeedEvent {
+    /// Its tuning changed, or it's said it for the first time.
+    Tuned(Context),
+    /// It can't be reached, and why.
+    Lost(String),
+    /// A recording played once has got to its end.
+    Ended,
+}
+
+impl Feed {
+    /// What the stream's said of itself since the last look, if anything.
+    pub fn next_event(&self) -> Option<FeedEvent> {
+        match *self {
+            Feed::Udp(ref udp) => udp.next_context().map(FeedEvent::Tuned),
+            Feed::Kiwi(ref kiwi) => kiwi.next_event(),
+            Feed::File(ref player) => player.next_event(),
+            Feed::Zmq(_) | Feed::Synth(_) => None,
+        }
+    }
+}
+
+/// Plays a recording at its sample rate, or a multiple of it, until dropped,
+/// pausing at the end unless it's looped back to the start. Played fast,
+/// it's read once through as quickly as the processing takes it instead.
+pub struct FilePlayer {
+    stop: Arc<AtomicBool>,
+    /// Set once all of a fast one's samples have been processed.
+    finished: Arc<AtomicBool>,
+    playback: Arc<Mutex<Playback>>,
+    events: Receiver<FeedEvent>,
+    num_samples: u64,
+    sample_rate_hz: f64,
+}
+
+/// Where a recording's got to and how it's being played, shared with the
+/// thread reading it.
+struct Playback {
+    /// In samples from the start.
+    position: u64,
+    /// Where to go next, in samples, once the thread's looked.
+    seek_to: Option<u64>,
+    speed: f64,
+    paused: bool,
+    /// Paused for having got to the end.
+    ended: bool,
+}
+
+impl FilePlayer {
+    /// Played fast, it keeps only a few buffers ahead of the samples `fast`
+    /// counts as processed.
+    pub fn start(path: &Path, sample_rate_hz: f64, looped: bool, fast: Option<Arc<Stats>>)
+                 -> io::Result<(FilePlayer, Receiver<Vec<Complex<i8>>>)> {
+        let mut file = try!(File::open(path));
+        let len = try!(file.metadata()).len();
+        if len < 2 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the file has no samples"));
+        }
+
+        let (send, recv) = channel();
+        let (event_send, events) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let finished = Arc::new(AtomicBool::new(false));
+        let playback = Arc::new(Mutex::new(Playback {
+            position: 0,
+            seek_to: None,
+            speed: 1.0,
+            paused: false,
+            ended: false,
+        }));
+        let thread_stop = stop.clone();
+        let thread_finished = finished.clone();
+        let thread_playback = playback.clone();
+        thread::spawn(move || {
+            // the pace is kept from here, set again whenever it changes
+            let mut paced_from = Instant::now();
+            let mut paced_samples = 0u64;
+            let mut paced_speed = 1.0;
+            let mut num_samples = 0u64;
+            let mut buf = vec![0u8; BUFFER_LEN];
+            let count = || fast.as_ref().map_or(0, |s| s.snapshot().samples_received as u64);
+            let start_count = count();
+            let processed = || count() - start_count;
+            while !thread_stop.load(Ordering::Relaxed) {
+                let (speed, paused, seek_to) = {
+                    let mut playback = thread_playback.lock().unwrap();
+                    (playback.speed, playback.paused, playback.seek_to.take())
+                };
+                if let Some(sample) = seek_to {
+                    if file.seek(SeekFrom::Start(sample * 2)).is_err() {
+                        return;
+                    }
+                    thread_playback.lock().unwrap().position = sample;
+                }
+                if paused || seek_to.is_some() || speed != paced_speed {
+                    paced_from = Instant::now();
+                    paced_samples = 0;
+                    paced_speed = speed;
+                }
+                if paused {
+                    thread::sleep(PAUSED_POLL_INTERVAL);
+                    continue;
+                }
+
+                let n = match file.read(&mut buf) {
+                    Ok(0) if fast.is_some() => {
+                        while processed() < num_samples {
+                            if thread_stop.load(Ordering::Relaxed) {
+                                return;
+                            }
+                            thread::sleep(FAST_POLL_INTERVAL);
+                        }
+                        thread_finished.store(true, Ordering::Relaxed);
+                        return;
+                    }
+                    Ok(0) if looped => {
+                        if file.seek(SeekFrom::Start(0)).is_err() {
+                            return;
+                        }
+                        thread_playback.lock().unwrap().position = 0;
+                        continue;
+                    }
+                    Ok(0) => {
+                        {
+                            let mut playback = thread_playback.lock().unwrap();
+                            playback.paused = true;
+                            playback.ended = true;
+                        }
+                        if event_send.send(FeedEvent::Ended).is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                    Ok(n) => n & !1,
+                    Err(_) => return,
+                };
+                let samples: Vec<Complex<i8>> = buf[..n].chunks(2)
+                    .map(|iq| Complex::new(iq[0] as i8, iq[1] as i8))
+                    .collect();
+                num_samples += samples.len() as u64;
+                paced_samples += samples.len() as u64;
+                thread_playback.lock().unwrap().position += samples.len() as u64;
+                if send.send(samples).is_err() {
+                    return;
+                }
+                if fast.is_some() {
+                    // not so far ahead that the samples pile up in memory
+                    while num_samples.saturating_sub(processed()) > FAST_AHEAD_LEN &&
+                          !thread_stop.load(Ordering::Relaxed) {
+                        thread::sleep(FAST_POLL_INTERVAL);
+                    }
+                    continue;
+                }
+
+                // keep to the sample rate, as the radio would
+                let due_ms = paced_samples as f64 / (sample_rate_hz * speed) * 1000.0;
+                let due = Duration::from_millis(due_ms as u64);
+                if let Some(wait) = due.checked_sub(paced_from.elapsed()) {
+                    thread::sleep(wait);
+                }
+            }
+        });
+        let player = FilePlayer {
+            stop: stop,
+            finished: finished,
+            playback: playback,
+            events: events,
+            num_samples: len / 2,
+            sample_rate_hz: sample_rate_hz,
+        };
+        Ok((player, recv))
+    }
+
+    /// Whether it's got to its end since the last look.
+    pub fn next_event(&self) -> Option<FeedEvent> {
+        self.events.try_recv().ok()
+    }
+
+    /// True once a fast one's last samples have been processed.
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    /// How far into the recording the samples read so far go, in seconds.
+    pub fn position_secs(&self) -> f64 {
+        let playback = self.playback.lock().unwrap();
+        playback.seek_to.unwrap_or(playback.position) as f64 / self.sample_rate_hz
+    }
+
+    /// Moves `secs` on through the recording, or back if it's below 0, as
+    /// far as the start or end, returning the new position.
+    pub fn seek_by(&self, secs: f64) -> f64 {
+        let mut playback = self.playback.lock().unwrap();
+        let position = playback.seek_to.unwrap_or(playback.position) as f64 +
+                       secs * self.sample_rate_hz;
+        let sample = position.max(0.0).min((self.num_samples - 1) as f64) as u64;
+        playback.seek_to = Some(sample);
+        playback.ended = false;
+        sample as f64 / self.sample_rate_hz
+    }
+
+    /// Doubles the speed it's played at, or halves it, for a step below 0,
+    /// returning the new speed.
+    pub fn step_speed(&self, step: isize) -> f64 {
+        let mut playback = self.playback.lock().unwrap();
+        let speed = playback.speed * 2f64.powi(step as i32);
+        playback.speed = speed.max(MIN_SPEED).min(MAX_SPEED);
+        playback.speed
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.playback.lock().unwrap().paused
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.playback.lock().unwrap().speed
+    }
+
+    /// Stops reading the recording, or goes on, from the start if it's at
+    /// the end, returning whether it's now paused.
+    pub fn toggle_paused(&self) -> bool {
+        let mut playback = self.playback.lock().unwrap();
+        if playback.ended {
+            playback.ended = false;
+            playback.seek_to = Some(0);
+        }
+        playback.paused = !playback.paused;
+        playback.paused
+    }
+}
+
+impl Drop for FilePlayer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
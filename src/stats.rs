@@ -0,0 +1,91 @@
+//! Counts of what's gone through the pipeline, from the samples taken in to
+//! the frames drawn, for telling where it's falling behind. The processing
+//! thread and the canvas each count their own part into one `Stats`.
+
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use units::format_frequency;
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    samples_received: AtomicUsize,
+    samples_discarded: AtomicUsize,
+    ffts: AtomicUsize,
+    frames_drawn: AtomicUsize,
+    frames_dropped: AtomicUsize,
+}
+
+/// `Stats` as they were at one moment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Snapshot {
+    /// Samples that reached the processing.
+    pub samples_received: usize,
+    /// Of those, the ones skipped between FFTs to keep to the FFT rate.
+    pub samples_discarded: usize,
+    pub ffts: usize,
+    /// Frames put on the terminal.
+    pub frames_drawn: usize,
+    /// Spectra that didn't reach the drawing on their own, dropped or
+    /// averaged with others while it was behind.
+    pub frames_dropped: usize,
+}
+
+impl Stats {
+    /// Counts a buffer of `received` samples, `discarded` of which were
+    /// skipped, making `ffts` FFTs.
+    pub fn add_buffer(&self, received: usize, discarded: usize, ffts: usize) {
+        self.samples_received.fetch_add(received, Ordering::Relaxed);
+        self.samples_discarded.fetch_add(discarded, Ordering::Relaxed);
+        self.ffts.fetch_add(ffts, Ordering::Relaxed);
+    }
+
+    pub fn add_frame_drawn(&self) {
+        self.frames_drawn.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_frame_dropped(&self) {
+        self.frames_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            samples_received: self.samples_received.load(Ordering::Relaxed),
+            samples_discarded: self.samples_discarded.load(Ordering::Relaxed),
+            ffts: self.ffts.load(Ordering::Relaxed),
+            frames_drawn: self.frames_drawn.load(Ordering::Relaxed),
+            frames_dropped: self.frames_dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Short enough for the status bar, e.g. `rx 20M skip 19.9M FFT 10 drawn 8
+/// dropped 2`.
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rx {} skip {} FFT {} drawn {} dropped {}",
+               format_frequency(self.samples_received as f64),
+               format_frequency(self.samples_discarded as f64),
+               format_frequency(self.ffts as f64), format_frequency(self.frames_drawn as f64),
+               self.frames_dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Stats;
+
+    #[test]
+    fn test_stats() {
+        let stats = Stats::default();
+        stats.add_buffer(262144, 200000, 3);
+        stats.add_buffer(1000, 1000, 0);
+        stats.add_frame_drawn();
+        stats.add_frame_dropped();
+        stats.add_frame_dropped();
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.samples_received, 263144);
+        assert_eq!(snapshot.ffts, 3);
+        assert_eq!(snapshot.to_string(), "rx 263.144k skip 201k FFT 3 drawn 1 dropped 2");
+    }
+}
@@ -0,0 +1,247 @@
+//! Spectra as an iterator, for using the processing without the threads and
+//! channels the spectrograph wires up. Buffers of samples go in from any
+//! iterator, or a channel's receiving end, and spectra scaled for drawing
+//! come out, lowest frequency first. With the `futures` feature they can go
+//! in from a stream too, such as `HackRF::start_rx_stream`'s, and come out
+//! of one in an async task.
+
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::sync::mpsc::Receiver;
+#[cfg(feature = "futures")]
+use std::pin::Pin;
+#[cfg(feature = "futures")]
+use std::task::{Context, Poll};
+#[cfg(feature = "futures")]
+use futures::{Future, Sink, Stream, StreamExt};
+use num::Complex;
+
+use drawing::normalize_spectrum;
+use processing::{IntoComplex, SignalProcessor};
+
+/// The power range streams start with, in dB, scaled to 0 and 1.
+const MIN_DB: f32 = 0.0;
+const MAX_DB: f32 = 50.0;
+
+/// The spectra `processor` makes of each buffer of `buffers`, in turn, each
+/// scaled between 0 at the bottom of the power range and 1 at the top. It
+/// ends when the buffers do.
+pub struct SpectrumStream<I, S> {
+    buffers: I,
+    processor: SignalProcessor,
+    min_db: f32,
+    max_db: f32,
+    /// Made from the last buffer and not yet handed out.
+    pending: VecDeque<Vec<Complex<f32>>>,
+    samples: PhantomData<fn() -> S>,
+}
+
+impl<I, S: IntoComplex<f32>> SpectrumStream<I, S> {
+    fn with_buffers(buffers: I, processor: SignalProcessor) -> Self {
+        SpectrumStream {
+            buffers: buffers,
+            processor: processor,
+            min_db: MIN_DB,
+            max_db: MAX_DB,
+            pending: VecDeque::new(),
+            samples: PhantomData,
+        }
+    }
+
+    /// Scales the spectra between `min_db` and `max_db` instead.
+    pub fn db_range(mut self, min_db: f32, max_db: f32) -> Self {
+        self.min_db = min_db;
+        self.max_db = max_db;
+        self
+    }
+
+    fn add(&mut self, buff: Vec<S>) {
+        self.pending.extend(self.processor.add_signal_buffer(buff));
+    }
+
+    fn next_pending(&mut self) -> Option<Vec<f32>> {
+        self.pending.pop_front().map(|spec| {
+            normalize_spectrum(&spec, self.min_db, self.max_db, false)
+        })
+    }
+}
+
+impl<I, S> SpectrumStream<I, S>
+    where I: Iterator<Item = Vec<S>>,
+          S: IntoComplex<f32>
+{
+    pub fn new(buffers: I, processor: SignalProcessor) -> Self {
+        SpectrumStream::with_buffers(buffers, processor)
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<St, S> SpectrumStream<St, S>
+    where St: Stream<Item = Vec<S>> + Unpin,
+          S: IntoComplex<f32>
+{
+    /// The spectra of the buffers `buffers` yields, as a stream itself.
+    pub fn from_stream(buffers: St, processor: SignalProcessor) -> Self {
+        SpectrumStream::with_buffers(buffers, processor)
+    }
+}
+
+/// The buffers a channel gets, waiting for each, until every sender is gone.
+pub struct Received<S>(Receiver<Vec<S>>);
+
+impl<S> Iterator for Received<S> {
+    type Item = Vec<S>;
+
+    fn next(&mut self) -> Option<Vec<S>> {
+        self.0.recv().ok()
+    }
+}
+
+impl<S: IntoComplex<f32>> SpectrumStream<Received<S>, S> {
+    /// The spectra of the buffers `recv` gets.
+    pub fn from_receiver(recv: Receiver<Vec<S>>, processor: SignalProcessor) -> Self {
+        SpectrumStream::new(Received(recv), processor)
+    }
+
+    /// The next spectrum, if the buffers already waiting make one, without
+    /// waiting for more. This is for an event loop that looks again each
+    /// time round rather than blocking a thread; an async task wants
+    /// `from_stream` instead, which wakes it.
+    pub fn try_next(&mut self) -> Option<Vec<f32>> {
+        while self.pending.is_empty() {
+            let buff = match self.buffers.0.try_recv() {
+                Ok(buff) => buff,
+                Err(_) => return None,
+            };
+            self.add(buff);
+        }
+        self.next_pending()
+    }
+}
+
+impl<I, S> Iterator for SpectrumStream<I, S>
+    where I: Iterator<Item = Vec<S>>,
+          S: IntoComplex<f32>
+{
+    type Item = Vec<f32>;
+
+    fn next(&mut self) -> Option<Vec<f32>> {
+        while self.pending.is_empty() {
+            let buff = match self.buffers.next() {
+                Some(buff) => buff,
+                None => return None,
+            };
+            self.add(buff);
+        }
+        self.next_pending()
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<St, S> Stream for SpectrumStream<St, S>
+    where St: Stream<Item = Vec<S>> + Unpin,
+          S: IntoComplex<f32>
+{
+    type Item = Vec<f32>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Vec<f32>>> {
+        let this = self.get_mut();
+        while this.pending.is_empty() {
+            match Pin::new(&mut this.buffers).poll_next(cx) {
+                Poll::Ready(Some(buff)) => this.add(buff),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(this.next_pending())
+    }
+}
+
+/// A task processing the buffers `buffers` yields into `sink`, for running
+/// on whichever executor the caller has, so no thread waits on the samples.
+/// It finishes once the buffers end and `sink` has every spectrum, or when
+/// `sink` fails.
+#[cfg(feature = "futures")]
+pub fn spectra_task<St, S, Si>(buffers: St, processor: SignalProcessor, sink: Si)
+                               -> impl Future<Output = Result<(), Si::Error>>
+    where St: Stream<Item = Vec<S>> + Unpin,
+          S: IntoComplex<f32>,
+          Si: Sink<Vec<f32>>
+{
+    SpectrumStream::from_stream(buffers, processor).map(Ok).forward(sink)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use num::Complex;
+    use processing::SignalProcessor;
+    use super::SpectrumStream;
+
+    #[test]
+    fn test_spectrum_stream() {
+        // DC at 10 dB and 100 dB, a spectrum and a half of it each time
+        let buffers = vec![vec![Complex::new(10.0f32 / 4.0, 0.0); 6],
+                           vec![Complex::new(1e10f32 / 4.0, 0.0); 6]];
+        let processor = SignalProcessor::builder(40).fft_rate_hz(10).fft_len(4).build();
+        let spectra: Vec<Vec<f32>> = SpectrumStream::new(buffers.into_iter(), processor)
+                                         .db_range(0.0, 100.0).collect();
+        assert_eq!(spectra.len(), 3);
+        // DC is in the middle, once shifted
+        assert_eq!(spectra[0][2], 0.1);
+        assert_eq!(spectra[2][2], 1.0);
+
+        let (send, recv) = channel();
+        send.send(vec![100i16; 8]).unwrap();
+        drop(send);
+        let processor = SignalProcessor::builder(40).fft_len(4).build();
+        assert_eq!(SpectrumStream::from_receiver(recv, processor).count(), 2);
+    }
+
+    #[test]
+    fn test_try_next() {
+        let (send, recv) = channel();
+        send.send(vec![100i16; 6]).unwrap();
+        let processor = SignalProcessor::builder(40).fft_len(4).build();
+        let mut stream = SpectrumStream::from_receiver(recv, processor);
+        // half a spectrum short of a second one, so that's all for now
+        assert!(stream.try_next().is_some());
+        assert!(stream.try_next().is_none());
+        send.send(vec![100i16; 6]).unwrap();
+        drop(send);
+        assert_eq!(stream.count(), 2);
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn test_from_stream() {
+        use futures::executor::block_on;
+        use futures::stream::{self, StreamExt};
+
+        let buffers = stream::iter(vec![vec![100i16; 6], vec![100i16; 6]]);
+        let processor = SignalProcessor::builder(40).fft_len(4).build();
+        let stream = SpectrumStream::from_stream(buffers, processor);
+        assert_eq!(block_on(stream.collect::<Vec<_>>()).len(), 3);
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn test_spectra_task() {
+        use std::thread;
+        use futures::channel::mpsc;
+        use futures::executor::block_on;
+        use futures::StreamExt;
+        use super::spectra_task;
+
+        let (mut buff_send, buff_recv) = mpsc::channel(1);
+        let (spec_send, spec_recv) = mpsc::unbounded();
+        // fed from another thread, so the task waits on the buffers
+        let feeder = thread::spawn(move || for _ in 0..4 {
+            block_on(futures::SinkExt::send(&mut buff_send, vec![100i16; 6])).unwrap();
+        });
+        let processor = SignalProcessor::builder(40).fft_len(4).build();
+        block_on(spectra_task(buff_recv, processor, spec_send)).unwrap();
+        feeder.join().unwrap();
+        assert_eq!(block_on(spec_recv.collect::<Vec<_>>()).len(), 6);
+    }
+}
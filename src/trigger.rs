@@ -0,0 +1,101 @@
+//! Deciding when to record, from the power in a range of frequencies, so a
+//! run left going unattended only keeps the bursts.
+
+use std::time::{Duration, Instant};
+use num::Complex;
+
+use export::shifted_powers_db;
+use units::parse_frequency;
+
+/// Parses a `<low-hz>:<high-hz>` range.
+pub fn parse_range(s: &str) -> Result<(f64, f64), String> {
+    let parse = |x: &str| match parse_frequency(x) {
+        Some(hz) if hz >= 0.0 => Ok(hz),
+        _ => Err(format!("'{}' isn't a frequency", x.trim())),
+    };
+    let ends: Vec<&str> = s.split(':').collect();
+    if ends.len() != 2 {
+        return Err("expected <low-hz>:<high-hz>".to_string());
+    }
+    let (low, high) = (try!(parse(ends[0])), try!(parse(ends[1])));
+    if high < low {
+        return Err("the high end is below the low end".to_string());
+    }
+    Ok((low, high))
+}
+
+/// A recording starting or stopping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Start,
+    Stop,
+}
+
+/// Fires while the strongest bin in its range is above the threshold, and for
+/// the hold time after, so a burst with gaps stays in one recording.
+pub struct Trigger {
+    range_hz: (f64, f64),
+    threshold_db: f32,
+    hold: Duration,
+    /// When a spectrum was last above the threshold, while fired.
+    above_at: Option<Instant>,
+}
+
+impl Trigger {
+    pub fn new(range_hz: (f64, f64), threshold_db: f32, hold: Duration) -> Self {
+        Trigger { range_hz: range_hz, threshold_db: threshold_db, hold: hold, above_at: None }
+    }
+
+    /// Checks an unshifted FFT output taken at the given tuning, returning
+    /// whether a recording should start or stop.
+    pub fn observe(&mut self, spec: &[Complex<f32>], center_freq_hz: f64,
+                   sample_rate_hz: f64) -> Option<Edge> {
+        let powers = shifted_powers_db(spec);
+        let above = range_peak_db(&powers, center_freq_hz, sample_rate_hz, self.range_hz)
+                        .map_or(false, |db| db >= self.threshold_db);
+        if above {
+            let fired = self.above_at.is_none();
+            self.above_at = Some(Instant::now());
+            if fired { Some(Edge::Start) } else { None }
+        } else if self.above_at.map_or(false, |t| t.elapsed() >= self.hold) {
+            self.above_at = None;
+            Some(Edge::Stop)
+        } else {
+            None
+        }
+    }
+}
+
+/// Strongest power in a shifted spectrum between the ends of `range_hz`,
+/// skipping the DC bin, or `None` if the tuning doesn't cover any of it.
+fn range_peak_db(powers: &[f32], center_freq_hz: f64, sample_rate_hz: f64,
+                 range_hz: (f64, f64)) -> Option<f32> {
+    let bin_width_hz = sample_rate_hz / powers.len() as f64;
+    let dc = powers.len() / 2;
+    powers.iter().enumerate()
+        .filter(|&(i, _)| {
+            let freq_hz = center_freq_hz + (i as f64 - dc as f64) * bin_width_hz;
+            i != dc && freq_hz >= range_hz.0 && freq_hz <= range_hz.1
+        })
+        .map(|(_, &db)| db)
+        .fold(None, |peak, db| Some(peak.map_or(db, |p: f32| p.max(db))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_range, range_peak_db};
+
+    #[test]
+    fn test_range_peak_db() {
+        assert_eq!(parse_range("433.8e6:434.1e6"), Ok((433.8e6, 434.1e6)));
+        assert!(parse_range("434e6:433e6").is_err());
+        assert!(parse_range("433e6").is_err());
+
+        // 8 bins of 1 MHz around 100 MHz, from 96 to 103 MHz
+        let powers = [1.0, 2.0, 9.0, 3.0, 50.0, 4.0, 5.0, 6.0];
+        assert_eq!(range_peak_db(&powers, 100e6, 8e6, (97.5e6, 99.5e6)), Some(9.0));
+        // the DC bin is left out
+        assert_eq!(range_peak_db(&powers, 100e6, 8e6, (99e6, 101e6)), Some(4.0));
+        assert_eq!(range_peak_db(&powers, 100e6, 8e6, (200e6, 201e6)), None);
+    }
+}
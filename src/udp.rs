@@ -0,0 +1,253 @@
+//! Samples streamed over UDP, for radios and channelizers that send IQ over
+//! the network instead of USB, like a USRP. Datagrams are either plain
+//! interleaved samples, or VITA-49 packets (DIFI's among them) whose
+//! context packets say the frequency and sample rate. Samples are squeezed
+//! to the 8 bits the rest of the program takes, as the HackRF's are.
+
+use std::io::{self, ErrorKind};
+use std::mem;
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+use num::Complex;
+
+/// Samples sent on at a time, fewer if a datagram doesn't come for a while.
+const BATCH_LEN: usize = 16384;
+
+/// How long to wait for a datagram before sending on what's come, and
+/// looking whether to stop.
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Frequencies and sample rates in context packets have 20 bits after the
+/// binary point.
+const FIXED_POINT_SCALE: f64 = (1 << 20) as f64;
+
+/// How the datagrams' samples are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, RustcDecodable)]
+pub enum Format {
+    /// Signed 8 bit I and Q, as the HackRF's recordings are.
+    Cs8,
+    /// Signed 16 bit little endian I and Q.
+    Cs16,
+    /// 32 bit little endian float I and Q, from -1 to 1.
+    Cf32,
+    /// VITA-49 signal data packets of signed 16 bit big endian I and Q, and
+    /// context packets.
+    Vita49,
+}
+
+/// What a context packet says about the stream, each only if it says it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Context {
+    pub freq_hz: Option<f64>,
+    pub sample_rate_hz: Option<f64>,
+}
+
+/// Receives datagrams on a port, until dropped.
+pub struct UdpReceiver {
+    stop: Arc<AtomicBool>,
+    contexts: Receiver<Context>,
+}
+
+impl UdpReceiver {
+    pub fn start(port: u16, format: Format)
+                 -> io::Result<(UdpReceiver, Receiver<Vec<Complex<i8>>>)> {
+        let socket = try!(UdpSocket::bind(("0.0.0.0", port)));
+        try!(socket.set_read_timeout(Some(READ_TIMEOUT)));
+
+        let (send, recv) = channel();
+        let (context_send, contexts) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        thread::spawn(move || {
+            // as big as a datagram gets
+            let mut buf = vec![0u8; 65536];
+            let mut samples = Vec::with_capacity(BATCH_LEN);
+            let mut last_context = None;
+            while !thread_stop.load(Ordering::Relaxed) {
+                let timed_out = match socket.recv_from(&mut buf) {
+                    Ok((n, _)) => {
+                        let context = decode(format, &buf[..n], &mut samples);
+                        if let Some(changed) = context.filter(|_| context != last_context) {
+                            last_context = context;
+                            if context_send.send(changed).is_err() {
+                                return;
+                            }
+                        }
+                        false
+                    }
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock ||
+                                  e.kind() == ErrorKind::TimedOut => true,
+                    Err(_) => return,
+                };
+                if samples.len() >= BATCH_LEN || (timed_out && !samples.is_empty()) {
+                    let batch = mem::replace(&mut samples, Vec::with_capacity(BATCH_LEN));
+                    if send.send(batch).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok((UdpReceiver { stop: stop, contexts: contexts }, recv))
+    }
+
+    /// The stream's tuning, each time a context packet changes it.
+    pub fn next_context(&self) -> Option<Context> {
+        self.contexts.try_recv().ok()
+    }
+}
+
+impl Drop for UdpReceiver {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Adds the samples of a datagram, or any message laid out the same, to
+/// `samples`, returning what it says of the stream if it's a context packet.
+/// What can't be made sense of is skipped.
+pub fn decode(format: Format, datagram: &[u8], samples: &mut Vec<Complex<i8>>) -> Option<Context> {
+    match format {
+        Format::Cs8 => {
+            samples.extend(datagram.chunks(2).filter(|iq| iq.len() == 2)
+                                   .map(|iq| Complex::new(iq[0] as i8, iq[1] as i8)));
+        }
+        Format::Cs16 => {
+            samples.extend(datagram.chunks(4).filter(|iq| iq.len() == 4).map(|iq| {
+                let i = (iq[0] as u16 | (iq[1] as u16) << 8) as i16;
+                let q = (iq[2] as u16 | (iq[3] as u16) << 8) as i16;
+                Complex::new((i >> 8) as i8, (q >> 8) as i8)
+            }));
+        }
+        Format::Cf32 => {
+            let sample = |b: &[u8]| {
+                let bits = b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 |
+                           (b[3] as u32) << 24;
+                // out of range casts saturate
+                (f32::from_bits(bits) * 127.0).round() as i8
+            };
+            samples.extend(datagram.chunks(8).filter(|iq| iq.len() == 8)
+                                   .map(|iq| Complex::new(sample(&iq[..4]), sample(&iq[4..]))));
+        }
+        Format::Vita49 => return decode_vita49(datagram, samples),
+    }
+    None
+}
+
+fn decode_vita49(datagram: &[u8], samples: &mut Vec<Complex<i8>>) -> Option<Context> {
+    let words: Vec<u32> = datagram.chunks(4).filter(|w| w.len() == 4).map(|w| {
+        (w[0] as u32) << 24 | (w[1] as u32) << 16 | (w[2] as u32) << 8 | w[3] as u32
+    }).collect();
+    let header = match words.first() {
+        Some(&header) => header,
+        None => return None,
+    };
+    let packet_type = header >> 28;
+    let len = (header & 0xffff) as usize;
+    if len > words.len() {
+        return None;
+    }
+    // the header, then the stream ID, class ID and timestamps there are
+    let mut start = 1;
+    if packet_type == 1 || packet_type >= 3 {
+        start += 1;
+    }
+    if header & (1 << 27) != 0 {
+        start += 2;
+    }
+    if (header >> 22) & 3 != 0 {
+        start += 1;
+    }
+    if (header >> 20) & 3 != 0 {
+        start += 2;
+    }
+    if start > len {
+        return None;
+    }
+
+    match packet_type {
+        // signal data, with or without a stream ID
+        0 | 1 => {
+            let end = if header & (1 << 26) != 0 { len - 1 } else { len };
+            samples.extend(words[start..end.max(start)].iter().map(|&w| {
+                Complex::new(((w >> 24) as u8) as i8, ((w >> 8) as u8) as i8)
+            }));
+            None
+        }
+        4 => parse_context(&words[start..len]),
+        _ => None,
+    }
+}
+
+/// Picks the RF reference frequency and sample rate out of a context
+/// packet's fields, after its prologue.
+fn parse_context(words: &[u32]) -> Option<Context> {
+    let indicators = match words.first() {
+        Some(&indicators) => indicators,
+        None => return None,
+    };
+    let mut context = Context { freq_hz: None, sample_rate_hz: None };
+    let mut at = 1;
+    // the words each field takes, from bit 30 down to the sample rate at 21
+    let sizes = [1, 2, 2, 2, 2, 2, 1, 1, 1, 2];
+    for (size, bit) in sizes.iter().zip((21..31).rev()) {
+        if indicators & (1 << bit) == 0 {
+            continue;
+        }
+        if at + size > words.len() {
+            return None;
+        }
+        let fixed_point = || ((words[at] as u64) << 32 | words[at + 1] as u64) as i64 as f64 /
+                             FIXED_POINT_SCALE;
+        match bit {
+            27 => context.freq_hz = Some(fixed_point()),
+            21 => context.sample_rate_hz = Some(fixed_point()),
+            _ => {}
+        }
+        at += size;
+    }
+    Some(context)
+}
+
+#[cfg(test)]
+mod tests {
+    use num::Complex;
+    use super::{decode, Context, Format};
+
+    #[test]
+    fn test_decode() {
+        let mut samples = Vec::new();
+        // 0x1234 and -2 little endian, and half a sample
+        assert_eq!(decode(Format::Cs16, &[0x34, 0x12, 0xfe, 0xff, 0x00], &mut samples), None);
+        assert_eq!(samples, vec![Complex::new(0x12, -1)]);
+        samples.clear();
+        decode(Format::Cf32, &[0, 0, 0, 0x3f, 0, 0, 0x80, 0xbf], &mut samples);
+        assert_eq!(samples, vec![Complex::new(64, -127)]);
+
+        // signal data with a stream ID, an integer timestamp and a trailer
+        let data = [0x14, 0x40, 0x00, 0x06, 0, 0, 0, 7, 0, 0, 0, 1,
+                    0x12, 0x34, 0xff, 0x00, 0x80, 0x00, 0x7f, 0xff, 0, 0, 0, 0];
+        samples.clear();
+        assert_eq!(decode(Format::Vita49, &data, &mut samples), None);
+        assert_eq!(samples, vec![Complex::new(0x12, -1), Complex::new(-128, 0x7f)]);
+
+        // context with the RF reference frequency, 100.5 MHz, and the
+        // sample rate, 2 MHz, but not the gain between them
+        let mut context = vec![0x40, 0x00, 0x00, 0x07, 0, 0, 0, 7, 0x08, 0xa0, 0x00, 0x00];
+        let fixed = |hz: u64| {
+            let x = hz << 20;
+            (0..8).rev().map(|i| (x >> (8 * i)) as u8).collect::<Vec<u8>>()
+        };
+        context.extend(fixed(100500000));
+        context.extend(&[0, 0, 0, 0]);
+        context.extend(fixed(2000000));
+        context[3] = (context.len() / 4) as u8;
+        assert_eq!(decode(Format::Vita49, &context, &mut samples), Some(Context {
+            freq_hz: Some(100500000.0),
+            sample_rate_hz: Some(2000000.0),
+        }));
+    }
+}
@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+/// Formats a frequency in Hz with an SI suffix, e.g. `100.125M`.
+pub fn format_frequency(hz: f64) -> String {
+    let abs = hz.abs();
+    let (scaled, suffix) = if abs >= 1e9 {
+        (hz / 1e9, "G")
+    } else if abs >= 1e6 {
+        (hz / 1e6, "M")
+    } else if abs >= 1e3 {
+        (hz / 1e3, "k")
+    } else {
+        (hz, "")
+    };
+
+    // trim trailing zeros so labels stay short
+    let s = format!("{:.3}", scaled);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    format!("{}{}", s, suffix)
+}
+
+/// Parses a frequency in Hz, with an optional k, M or G suffix.
+pub fn parse_frequency(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let (number, scale) = match s.chars().last() {
+        Some('k') => (&s[..s.len() - 1], 1e3),
+        Some('M') => (&s[..s.len() - 1], 1e6),
+        Some('G') => (&s[..s.len() - 1], 1e9),
+        _ => (s, 1.0),
+    };
+    number.parse::<f64>().ok().map(|x| x * scale)
+}
+
+/// Parses a length of time in seconds, with an optional s, m or h suffix,
+/// e.g. `30s` or `1.5h`.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let (number, scale) = match s.chars().last() {
+        Some('s') => (&s[..s.len() - 1], 1.0),
+        Some('m') => (&s[..s.len() - 1], 60.0),
+        Some('h') => (&s[..s.len() - 1], 3600.0),
+        _ => (s, 1.0),
+    };
+    match number.parse::<f64>() {
+        Ok(x) if x >= 0.0 => Some(Duration::from_millis((x * scale * 1000.0) as u64)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::{format_frequency, parse_duration, parse_frequency};
+
+    #[test]
+    fn test_format_frequency() {
+        assert_eq!(format_frequency(100.125e6), "100.125M");
+        assert_eq!(format_frequency(2.4e9), "2.4G");
+        assert_eq!(format_frequency(250e3), "250k");
+        assert_eq!(format_frequency(-1500.0), "-1.5k");
+        assert_eq!(format_frequency(12.0), "12");
+    }
+
+    #[test]
+    fn test_parse_frequency() {
+        assert_eq!(parse_frequency("100M"), Some(100e6));
+        assert_eq!(parse_frequency("433.92M"), Some(433.92e6));
+        assert_eq!(parse_frequency("2.4G"), Some(2.4e9));
+        assert_eq!(parse_frequency("1000000"), Some(1e6));
+        // just short of the whole number, so it's rounded to it, not truncated
+        assert_eq!(parse_frequency("128.01M").map(|hz| hz.round() as u64), Some(128010000));
+        assert_eq!(parse_frequency("M"), None);
+        assert_eq!(parse_frequency("1.2.3k"), None);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("1.5h"), Some(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_duration("0.25"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_duration("-1s"), None);
+        assert_eq!(parse_duration("s"), None);
+    }
+}
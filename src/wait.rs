@@ -0,0 +1,270 @@
+//! Blocking waits on terminal input and new spectra at the same time.
+//!
+//! The processing thread signals new data by writing a byte to a pipe, so the
+//! UI can sleep in `poll` until there's either a key press or a spectrum to
+//! draw, instead of spinning between the two. A stop signal interrupts the
+//! wait too, so the loop can shut down cleanly.
+//!
+//! That's on Unix. Elsewhere the waker is a channel, and the wait asks
+//! crossterm whether there's input a few milliseconds at a time, checking the
+//! channel in between.
+
+#[cfg(not(unix))]
+use std::cmp::min;
+#[cfg(unix)]
+use std::fs::File;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(not(unix))]
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TryRecvError};
+#[cfg(not(unix))]
+use std::thread;
+use std::time::Duration;
+#[cfg(not(unix))]
+use std::time::Instant;
+#[cfg(not(unix))]
+use crossterm::event;
+use libc;
+
+#[cfg(all(not(unix), not(feature = "crossterm")))]
+compile_error!("waiting on input needs the crossterm feature away from Unix");
+
+/// How long a wait goes without checking for data, away from Unix.
+#[cfg(not(unix))]
+const DATA_CHECK_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Set by SIGINT, SIGTERM and SIGHUP once `catch_stop_signals` is called.
+static STOP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_stop_signal(_: libc::c_int) {
+    STOP.store(true, Ordering::Relaxed);
+}
+
+/// Has the signals that would kill the process set a flag instead, so the
+/// radio is stopped and the terminal restored on the way out.
+pub fn catch_stop_signals() {
+    let handler = on_stop_signal as extern "C" fn(libc::c_int);
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, handler as libc::sighandler_t);
+        #[cfg(unix)]
+        libc::signal(libc::SIGHUP, handler as libc::sighandler_t);
+    }
+}
+
+pub fn stop_requested() -> bool {
+    STOP.load(Ordering::Relaxed)
+}
+
+/// Wakes up a `WaitSet`. The wait set sees a data wakeup when this is dropped
+/// too, so the UI notices when the processing thread exits.
+#[cfg(unix)]
+pub struct Waker {
+    fd: RawFd,
+}
+
+#[cfg(not(unix))]
+pub struct Waker {
+    send: SyncSender<()>,
+}
+
+#[cfg(unix)]
+impl Waker {
+    pub fn wake(&self) {
+        let byte = [1u8];
+        // If the pipe is full the UI hasn't caught up yet, and is already due
+        // to wake, so a failed write can be ignored.
+        unsafe {
+            libc::write(self.fd, byte.as_ptr() as *const libc::c_void, 1);
+        }
+    }
+
+    /// Another waker for the same wait set. The drop wakeup only comes once
+    /// every copy is dropped.
+    pub fn try_clone(&self) -> io::Result<Waker> {
+        let fd = unsafe { libc::dup(self.fd) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Waker { fd: fd })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Waker {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+impl Waker {
+    pub fn wake(&self) {
+        // a wakeup already waiting will do
+        let _ = self.send.try_send(());
+    }
+
+    pub fn try_clone(&self) -> io::Result<Waker> {
+        Ok(Waker { send: self.send.clone() })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wakeup {
+    Data,
+    Input,
+    Timeout,
+}
+
+#[cfg(unix)]
+pub struct WaitSet {
+    wake_fd: RawFd,
+    // Only polled for readability, the backend does the actual reading.
+    // `None` without a terminal, to wait on data alone.
+    tty: Option<File>,
+}
+
+#[cfg(not(unix))]
+pub struct WaitSet {
+    wake: Receiver<()>,
+    // whether to wait on crossterm's input too
+    tty: bool,
+}
+
+#[cfg(unix)]
+impl WaitSet {
+    pub fn new() -> io::Result<(WaitSet, Waker)> {
+        let tty = try!(File::open("/dev/tty"));
+        WaitSet::open(Some(tty))
+    }
+
+    /// A wait set that never sees input, for running without a terminal.
+    pub fn without_tty() -> io::Result<(WaitSet, Waker)> {
+        WaitSet::open(None)
+    }
+
+    fn open(tty: Option<File>) -> io::Result<(WaitSet, Waker)> {
+        let mut fds = [0; 2];
+        unsafe {
+            if libc::pipe(fds.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            for &fd in fds.iter() {
+                let flags = libc::fcntl(fd, libc::F_GETFL);
+                libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+            }
+        }
+
+        Ok((WaitSet { wake_fd: fds[0], tty: tty }, Waker { fd: fds[1] }))
+    }
+
+    /// Blocks until there's terminal input, or data if `want_data` is set, or
+    /// until `timeout` passes or a signal arrives.
+    pub fn wait(&mut self, want_data: bool, timeout: Duration) -> io::Result<Wakeup> {
+        let mut fds = [
+            // poll skips negative fds
+            libc::pollfd {
+                fd: self.tty.as_ref().map_or(-1, |tty| tty.as_raw_fd()),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd { fd: self.wake_fd, events: libc::POLLIN, revents: 0 },
+        ];
+        let nfds = if want_data { 2 } else { 1 };
+        let timeout_ms = timeout.as_secs() as i32 * 1000 + (timeout.subsec_nanos() / 1_000_000) as i32;
+
+        let res = unsafe { libc::poll(fds.as_mut_ptr(), nfds, timeout_ms) };
+        if res < 0 {
+            let err = io::Error::last_os_error();
+            // a SIGWINCH handler or a stop signal, which the caller
+            // checks for on any wakeup
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(Wakeup::Timeout);
+            }
+            return Err(err);
+        }
+
+        if fds[0].revents != 0 {
+            Ok(Wakeup::Input)
+        } else if want_data && fds[1].revents != 0 {
+            self.drain();
+            Ok(Wakeup::Data)
+        } else {
+            Ok(Wakeup::Timeout)
+        }
+    }
+
+    fn drain(&mut self) {
+        let mut buf = [0u8; 64];
+        loop {
+            let n = unsafe {
+                libc::read(self.wake_fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+            };
+            if n <= 0 {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for WaitSet {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.wake_fd);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+impl WaitSet {
+    pub fn new() -> io::Result<(WaitSet, Waker)> {
+        Ok(WaitSet::open(true))
+    }
+
+    /// A wait set that never sees input, for running without a terminal.
+    pub fn without_tty() -> io::Result<(WaitSet, Waker)> {
+        Ok(WaitSet::open(false))
+    }
+
+    fn open(tty: bool) -> (WaitSet, Waker) {
+        let (send, wake) = sync_channel(1);
+        (WaitSet { wake: wake, tty: tty }, Waker { send: send })
+    }
+
+    /// Blocks until there's terminal input, or data if `want_data` is set, or
+    /// until `timeout` passes or a stop is requested.
+    pub fn wait(&mut self, want_data: bool, timeout: Duration) -> io::Result<Wakeup> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            // input first, so a steady stream of data can't keep keys waiting
+            if self.tty && try!(event::poll(Duration::from_secs(0))) {
+                return Ok(Wakeup::Input);
+            }
+            if want_data {
+                match self.wake.try_recv() {
+                    Ok(()) => return Ok(Wakeup::Data),
+                    // every waker's gone, like a closed pipe
+                    Err(TryRecvError::Disconnected) => return Ok(Wakeup::Data),
+                    Err(TryRecvError::Empty) => {}
+                }
+            }
+            let now = Instant::now();
+            if now >= deadline || stop_requested() {
+                return Ok(Wakeup::Timeout);
+            }
+            let slice = min(deadline - now, DATA_CHECK_INTERVAL);
+            if self.tty {
+                if try!(event::poll(slice)) {
+                    return Ok(Wakeup::Input);
+                }
+            } else {
+                thread::sleep(slice);
+            }
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Noticing when the radio stops delivering samples, so it can be restarted.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+use num::Complex;
+
+/// Buffers waiting for whatever takes the samples, past which they're
+/// dropped as overruns rather than piling up while it's behind.
+const QUEUED_BUFFERS: usize = 64;
+
+/// What `Watchdog::poll` noticed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// No samples have arrived for the timeout, since they last did or since
+    /// the last `Stalled`.
+    Stalled,
+    /// Samples are arriving again, after an outage of this long.
+    Recovered(Duration),
+}
+
+/// Passes samples on from each receive session of the radio to a single
+/// channel that outlives them, keeping track of when they last arrived.
+pub struct Watchdog {
+    send: SyncSender<Vec<Complex<i8>>>,
+    /// Buffers arrived so far, counted by the forwarding threads.
+    num_buffers: Arc<AtomicUsize>,
+    /// Of those, the ones dropped for the channel being full.
+    overruns: Arc<AtomicUsize>,
+    last_count: usize,
+    /// When the count last changed, or the last `Stalled`.
+    last_checked: Instant,
+    timeout: Duration,
+    stalled_since: Option<Instant>,
+}
+
+impl Watchdog {
+    /// Returns the watchdog and the receiving end of its channel.
+    pub fn new(timeout: Duration) -> (Self, Receiver<Vec<Complex<i8>>>) {
+        let (send, recv) = sync_channel(QUEUED_BUFFERS);
+        let watchdog = Watchdog {
+            send: send,
+            num_buffers: Arc::new(AtomicUsize::new(0)),
+            overruns: Arc::new(AtomicUsize::new(0)),
+            last_count: 0,
+            last_checked: Instant::now(),
+            timeout: timeout,
+            stalled_since: None,
+        };
+        (watchdog, recv)
+    }
+
+    /// Forwards the samples from `recv` until the radio drops its end.
+    pub fn watch(&mut self, recv: Receiver<Vec<Complex<i8>>>) {
+        let send = self.send.clone();
+        let num_buffers = self.num_buffers.clone();
+        let overruns = self.overruns.clone();
+        thread::spawn(move || {
+            for buff in recv.iter() {
+                // counted even if dropped, the radio's still delivering
+                num_buffers.fetch_add(1, Ordering::Relaxed);
+                match send.try_send(buff) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => {
+                        overruns.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(TrySendError::Disconnected(_)) => return,
+                }
+            }
+        });
+        // the new session gets the whole timeout to start up
+        self.last_checked = Instant::now();
+    }
+
+    /// Takes samples not arriving until now as expected, for an input that's
+    /// stopped sending them on purpose.
+    pub fn excuse(&mut self) {
+        self.last_checked = Instant::now();
+        self.stalled_since = None;
+    }
+
+    /// Buffers dropped so far for the receiving end falling behind, here or
+    /// at a tee counting into `overrun_counter`.
+    pub fn overruns(&self) -> usize {
+        self.overruns.load(Ordering::Relaxed)
+    }
+
+    /// The count behind `overruns`, for the tees after the watchdog to add
+    /// what they drop to.
+    pub fn overrun_counter(&self) -> Arc<AtomicUsize> {
+        self.overruns.clone()
+    }
+
+    pub fn poll(&mut self) -> Option<Event> {
+        let count = self.num_buffers.load(Ordering::Relaxed);
+        if count != self.last_count {
+            self.last_count = count;
+            self.last_checked = Instant::now();
+            return self.stalled_since.take().map(|since| Event::Recovered(since.elapsed()));
+        }
+
+        if self.last_checked.elapsed() < self.timeout {
+            return None;
+        }
+        if self.stalled_since.is_none() {
+            // samples stopped when they last arrived, not when it was noticed
+            self.stalled_since = Some(self.last_checked);
+        }
+        self.last_checked = Instant::now();
+        Some(Event::Stalled)
+    }
+}
+
+/// Passes the samples from `recv` on through a channel as bounded as the
+/// watchdog's, calling `f` with each buffer first. Buffers that don't fit
+/// are dropped and counted in `overruns`, so a stage that's behind can't
+/// pile them up ahead of the next.
+pub fn tee<F>(recv: Receiver<Vec<Complex<i8>>>, overruns: Arc<AtomicUsize>, mut f: F)
+              -> Receiver<Vec<Complex<i8>>>
+    where F: FnMut(&[Complex<i8>]) + Send + 'static
+{
+    let (send, tee_recv) = sync_channel(QUEUED_BUFFERS);
+    thread::spawn(move || {
+        for buff in recv.iter() {
+            f(&buff);
+            match send.try_send(buff) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    overruns.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(TrySendError::Disconnected(_)) => return,
+            }
+        }
+    });
+    tee_recv
+}
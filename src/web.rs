@@ -0,0 +1,129 @@
+//! A web page mirroring the spectrum and waterfall, for watching the band
+//! from a browser without a shell on the machine. The page draws on a
+//! canvas from the WebSocket feed of `ws`, colored with the palette and
+//! power range it's served with.
+
+use std::io;
+use std::net::IpAddr;
+
+use http::{self, Response};
+use palette::Palette;
+
+/// Starts serving the page on `port` of `addr`, for spectra from the
+/// WebSocket server on `ws_port` of the same host.
+pub fn serve(addr: IpAddr, port: u16, ws_port: u16, min_db: f32, max_db: f32,
+             palette: Palette) -> io::Result<()> {
+    let page = page(ws_port, min_db, max_db, palette).into_bytes();
+    http::serve(addr, port, move |path: &str| match path {
+        "/" | "/index.html" => Response::ok("text/html; charset=utf-8", page.clone()),
+        _ => Response::not_found(),
+    })
+}
+
+/// The page, with its settings filled in.
+fn page(ws_port: u16, min_db: f32, max_db: f32, palette: Palette) -> String {
+    let colors: Vec<String> = (0..256).map(|i| {
+        let (r, g, b) = palette.rgb(i as f32 / 255.0);
+        format!("[{},{},{}]", r, g, b)
+    }).collect();
+    PAGE.replace("{ws_port}", &ws_port.to_string())
+        .replace("{min_db}", &min_db.to_string())
+        .replace("{max_db}", &max_db.to_string())
+        .replace("{colors}", &colors.join(","))
+}
+
+const PAGE: &'static str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>terminal_spectrograph</title>
+<style>
+  html, body { margin: 0; height: 100%; background: #000; color: #ccc; font: 12px monospace; }
+  canvas { display: block; width: 100%; }
+  #status { position: absolute; top: 4px; left: 6px; }
+</style>
+</head>
+<body>
+<div id="status">Connecting...</div>
+<canvas id="spectrum"></canvas>
+<canvas id="waterfall"></canvas>
+<script>
+var MIN_DB = {min_db}, MAX_DB = {max_db};
+var COLORS = [{colors}];
+var spectrum = document.getElementById('spectrum');
+var waterfall = document.getElementById('waterfall');
+var label = document.getElementById('status');
+
+function fit() {
+  spectrum.width = waterfall.width = window.innerWidth;
+  spectrum.height = Math.floor(window.innerHeight * 0.3);
+  waterfall.height = window.innerHeight - spectrum.height;
+}
+window.addEventListener('resize', fit);
+fit();
+
+// from 0 at the bottom of the power range to 1 at the top
+function level(db) {
+  if (db === null) {
+    return 0;
+  }
+  return Math.min(Math.max((db - MIN_DB) / (MAX_DB - MIN_DB), 0), 1);
+}
+
+function draw(frame) {
+  var powers = frame.powers_db, width = spectrum.width;
+  var ctx = spectrum.getContext('2d');
+  ctx.fillStyle = '#000';
+  ctx.fillRect(0, 0, width, spectrum.height);
+  ctx.strokeStyle = '#0f0';
+  ctx.beginPath();
+  for (var x = 0; x < width; x++) {
+    var y = (1 - level(powers[Math.floor(x * powers.length / width)])) * spectrum.height;
+    if (x === 0) { ctx.moveTo(x, y); } else { ctx.lineTo(x, y); }
+  }
+  ctx.stroke();
+
+  // the newest row at the top, the rest moved down one
+  var wctx = waterfall.getContext('2d');
+  wctx.drawImage(waterfall, 0, 0, width, waterfall.height - 1, 0, 1, width, waterfall.height - 1);
+  var row = wctx.createImageData(width, 1);
+  for (var x = 0; x < width; x++) {
+    var color = COLORS[Math.round(255 * level(powers[Math.floor(x * powers.length / width)]))];
+    row.data.set([color[0], color[1], color[2], 255], 4 * x);
+  }
+  wctx.putImageData(row, 0, 0);
+
+  var span = frame.sample_rate_hz / 1e6;
+  label.textContent = (frame.center_freq_hz / 1e6).toFixed(3) + ' MHz, span ' +
+                       span.toFixed(3) + ' MHz';
+}
+
+function connect() {
+  var ws = new WebSocket('ws://' + location.hostname + ':{ws_port}/');
+  ws.onmessage = function (event) { draw(JSON.parse(event.data)); };
+  ws.onclose = function () {
+    label.textContent = 'Disconnected, trying again...';
+    setTimeout(connect, 2000);
+  };
+}
+connect();
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use palette::Palette;
+    use super::page;
+
+    #[test]
+    fn test_page() {
+        let page = page(8081, -10.0, 40.0, Palette::Classic);
+        assert!(page.contains("var MIN_DB = -10, MAX_DB = 40;"));
+        assert!(page.contains("location.hostname + ':8081/'"));
+        // every color filled in
+        assert_eq!(page.matches("],[").count(), 255);
+        assert!(!page.contains("{colors}"));
+    }
+}
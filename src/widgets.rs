@@ -0,0 +1,343 @@
+//! The spectrum view and the waterfall as widgets of their own, each keeping
+//! what it needs to draw itself, for laying out however an interface likes.
+//! They're placed and drawn like any `Widget`, and spectra go straight in as
+//! they come from the FFT.
+//!
+//! `Canvas` draws its spectrum views with `SpectrumWidget`. Its waterfalls
+//! share a pane's history between bands and the renderer, so they're drawn
+//! from that rather than with `WaterfallWidget`.
+
+use std::collections::VecDeque;
+use num::Complex;
+
+use cells::{Cell, CellAccessor, HasPosition, HasSize, Widget};
+use drawing::{draw_traces, draw_waterfall, draw_waterfall_ascii, draw_waterfall_braille,
+              normalize_spectrum, rescale_row, Glyphs, SpectrumStyle, Trace};
+use palette::Palette;
+
+/// The power range widgets start with, in dB, from bottom to top.
+const MIN_DB: f32 = 0.0;
+const MAX_DB: f32 = 50.0;
+
+/// The newest spectrum, one pixel per bin, two bins to a column.
+pub struct SpectrumWidget {
+    widget: Widget,
+    min_db: f32,
+    max_db: f32,
+    invert: bool,
+    palette: Palette,
+    glyphs: Glyphs,
+    style: SpectrumStyle,
+    ascii: bool,
+    /// Scaled between 0 and 1, and to the widget's width.
+    spectrum: Vec<f32>,
+}
+
+impl SpectrumWidget {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        SpectrumWidget {
+            widget: Widget::new(cols, rows),
+            min_db: MIN_DB,
+            max_db: MAX_DB,
+            invert: false,
+            palette: Palette::Classic,
+            glyphs: Glyphs::Braille,
+            style: SpectrumStyle::Dots,
+            ascii: false,
+            spectrum: Vec::new(),
+        }
+    }
+
+    /// Shows `spec`, straight from the FFT, in place of the last spectrum.
+    pub fn add_spectrum(&mut self, spec: &[Complex<f32>]) {
+        let normalized = normalize_spectrum(spec, self.min_db, self.max_db, self.invert);
+        let num_bins = 2 * self.widget.size().0;
+        self.spectrum = if normalized.len() == num_bins || num_bins == 0 {
+            normalized
+        } else {
+            rescale_row(&normalized, num_bins)
+        };
+        self.draw();
+    }
+
+    /// The spectrum shown, scaled between 0 and 1.
+    pub fn spectrum(&self) -> &[f32] {
+        &self.spectrum
+    }
+
+    /// Redraws the cells from the spectrum shown.
+    pub fn draw(&mut self) {
+        self.widget.clear(Cell::default());
+        draw_traces(&mut self.widget, &[(Trace::Live, &self.spectrum)], self.ascii, self.glyphs,
+                    self.style, self.palette);
+    }
+
+    /// Draws each of `traces`, scaled between 0 and 1, over what's there, the
+    /// live trace filled in under it.
+    pub fn draw_traces(&mut self, traces: &[(Trace, &[f32])]) {
+        draw_traces(&mut self.widget, traces, self.ascii, self.glyphs, self.style, self.palette);
+    }
+
+    /// Changes the size, the spectrum shown being stretched or squeezed to it.
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        let origin = self.widget.origin();
+        self.widget = Widget::new(cols, rows);
+        self.widget.set_origin(origin);
+        if !self.spectrum.is_empty() && cols > 0 {
+            self.spectrum = rescale_row(&self.spectrum, 2 * cols);
+        }
+        self.draw();
+    }
+
+    /// Sets the power range shown, in dB, from bottom to top, for the spectra
+    /// added from now on.
+    pub fn set_db_range(&mut self, min_db: f32, max_db: f32) {
+        self.min_db = min_db;
+        self.max_db = max_db;
+    }
+
+    /// Flips the spectra added from now on left to right.
+    pub fn set_invert(&mut self, invert: bool) {
+        self.invert = invert;
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    pub fn set_glyphs(&mut self, glyphs: Glyphs) {
+        self.glyphs = glyphs;
+    }
+
+    pub fn set_style(&mut self, style: SpectrumStyle) {
+        self.style = style;
+    }
+
+    pub fn set_ascii(&mut self, ascii: bool) {
+        self.ascii = ascii;
+    }
+
+    pub fn draw_into<T: CellAccessor>(&self, cells: &mut T) {
+        self.widget.draw_into(cells);
+    }
+}
+
+impl HasSize for SpectrumWidget {
+    fn size(&self) -> (usize, usize) {
+        self.widget.size()
+    }
+}
+
+impl HasPosition for SpectrumWidget {
+    fn origin(&self) -> (usize, usize) {
+        self.widget.origin()
+    }
+
+    fn set_origin(&mut self, origin: (usize, usize)) {
+        self.widget.set_origin(origin);
+    }
+}
+
+impl CellAccessor for SpectrumWidget {
+    fn cellvec(&self) -> &Vec<Cell> {
+        self.widget.cellvec()
+    }
+
+    fn cellvec_mut(&mut self) -> &mut Vec<Cell> {
+        self.widget.cellvec_mut()
+    }
+}
+
+/// Spectra as rows of colors, the newest at the top, keeping as many as it
+/// has room for.
+pub struct WaterfallWidget {
+    widget: Widget,
+    /// Scaled between 0 and 1 and to the widget's width, newest first.
+    history: VecDeque<Vec<f32>>,
+    min_db: f32,
+    max_db: f32,
+    invert: bool,
+    palette: Palette,
+    braille: bool,
+    ascii: bool,
+}
+
+impl WaterfallWidget {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        WaterfallWidget {
+            widget: Widget::new(cols, rows),
+            history: VecDeque::new(),
+            min_db: MIN_DB,
+            max_db: MAX_DB,
+            invert: false,
+            palette: Palette::Classic,
+            braille: false,
+            ascii: false,
+        }
+    }
+
+    /// Adds `spec`, straight from the FFT, at the top.
+    pub fn add_spectrum(&mut self, spec: &[Complex<f32>]) {
+        let normalized = normalize_spectrum(spec, self.min_db, self.max_db, self.invert);
+        let num_bins = 2 * self.widget.size().0;
+        self.history.push_front(if normalized.len() == num_bins || num_bins == 0 {
+            normalized
+        } else {
+            rescale_row(&normalized, num_bins)
+        });
+        let capacity = self.capacity();
+        self.history.truncate(capacity);
+        self.draw();
+    }
+
+    /// The spectra kept, scaled between 0 and 1, newest first.
+    pub fn history(&self) -> &VecDeque<Vec<f32>> {
+        &self.history
+    }
+
+    /// Forgets every spectrum.
+    pub fn clear(&mut self) {
+        self.history.clear();
+        self.draw();
+    }
+
+    /// Redraws the cells from the spectra kept.
+    pub fn draw(&mut self) {
+        let spectra = self.history.iter().map(|spec| &spec[..]);
+        if self.ascii {
+            draw_waterfall_ascii(&mut self.widget, spectra);
+        } else if self.braille {
+            draw_waterfall_braille(&mut self.widget, spectra, self.palette);
+        } else {
+            draw_waterfall(&mut self.widget, spectra, self.palette);
+        }
+    }
+
+    /// Changes the size, the spectra kept being stretched or squeezed to it.
+    /// Any that no longer fit are dropped.
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        let origin = self.widget.origin();
+        self.widget = Widget::new(cols, rows);
+        self.widget.set_origin(origin);
+        if cols > 0 {
+            for spec in self.history.iter_mut() {
+                *spec = rescale_row(spec, 2 * cols);
+            }
+        }
+        let capacity = self.capacity();
+        self.history.truncate(capacity);
+        self.draw();
+    }
+
+    /// Sets the power range shown, in dB, from weakest to strongest, for the
+    /// spectra added from now on.
+    pub fn set_db_range(&mut self, min_db: f32, max_db: f32) {
+        self.min_db = min_db;
+        self.max_db = max_db;
+    }
+
+    /// Flips the spectra added from now on left to right.
+    pub fn set_invert(&mut self, invert: bool) {
+        self.invert = invert;
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.draw();
+    }
+
+    /// Draws four spectra to a row with braille dots, rather than two with
+    /// half blocks.
+    pub fn set_braille(&mut self, braille: bool) {
+        self.braille = braille;
+        self.draw();
+    }
+
+    /// Draws a spectrum to a row in ASCII, for terminals without colors.
+    pub fn set_ascii(&mut self, ascii: bool) {
+        self.ascii = ascii;
+        self.draw();
+    }
+
+    pub fn draw_into<T: CellAccessor>(&self, cells: &mut T) {
+        self.widget.draw_into(cells);
+    }
+
+    /// Number of spectra the widget has room for.
+    fn capacity(&self) -> usize {
+        let per_row = if self.ascii { 1 } else if self.braille { 4 } else { 2 };
+        per_row * self.widget.size().1
+    }
+}
+
+impl HasSize for WaterfallWidget {
+    fn size(&self) -> (usize, usize) {
+        self.widget.size()
+    }
+}
+
+impl HasPosition for WaterfallWidget {
+    fn origin(&self) -> (usize, usize) {
+        self.widget.origin()
+    }
+
+    fn set_origin(&mut self, origin: (usize, usize)) {
+        self.widget.set_origin(origin);
+    }
+}
+
+impl CellAccessor for WaterfallWidget {
+    fn cellvec(&self) -> &Vec<Cell> {
+        self.widget.cellvec()
+    }
+
+    fn cellvec_mut(&mut self) -> &mut Vec<Cell> {
+        self.widget.cellvec_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num::Complex;
+    use cells::{CellAccessor, HasPosition, HasSize, Widget};
+    use super::{SpectrumWidget, WaterfallWidget};
+
+    #[test]
+    fn test_widgets() {
+        // a tone at 50 dB, the top of the range, in the fourth bin once shifted
+        let mut spec = vec![Complex::new(0.0, 0.0); 8];
+        spec[7] = Complex::new(1e5, 0.0);
+
+        let mut spectrum = SpectrumWidget::new(4, 2);
+        spectrum.set_origin((1, 0));
+        spectrum.add_spectrum(&spec);
+        assert_eq!(spectrum.spectrum().len(), 8);
+        assert_eq!(spectrum.spectrum()[3], 1.0);
+        assert!(spectrum.get(1, 0).unwrap().ch() != ' ');
+        assert_eq!(spectrum.get(3, 0).unwrap().ch(), ' ');
+
+        let mut screen = Widget::new(6, 2);
+        spectrum.draw_into(&mut screen);
+        assert_eq!(screen.get(2, 0).unwrap().ch(), spectrum.get(1, 0).unwrap().ch());
+
+        // half as wide, so a bin to a dot column
+        spectrum.resize(2, 2);
+        assert_eq!(spectrum.size(), (2, 2));
+        assert_eq!(spectrum.origin(), (1, 0));
+        assert_eq!(spectrum.spectrum().len(), 4);
+
+        // room for four spectra, two to a row
+        let mut waterfall = WaterfallWidget::new(4, 2);
+        for _ in 0..5 {
+            waterfall.add_spectrum(&spec);
+        }
+        assert_eq!(waterfall.history().len(), 4);
+        assert!(waterfall.get(1, 1).unwrap().ch() != ' ');
+
+        waterfall.set_braille(true);
+        waterfall.resize(4, 1);
+        assert_eq!(waterfall.history().len(), 4);
+        waterfall.clear();
+        assert_eq!(waterfall.get(1, 0).unwrap().ch(), ' ');
+    }
+}
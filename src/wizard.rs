@@ -0,0 +1,140 @@
+//! The setup screen shown when started without any arguments, for picking the
+//! input, frequency, span and gains without learning the options first.
+
+use std::cmp::min;
+use std::time::Duration;
+
+use drawing::Canvas;
+use input::{read_key, Key, INTERRUPT};
+use source::{self, Source};
+use units::{format_frequency, parse_frequency};
+use wait::stop_requested;
+
+/// Spans offered, sample rates the HackRF's filters cover well.
+const BANDWIDTHS_HZ: [f64; 6] = [2e6, 4e6, 8e6, 10e6, 16e6, 20e6];
+
+/// What the HackRF tunes to and its gain steps.
+pub const MIN_FREQ_HZ: f64 = 1e6;
+pub const MAX_FREQ_HZ: f64 = 6e9;
+const MAX_LNA_GAIN_DB: u32 = 40;
+const LNA_GAIN_STEP_DB: u32 = 8;
+const MAX_VGA_GAIN_DB: u32 = 62;
+const VGA_GAIN_STEP_DB: u32 = 2;
+
+const HINT: &'static str = "Up/Down choose, Left/Right change, Enter starts, Esc quits.";
+
+/// Rows of the form, in order.
+const NUM_FIELDS: usize = 6;
+const FREQUENCY_FIELD: usize = 1;
+
+/// What was picked, for the options it stands in for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setup {
+    pub source: Source,
+    pub freq_hz: u64,
+    pub bandwidth_hz: f64,
+    pub lna_gain_db: u32,
+    pub vga_gain_db: u32,
+    pub amp: bool,
+}
+
+/// Shows the form until it's filled in, or until it's dismissed, which gives
+/// `None`.
+pub fn run(canvas: &mut Canvas, lna_gain_db: u32, vga_gain_db: u32) -> Option<Setup> {
+    let sources = source::available();
+    let mut source_index = 0;
+    let mut freq = "100M".to_string();
+    let mut span_index = 3;
+    let (mut lna_gain_db, mut vga_gain_db, mut amp) = (lna_gain_db, vga_gain_db, false);
+    let mut field = FREQUENCY_FIELD;
+    let mut error = None;
+
+    loop {
+        let items = vec![
+            format!("Input      < {} >", sources[source_index]),
+            format!("Frequency  {}Hz, type to change", freq),
+            format!("Span       < {}Hz >", format_frequency(BANDWIDTHS_HZ[span_index])),
+            format!("LNA gain   < {} dB >", lna_gain_db),
+            format!("VGA gain   < {} dB >", vga_gain_db),
+            format!("Amplifier  < {} >", if amp { "on" } else { "off" }),
+        ];
+        let hint: String = error.take().unwrap_or_else(|| HINT.to_string());
+        canvas.show_picker("Setup", items, field, &hint);
+
+        let key = match read_key(canvas.get_term(), Duration::from_secs(1)) {
+            Ok(_) if stop_requested() => return None,
+            Ok(Some(key)) => key,
+            Ok(None) => continue,
+            Err(_) => return None,
+        };
+        let step = match key {
+            Key::Left => -1,
+            Key::Right => 1,
+            _ => 0,
+        };
+        match key {
+            Key::Esc | INTERRUPT => {
+                canvas.hide_picker();
+                return None;
+            }
+            Key::Up => field = field.saturating_sub(1),
+            Key::Down => field = min(field + 1, NUM_FIELDS - 1),
+            Key::Left | Key::Right => match field {
+                0 => source_index = (source_index as isize + step)
+                                        .max(0).min(sources.len() as isize - 1) as usize,
+                2 => span_index = (span_index as isize + step)
+                                      .max(0).min(BANDWIDTHS_HZ.len() as isize - 1) as usize,
+                3 => lna_gain_db = step_gain(lna_gain_db, step, LNA_GAIN_STEP_DB,
+                                             MAX_LNA_GAIN_DB),
+                4 => vga_gain_db = step_gain(vga_gain_db, step, VGA_GAIN_STEP_DB,
+                                             MAX_VGA_GAIN_DB),
+                5 => amp = !amp,
+                _ => {}
+            },
+            Key::Backspace if field == FREQUENCY_FIELD => {
+                freq.pop();
+            }
+            Key::Char(c) if field == FREQUENCY_FIELD && (c.is_digit(10) || ".ekMG".contains(c)) => {
+                freq.push(c);
+            }
+            Key::Enter => match parse_frequency(&freq) {
+                Some(hz) if hz >= MIN_FREQ_HZ && hz <= MAX_FREQ_HZ => {
+                    canvas.hide_picker();
+                    return Some(Setup {
+                        source: sources[source_index].clone(),
+                        freq_hz: hz as u64,
+                        bandwidth_hz: BANDWIDTHS_HZ[span_index],
+                        lna_gain_db: lna_gain_db,
+                        vga_gain_db: vga_gain_db,
+                        amp: amp,
+                    });
+                }
+                _ => {
+                    field = FREQUENCY_FIELD;
+                    error = Some(format!("Give a frequency from {}Hz to {}Hz, like 100M.",
+                                         format_frequency(MIN_FREQ_HZ),
+                                         format_frequency(MAX_FREQ_HZ)));
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+/// `gain_db` moved by `step` steps of `step_db`, within 0 to `max_db`.
+fn step_gain(gain_db: u32, step: isize, step_db: u32, max_db: u32) -> u32 {
+    let gain_db = gain_db as isize + step * step_db as isize;
+    gain_db.max(0).min(max_db as isize) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::step_gain;
+
+    #[test]
+    fn test_step_gain() {
+        assert_eq!(step_gain(16, 1, 8, 40), 24);
+        assert_eq!(step_gain(0, -1, 2, 62), 0);
+        assert_eq!(step_gain(40, 1, 8, 40), 40);
+    }
+}
@@ -0,0 +1,349 @@
+//! A small WebSocket server pushing every spectrum to whoever connects, so a
+//! browser or another machine can mirror the display.
+//!
+//! Each spectrum is a text message holding the same JSON object `--json`
+//! writes. Nothing sent by the clients is read past the handshake.
+//!
+//! There's enough of a client too, for the KiwiSDR input.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use num::Complex;
+
+use export::spectrum_json;
+
+/// Messages waiting for the sending thread, past which new ones are dropped
+/// rather than holding up the UI.
+const QUEUE_LEN: usize = 16;
+
+/// A client that can't take a message for this long is dropped, so it doesn't
+/// hold up the others.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Appended to a client's key to get the accept key, as RFC 6455 specifies.
+const HANDSHAKE_GUID: &'static str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+pub struct Broadcaster {
+    send: SyncSender<Arc<Vec<u8>>>,
+}
+
+impl Broadcaster {
+    /// Starts listening for clients on `addr`, which only lets in others
+    /// than this machine if it's one they can reach.
+    pub fn listen(addr: IpAddr, port: u16) -> io::Result<Self> {
+        let listener = try!(TcpListener::bind((addr, port)));
+        let clients = Arc::new(Mutex::new(Vec::new()));
+
+        let accepted = clients.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                // a slow handshake shouldn't hold up the next client
+                let accepted = accepted.clone();
+                thread::spawn(move || {
+                    if let Ok(stream) = handshake(stream) {
+                        accepted.lock().unwrap().push(stream);
+                    }
+                });
+            }
+        });
+
+        let (send, recv) = sync_channel::<Arc<Vec<u8>>>(QUEUE_LEN);
+        thread::spawn(move || {
+            for frame in recv.iter() {
+                // clients that have gone away are dropped on the first failed write
+                clients.lock().unwrap().retain(|mut client: &TcpStream| {
+                    client.write_all(&frame).is_ok()
+                });
+            }
+        });
+        Ok(Broadcaster { send: send })
+    }
+
+    /// Sends an unshifted FFT output taken at the given tuning to every client,
+    /// or drops it while they're behind. An error once the sending thread has
+    /// gone, when nothing more will get to them.
+    pub fn send_spectrum(&self, spec: &[Complex<f32>], center_freq_hz: f64,
+                         sample_rate_hz: f64) -> io::Result<()> {
+        let json = spectrum_json(spec, center_freq_hz, sample_rate_hz);
+        match self.send.try_send(Arc::new(text_frame(json.as_bytes()))) {
+            Ok(()) | Err(TrySendError::Full(_)) => Ok(()),
+            Err(TrySendError::Disconnected(_)) => {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "the sending thread has stopped"))
+            }
+        }
+    }
+}
+
+/// Answers a client's opening handshake, returning the stream ready for
+/// messages.
+fn handshake(stream: TcpStream) -> io::Result<TcpStream> {
+    let mut key = None;
+    {
+        let mut reader = BufReader::new(&stream);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if try!(reader.read_line(&mut line)) == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "closed in the handshake"));
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            let mut parts = header.splitn(2, ':');
+            let name = parts.next().unwrap_or("");
+            if name.eq_ignore_ascii_case("Sec-WebSocket-Key") {
+                key = parts.next().map(|value| value.trim().to_string());
+            }
+        }
+    }
+
+    let mut stream = stream;
+    try!(stream.set_write_timeout(Some(WRITE_TIMEOUT)));
+    let key = match key {
+        Some(key) => key,
+        None => {
+            try!(stream.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\nExpected a WebSocket.\r\n"));
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a WebSocket request"));
+        }
+    };
+    try!(write!(stream, "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                         Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept_key(&key)));
+    Ok(stream)
+}
+
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, HANDSHAKE_GUID).as_bytes()))
+}
+
+/// A single unmasked text frame, as servers send them.
+fn text_frame(payload: &[u8]) -> Vec<u8> {
+    frame(OPCODE_TEXT, payload, None)
+}
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+/// A single, final frame, masked with `mask` if it's from a client.
+fn frame(opcode: u8, payload: &[u8], mask: Option<[u8; 4]>) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    let masked = if mask.is_some() { 0x80 } else { 0 };
+    let len = payload.len();
+    if len < 126 {
+        frame.push(masked | len as u8);
+    } else if len <= 0xffff {
+        frame.push(masked | 126);
+        frame.push((len >> 8) as u8);
+        frame.push(len as u8);
+    } else {
+        frame.push(masked | 127);
+        for i in (0..8).rev() {
+            frame.push((len as u64 >> (8 * i)) as u8);
+        }
+    }
+    match mask {
+        Some(mask) => {
+            frame.extend_from_slice(&mask);
+            frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        }
+        None => frame.extend_from_slice(payload),
+    }
+    frame
+}
+
+/// Opens a WebSocket to `path` on `host`, like `example.org:8073`, with the
+/// opening handshake done, ready for messages.
+pub fn connect(host: &str, path: &str) -> io::Result<TcpStream> {
+    let mut stream = try!(TcpStream::connect(host));
+    // sixteen bytes, which only have to be different each time
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+    let key = base64(format!("{:016}", now.as_secs() % 10_000_000 * 1_000_000_000 +
+                                       now.subsec_nanos() as u64).as_bytes());
+    try!(write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\n\
+                         Connection: Upgrade\r\nSec-WebSocket-Key: {}\r\n\
+                         Sec-WebSocket-Version: 13\r\n\r\n", path, host, key));
+
+    // a byte at a time, so nothing after the headers is read
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        let mut byte = [0];
+        if try!(stream.read(&mut byte)) == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "closed in the handshake"));
+        }
+        response.push(byte[0]);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let accept = format!("sec-websocket-accept: {}", accept_key(&key)).to_lowercase();
+    if !response.starts_with("HTTP/1.1 101") || !response.to_lowercase().contains(&accept) {
+        let status = response.lines().next().unwrap_or("").to_string();
+        return Err(io::Error::new(io::ErrorKind::InvalidData, status));
+    }
+    Ok(stream)
+}
+
+/// Sends a text message from the client end.
+pub fn send_text(stream: &mut TcpStream, text: &str) -> io::Result<()> {
+    stream.write_all(&frame(OPCODE_TEXT, text.as_bytes(), Some(new_mask())))
+}
+
+/// The next message from the server, text or binary, fragments put back
+/// together and pings answered. An error once it's closed.
+pub fn read_message(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut message = Vec::new();
+    loop {
+        let mut head = [0; 2];
+        try!(stream.read_exact(&mut head));
+        let len = match head[1] & 0x7f {
+            126 => {
+                let mut len = [0; 2];
+                try!(stream.read_exact(&mut len));
+                (len[0] as u64) << 8 | len[1] as u64
+            }
+            127 => {
+                let mut len = [0; 8];
+                try!(stream.read_exact(&mut len));
+                len.iter().fold(0, |n, &b| n << 8 | b as u64)
+            }
+            len => len as u64,
+        };
+        // servers don't mask theirs
+        let mut payload = Vec::new();
+        try!(Read::by_ref(stream).take(len).read_to_end(&mut payload));
+        if payload.len() as u64 != len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "closed in a frame"));
+        }
+        match head[0] & 0x0f {
+            OPCODE_CLOSE => {
+                return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "closed"));
+            }
+            OPCODE_PING => try!(stream.write_all(&frame(OPCODE_PONG, &payload,
+                                                        Some(new_mask())))),
+            OPCODE_PONG => {}
+            _ => {
+                message.extend(payload);
+                if head[0] & 0x80 != 0 {
+                    return Ok(message);
+                }
+            }
+        }
+    }
+}
+
+/// A client's masking key, which servers only check is there.
+fn new_mask() -> [u8; 4] {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.subsec_nanos());
+    [(nanos >> 24) as u8, (nanos >> 16) as u8, (nanos >> 8) as u8, nanos as u8]
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    let bits = data.len() as u64 * 8;
+    for i in (0..8).rev() {
+        message.push((bits >> (8 * i)) as u8);
+    }
+
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = (chunk[4 * i] as u32) << 24 | (chunk[4 * i + 1] as u32) << 16 |
+                   (chunk[4 * i + 2] as u32) << 8 | chunk[4 * i + 3] as u32;
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let (mut a, mut b, mut c, mut d, mut e) = (h[0], h[1], h[2], h[3], h[4]);
+        for (i, &wi) in w.iter().enumerate() {
+            let (f, k) = match i / 20 {
+                0 => ((b & c) | (!b & d), 0x5a827999),
+                1 => (b ^ c ^ d, 0x6ed9eba1),
+                2 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k)
+                     .wrapping_add(wi);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        h[0] = h[0].wrapping_add(a);
+        h[1] = h[1].wrapping_add(b);
+        h[2] = h[2].wrapping_add(c);
+        h[3] = h[3].wrapping_add(d);
+        h[4] = h[4].wrapping_add(e);
+    }
+
+    let mut digest = [0u8; 20];
+    for (i, word) in h.iter().enumerate() {
+        for j in 0..4 {
+            digest[4 * i + j] = (word >> (24 - 8 * j)) as u8;
+        }
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &'static [u8] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for group in data.chunks(3) {
+        let n = (group[0] as u32) << 16 | (*group.get(1).unwrap_or(&0) as u32) << 8 |
+                *group.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= group.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::sync_channel;
+    use num::Complex;
+    use super::{accept_key, frame, text_frame, Broadcaster, OPCODE_TEXT};
+
+    #[test]
+    fn test_handshake_and_frames() {
+        // the example from RFC 6455
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(text_frame(b"hi"), vec![0x81, 2, b'h', b'i']);
+        assert_eq!(&text_frame(&[0; 300])[..4], &[0x81, 126, 1, 44]);
+        // and RFC 6455's masked "Hello"
+        assert_eq!(frame(OPCODE_TEXT, b"Hello", Some([0x37, 0xfa, 0x21, 0x3d])),
+                   vec![0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58]);
+    }
+
+    #[test]
+    fn test_sending_thread_gone() {
+        // an error to report rather than a panic
+        let (send, recv) = sync_channel(1);
+        let broadcaster = Broadcaster { send: send };
+        let spec = vec![Complex::new(1.0, 0.0); 4];
+        assert!(broadcaster.send_spectrum(&spec, 100e6, 2e6).is_ok());
+        drop(recv);
+        assert!(broadcaster.send_spectrum(&spec, 100e6, 2e6).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! A subscriber to GNU Radio's ZMQ PUB Sink, for looking at what a
+//! flowgraph makes. It speaks just enough of ZMTP 3.0, ZeroMQ's protocol
+//! over TCP, to connect as a SUB socket with no security and take every
+//! message, each of complex floats.
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use num::Complex;
+
+use udp;
+
+/// How long to wait before connecting again once the publisher's gone.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Frames bigger than this aren't from a sink, so the connection's dropped.
+const MAX_FRAME_LEN: u64 = 1 << 28;
+
+/// Flags of a frame's first byte.
+const MORE: u8 = 0x01;
+const LONG: u8 = 0x02;
+const COMMAND: u8 = 0x04;
+
+/// Takes the samples published at an endpoint, connecting again whenever
+/// the publisher goes, until dropped.
+pub struct Subscriber {
+    stop: Arc<AtomicBool>,
+    /// The connection there is, to hang up on when dropped.
+    stream: Arc<Mutex<Option<TcpStream>>>,
+}
+
+impl Subscriber {
+    /// Starts subscribing to `endpoint`, like `tcp://localhost:5555`.
+    pub fn start(endpoint: &str) -> Result<(Subscriber, Receiver<Vec<Complex<i8>>>), String> {
+        let addr = try!(parse_endpoint(endpoint));
+        let (send, recv) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stream = Arc::new(Mutex::new(None));
+        let thread_stop = stop.clone();
+        let thread_stream = stream.clone();
+        thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                if let Ok(stream) = TcpStream::connect(&addr[..]) {
+                    *thread_stream.lock().unwrap() = stream.try_clone().ok();
+                    // checked again, in case it was dropped while connecting
+                    if thread_stop.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let gone = subscribe(stream, &send);
+                    *thread_stream.lock().unwrap() = None;
+                    if gone.is_err() && thread_stop.load(Ordering::Relaxed) {
+                        return;
+                    }
+                }
+                thread::sleep(RECONNECT_INTERVAL);
+            }
+        });
+        Ok((Subscriber { stop: stop, stream: stream }, recv))
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(ref stream) = *self.stream.lock().unwrap() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+/// The `host:port` of a `tcp://host:port` endpoint.
+fn parse_endpoint(endpoint: &str) -> Result<String, String> {
+    let addr = match endpoint.find("://") {
+        Some(i) if &endpoint[..i] == "tcp" => &endpoint[i + 3..],
+        _ => return Err(format!("'{}' isn't a tcp:// endpoint", endpoint)),
+    };
+    match addr.rfind(':') {
+        Some(i) if i > 0 && addr[i + 1..].parse::<u16>().is_ok() => Ok(addr.to_string()),
+        _ => Err(format!("'{}' has no port", endpoint)),
+    }
+}
+
+/// Greets the publisher, subscribes to everything and sends on its
+/// messages' samples, until the connection or the receiver goes.
+fn subscribe(mut stream: TcpStream, send: &Sender<Vec<Complex<i8>>>) -> io::Result<()> {
+    try!(stream.write_all(&greeting()));
+    let mut theirs = [0; 64];
+    try!(stream.read_exact(&mut theirs));
+    if theirs[0] != 0xff || theirs[9] != 0x7f || theirs[10] < 3 || &theirs[12..17] != b"NULL\0" {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a ZMTP 3 publisher"));
+    }
+    try!(write_frame(&mut stream, COMMAND, &ready()));
+    // an empty topic, matching everything
+    try!(write_frame(&mut stream, 0, &[1]));
+
+    loop {
+        let (flags, body) = try!(read_frame(&mut stream));
+        // their READY, and any other commands, don't matter
+        if flags & COMMAND != 0 {
+            continue;
+        }
+        let mut samples = Vec::with_capacity(body.len() / 8);
+        udp::decode(udp::Format::Cf32, &body, &mut samples);
+        if !samples.is_empty() && send.send(samples).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// ZMTP 3.0's greeting, for the NULL mechanism as a client.
+fn greeting() -> Vec<u8> {
+    let mut greeting = vec![0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0x7f, 3, 0];
+    greeting.extend(b"NULL");
+    greeting.resize(64, 0);
+    greeting
+}
+
+/// The body of the READY command, saying it's a SUB socket.
+fn ready() -> Vec<u8> {
+    let mut body = vec![5];
+    body.extend(b"READY");
+    body.push(11);
+    body.extend(b"Socket-Type");
+    body.extend(&[0, 0, 0, 3]);
+    body.extend(b"SUB");
+    body
+}
+
+fn write_frame(stream: &mut TcpStream, flags: u8, body: &[u8]) -> io::Result<()> {
+    // nothing this sends needs a long one
+    try!(stream.write_all(&[flags, body.len() as u8]));
+    stream.write_all(body)
+}
+
+/// The next frame's flags, but for its length, and its body. The parts of
+/// a multipart message come as frames of their own.
+fn read_frame(stream: &mut TcpStream) -> io::Result<(u8, Vec<u8>)> {
+    let mut flags = [0];
+    try!(stream.read_exact(&mut flags));
+    let len = if flags[0] & LONG != 0 {
+        let mut len = [0; 8];
+        try!(stream.read_exact(&mut len));
+        len.iter().fold(0, |n, &b| n << 8 | b as u64)
+    } else {
+        let mut len = [0];
+        try!(stream.read_exact(&mut len));
+        len[0] as u64
+    };
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too big"));
+    }
+    let mut body = vec![0; len as usize];
+    try!(stream.read_exact(&mut body));
+    Ok((flags[0] & (MORE | COMMAND), body))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::RecvTimeoutError;
+    use std::time::Duration;
+    use num::Complex;
+    use super::{greeting, parse_endpoint, Subscriber};
+
+    #[test]
+    fn test_subscriber() {
+        assert_eq!(parse_endpoint("tcp://localhost:5555"), Ok("localhost:5555".to_string()));
+        assert!(parse_endpoint("ipc:///tmp/feed").is_err());
+        assert!(parse_endpoint("tcp://localhost").is_err());
+
+        // a publisher as GNU Radio's is, from its side
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let endpoint = format!("tcp://{}", listener.local_addr().unwrap());
+        let (subscriber, recv) = Subscriber::start(&endpoint).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut theirs = [0; 64];
+        stream.read_exact(&mut theirs).unwrap();
+        assert_eq!(&theirs[..], &greeting()[..]);
+        stream.write_all(&greeting()).unwrap();
+        let mut ready = [0; 2 + 25];
+        stream.read_exact(&mut ready).unwrap();
+        assert_eq!(&ready[..8], b"\x04\x19\x05READY");
+        assert_eq!(&ready[ready.len() - 3..], b"SUB");
+        let mut subscription = [0; 3];
+        stream.read_exact(&mut subscription).unwrap();
+        assert_eq!(subscription, [0, 1, 1]);
+
+        // their READY, then 0.5 - 1j and 0 + 0j
+        stream.write_all(b"\x04\x06\x05READY").unwrap();
+        stream.write_all(&[0, 16, 0, 0, 0, 0x3f, 0, 0, 0x80, 0xbf]).unwrap();
+        stream.write_all(&[0; 8]).unwrap();
+        let samples = recv.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(samples, vec![Complex::new(64, -127), Complex::new(0, 0)]);
+
+        // dropped, it hangs up
+        drop(subscriber);
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).unwrap();
+        assert_eq!(recv.recv_timeout(Duration::from_secs(5)), Err(RecvTimeoutError::Disconnected));
+    }
+}